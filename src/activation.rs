@@ -0,0 +1,199 @@
+// Handling of the xdg-activation, focusing the windows.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use smithay_client_toolkit::{
+    activation::{ActivationHandler, RequestData, RequestDataExt},
+    delegate_activation,
+    reexports::{
+        calloop::{
+            RegistrationToken,
+            timer::{TimeoutAction, Timer},
+        },
+        client::{
+            Proxy,
+            backend::ObjectId,
+            protocol::{wl_seat::WlSeat, wl_surface::WlSurface},
+        },
+    },
+};
+use tracing::{error, warn};
+
+use crate::{Events, WaylandState, WindowId};
+
+/// Time the compositor has to send the token before the activation is reported declined.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A request of an activation token, the token activates the target window.
+#[derive(Debug)]
+pub struct ActivationRequest {
+    data: RequestData,
+    /// The window to activate, the surface of the request when `None`.
+    target: Option<WlSurface>,
+    /// Reports the activation declined unless the token comes first.
+    timeout: Arc<Mutex<Option<RegistrationToken>>>,
+}
+
+impl ActivationRequest {
+    pub(crate) fn new(data: RequestData) -> Self {
+        Self {
+            data,
+            target: None,
+            timeout: Arc::default(),
+        }
+    }
+
+    /// The window the token activates, `None` once it's closed.
+    fn live_target(&self, is_live: impl Fn(&ObjectId) -> bool) -> Option<&WlSurface> {
+        self.target
+            .as_ref()
+            .or(self.data.surface.as_ref())
+            .filter(|surface| is_live(&surface.id()))
+    }
+}
+
+impl RequestDataExt for ActivationRequest {
+    fn app_id(&self) -> Option<&str> {
+        self.data.app_id()
+    }
+
+    fn seat_and_serial(&self) -> Option<(&WlSeat, u32)> {
+        self.data.seat_and_serial()
+    }
+
+    fn surface(&self) -> Option<&WlSurface> {
+        self.data.surface()
+    }
+}
+
+impl WaylandState {
+    /// Focus the target window with a token requested from the source one, such as the window
+    /// of the latest input.
+    ///
+    /// The compositor only grants the focus for a recent input, when it declines the target gets
+    /// an [`Events::ActivationDeclined`]. See [`WaylandState::request_user_attention`] then.
+    pub fn activate_window(&mut self, source: WindowId, target: WindowId) {
+        let (Some(activation), Some(source), Some(target)) = (
+            self.activation_state.as_ref(),
+            self.windows.get(&source),
+            self.windows.get(&target),
+        ) else {
+            if let Some(window) = self.windows.get(&target) {
+                let id = window.get_surface_id().clone();
+                self.events.push_back(Events::ActivationDeclined(id));
+            }
+            return;
+        };
+        let target_surface = target.immutable.window.wl_surface().clone();
+        let seat_and_serial = self
+            .seat_state
+            .serials
+            .latest_focus_serial()
+            .map(|(seat, serial)| (seat.clone(), serial));
+        let timeout = Arc::new(Mutex::new(None));
+        activation.request_token_with_data::<WaylandState, _>(
+            &self.queue_handle,
+            ActivationRequest {
+                data: RequestData {
+                    seat_and_serial,
+                    surface: Some(source.immutable.window.wl_surface().clone()),
+                    app_id: None,
+                },
+                target: Some(target_surface.clone()),
+                timeout: timeout.clone(),
+            },
+        );
+        let inserted = self.loop_handle.insert_source(
+            Timer::from_duration(TOKEN_TIMEOUT),
+            move |_, _, state| {
+                let id = target_surface.id();
+                if state.windows.get_by_object_id(&id).is_some() {
+                    state.events.push_back(Events::ActivationDeclined(id));
+                }
+                TimeoutAction::Drop
+            },
+        );
+        match inserted {
+            Ok(token) => *timeout.lock().unwrap() = Some(token),
+            Err(err) => error!("Failed to time the activation: {err}"),
+        }
+    }
+
+    /// Ask the compositor to draw the attention to the window, without taking the focus.
+    pub fn request_user_attention(&mut self, window_id: WindowId) {
+        let (Some(activation), Some(window)) =
+            (self.activation_state.as_ref(), self.windows.get(&window_id))
+        else {
+            return;
+        };
+        // A token without an input can't focus, the compositor marks the window urgent instead.
+        activation.request_token_with_data::<WaylandState, _>(
+            &self.queue_handle,
+            ActivationRequest::new(RequestData {
+                seat_and_serial: None,
+                surface: Some(window.immutable.window.wl_surface().clone()),
+                app_id: None,
+            }),
+        );
+    }
+}
+
+impl ActivationHandler for WaylandState {
+    type RequestData = ActivationRequest;
+
+    fn new_token(&mut self, token: String, data: &Self::RequestData) {
+        if let Some(timeout) = data.timeout.lock().unwrap().take() {
+            self.loop_handle.remove(timeout);
+        }
+        let Some(activation) = self.activation_state.as_ref() else {
+            warn!("Dropping an activation token, xdg-activation is not available");
+            return;
+        };
+        // The window may be closed by the time the token arrives.
+        match data.live_target(|id| self.windows.get_by_object_id(id).is_some()) {
+            Some(surface) => activation.activate::<WaylandState>(surface, token),
+            None => warn!("Dropping an activation token without a live window"),
+        }
+    }
+}
+
+delegate_activation!(WaylandState, ActivationRequest);
+
+#[cfg(test)]
+mod tests {
+    use smithay_client_toolkit::compositor::CompositorState;
+
+    use super::*;
+    use crate::testing::FakeCompositor;
+
+    #[test]
+    fn tokens_of_closed_windows_are_dropped() {
+        let compositor = FakeCompositor::new(&[("wl_compositor", 6)]);
+        let queue_handle = &compositor.queue_handle;
+        let surfaces = CompositorState::bind(&compositor.globals, queue_handle).unwrap();
+        let (source, target) = (
+            surfaces.create_surface(queue_handle),
+            surfaces.create_surface(queue_handle),
+        );
+        let request = |target: Option<WlSurface>| ActivationRequest {
+            target,
+            ..ActivationRequest::new(RequestData {
+                seat_and_serial: None,
+                surface: Some(source.clone()),
+                app_id: None,
+            })
+        };
+
+        let activation = request(Some(target.clone()));
+        assert_eq!(activation.live_target(|_| true), Some(&target));
+        // The source is not activated in place of the closed target.
+        assert_eq!(activation.live_target(|id| *id == source.id()), None);
+
+        let attention = request(None);
+        assert_eq!(attention.live_target(|_| true), Some(&source));
+        assert_eq!(attention.live_target(|_| false), None);
+    }
+}
@@ -0,0 +1,94 @@
+// Handling of the content-type-v1.
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::{
+        client::{
+            Connection, Dispatch, Proxy, QueueHandle, delegate_dispatch,
+            globals::{BindError, GlobalList},
+            protocol::wl_surface::WlSurface,
+        },
+        protocols::wp::content_type::v1::client::{
+            wp_content_type_manager_v1::WpContentTypeManagerV1,
+            wp_content_type_v1::{self, WpContentTypeV1},
+        },
+    },
+};
+
+use crate::WaylandState;
+
+/// The kind of content of a window, the compositor may tune the latency and the quality for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContentType {
+    #[default]
+    None,
+    Photo,
+    Video,
+    Game,
+}
+
+impl From<ContentType> for wp_content_type_v1::Type {
+    fn from(content_type: ContentType) -> Self {
+        match content_type {
+            ContentType::None => Self::None,
+            ContentType::Photo => Self::Photo,
+            ContentType::Video => Self::Video,
+            ContentType::Game => Self::Game,
+        }
+    }
+}
+
+/// Content type.
+#[derive(Debug, Clone)]
+pub struct ContentTypeState {
+    manager: WpContentTypeManagerV1,
+}
+
+impl ContentTypeState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    /// A surface can only have one content type object, it must be destroyed with the surface.
+    pub(crate) fn get_surface_content_type(
+        &self,
+        surface: &WlSurface,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> WpContentTypeV1 {
+        self.manager
+            .get_surface_content_type(surface, queue_handle, GlobalData)
+    }
+}
+
+impl Dispatch<WpContentTypeManagerV1, GlobalData, WaylandState> for ContentTypeState {
+    fn event(
+        _: &mut WaylandState,
+        _: &WpContentTypeManagerV1,
+        _: <WpContentTypeManagerV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+impl Dispatch<WpContentTypeV1, GlobalData, WaylandState> for ContentTypeState {
+    fn event(
+        _: &mut WaylandState,
+        _: &WpContentTypeV1,
+        _: <WpContentTypeV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+delegate_dispatch!(WaylandState: [WpContentTypeManagerV1: GlobalData] => ContentTypeState);
+delegate_dispatch!(WaylandState: [WpContentTypeV1: GlobalData] => ContentTypeState);
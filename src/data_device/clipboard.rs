@@ -0,0 +1,341 @@
+use std::{
+    error::Error,
+    fmt,
+    io::ErrorKind,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use smithay_client_toolkit::{
+    data_device_manager::{
+        WritePipe,
+        data_device::{DataDevice, DataDeviceData},
+    },
+    reexports::client::{Proxy, protocol::wl_data_source::WlDataSource},
+};
+use tracing::warn;
+
+use crate::{
+    Events, WaylandState,
+    data_device::{TransferError, read_from_pipe, write_to_pipe},
+};
+
+/// MIME types of UTF-8 text, the X11 ones included for the Xwayland clients.
+const TEXT_MIME_TYPES: [&str; 5] = [
+    "text/plain;charset=utf-8",
+    "text/plain",
+    "UTF8_STRING",
+    "STRING",
+    "TEXT",
+];
+
+/// Produces the data of a MIME type once a client asks for it.
+type Provider = Arc<dyn Fn(&str) -> Vec<u8> + Send + Sync>;
+
+#[derive(Clone)]
+enum ClipboardData {
+    Bytes(Arc<[u8]>),
+    Provider(Provider),
+}
+
+/// Data put on the clipboard, in one or more MIME types.
+#[derive(Clone, Default)]
+pub struct ClipboardContent {
+    formats: Vec<(String, ClipboardData)>,
+}
+
+impl ClipboardContent {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// UTF-8 text, offered under the usual text MIME types.
+    pub fn text(text: impl Into<String>) -> Self {
+        let text: Arc<[u8]> = text.into().into_bytes().into();
+        Self {
+            formats: TEXT_MIME_TYPES
+                .iter()
+                .map(|mime_type| (mime_type.to_string(), ClipboardData::Bytes(text.clone())))
+                .collect(),
+        }
+    }
+
+    /// Add the data of a MIME type, it replaces the previous one of the same type.
+    pub fn with_data(self, mime_type: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        self.with(mime_type.into(), ClipboardData::Bytes(data.into().into()))
+    }
+
+    /// Add a MIME type whose data is produced on every paste, with the requested type.
+    pub fn with_provider(
+        self,
+        mime_type: impl Into<String>,
+        provider: impl Fn(&str) -> Vec<u8> + Send + Sync + 'static,
+    ) -> Self {
+        self.with(
+            mime_type.into(),
+            ClipboardData::Provider(Arc::new(provider)),
+        )
+    }
+
+    fn with(mut self, mime_type: String, data: ClipboardData) -> Self {
+        self.formats.retain(|(format, _)| *format != mime_type);
+        self.formats.push((mime_type, data));
+        self
+    }
+
+    /// The offered MIME types, in the order they were added.
+    pub fn mime_types(&self) -> impl Iterator<Item = &str> {
+        self.formats.iter().map(|(mime_type, _)| mime_type.as_str())
+    }
+
+    pub(crate) fn data(&self, mime_type: &str) -> Option<Arc<[u8]>> {
+        let (_, data) = self
+            .formats
+            .iter()
+            .find(|(format, _)| format == mime_type)?;
+        Some(match data {
+            ClipboardData::Bytes(bytes) => bytes.clone(),
+            ClipboardData::Provider(provider) => provider(mime_type).into(),
+        })
+    }
+}
+
+impl fmt::Debug for ClipboardContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.mime_types()).finish()
+    }
+}
+
+/// Identifies a clipboard read, its data comes with the same request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClipboardRequest(u64);
+
+impl ClipboardRequest {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Clipboard changes reported to the application.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardEvent {
+    /// Another client took the clipboard, the content set by the application is gone.
+    Lost,
+    /// New content is on the clipboard, in these MIME types.
+    ///
+    /// The compositor only sends it to the focused client, a cleared clipboard is not reported.
+    Offer(Vec<String>),
+    /// Data of a read started with [`WaylandState::read_clipboard`].
+    Data {
+        request: ClipboardRequest,
+        mime_type: String,
+        data: Result<Vec<u8>, TransferError>,
+    },
+}
+
+/// Why the clipboard could not be set or read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardError {
+    /// The compositor does not support the data devices.
+    Unsupported,
+    /// No input was received yet, the compositor only lets the focused client set it.
+    NoInput,
+    /// Nothing is on the clipboard, or the application is not focused.
+    Empty,
+    /// The content on the clipboard is not offered in the MIME type.
+    UnknownMimeType,
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported => f.write_str("the clipboard is not supported"),
+            Self::NoInput => f.write_str("no input to set the clipboard with"),
+            Self::Empty => f.write_str("the clipboard is empty"),
+            Self::UnknownMimeType => f.write_str("the clipboard has no data of the MIME type"),
+        }
+    }
+}
+
+impl Error for ClipboardError {}
+
+impl WaylandState {
+    /// Put the content on the clipboard, or clear it with `None`.
+    ///
+    /// It's done on the seat of the latest input, the compositor may ignore it when the
+    /// application is not focused.
+    pub fn set_clipboard(
+        &mut self,
+        content: Option<ClipboardContent>,
+    ) -> Result<(), ClipboardError> {
+        let manager = self
+            .data_device_manager
+            .as_ref()
+            .ok_or(ClipboardError::Unsupported)?;
+        let (seat, serial) = self
+            .seat_state
+            .serials
+            .latest_focus_serial()
+            .ok_or(ClipboardError::NoInput)?;
+        let seat_id = seat.id();
+        let device = self
+            .seat_state
+            .data_devices
+            .get(&seat_id)
+            .ok_or(ClipboardError::NoInput)?;
+        match content {
+            Some(content) => {
+                let source =
+                    manager.create_copy_paste_source(&self.queue_handle, content.mime_types());
+                source.set_selection(device, serial);
+                self.selection = Some((source, content));
+            }
+            None => {
+                device.unset_selection(serial);
+                self.selection = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// MIME types of the content on the clipboard.
+    pub fn clipboard_mime_types(&self) -> Vec<String> {
+        self.clipboard_device()
+            .and_then(|device| device.data().selection_offer())
+            .map(|offer| offer.with_mime_types(<[String]>::to_vec))
+            .unwrap_or_default()
+    }
+
+    /// Read the clipboard in the MIME type, the data comes as [`ClipboardEvent::Data`].
+    ///
+    /// The data is read on the event loop, so large content doesn't block it.
+    pub fn read_clipboard(&mut self, mime_type: &str) -> Result<ClipboardRequest, ClipboardError> {
+        let request = ClipboardRequest::next();
+        self.read_clipboard_as(request, mime_type)?;
+        Ok(request)
+    }
+
+    pub(crate) fn read_clipboard_as(
+        &mut self,
+        request: ClipboardRequest,
+        mime_type: &str,
+    ) -> Result<(), ClipboardError> {
+        if self.data_device_manager.is_none() {
+            return Err(ClipboardError::Unsupported);
+        }
+        let device = self.clipboard_device().ok_or(ClipboardError::Empty)?;
+        let seat_id = device.data().seat().id();
+        let offer = device
+            .data()
+            .selection_offer()
+            .ok_or(ClipboardError::Empty)?;
+        if !offer.with_mime_types(|mime_types| mime_types.iter().any(|mime| mime == mime_type)) {
+            return Err(ClipboardError::UnknownMimeType);
+        }
+        let mime_type = mime_type.to_owned();
+        let pipe = match offer.receive(mime_type.clone()) {
+            Ok(pipe) => pipe,
+            Err(err) => {
+                warn!("Failed to read the clipboard: {err}");
+                let data = Err(TransferError::Io(ErrorKind::Other));
+                self.push_clipboard_data(request, mime_type, data);
+                return Ok(());
+            }
+        };
+        let limits = self.transfer_limits;
+        read_from_pipe(&self.loop_handle, pipe, limits, move |state, data| {
+            // The source client may stop writing once its selection is replaced.
+            let replaced = state
+                .seat_state
+                .data_devices
+                .get(&seat_id)
+                .and_then(|device| device.data().selection_offer())
+                .is_none_or(|current| current != offer);
+            let data = data.and_then(|data| {
+                if replaced {
+                    Err(TransferError::OfferGone)
+                } else {
+                    Ok(data)
+                }
+            });
+            state.push_clipboard_data(request, mime_type, data);
+        });
+        Ok(())
+    }
+
+    /// Read for [`LoopHandler::read_clipboard`], a failure to start comes as the data.
+    ///
+    /// [`LoopHandler::read_clipboard`]: crate::LoopHandler::read_clipboard
+    pub(crate) fn read_clipboard_or_fail(&mut self, request: ClipboardRequest, mime_type: String) {
+        if let Err(err) = self.read_clipboard_as(request, &mime_type) {
+            self.push_clipboard_data(request, mime_type, Err(TransferError::Unavailable(err)));
+        }
+    }
+
+    fn push_clipboard_data(
+        &mut self,
+        request: ClipboardRequest,
+        mime_type: String,
+        data: Result<Vec<u8>, TransferError>,
+    ) {
+        self.events
+            .push_back(Events::Clipboard(ClipboardEvent::Data {
+                request,
+                mime_type,
+                data,
+            }));
+    }
+
+    /// The data device of the seat of the latest input, the clipboard is the one of the focus.
+    fn clipboard_device(&self) -> Option<&DataDevice> {
+        self.seat_state
+            .serials
+            .latest_seat()
+            .and_then(|seat_id| self.seat_state.data_devices.get(&seat_id))
+            .or_else(|| self.seat_state.data_devices.values().next())
+    }
+
+    pub(crate) fn selection_changed(&mut self, data: &DataDeviceData) {
+        let Some(offer) = data.selection_offer() else {
+            return;
+        };
+        let mime_types = offer.with_mime_types(<[String]>::to_vec);
+        self.events
+            .push_back(Events::Clipboard(ClipboardEvent::Offer(mime_types)));
+    }
+
+    pub(crate) fn send_selection(
+        &mut self,
+        source: &WlDataSource,
+        mime_type: &str,
+        pipe: WritePipe,
+    ) {
+        let Some((_, content)) = self
+            .selection
+            .as_ref()
+            .filter(|(selection, _)| selection.inner() == source)
+        else {
+            return;
+        };
+        match content.data(mime_type) {
+            Some(data) => write_to_pipe(&self.loop_handle, pipe, data, self.transfer_limits),
+            None => warn!("The clipboard has no {mime_type} data"),
+        }
+    }
+
+    pub(crate) fn selection_cancelled(&mut self, source: &WlDataSource) {
+        // A replaced selection is cancelled too, it's not reported.
+        if self
+            .selection
+            .as_ref()
+            .is_some_and(|(selection, _)| selection.inner() == source)
+        {
+            self.selection = None;
+            self.events
+                .push_back(Events::Clipboard(ClipboardEvent::Lost));
+        }
+    }
+}
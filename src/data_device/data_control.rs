@@ -0,0 +1,443 @@
+// Handling of the wlr-data-control-unstable-v1, the clipboard of the clipboard managers.
+
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsFd, OwnedFd},
+    sync::Mutex,
+};
+
+use smithay_client_toolkit::{
+    data_device_manager::{ReadPipe, WritePipe},
+    globals::GlobalData,
+    reexports::client::{
+        Connection, Dispatch, Proxy, QueueHandle, delegate_dispatch, event_created_child,
+        globals::{BindError, GlobalList},
+        protocol::wl_seat::WlSeat,
+    },
+};
+use tracing::warn;
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+
+use crate::{
+    ClipboardContent, ClipboardError, ClipboardRequest, Events, WaylandState,
+    data_device::{TransferError, read_from_pipe, write_to_pipe},
+    seat::WlSeatId,
+};
+
+/// A selection shared between the clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SelectionKind {
+    Clipboard,
+    /// The selection pasted with the middle button.
+    Primary,
+}
+
+/// Changes of the selections seen through the data control.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataControlEvent {
+    /// The selection changed, its content is offered in these MIME types, none once cleared.
+    ///
+    /// It's sent whatever the focus, the content set by the application included.
+    Selection {
+        kind: SelectionKind,
+        mime_types: Vec<String>,
+    },
+    /// Data of a read started with [`DataControl::get_clipboard`] or [`DataControl::get_primary`].
+    Data {
+        request: ClipboardRequest,
+        mime_type: String,
+        data: Result<Vec<u8>, TransferError>,
+    },
+    /// Another client replaced the content set by the application.
+    Lost(SelectionKind),
+}
+
+/// Reads and writes of the selections without the keyboard focus, for the clipboard managers.
+#[derive(Debug)]
+pub struct DataControlState {
+    manager: ZwlrDataControlManagerV1,
+    devices: HashMap<WlSeatId, ZwlrDataControlDeviceV1>,
+    /// The content set by the application.
+    sources: HashMap<SelectionKind, (ZwlrDataControlSourceV1, ClipboardContent)>,
+}
+
+impl DataControlState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=2, GlobalData)?;
+        Ok(Self {
+            manager,
+            devices: HashMap::new(),
+            sources: HashMap::new(),
+        })
+    }
+
+    pub(crate) fn add_seat(&mut self, seat: &WlSeat, queue_handle: &QueueHandle<WaylandState>) {
+        let device =
+            self.manager
+                .get_data_device(seat, queue_handle, DataControlDeviceData::default());
+        self.devices.insert(seat.id(), device);
+    }
+
+    pub(crate) fn remove_seat(&mut self, seat_id: &WlSeatId) {
+        if let Some(device) = self.devices.remove(seat_id) {
+            device.destroy();
+        }
+    }
+
+    /// The device of the seat of the latest input, the selections are the ones of a seat.
+    fn device(&self, latest_seat: Option<&WlSeatId>) -> Option<&ZwlrDataControlDeviceV1> {
+        latest_seat
+            .and_then(|seat_id| self.devices.get(seat_id))
+            .or_else(|| self.devices.values().next())
+    }
+}
+
+/// The selections of the data control of the application.
+///
+/// It's available with the `data-control` feature, when the compositor supports it.
+pub struct DataControl<'a> {
+    pub(crate) state: &'a mut WaylandState,
+}
+
+impl DataControl<'_> {
+    /// MIME types of the content of the selection.
+    pub fn mime_types(&self, kind: SelectionKind) -> Vec<String> {
+        self.state
+            .data_control_offer(kind)
+            .and_then(|offer| {
+                offer
+                    .data::<DataControlOfferData>()
+                    .map(|data| data.mime_types.lock().unwrap().clone())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Read the clipboard in the MIME type, the data comes as [`DataControlEvent::Data`].
+    pub fn get_clipboard(&mut self, mime_type: &str) -> Result<ClipboardRequest, ClipboardError> {
+        self.read(SelectionKind::Clipboard, mime_type)
+    }
+
+    /// Read the primary selection in the MIME type, the data comes as [`DataControlEvent::Data`].
+    pub fn get_primary(&mut self, mime_type: &str) -> Result<ClipboardRequest, ClipboardError> {
+        self.read(SelectionKind::Primary, mime_type)
+    }
+
+    /// Put the content on the clipboard, or clear it with `None`.
+    pub fn set_clipboard(
+        &mut self,
+        content: Option<ClipboardContent>,
+    ) -> Result<(), ClipboardError> {
+        self.state
+            .set_data_control_selection(SelectionKind::Clipboard, content)
+    }
+
+    /// Put the content on the primary selection, or clear it with `None`.
+    pub fn set_primary(&mut self, content: Option<ClipboardContent>) -> Result<(), ClipboardError> {
+        self.state
+            .set_data_control_selection(SelectionKind::Primary, content)
+    }
+
+    fn read(
+        &mut self,
+        kind: SelectionKind,
+        mime_type: &str,
+    ) -> Result<ClipboardRequest, ClipboardError> {
+        let request = ClipboardRequest::next();
+        self.state
+            .read_data_control_selection(request, kind, mime_type)?;
+        Ok(request)
+    }
+}
+
+impl WaylandState {
+    pub(crate) fn set_data_control_selection(
+        &mut self,
+        kind: SelectionKind,
+        content: Option<ClipboardContent>,
+    ) -> Result<(), ClipboardError> {
+        let latest_seat = self.seat_state.serials.latest_seat();
+        let data_control = self
+            .data_control
+            .as_mut()
+            .ok_or(ClipboardError::Unsupported)?;
+        let device = data_control
+            .device(latest_seat.as_ref())
+            .ok_or(ClipboardError::NoInput)?
+            .clone();
+        if kind == SelectionKind::Primary && device.version() < 2 {
+            return Err(ClipboardError::Unsupported);
+        }
+        let source = content.map(|content| {
+            let source = data_control
+                .manager
+                .create_data_source(&self.queue_handle, kind);
+            for mime_type in content.mime_types() {
+                source.offer(mime_type.to_owned());
+            }
+            (source, content)
+        });
+        match kind {
+            SelectionKind::Clipboard => {
+                device.set_selection(source.as_ref().map(|(source, _)| source))
+            }
+            SelectionKind::Primary => {
+                device.set_primary_selection(source.as_ref().map(|(source, _)| source))
+            }
+        }
+        let previous = match source {
+            Some(source) => data_control.sources.insert(kind, source),
+            None => data_control.sources.remove(&kind),
+        };
+        // The compositor cancels the replaced source, it's not reported.
+        if let Some((source, _)) = previous {
+            source.destroy();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_data_control_selection(
+        &mut self,
+        request: ClipboardRequest,
+        kind: SelectionKind,
+        mime_type: &str,
+    ) -> Result<(), ClipboardError> {
+        if self.data_control.is_none() {
+            return Err(ClipboardError::Unsupported);
+        }
+        let offer = self.data_control_offer(kind).ok_or(ClipboardError::Empty)?;
+        let offered = offer.data::<DataControlOfferData>().is_some_and(|data| {
+            data.mime_types
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|m| m == mime_type)
+        });
+        if !offered {
+            return Err(ClipboardError::UnknownMimeType);
+        }
+        let mime_type = mime_type.to_owned();
+        let pipe = match receive(&offer, mime_type.clone()) {
+            Ok(pipe) => pipe,
+            Err(err) => {
+                warn!("Failed to read the selection: {err}");
+                self.push_data_control_data(request, mime_type, Err(TransferError::Io(err.kind())));
+                return Ok(());
+            }
+        };
+        let limits = self.transfer_limits;
+        read_from_pipe(&self.loop_handle, pipe, limits, move |state, data| {
+            // The source client may stop writing once its selection is replaced.
+            let data = data.and_then(|data| {
+                if state
+                    .data_control_offer(kind)
+                    .is_some_and(|current| current == offer)
+                {
+                    Ok(data)
+                } else {
+                    Err(TransferError::OfferGone)
+                }
+            });
+            state.push_data_control_data(request, mime_type, data);
+        });
+        Ok(())
+    }
+
+    /// Read for [`LoopHandler`], a failure to start comes as the data.
+    ///
+    /// [`LoopHandler`]: crate::LoopHandler
+    pub(crate) fn read_data_control_or_fail(
+        &mut self,
+        request: ClipboardRequest,
+        kind: SelectionKind,
+        mime_type: String,
+    ) {
+        if let Err(err) = self.read_data_control_selection(request, kind, &mime_type) {
+            let data = Err(TransferError::Unavailable(err));
+            self.push_data_control_data(request, mime_type, data);
+        }
+    }
+
+    fn push_data_control_data(
+        &mut self,
+        request: ClipboardRequest,
+        mime_type: String,
+        data: Result<Vec<u8>, TransferError>,
+    ) {
+        self.events
+            .push_back(Events::DataControl(DataControlEvent::Data {
+                request,
+                mime_type,
+                data,
+            }));
+    }
+
+    fn data_control_offer(&self, kind: SelectionKind) -> Option<ZwlrDataControlOfferV1> {
+        let latest_seat = self.seat_state.serials.latest_seat();
+        let device = self.data_control.as_ref()?.device(latest_seat.as_ref())?;
+        let selections = device
+            .data::<DataControlDeviceData>()?
+            .selections
+            .lock()
+            .unwrap();
+        selections.get(&kind).cloned()
+    }
+}
+
+/// Receive the offer in the MIME type through a new pipe.
+fn receive(offer: &ZwlrDataControlOfferV1, mime_type: String) -> io::Result<ReadPipe> {
+    let (reader, writer) = io::pipe()?;
+    offer.receive(mime_type, writer.as_fd());
+    // Our end is closed so the reader sees the end of the data.
+    drop(writer);
+    Ok(ReadPipe::from(OwnedFd::from(reader)))
+}
+
+#[derive(Debug, Default)]
+pub struct DataControlDeviceData {
+    /// The offers of the selections, the pending one is introduced before its selection.
+    selections: Mutex<HashMap<SelectionKind, ZwlrDataControlOfferV1>>,
+}
+
+#[derive(Debug, Default)]
+pub struct DataControlOfferData {
+    mime_types: Mutex<Vec<String>>,
+}
+
+impl Dispatch<ZwlrDataControlManagerV1, GlobalData, WaylandState> for DataControlState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwlrDataControlManagerV1,
+        _: <ZwlrDataControlManagerV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, DataControlDeviceData, WaylandState> for DataControlState {
+    fn event(
+        state: &mut WaylandState,
+        device: &ZwlrDataControlDeviceV1,
+        event: <ZwlrDataControlDeviceV1 as Proxy>::Event,
+        data: &DataControlDeviceData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        let (kind, offer) = match event {
+            zwlr_data_control_device_v1::Event::Selection { id } => (SelectionKind::Clipboard, id),
+            zwlr_data_control_device_v1::Event::PrimarySelection { id } => {
+                (SelectionKind::Primary, id)
+            }
+            zwlr_data_control_device_v1::Event::Finished => {
+                if let Some(data_control) = state.data_control.as_mut() {
+                    data_control
+                        .devices
+                        .retain(|_, current| *current != *device);
+                }
+                device.destroy();
+                return;
+            }
+            // The offer is created with its data, its MIME types follow.
+            _ => return,
+        };
+        let mime_types = offer
+            .as_ref()
+            .and_then(|offer| offer.data::<DataControlOfferData>())
+            .map(|data| data.mime_types.lock().unwrap().clone())
+            .unwrap_or_default();
+        let previous = {
+            let mut selections = data.selections.lock().unwrap();
+            match offer {
+                Some(offer) => selections.insert(kind, offer),
+                None => selections.remove(&kind),
+            }
+        };
+        if let Some(previous) = previous {
+            previous.destroy();
+        }
+        state
+            .events
+            .push_back(Events::DataControl(DataControlEvent::Selection {
+                kind,
+                mime_types,
+            }));
+    }
+
+    event_created_child!(WaylandState, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, DataControlOfferData::default())
+    ]);
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, DataControlOfferData, WaylandState> for DataControlState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwlrDataControlOfferV1,
+        event: <ZwlrDataControlOfferV1 as Proxy>::Event,
+        data: &DataControlOfferData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+            data.mime_types.lock().unwrap().push(mime_type);
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, SelectionKind, WaylandState> for DataControlState {
+    fn event(
+        state: &mut WaylandState,
+        source: &ZwlrDataControlSourceV1,
+        event: <ZwlrDataControlSourceV1 as Proxy>::Event,
+        kind: &SelectionKind,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        let Some(data_control) = state.data_control.as_mut() else {
+            return;
+        };
+        let current = data_control
+            .sources
+            .get(kind)
+            .filter(|(current, _)| current == source);
+        match event {
+            zwlr_data_control_source_v1::Event::Send { mime_type, fd } => {
+                let Some((_, content)) = current else {
+                    return;
+                };
+                match content.data(&mime_type) {
+                    Some(data) => {
+                        let pipe = WritePipe::from(fd);
+                        write_to_pipe(&state.loop_handle, pipe, data, state.transfer_limits);
+                    }
+                    None => warn!("The selection has no {mime_type} data"),
+                }
+            }
+            zwlr_data_control_source_v1::Event::Cancelled => {
+                if current.is_some() {
+                    data_control.sources.remove(kind);
+                    state
+                        .events
+                        .push_back(Events::DataControl(DataControlEvent::Lost(*kind)));
+                }
+                source.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+delegate_dispatch!(WaylandState: [ZwlrDataControlManagerV1: GlobalData] => DataControlState);
+delegate_dispatch!(WaylandState: [ZwlrDataControlDeviceV1: DataControlDeviceData] => DataControlState);
+delegate_dispatch!(WaylandState: [ZwlrDataControlOfferV1: DataControlOfferData] => DataControlState);
+delegate_dispatch!(WaylandState: [ZwlrDataControlSourceV1: SelectionKind] => DataControlState);
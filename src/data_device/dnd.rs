@@ -0,0 +1,576 @@
+use std::{fmt, path::PathBuf};
+
+use dpi::{LogicalPosition, PhysicalPosition};
+use smithay_client_toolkit::{
+    compositor::Surface,
+    data_device_manager::{
+        WritePipe, data_device::DataDeviceData, data_offer::DragOffer, data_source::DragSource,
+    },
+    reexports::{
+        calloop::LoopHandle,
+        client::{
+            Proxy,
+            backend::ObjectId,
+            protocol::{
+                wl_data_device::WlDataDevice, wl_data_device_manager::DndAction,
+                wl_data_source::WlDataSource, wl_seat::WlSeat, wl_surface::WlSurface,
+            },
+        },
+    },
+    shm::{
+        Shm,
+        slot::{Buffer, SlotPool},
+    },
+};
+use tracing::{debug, error, warn};
+
+use crate::{
+    ClipboardContent, CursorImage, Events, WaylandState,
+    data_device::{
+        TransferError, TransferLimits, read_from_pipe,
+        uri_list::{URI_LIST_MIME_TYPE, parse_uri_list},
+        write_to_pipe,
+    },
+};
+
+/// What happens to the dragged data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DragAction {
+    Copy,
+    Move,
+    /// The user picks the action once dropped, see [`crate::WaylandWindow::choose_drag_action`].
+    Ask,
+}
+
+impl DragAction {
+    pub(crate) fn from_wayland(action: DndAction) -> Option<Self> {
+        if action.contains(DndAction::Ask) {
+            Some(Self::Ask)
+        } else if action.contains(DndAction::Move) {
+            Some(Self::Move)
+        } else if action.contains(DndAction::Copy) {
+            Some(Self::Copy)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn to_wayland(self) -> DndAction {
+        match self {
+            Self::Copy => DndAction::Copy,
+            Self::Move => DndAction::Move,
+            Self::Ask => DndAction::Ask,
+        }
+    }
+}
+
+/// Drag and drop over a window.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DragEvent {
+    /// A drag entered the window, its data is offered in these MIME types.
+    Entered {
+        position: PhysicalPosition<f64>,
+        mime_types: Vec<String>,
+    },
+    Moved {
+        position: PhysicalPosition<f64>,
+    },
+    /// The drag left the window, or it was dropped without an accepted MIME type.
+    Left,
+    /// The compositor picked the action from the ones of the source and the window.
+    Action(Option<DragAction>),
+    /// The drag was dropped with [`DragAction::Ask`], the data comes once the action is chosen.
+    Ask,
+    /// The drag was dropped, with the data of the accepted MIME type.
+    Dropped {
+        mime_type: String,
+        data: Result<Vec<u8>, TransferError>,
+        action: DragAction,
+    },
+    /// The files of a `text/uri-list` drag over the window, after [`DragEvent::Entered`].
+    HoveredFiles(Vec<PathBuf>),
+    /// The files of a drop accepted as `text/uri-list`, after [`DragEvent::Dropped`].
+    DroppedFiles(Vec<PathBuf>),
+}
+
+/// Data dragged out of a window, see [`crate::WaylandWindow::start_drag`].
+#[derive(Debug, Clone)]
+pub struct DragContent {
+    data: ClipboardContent,
+    actions: DndAction,
+}
+
+impl DragContent {
+    /// The data and the actions allowed on it, without actions it's copied.
+    pub fn new(data: ClipboardContent, actions: &[DragAction]) -> Self {
+        let actions = actions.iter().fold(DndAction::empty(), |actions, action| {
+            actions | action.to_wayland()
+        });
+        Self {
+            data,
+            actions: if actions.is_empty() {
+                DndAction::Copy
+            } else {
+                actions
+            },
+        }
+    }
+}
+
+/// Image following the pointer during a drag, its hotspot is under the pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DragIcon {
+    image: CursorImage,
+}
+
+impl DragIcon {
+    pub fn new(image: CursorImage) -> Self {
+        Self { image }
+    }
+}
+
+/// The end of a drag started by the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DragSourceEvent {
+    /// The compositor picked the action from the ones of the drag and the target, `None`
+    /// when no target accepts it.
+    Action(Option<DragAction>),
+    /// The target received the data, the dragged item is removed on [`DragAction::Move`].
+    Finished(DragAction),
+    /// The drag was cancelled or rejected by the target.
+    Cancelled,
+}
+
+/// The icon surface of a drag, kept until the drag ends.
+struct IconSurface {
+    surface: Surface,
+    _pool: SlotPool,
+    _buffer: Buffer,
+}
+
+impl fmt::Debug for IconSurface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("IconSurface")
+            .field(self.surface.wl_surface())
+            .finish()
+    }
+}
+
+/// A drag started by the application.
+#[derive(Debug)]
+pub(crate) struct OutgoingDrag {
+    source: DragSource,
+    data: ClipboardContent,
+    /// The window the drag started from.
+    surface_id: ObjectId,
+    action: Option<DragAction>,
+    _icon: Option<IconSurface>,
+}
+
+/// A drag over a window.
+#[derive(Debug)]
+pub(crate) struct DropTarget {
+    offer: DragOffer,
+    /// The MIME type accepted by the application.
+    mime_type: Option<String>,
+    dropped: bool,
+    limits: TransferLimits,
+}
+
+impl DropTarget {
+    pub(crate) fn accept(&mut self, mime_type: Option<String>, action: DragAction) {
+        if self.dropped {
+            return;
+        }
+        self.offer
+            .accept_mime_type(self.offer.serial, mime_type.clone());
+        let actions = DndAction::Copy | DndAction::Move | DndAction::Ask;
+        self.offer.set_actions(actions, action.to_wayland());
+        self.mime_type = mime_type;
+    }
+
+    /// Receive the data once dropped with [`DragAction::Ask`], or cancel the drop with `None`.
+    ///
+    /// Returns whether the drop was cancelled.
+    pub(crate) fn choose_action(
+        &self,
+        loop_handle: &LoopHandle<'static, WaylandState>,
+        surface_id: ObjectId,
+        action: Option<DragAction>,
+    ) -> bool {
+        let (Some(mime_type), true) = (self.mime_type.clone(), self.dropped) else {
+            return false;
+        };
+        match action.filter(|action| *action != DragAction::Ask) {
+            Some(action) => {
+                self.offer
+                    .set_actions(action.to_wayland(), action.to_wayland());
+                self.offer
+                    .accept_mime_type(self.offer.serial, Some(mime_type.clone()));
+                receive_drop(
+                    loop_handle,
+                    self.limits,
+                    surface_id,
+                    self.offer.clone(),
+                    mime_type,
+                    action,
+                );
+                false
+            }
+            None => {
+                self.offer.destroy();
+                true
+            }
+        }
+    }
+}
+
+/// Read the files of the drag while it's over the window.
+fn read_hovered_files(
+    loop_handle: &LoopHandle<'static, WaylandState>,
+    limits: TransferLimits,
+    surface_id: ObjectId,
+    offer: DragOffer,
+) {
+    let pipe = match offer.receive(URI_LIST_MIME_TYPE.to_owned()) {
+        Ok(pipe) => pipe,
+        Err(err) => {
+            warn!("Failed to receive the hovered files: {err}");
+            return;
+        }
+    };
+    read_from_pipe(loop_handle, pipe, limits, move |state, data| {
+        // The files of a drag which left or was dropped are not reported.
+        let hovering = state
+            .windows
+            .get_by_object_id(&surface_id)
+            .and_then(|window| window.drop_target.as_ref())
+            .is_some_and(|target| target.offer == offer && !target.dropped);
+        match data {
+            Ok(data) if hovering => {
+                let files = DragEvent::HoveredFiles(parse_uri_list(&data));
+                state.events.push_back(Events::Drag(surface_id, files));
+            }
+            Ok(_) => {}
+            Err(err) => debug!("Failed to read the hovered files: {err}"),
+        }
+    });
+}
+
+/// Receive the dropped data, then finish the drag.
+fn receive_drop(
+    loop_handle: &LoopHandle<'static, WaylandState>,
+    limits: TransferLimits,
+    surface_id: ObjectId,
+    offer: DragOffer,
+    mime_type: String,
+    action: DragAction,
+) {
+    let received = offer.receive(mime_type.clone());
+    let finish = move |state: &mut WaylandState, data: Result<Vec<u8>, TransferError>| {
+        offer.finish();
+        offer.destroy();
+        if let Some(window) = state.windows.get_mut_by_object_id(&surface_id) {
+            window.drop_target = None;
+        }
+        let files = match &data {
+            Ok(data) if mime_type == URI_LIST_MIME_TYPE => Some(parse_uri_list(data)),
+            _ => None,
+        };
+        let dropped = DragEvent::Dropped {
+            mime_type,
+            data,
+            action,
+        };
+        state
+            .events
+            .push_back(Events::Drag(surface_id.clone(), dropped));
+        if let Some(files) = files {
+            state
+                .events
+                .push_back(Events::Drag(surface_id, DragEvent::DroppedFiles(files)));
+        }
+    };
+    match received {
+        Ok(pipe) => read_from_pipe(loop_handle, pipe, limits, finish),
+        Err(err) => {
+            warn!("Failed to receive the dropped data: {err}");
+            let kind = err.kind();
+            loop_handle.insert_idle(move |state| finish(state, Err(TransferError::Io(kind))));
+        }
+    }
+}
+
+impl WaylandState {
+    pub(crate) fn drag_entered(
+        &mut self,
+        data_device: &WlDataDevice,
+        x: f64,
+        y: f64,
+        surface: &WlSurface,
+    ) {
+        let Some(data) = data_device.data::<DataDeviceData>() else {
+            return;
+        };
+        // Drags inside of another client carry no offer.
+        let Some(offer) = data.drag_offer() else {
+            return;
+        };
+        let id = surface.id();
+        // The decorations are not drop targets.
+        let Some(window) = self.windows.get_mut_by_object_id(&id) else {
+            return;
+        };
+        let mime_types = offer.with_mime_types(<[String]>::to_vec);
+        if mime_types
+            .iter()
+            .any(|mime_type| mime_type == URI_LIST_MIME_TYPE)
+        {
+            let limits = self.transfer_limits;
+            read_hovered_files(&self.loop_handle, limits, id.clone(), offer.clone());
+        }
+        window.drop_target = Some(DropTarget {
+            offer,
+            mime_type: None,
+            dropped: false,
+            limits: self.transfer_limits,
+        });
+        let position = LogicalPosition::new(x, y).to_physical(window.scale_factor);
+        self.seat_state.drags.insert(data.seat().id(), id.clone());
+        self.events.push_back(Events::Drag(
+            id,
+            DragEvent::Entered {
+                position,
+                mime_types,
+            },
+        ));
+    }
+
+    pub(crate) fn drag_moved(&mut self, data_device: &WlDataDevice, x: f64, y: f64) {
+        let Some(id) = self.drag_surface(data_device) else {
+            return;
+        };
+        let Some(window) = self.windows.get_by_object_id(&id) else {
+            return;
+        };
+        let position = LogicalPosition::new(x, y).to_physical(window.scale_factor);
+        self.events
+            .push_back(Events::Drag(id, DragEvent::Moved { position }));
+    }
+
+    pub(crate) fn drag_left(&mut self, data_device: &WlDataDevice) {
+        let Some(data) = data_device.data::<DataDeviceData>() else {
+            return;
+        };
+        let Some(id) = self.seat_state.drags.remove(&data.seat().id()) else {
+            return;
+        };
+        let Some(window) = self.windows.get_mut_by_object_id(&id) else {
+            return;
+        };
+        // The leave follows the drop, the dropped data is still on its way.
+        if window
+            .drop_target
+            .take_if(|target| !target.dropped)
+            .is_some()
+        {
+            self.events.push_back(Events::Drag(id, DragEvent::Left));
+        }
+    }
+
+    pub(crate) fn drag_dropped(&mut self, data_device: &WlDataDevice) {
+        let Some(id) = self.drag_surface(data_device) else {
+            return;
+        };
+        let (Some(window), Some(offer)) = (
+            self.windows.get_mut_by_object_id(&id),
+            data_device
+                .data::<DataDeviceData>()
+                .and_then(DataDeviceData::drag_offer),
+        ) else {
+            return;
+        };
+        let Some(target) = window.drop_target.as_mut() else {
+            return;
+        };
+        target.dropped = true;
+        // The offer has the action picked last.
+        target.offer = offer.clone();
+        match (
+            target.mime_type.clone(),
+            DragAction::from_wayland(offer.selected_action),
+        ) {
+            (Some(_), Some(DragAction::Ask)) => {
+                self.events.push_back(Events::Drag(id, DragEvent::Ask));
+            }
+            (Some(mime_type), Some(action)) => {
+                let limits = target.limits;
+                receive_drop(&self.loop_handle, limits, id, offer, mime_type, action);
+            }
+            _ => {
+                offer.destroy();
+                window.drop_target = None;
+                self.events.push_back(Events::Drag(id, DragEvent::Left));
+            }
+        }
+    }
+
+    pub(crate) fn drag_action(&mut self, offer: &DragOffer, action: DndAction) {
+        let action = DragAction::from_wayland(action);
+        self.events
+            .push_back(Events::Drag(offer.surface.id(), DragEvent::Action(action)));
+    }
+
+    /// Start the drag from the window, with the serial of the press holding it.
+    pub(crate) fn start_drag(
+        &mut self,
+        surface_id: ObjectId,
+        seat: WlSeat,
+        serial: u32,
+        content: DragContent,
+        icon: Option<DragIcon>,
+    ) {
+        // A new drag replaces the previous one, which the compositor no longer reports.
+        if let Some(drag) = self.drag_source.take() {
+            self.events.push_back(Events::DragSource(
+                drag.surface_id,
+                DragSourceEvent::Cancelled,
+            ));
+        }
+        let (Some(manager), Some(device), Some(window)) = (
+            self.data_device_manager.as_ref(),
+            self.seat_state.data_devices.get(&seat.id()),
+            self.windows.get_by_object_id(&surface_id),
+        ) else {
+            self.events
+                .push_back(Events::DragSource(surface_id, DragSourceEvent::Cancelled));
+            return;
+        };
+        let source = manager.create_drag_and_drop_source(
+            &self.queue_handle,
+            content.data.mime_types(),
+            content.actions,
+        );
+        let icon = icon.and_then(|icon| {
+            let surface = match Surface::new(&*self.compositor_state, &self.queue_handle) {
+                Ok(surface) => surface,
+                Err(err) => {
+                    error!("{err}");
+                    return None;
+                }
+            };
+            Some((surface, icon))
+        });
+        let origin = window.immutable.window.wl_surface();
+        source.start_drag(
+            device,
+            origin,
+            icon.as_ref().map(|(surface, _)| surface.wl_surface()),
+            serial,
+        );
+        // The icon gets its role with the drag, it's drawn after.
+        let icon = icon.and_then(
+            |(surface, icon)| match draw_icon(&self.shm, surface, &icon) {
+                Ok(icon) => Some(icon),
+                Err(err) => {
+                    error!("Failed to draw the drag icon: {err}");
+                    None
+                }
+            },
+        );
+        self.drag_source = Some(OutgoingDrag {
+            source,
+            data: content.data,
+            surface_id,
+            action: None,
+            _icon: icon,
+        });
+    }
+
+    /// Send the dragged data to the target.
+    pub(crate) fn send_drag_data(
+        &mut self,
+        source: &WlDataSource,
+        mime_type: &str,
+        pipe: WritePipe,
+    ) {
+        let Some(drag) = self
+            .drag_source
+            .as_ref()
+            .filter(|drag| drag.source.inner() == source)
+        else {
+            return;
+        };
+        match drag.data.data(mime_type) {
+            Some(data) => write_to_pipe(&self.loop_handle, pipe, data, self.transfer_limits),
+            None => warn!("The drag has no {mime_type} data"),
+        }
+    }
+
+    pub(crate) fn is_drag_source(&self, source: &WlDataSource) -> bool {
+        self.drag_source
+            .as_ref()
+            .is_some_and(|drag| drag.source.inner() == source)
+    }
+
+    pub(crate) fn drag_source_action(&mut self, source: &WlDataSource, action: DndAction) {
+        let Some(drag) = self
+            .drag_source
+            .as_mut()
+            .filter(|drag| drag.source.inner() == source)
+        else {
+            return;
+        };
+        drag.action = DragAction::from_wayland(action);
+        let event = DragSourceEvent::Action(drag.action);
+        self.events
+            .push_back(Events::DragSource(drag.surface_id.clone(), event));
+    }
+
+    /// The drag ended, `finished` when the target received the data.
+    pub(crate) fn drag_source_ended(&mut self, source: &WlDataSource, finished: bool) {
+        let Some(drag) = self
+            .drag_source
+            .take_if(|drag| drag.source.inner() == source)
+        else {
+            return;
+        };
+        let event = if finished {
+            DragSourceEvent::Finished(drag.action.unwrap_or(DragAction::Copy))
+        } else {
+            DragSourceEvent::Cancelled
+        };
+        self.events
+            .push_back(Events::DragSource(drag.surface_id, event));
+    }
+
+    /// The window under the drag of the seat of the data device.
+    fn drag_surface(&self, data_device: &WlDataDevice) -> Option<ObjectId> {
+        let data = data_device.data::<DataDeviceData>()?;
+        self.seat_state.drags.get(&data.seat().id()).cloned()
+    }
+}
+
+/// Draw the icon on its surface, one image pixel per logical pixel.
+fn draw_icon(shm: &Shm, surface: Surface, icon: &DragIcon) -> Result<IconSurface, String> {
+    let image = &icon.image;
+    let mut pool = SlotPool::new(1, shm).map_err(|err| err.to_string())?;
+    let buffer = image.create_buffer(&mut pool)?;
+    let wl_surface = surface.wl_surface();
+    let (x, y) = (-(image.hotspot_x as i32), -(image.hotspot_y as i32));
+    wl_surface.set_buffer_scale(1);
+    // The offset of the attach request is an error since version 5.
+    if wl_surface.version() >= 5 {
+        wl_surface.attach(Some(buffer.wl_buffer()), 0, 0);
+        wl_surface.offset(x, y);
+    } else {
+        wl_surface.attach(Some(buffer.wl_buffer()), x, y);
+    }
+    wl_surface.damage_buffer(0, 0, image.width as i32, image.height as i32);
+    wl_surface.commit();
+    Ok(IconSurface {
+        surface,
+        _pool: pool,
+        _buffer: buffer,
+    })
+}
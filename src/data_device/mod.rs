@@ -0,0 +1,380 @@
+// Handling of the wl_data_device, the clipboard and drag and drop.
+
+use std::{
+    cell::Cell,
+    error::Error,
+    fmt,
+    io::{ErrorKind, Read, Write},
+    mem,
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
+
+use smithay_client_toolkit::{
+    data_device_manager::{
+        ReadPipe, WritePipe,
+        data_device::{DataDeviceData, DataDeviceHandler},
+        data_offer::{DataOfferHandler, DragOffer},
+        data_source::DataSourceHandler,
+    },
+    reexports::{
+        calloop::{
+            LoopHandle, PostAction, RegistrationToken,
+            timer::{TimeoutAction, Timer},
+        },
+        client::{
+            Connection, Proxy, QueueHandle,
+            protocol::{
+                wl_data_device::WlDataDevice, wl_data_device_manager::DndAction,
+                wl_data_source::WlDataSource, wl_surface::WlSurface,
+            },
+        },
+    },
+};
+use tracing::{debug, error, warn};
+
+use crate::{ClipboardError, WaylandState};
+
+pub mod clipboard;
+#[cfg(feature = "data-control")]
+pub mod data_control;
+pub mod dnd;
+pub mod uri_list;
+
+/// Bytes written to a pipe at once, a pipe with room for them never blocks.
+const PIPE_CHUNK: usize = 4096;
+
+/// Bytes read from a pipe at once.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Limits of each transfer of data between clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferLimits {
+    /// Bytes sent or received at most by each transfer, larger data is not sent and fails to be
+    /// received. The transfers running at the same time have a limit each.
+    pub max_size: usize,
+    /// Time a transfer may take before it's cancelled, `None` to wait for the other client.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for TransferLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 256 * 1024 * 1024,
+            timeout: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// Why a transfer of data between clients failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferError {
+    /// The transfer could not start.
+    Unavailable(ClipboardError),
+    Io(ErrorKind),
+    /// The offer was replaced before the end of the transfer, the data may be incomplete.
+    OfferGone,
+    /// The data is larger than [`TransferLimits::max_size`].
+    TooLarge,
+    /// The other client didn't finish within [`TransferLimits::timeout`].
+    TimedOut,
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable(err) => err.fmt(f),
+            Self::Io(kind) => write!(f, "failed to transfer the data: {kind}"),
+            Self::OfferGone => f.write_str("the offer was replaced during the transfer"),
+            Self::TooLarge => f.write_str("the data is larger than the transfer limit"),
+            Self::TimedOut => f.write_str("the transfer timed out"),
+        }
+    }
+}
+
+impl Error for TransferError {}
+
+/// Read the pipe whenever it has data, `done` gets the data once the writer closes it.
+pub(crate) fn read_from_pipe<F>(
+    loop_handle: &LoopHandle<'static, WaylandState>,
+    pipe: ReadPipe,
+    limits: TransferLimits,
+    done: F,
+) where
+    F: FnOnce(&mut WaylandState, Result<Vec<u8>, TransferError>) + 'static,
+{
+    let mut data = Vec::new();
+    // Shared with the timeout, the first to end the transfer reports it.
+    let done = Rc::new(Cell::new(Some(done)));
+    let timer = Rc::new(Cell::new(None));
+    let (read_done, read_timer, handle) = (done.clone(), timer.clone(), loop_handle.clone());
+    let inserted = loop_handle.insert_source(pipe, move |_, file, state| {
+        let len = data.len();
+        grow_read_buffer(&mut data, limits.max_size);
+        let result = match (&**file).read(&mut data[len..]) {
+            Ok(0) => {
+                data.truncate(len);
+                Ok(mem::take(&mut data))
+            }
+            Ok(count) if len + count > limits.max_size => Err(TransferError::TooLarge),
+            Ok(count) => {
+                data.truncate(len + count);
+                return PostAction::Continue;
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock) => {
+                data.truncate(len);
+                return PostAction::Continue;
+            }
+            Err(err) => Err(TransferError::Io(err.kind())),
+        };
+        if let Some(token) = read_timer.take() {
+            handle.remove(token);
+        }
+        if let Some(done) = read_done.take() {
+            done(state, result);
+        }
+        PostAction::Remove
+    });
+    match inserted {
+        Ok(token) => timer.set(cancel_after(loop_handle, token, limits, move |state| {
+            if let Some(done) = done.take() {
+                done(state, Err(TransferError::TimedOut));
+            }
+        })),
+        Err(err) => error!("Failed to read from the pipe: {err}"),
+    }
+}
+
+/// Make room for the next read, without going past the limit of the transfer.
+///
+/// The vector grows geometrically, so large transfers are not copied over and over, but its
+/// capacity stays within one byte past the limit, which is enough to know the data is too large.
+fn grow_read_buffer(data: &mut Vec<u8>, max_size: usize) {
+    let len = data.len();
+    let cap = max_size.saturating_add(1);
+    let end = cap.min(len + READ_CHUNK);
+    if data.capacity() < end {
+        let capacity = data.capacity().saturating_mul(2).clamp(end, cap);
+        data.reserve_exact(capacity - len);
+    }
+    data.resize(end, 0);
+}
+
+/// Write the data to the pipe whenever it has room, so a slow reader doesn't block the loop.
+pub(crate) fn write_to_pipe(
+    loop_handle: &LoopHandle<'static, WaylandState>,
+    pipe: WritePipe,
+    data: Arc<[u8]>,
+    limits: TransferLimits,
+) {
+    if data.len() > limits.max_size {
+        // The pipe is closed, the reader gets no data.
+        warn!("Not sending {} bytes, above the transfer limit", data.len());
+        return;
+    }
+    let mut written = 0;
+    let timer = Rc::new(Cell::new(None));
+    let (write_timer, handle) = (timer.clone(), loop_handle.clone());
+    let inserted = loop_handle.insert_source(pipe, move |_, file, _| {
+        let end = data.len().min(written + PIPE_CHUNK);
+        let action = match (&**file).write(&data[written..end]) {
+            Ok(count) => {
+                written += count;
+                if written < data.len() {
+                    return PostAction::Continue;
+                }
+                PostAction::Remove
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock) => {
+                return PostAction::Continue;
+            }
+            Err(err) => {
+                // The reader closed the pipe early.
+                debug!("Stopped writing to the pipe: {err}");
+                PostAction::Remove
+            }
+        };
+        if let Some(token) = write_timer.take() {
+            handle.remove(token);
+        }
+        action
+    });
+    match inserted {
+        Ok(token) => timer.set(cancel_after(loop_handle, token, limits, |_| {
+            debug!("Stopped writing to the pipe: the transfer timed out");
+        })),
+        Err(err) => error!("Failed to write to the pipe: {err}"),
+    }
+}
+
+/// Remove the source of the transfer once its timeout is over, the pipe is closed with it.
+fn cancel_after<F>(
+    loop_handle: &LoopHandle<'static, WaylandState>,
+    transfer: RegistrationToken,
+    limits: TransferLimits,
+    on_timeout: F,
+) -> Option<RegistrationToken>
+where
+    F: FnOnce(&mut WaylandState) + 'static,
+{
+    let timeout = limits.timeout?;
+    let handle = loop_handle.clone();
+    let mut on_timeout = Some(on_timeout);
+    let inserted = loop_handle.insert_source(Timer::from_duration(timeout), move |_, _, state| {
+        handle.remove(transfer);
+        if let Some(on_timeout) = on_timeout.take() {
+            on_timeout(state);
+        }
+        TimeoutAction::Drop
+    });
+    match inserted {
+        Ok(token) => Some(token),
+        Err(err) => {
+            error!("Failed to time the transfer: {err}");
+            None
+        }
+    }
+}
+
+impl DataDeviceHandler for WaylandState {
+    fn enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        data_device: &WlDataDevice,
+        x: f64,
+        y: f64,
+        wl_surface: &WlSurface,
+    ) {
+        self.drag_entered(data_device, x, y, wl_surface);
+    }
+
+    fn leave(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, data_device: &WlDataDevice) {
+        self.drag_left(data_device);
+    }
+
+    fn motion(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        data_device: &WlDataDevice,
+        x: f64,
+        y: f64,
+    ) {
+        self.drag_moved(data_device, x, y);
+    }
+
+    fn selection(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        data_device: &WlDataDevice,
+    ) {
+        if let Some(data) = data_device.data::<DataDeviceData>() {
+            self.selection_changed(data);
+        }
+    }
+
+    fn drop_performed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        data_device: &WlDataDevice,
+    ) {
+        self.drag_dropped(data_device);
+    }
+}
+
+impl DataOfferHandler for WaylandState {
+    fn source_actions(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _offer: &mut DragOffer,
+        _actions: DndAction,
+    ) {
+    }
+
+    fn selected_action(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        offer: &mut DragOffer,
+        actions: DndAction,
+    ) {
+        // The offer is locked by sctk here, it must not be queried.
+        self.drag_action(offer, actions);
+    }
+}
+
+impl DataSourceHandler for WaylandState {
+    fn accept_mime(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _source: &WlDataSource,
+        _mime: Option<String>,
+    ) {
+    }
+
+    fn send_request(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        source: &WlDataSource,
+        mime: String,
+        fd: WritePipe,
+    ) {
+        if self.is_drag_source(source) {
+            self.send_drag_data(source, &mime, fd);
+        } else {
+            self.send_selection(source, &mime, fd);
+        }
+    }
+
+    fn cancelled(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, source: &WlDataSource) {
+        self.selection_cancelled(source);
+        self.drag_source_ended(source, false);
+    }
+
+    fn dnd_dropped(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _source: &WlDataSource) {
+        // The target may still reject the drop, the outcome comes with finished or cancelled.
+    }
+
+    fn dnd_finished(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, source: &WlDataSource) {
+        self.drag_source_ended(source, true);
+    }
+
+    fn action(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        source: &WlDataSource,
+        action: DndAction,
+    ) {
+        self.drag_source_action(source, action);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_buffer_stays_within_the_limit() {
+        let max_size = 3 * READ_CHUNK + 10;
+        let mut data = Vec::new();
+        while data.len() <= max_size {
+            grow_read_buffer(&mut data, max_size);
+            assert!(data.capacity() <= max_size + 1);
+        }
+        assert_eq!(data.len(), max_size + 1);
+
+        // A small limit doesn't allocate a whole chunk.
+        let mut data = Vec::new();
+        grow_read_buffer(&mut data, 100);
+        assert_eq!(data.len(), 101);
+        assert!(data.capacity() <= 101);
+    }
+}
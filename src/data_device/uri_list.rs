@@ -0,0 +1,150 @@
+use std::{
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+/// MIME type of the lists of dragged or copied files.
+pub(crate) const URI_LIST_MIME_TYPE: &str = "text/uri-list";
+
+/// Paths of the `file` URIs of a `text/uri-list`.
+///
+/// The other schemes are skipped. A file URI which is not a local path, such as a remote one or a
+/// file of a Flatpak document portal not mounted here, is kept as the raw URI.
+pub fn parse_uri_list(data: &[u8]) -> Vec<PathBuf> {
+    data.split(|byte| *byte == b'\n')
+        .map(<[u8]>::trim_ascii)
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .filter_map(file_uri_to_path)
+        .collect()
+}
+
+fn file_uri_to_path(uri: &[u8]) -> Option<PathBuf> {
+    let (scheme, rest) = uri.split_at_checked(5)?;
+    if !scheme.eq_ignore_ascii_case(b"file:") {
+        return None;
+    }
+    // `file:///path`, `file://localhost/path` and the short `file:/path`.
+    let path = match rest.strip_prefix(b"//") {
+        Some(authority) => {
+            let start = authority.iter().position(|byte| *byte == b'/')?;
+            let host = &authority[..start];
+            if !host.is_empty() && !host.eq_ignore_ascii_case(b"localhost") {
+                return Some(raw_uri(uri));
+            }
+            &authority[start..]
+        }
+        None => rest,
+    };
+    match percent_decode(path) {
+        Some(path) if path.starts_with(b"/") => {
+            let path = PathBuf::from(OsStr::from_bytes(&path));
+            if is_portal_path(&path) && !path.exists() {
+                return Some(raw_uri(uri));
+            }
+            Some(path)
+        }
+        _ => Some(raw_uri(uri)),
+    }
+}
+
+/// Paths of the document portal, `/run/flatpak/doc/…` inside of a sandbox and
+/// `/run/user/<uid>/doc/…` outside, only valid where the portal is mounted.
+fn is_portal_path(path: &Path) -> bool {
+    if path.starts_with("/run/flatpak/doc") {
+        return true;
+    }
+    let Ok(rest) = path.strip_prefix("/run/user") else {
+        return false;
+    };
+    let mut components = rest.components();
+    components
+        .next()
+        .is_some_and(|uid| uid.as_os_str().as_bytes().iter().all(u8::is_ascii_digit))
+        && components
+            .next()
+            .is_some_and(|doc| doc.as_os_str() == "doc")
+}
+
+fn raw_uri(uri: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(uri).into_owned())
+}
+
+/// Returns `None` on a malformed escape.
+fn percent_decode(input: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut bytes = input.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'%' {
+            let high = (*bytes.next()? as char).to_digit(16)?;
+            let low = (*bytes.next()? as char).to_digit(16)?;
+            output.push((high * 16 + low) as u8);
+        } else {
+            output.push(byte);
+        }
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let list = b"# dragged from the file manager\nfile:///tmp/a\n\n#file:///tmp/b\n";
+        assert_eq!(parse_uri_list(list), [PathBuf::from("/tmp/a")]);
+    }
+
+    #[test]
+    fn crlf_lines() {
+        let list = b"file:///tmp/a\r\nfile:///tmp/b\r\n";
+        assert_eq!(
+            parse_uri_list(list),
+            [PathBuf::from("/tmp/a"), PathBuf::from("/tmp/b")]
+        );
+    }
+
+    #[test]
+    fn percent_decoding() {
+        let list = b"file:///tmp/with%20space/%D1%84%41\nfile://localhost/tmp/b\nfile:/tmp/c";
+        assert_eq!(
+            parse_uri_list(list),
+            [
+                PathBuf::from("/tmp/with space/фA"),
+                PathBuf::from("/tmp/b"),
+                PathBuf::from("/tmp/c"),
+            ]
+        );
+        // A malformed escape is not a path.
+        assert_eq!(
+            parse_uri_list(b"file:///tmp/%zz"),
+            [PathBuf::from("file:///tmp/%zz")]
+        );
+    }
+
+    #[test]
+    fn non_file_uris() {
+        let list = b"https://example.org/a\nfile://server/share/b\nfile:///tmp/c";
+        assert_eq!(
+            parse_uri_list(list),
+            [
+                PathBuf::from("file://server/share/b"),
+                PathBuf::from("/tmp/c")
+            ]
+        );
+    }
+
+    #[test]
+    fn portal_paths() {
+        assert!(is_portal_path(Path::new("/run/flatpak/doc/1a2b3c/a.txt")));
+        assert!(is_portal_path(Path::new("/run/user/1000/doc/1a2b3c/a.txt")));
+        assert!(!is_portal_path(Path::new("/run/user/1000/a.txt")));
+        assert!(!is_portal_path(Path::new("/run/user/me/doc/a.txt")));
+        assert!(!is_portal_path(Path::new("/tmp/doc/a.txt")));
+
+        // Not mounted here.
+        let uri = "file:///run/flatpak/doc/0000000/missing%20file.txt";
+        assert_eq!(parse_uri_list(uri.as_bytes()), [PathBuf::from(uri)]);
+    }
+}
@@ -0,0 +1,72 @@
+// Handling of the xdg-dialog-v1.
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::{
+        client::{
+            Connection, Dispatch, Proxy, QueueHandle, delegate_dispatch,
+            globals::{BindError, GlobalList},
+        },
+        protocols::xdg::{
+            dialog::v1::client::{xdg_dialog_v1::XdgDialogV1, xdg_wm_dialog_v1::XdgWmDialogV1},
+            shell::client::xdg_toplevel::XdgToplevel,
+        },
+    },
+};
+
+use crate::WaylandState;
+
+/// Dialogs, to mark the windows with a parent as modal.
+#[derive(Debug, Clone)]
+pub struct DialogState {
+    manager: XdgWmDialogV1,
+}
+
+impl DialogState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    /// A toplevel can only have one dialog object, it must be destroyed with the toplevel.
+    pub(crate) fn get_xdg_dialog(
+        &self,
+        toplevel: &XdgToplevel,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> XdgDialogV1 {
+        self.manager
+            .get_xdg_dialog(toplevel, queue_handle, GlobalData)
+    }
+}
+
+impl Dispatch<XdgWmDialogV1, GlobalData, WaylandState> for DialogState {
+    fn event(
+        _: &mut WaylandState,
+        _: &XdgWmDialogV1,
+        _: <XdgWmDialogV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+impl Dispatch<XdgDialogV1, GlobalData, WaylandState> for DialogState {
+    fn event(
+        _: &mut WaylandState,
+        _: &XdgDialogV1,
+        _: <XdgDialogV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+delegate_dispatch!(WaylandState: [XdgWmDialogV1: GlobalData] => DialogState);
+delegate_dispatch!(WaylandState: [XdgDialogV1: GlobalData] => DialogState);
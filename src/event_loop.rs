@@ -2,10 +2,11 @@ use std::{
     cell::RefCell,
     collections::VecDeque,
     mem,
+    num::NonZeroU64,
     rc::Rc,
     sync::{
-        OnceLock,
-        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
     },
 };
 
@@ -16,20 +17,58 @@ use smithay_client_toolkit::reexports::{
     calloop::{self, EventLoop, channel::Sender as WlSender},
     client::backend::ObjectId,
 };
-use tracing::error;
-use ui_events::{keyboard::KeyboardEvent, pointer::PointerEvent};
+use tracing::{error, warn};
+use ui_events::{
+    keyboard::{KeyboardEvent, Modifiers},
+    pointer::{PointerEvent, PointerInfo, PointerState},
+};
 
+#[cfg(feature = "data-control")]
+use crate::data_device::data_control::{DataControl, DataControlEvent, SelectionKind};
+#[cfg(feature = "layer-shell")]
+use crate::layer_shell::LayerSurfaceAttributes;
 use crate::{
-    WaylandState, WaylandWindow, WindowAttributes, WindowId, WindowsRegistry,
+    ClipboardContent, ClipboardError, ClipboardEvent, ClipboardRequest, ComposeState, CursorTheme,
+    DragEvent, DragSourceEvent, GestureEvent, ImeEvent, KeyboardLayout, KeyboardQuery, Monitor,
+    MonitorEvent, PointerConstraintEvent, PopupError, PopupId, PopupPlacement, PresentationEvent,
+    RawKey, TransferLimits, Transform, WaylandState, WaylandWindow, WindowAttributes, WindowId,
+    WindowImmutable, WindowsRegistry,
+    instrumentation::{Instrumentation, Phase, QueueLengths, Recorder, WakeMarker, WakeTime},
     state::logical_to_physical_rounded,
-    window::{DEFAULT_SCALE_FACTOR, DEFAULT_WINDOW_SIZE},
+    window::{DEFAULT_SCALE_FACTOR, DEFAULT_WINDOW_SIZE, proxy::WindowProxy},
 };
 
 static LOOP_RUNNING: AtomicBool = AtomicBool::new(true);
 
+/// Mirror of the keyboard focus for [`LoopHandler`], `0` when no window is focused.
+pub(crate) static FOCUSED_WINDOW: AtomicU64 = AtomicU64::new(0);
+
 static WINDOWS_CREATION_EVENT: OnceLock<WlSender<Vec<(WindowId, WindowAttributes)>>> =
     OnceLock::new();
 
+static CURSOR_THEME_EVENT: OnceLock<WlSender<Option<CursorTheme>>> = OnceLock::new();
+
+static CLIPBOARD_EVENT: OnceLock<WlSender<Option<ClipboardContent>>> = OnceLock::new();
+
+static CLIPBOARD_READ_EVENT: OnceLock<WlSender<(ClipboardRequest, String)>> = OnceLock::new();
+
+static ACTIVATION_EVENT: OnceLock<WlSender<(WindowId, WindowId)>> = OnceLock::new();
+
+#[cfg(feature = "data-control")]
+static DATA_CONTROL_EVENT: OnceLock<WlSender<DataControlCommand>> = OnceLock::new();
+
+#[cfg(feature = "layer-shell")]
+static LAYER_SURFACE_CREATION_EVENT: OnceLock<WlSender<(WindowId, LayerSurfaceAttributes)>> =
+    OnceLock::new();
+
+/// Request of the data control from a [`LoopHandler`].
+#[cfg(feature = "data-control")]
+#[derive(Debug)]
+enum DataControlCommand {
+    Set(SelectionKind, Option<ClipboardContent>),
+    Read(ClipboardRequest, SelectionKind, String),
+}
+
 #[derive(Debug)]
 pub enum AccesskitEvents {
     AccessabilityActivate(ObjectId),   // done
@@ -47,17 +86,104 @@ pub trait LoopHandler {
             .ok_or(String::from("Event loop has not been initialized yet"))
     }
 
+    /// Create a layer surface instead of a window, e.g. a panel, a notification or an overlay.
+    ///
+    /// It's registered like the windows under the returned id: it's announced with
+    /// [`ApplicationHandler::create_window_handle`] once configured, then resized, drawn and
+    /// given the input like them. Nothing is created when the compositor does not support
+    /// wlr-layer-shell, see [`WlEventLoop::layer_shell_supported`].
+    ///
+    /// Fails when a zero width or height lacks the two opposite anchors.
+    #[cfg(feature = "layer-shell")]
+    fn create_layer_surface(&self, attributes: LayerSurfaceAttributes) -> Result<WindowId, String> {
+        attributes.validate()?;
+        let id = WindowId::next();
+        LAYER_SURFACE_CREATION_EVENT
+            .get()
+            .and_then(|s| s.send((id, attributes)).ok())
+            .map(|_| id)
+            .ok_or(String::from("Event loop has not been initialized yet"))
+    }
+
     fn default_window_size(&self) -> LogicalSize<u32> {
         DEFAULT_WINDOW_SIZE.to_owned()
     }
 
-    fn default_scale_factor(&self) -> i32 {
+    fn default_scale_factor(&self) -> f64 {
         DEFAULT_SCALE_FACTOR
     }
 
     fn stop(&self) {
         LOOP_RUNNING.store(false, Ordering::Release);
     }
+
+    /// The window with the keyboard focus.
+    fn focused_window(&self) -> Option<WindowId> {
+        NonZeroU64::new(FOCUSED_WINDOW.load(Ordering::Acquire)).map(WindowId)
+    }
+
+    /// See [`WlEventLoop::set_cursor_theme`].
+    fn set_cursor_theme(&self, theme: Option<CursorTheme>) -> Result<(), String> {
+        CURSOR_THEME_EVENT
+            .get()
+            .and_then(|s| s.send(theme).ok())
+            .ok_or(String::from("Event loop has not been initialized yet"))
+    }
+
+    /// See [`WlEventLoop::set_clipboard`], the errors are only logged.
+    fn set_clipboard(&self, content: Option<ClipboardContent>) -> Result<(), String> {
+        CLIPBOARD_EVENT
+            .get()
+            .and_then(|s| s.send(content).ok())
+            .ok_or(String::from("Event loop has not been initialized yet"))
+    }
+
+    /// See [`WlEventLoop::read_clipboard`], the errors come with the data.
+    fn read_clipboard(&self, mime_type: &str) -> Result<ClipboardRequest, String> {
+        let request = ClipboardRequest::next();
+        CLIPBOARD_READ_EVENT
+            .get()
+            .and_then(|s| s.send((request, mime_type.to_owned())).ok())
+            .map(|_| request)
+            .ok_or(String::from("Event loop has not been initialized yet"))
+    }
+
+    /// See [`WlEventLoop::activate_window`].
+    fn activate_window(&self, source: WindowId, target: WindowId) -> Result<(), String> {
+        ACTIVATION_EVENT
+            .get()
+            .and_then(|s| s.send((source, target)).ok())
+            .ok_or(String::from("Event loop has not been initialized yet"))
+    }
+
+    /// See [`DataControl::set_clipboard`], the errors are only logged.
+    #[cfg(feature = "data-control")]
+    fn set_data_control(
+        &self,
+        kind: SelectionKind,
+        content: Option<ClipboardContent>,
+    ) -> Result<(), String> {
+        DATA_CONTROL_EVENT
+            .get()
+            .and_then(|s| s.send(DataControlCommand::Set(kind, content)).ok())
+            .ok_or(String::from("Event loop has not been initialized yet"))
+    }
+
+    /// See [`DataControl::get_clipboard`], the errors come with the data.
+    #[cfg(feature = "data-control")]
+    fn read_data_control(
+        &self,
+        kind: SelectionKind,
+        mime_type: &str,
+    ) -> Result<ClipboardRequest, String> {
+        let request = ClipboardRequest::next();
+        let command = DataControlCommand::Read(request, kind, mime_type.to_owned());
+        DATA_CONTROL_EVENT
+            .get()
+            .and_then(|s| s.send(command).ok())
+            .map(|_| request)
+            .ok_or(String::from("Event loop has not been initialized yet"))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -109,9 +235,28 @@ impl DeactivationHandler for AccesskitHandler {
 #[derive(Debug)]
 pub enum Events {
     RedrawRequest(ObjectId),
-    Keyboard(KeyboardEvent),
+    Keyboard(WindowId, KeyboardEvent, RawKey),
     Pointer(ObjectId, PointerEvent),
+    Gesture(ObjectId, GestureEvent),
+    PointerConstraint(ObjectId, PointerConstraintEvent),
     Focus(ObjectId, bool),
+    KeymapChanged(KeyboardLayout),
+    Modifiers(Option<WindowId>, Modifiers),
+    Compose(WindowId, ComposeState),
+    ShortcutsInhibited(ObjectId, bool),
+    Ime(ObjectId, ImeEvent),
+    Clipboard(ClipboardEvent),
+    Drag(ObjectId, DragEvent),
+    DragSource(ObjectId, DragSourceEvent),
+    ActivationDeclined(ObjectId),
+    TransformChanged(ObjectId, Transform),
+    Presentation(ObjectId, PresentationEvent),
+    PopupConfigure(ObjectId, PopupPlacement),
+    PopupDone(ObjectId),
+    PopupFailed(PopupId, PopupError),
+    Monitor(MonitorEvent),
+    #[cfg(feature = "data-control")]
+    DataControl(DataControlEvent),
 }
 
 pub struct WlEventLoop<UserEvent> {
@@ -120,6 +265,8 @@ pub struct WlEventLoop<UserEvent> {
     event_loop: EventLoop<'static, WaylandState>,
     event_sender: WlSender<UserEvent>,
     running: bool,
+    instrumentation: Option<Box<dyn Instrumentation>>,
+    woken: WakeTime,
 }
 
 impl<UserEvent> WlEventLoop<UserEvent>
@@ -143,6 +290,94 @@ where
             .expect("Failed to create user event handle");
         WINDOWS_CREATION_EVENT.set(create_windows).unwrap();
 
+        let (cursor_theme, rx) = calloop::channel::channel::<Option<CursorTheme>>();
+        let cursor_theme_token = event_loop
+            .handle()
+            .insert_source(rx, move |event, _, state| {
+                if let calloop::channel::Event::Msg(theme) = event {
+                    state.set_cursor_theme(theme);
+                }
+            })
+            .expect("Failed to create cursor theme handle");
+        CURSOR_THEME_EVENT.set(cursor_theme).unwrap();
+
+        let (clipboard, rx) = calloop::channel::channel::<Option<ClipboardContent>>();
+        let clipboard_token = event_loop
+            .handle()
+            .insert_source(rx, move |event, _, state| {
+                if let calloop::channel::Event::Msg(content) = event
+                    && let Err(err) = state.set_clipboard(content)
+                {
+                    warn!("Failed to set the clipboard: {err}");
+                }
+            })
+            .expect("Failed to create clipboard handle");
+        CLIPBOARD_EVENT.set(clipboard).unwrap();
+
+        let (clipboard_read, rx) = calloop::channel::channel::<(ClipboardRequest, String)>();
+        let clipboard_read_token = event_loop
+            .handle()
+            .insert_source(rx, move |event, _, state| {
+                if let calloop::channel::Event::Msg((request, mime_type)) = event {
+                    state.read_clipboard_or_fail(request, mime_type);
+                }
+            })
+            .expect("Failed to create clipboard read handle");
+        CLIPBOARD_READ_EVENT.set(clipboard_read).unwrap();
+
+        let (activation, rx) = calloop::channel::channel::<(WindowId, WindowId)>();
+        let activation_token = event_loop
+            .handle()
+            .insert_source(rx, move |event, _, state| {
+                if let calloop::channel::Event::Msg((source, target)) = event {
+                    state.activate_window(source, target);
+                }
+            })
+            .expect("Failed to create activation handle");
+        ACTIVATION_EVENT.set(activation).unwrap();
+
+        #[cfg(feature = "data-control")]
+        {
+            let (data_control, rx) = calloop::channel::channel::<DataControlCommand>();
+            let data_control_token = event_loop
+                .handle()
+                .insert_source(rx, move |event, _, state| {
+                    if let calloop::channel::Event::Msg(command) = event {
+                        match command {
+                            DataControlCommand::Set(kind, content) => {
+                                if let Err(err) = state.set_data_control_selection(kind, content) {
+                                    warn!("Failed to set the selection: {err}");
+                                }
+                            }
+                            DataControlCommand::Read(request, kind, mime_type) => {
+                                state.read_data_control_or_fail(request, kind, mime_type);
+                            }
+                        }
+                    }
+                })
+                .expect("Failed to create data control handle");
+            DATA_CONTROL_EVENT.set(data_control).unwrap();
+            state.event_source_token.push(data_control_token);
+        }
+
+        #[cfg(feature = "layer-shell")]
+        {
+            let (create_layer_surface, rx) =
+                calloop::channel::channel::<(WindowId, LayerSurfaceAttributes)>();
+            let create_layer_surface_token = event_loop
+                .handle()
+                .insert_source(rx, move |event, _, state| {
+                    if let calloop::channel::Event::Msg((id, attributes)) = event {
+                        state.create_layer_surface(id, attributes);
+                    }
+                })
+                .expect("Failed to create layer surface handle");
+            LAYER_SURFACE_CREATION_EVENT
+                .set(create_layer_surface)
+                .unwrap();
+            state.event_source_token.push(create_layer_surface_token);
+        }
+
         // User events handler preparation
         let user_events = Rc::new(RefCell::new(VecDeque::new()));
         let user_events_clone = user_events.clone();
@@ -156,57 +391,172 @@ where
             })
             .expect("Failed to create user event handle");
 
+        // Marks the end of the wait of every dispatch, for the instrumentation.
+        let (wake_marker, woken) = WakeMarker::new();
+        let wake_marker_token = event_loop
+            .handle()
+            .insert_source(wake_marker, |_, _, _| {})
+            .expect("Failed to create wake marker");
+
         // To release sources after app exit properly
         state.event_source_token.push(create_window_token);
+        state.event_source_token.push(cursor_theme_token);
+        state.event_source_token.push(clipboard_token);
+        state.event_source_token.push(clipboard_read_token);
+        state.event_source_token.push(activation_token);
         state.event_source_token.push(user_event_token);
+        state.event_source_token.push(wake_marker_token);
         Self {
             state,
             user_events,
             event_loop,
             event_sender,
             running: true,
+            instrumentation: None,
+            woken,
         }
     }
 
+    /// Register a hook receiving timing records of every loop iteration.
+    ///
+    /// Nothing is measured while no hook is registered.
+    pub fn set_instrumentation(&mut self, instrumentation: impl Instrumentation + 'static) {
+        self.instrumentation = Some(Box::new(instrumentation));
+    }
+
     pub fn run(&mut self, app: &mut impl ApplicationHandler<UserEvent>) -> Result<(), String> {
         self.running = true;
         while self.running {
             tracing::trace!("Wayland app running");
+            let mut recorder = Recorder::new(self.instrumentation.is_some());
+            let start = recorder.start();
             // TODO: what timeout should be set?
-            match self.event_loop.dispatch(None, &mut self.state) {
+            let dispatched = self.event_loop.dispatch(None, &mut self.state);
+            recorder.record_dispatch(start, &self.woken);
+            match dispatched {
                 Ok(_) => {
-                    let rescale_req = mem::take(&mut self.state.windows.rescale_request);
+                    let create_req = mem::take(&mut self.state.windows.create_request);
+                    let rescale_req = self.state.windows.take_rescale_requests(&create_req);
+                    let monitor_req = mem::take(&mut self.state.windows.monitor_request);
+                    let kiosk_req = mem::take(&mut self.state.windows.kiosk_request);
                     let mut resize_req = mem::take(&mut self.state.windows.resize_request);
                     let mut redraw_req = mem::take(&mut self.state.windows.redraw_request);
                     let close_req = mem::take(&mut self.state.windows.close_request);
+                    recorder.queues(|| QueueLengths {
+                        user_events: self.user_events.borrow().len(),
+                        events: self.state.events.len(),
+                        accesskit_events: self.state.accesskit_events.len(),
+                        create_requests: create_req.len(),
+                        rescale_requests: rescale_req.len(),
+                        resize_requests: resize_req.len(),
+                        redraw_requests: redraw_req.len(),
+                        close_requests: close_req.len(),
+                    });
 
                     // Let's handle all user events
+                    let start = recorder.start();
                     if let Ok(mut events) = self.user_events.try_borrow_mut() {
                         while let Some(event) = (*events).pop_front() {
                             app.user_events_handle(event);
                         }
                     }
+                    recorder.record(Phase::UserEvents, None, start);
+                    for object_id in create_req.iter() {
+                        if let Some(window) = self.state.windows.get_mut_by_object_id(object_id) {
+                            let start = recorder.start();
+                            window.reported_scale_factor = window.scale_factor;
+                            app.create_window_handle(
+                                window.get_id(),
+                                window.immutable.clone(),
+                                logical_to_physical_rounded(window.size, window.scale_factor),
+                                window.scale_factor,
+                            );
+                            recorder.record(Phase::CreateWindow, Some(window.get_id()), start);
+                        }
+                    }
                     for object_id in rescale_req.iter() {
-                        if let Some(window) = self.state.windows.get_by_object_id(object_id) {
-                            app.rescale_handle(window.get_id(), window.scale_factor as f64);
+                        if let Some(window) = self
+                            .state
+                            .windows
+                            .get_mut_by_object_id(object_id)
+                            .filter(|window| window.configured)
+                            .filter(|window| window.reported_scale_factor != window.scale_factor)
+                        {
+                            let start = recorder.start();
+                            window.reported_scale_factor = window.scale_factor;
+                            let scale_factor = window.scale_factor;
+                            let mut surface_size = SurfaceSizeWriter::new(
+                                logical_to_physical_rounded(window.size, scale_factor),
+                            );
+                            app.rescale_handle(window.get_id(), scale_factor, &mut surface_size);
+                            if let Some(size) = surface_size.requested {
+                                window.request_inner_size(size);
+                            }
+                            recorder.record(Phase::Rescale, Some(window.get_id()), start);
                             resize_req.insert(object_id.clone());
                         }
                     }
+                    for object_id in monitor_req.iter() {
+                        if self
+                            .state
+                            .windows
+                            .get_by_object_id(object_id)
+                            .is_some_and(|window| !window.configured)
+                        {
+                            // Report it once the application knows the window.
+                            self.state.windows.monitor_request.insert(object_id.clone());
+                            continue;
+                        }
+                        if let Some(window) = self
+                            .state
+                            .windows
+                            .get_mut_by_object_id(object_id)
+                            .filter(|window| window.reported_output != window.output)
+                        {
+                            let start = recorder.start();
+                            window.reported_output = window.output.clone();
+                            let monitor = window
+                                .output
+                                .clone()
+                                .map(|output| Monitor::new(&self.state.output_state, output));
+                            app.monitor_changed_handle(window.get_id(), monitor);
+                            recorder.record(Phase::Monitor, Some(window.get_id()), start);
+                        }
+                    }
+                    for object_id in kiosk_req.iter() {
+                        if let Some((window_id, attempts)) = self
+                            .state
+                            .windows
+                            .get_by_object_id(object_id)
+                            .and_then(|window| {
+                                Some((window.get_id(), window.kiosk.as_ref()?.attempts))
+                            })
+                        {
+                            app.kiosk_recovery_handle(window_id, attempts);
+                        }
+                    }
                     for object_id in resize_req.iter() {
-                        if let Some(window) = self.state.windows.get_by_object_id(object_id) {
+                        if let Some(window) = self
+                            .state
+                            .windows
+                            .get_by_object_id(object_id)
+                            .filter(|window| window.configured)
+                        {
+                            let start = recorder.start();
                             app.resize_handle(
                                 window.get_id(),
-                                logical_to_physical_rounded(
-                                    window.size,
-                                    window.scale_factor as f64,
-                                ),
+                                logical_to_physical_rounded(window.size, window.scale_factor),
                             );
+                            recorder.record(Phase::Resize, Some(window.get_id()), start);
                             redraw_req.insert(object_id.clone());
                         }
                     }
                     // Let's handle all user changes to windows
+                    let start = recorder.start();
                     app.user_signals_handle(&mut self.state.windows);
+                    recorder.record(Phase::UserSignals, None, start);
                     // Let's handle accesskit events and then compositor events
+                    let start = recorder.start();
                     while let Some(event) = self.state.accesskit_events.pop_front() {
                         // Accesskit events do not request `draw_handle` method. So, one needs to request this in `user_signals_handle` via `redraw_request()` method on WaylandWindow
                         let window = match &event {
@@ -232,51 +582,246 @@ where
                             }
                         }
                     }
+                    recorder.record(Phase::Accesskit, None, start);
                     while let Some(event) = self.state.events.pop_front() {
                         let window_id = match &event {
                             Events::Pointer(object_id, _)
+                            | Events::Gesture(object_id, _)
                             | Events::Focus(object_id, _)
+                            | Events::ShortcutsInhibited(object_id, _)
+                            | Events::Ime(object_id, _)
+                            | Events::Drag(object_id, _)
+                            | Events::DragSource(object_id, _)
+                            | Events::PopupDone(object_id)
                             | Events::RedrawRequest(object_id) => {
                                 self.state.windows.redraw_request.insert(object_id.clone());
-                                self.state.windows.get_id(object_id).cloned()
+                                self.state
+                                    .windows
+                                    .get_by_object_id(object_id)
+                                    .filter(|window| window.configured)
+                                    .map(WaylandWindow::get_id)
                             }
-                            Events::Keyboard(_) => {
-                                match self.state.seat_state.keyboard_focus.as_ref() {
+                            // The application redraws for them itself if it needs to, a redraw
+                            // for every presentation feedback would never stop.
+                            Events::ActivationDeclined(object_id)
+                            | Events::PointerConstraint(object_id, _)
+                            | Events::TransformChanged(object_id, _)
+                            | Events::Presentation(object_id, _)
+                            | Events::PopupConfigure(object_id, _) => self
+                                .state
+                                .windows
+                                .get_by_object_id(object_id)
+                                .filter(|window| window.configured)
+                                .map(WaylandWindow::get_id),
+                            // A modifier alone changes nothing on the screen.
+                            Events::Modifiers(Some(window_id), _) => self
+                                .state
+                                .windows
+                                .get(window_id)
+                                .filter(|window| window.configured)
+                                .map(WaylandWindow::get_id),
+                            Events::Keyboard(window_id, ..) | Events::Compose(window_id, _) => {
+                                match self.state.windows.id_converter.get(window_id) {
                                     Some(object_id) => {
                                         self.state.windows.redraw_request.insert(object_id.clone());
-                                        self.state.windows.get_id(object_id).cloned()
+                                        self.state
+                                            .windows
+                                            .get(window_id)
+                                            .filter(|window| window.configured)
+                                            .map(WaylandWindow::get_id)
                                     }
                                     None => None,
                                 }
                             }
+                            Events::Modifiers(None, _)
+                            | Events::Clipboard(_)
+                            | Events::Monitor(_)
+                            | Events::PopupFailed(..) => None,
+                            #[cfg(feature = "data-control")]
+                            Events::DataControl(_) => None,
+                            Events::KeymapChanged(_) => {
+                                self.state.seat_state.keyboard_focus.filter(|id| {
+                                    self.state
+                                        .windows
+                                        .get(id)
+                                        .is_some_and(|window| window.configured)
+                                })
+                            }
                         };
                         if let Some(window_id) = window_id {
+                            let start = recorder.start();
                             match event {
                                 // Receiving redraw request from WaylandWindow
                                 Events::RedrawRequest(object_id) => {
                                     self.state.windows.redraw_request.insert(object_id);
                                 }
-                                Events::Keyboard(keyboard_event) => {
-                                    app.keyboard_handle(window_id, keyboard_event)
+                                Events::Keyboard(_, keyboard_event, raw_key) => {
+                                    app.raw_keyboard_handle(window_id, keyboard_event, raw_key);
+                                    recorder.record(Phase::Keyboard, Some(window_id), start);
                                 }
                                 Events::Pointer(_, pointer_event) => {
-                                    app.pointer_handle(window_id, pointer_event)
+                                    app.pointer_handle(window_id, pointer_event);
+                                    recorder.record(Phase::Pointer, Some(window_id), start);
+                                }
+                                Events::Gesture(_, gesture_event) => {
+                                    app.gesture_handle(window_id, gesture_event);
+                                    recorder.record(Phase::Gesture, Some(window_id), start);
+                                }
+                                Events::PointerConstraint(_, constraint_event) => {
+                                    app.pointer_constraint_handle(window_id, constraint_event);
+                                    recorder.record(Phase::Pointer, Some(window_id), start);
                                 }
                                 Events::Focus(_, new_focus) => {
-                                    app.focus_handle(window_id, new_focus)
+                                    app.focus_handle(window_id, new_focus);
+                                    recorder.record(Phase::Focus, Some(window_id), start);
+                                }
+                                Events::Modifiers(_, modifiers) => {
+                                    app.modifiers_handle(Some(window_id), modifiers);
+                                    recorder.record(Phase::Keyboard, Some(window_id), start);
                                 }
+                                Events::ShortcutsInhibited(_, active) => {
+                                    app.shortcuts_inhibited_handle(window_id, active);
+                                    recorder.record(Phase::Focus, Some(window_id), start);
+                                }
+                                Events::Ime(_, ime_event) => {
+                                    app.ime_handle(window_id, ime_event);
+                                    recorder.record(Phase::Keyboard, Some(window_id), start);
+                                }
+                                Events::Compose(_, compose_state) => {
+                                    app.compose_handle(window_id, compose_state);
+                                    recorder.record(Phase::Keyboard, Some(window_id), start);
+                                }
+                                Events::KeymapChanged(layout) => {
+                                    app.keymap_changed_handle(Some(window_id), layout);
+                                    recorder.record(Phase::Keyboard, Some(window_id), start);
+                                }
+                                Events::Drag(_, drag_event) => {
+                                    app.drag_handle(window_id, drag_event);
+                                    recorder.record(Phase::DragAndDrop, Some(window_id), start);
+                                }
+                                Events::DragSource(_, source_event) => {
+                                    app.drag_source_handle(window_id, source_event);
+                                    recorder.record(Phase::DragAndDrop, Some(window_id), start);
+                                }
+                                Events::Presentation(_, presentation_event) => {
+                                    app.presentation_handle(window_id, presentation_event);
+                                    recorder.record(Phase::Draw, Some(window_id), start);
+                                }
+                                Events::TransformChanged(_, transform) => {
+                                    app.transform_changed_handle(window_id, transform);
+                                    recorder.record(Phase::Rescale, Some(window_id), start);
+                                }
+                                Events::ActivationDeclined(_) => {
+                                    app.activation_declined_handle(window_id);
+                                    recorder.record(Phase::Focus, Some(window_id), start);
+                                }
+                                Events::PopupConfigure(_, placement) => {
+                                    app.popup_configure_handle(window_id, placement);
+                                    recorder.record(Phase::Resize, Some(window_id), start);
+                                }
+                                Events::PopupDone(_) => {
+                                    app.popup_done_handle(window_id);
+                                    recorder.record(Phase::Close, Some(window_id), start);
+                                }
+                                Events::Clipboard(_)
+                                | Events::Monitor(_)
+                                | Events::PopupFailed(..) => {}
+                                #[cfg(feature = "data-control")]
+                                Events::DataControl(_) => {}
                             }
+                        } else {
+                            // Delivered even without a focused window.
+                            let start = recorder.start();
+                            let phase = match event {
+                                Events::KeymapChanged(layout) => {
+                                    app.keymap_changed_handle(None, layout);
+                                    Phase::Keyboard
+                                }
+                                Events::Modifiers(_, modifiers) => {
+                                    app.modifiers_handle(None, modifiers);
+                                    Phase::Keyboard
+                                }
+                                Events::Clipboard(clipboard_event) => {
+                                    app.clipboard_handle(clipboard_event);
+                                    Phase::Clipboard
+                                }
+                                Events::Monitor(monitor_event) => {
+                                    app.monitor_handle(monitor_event);
+                                    Phase::Monitor
+                                }
+                                Events::PopupFailed(popup_id, error) => {
+                                    app.popup_failed_handle(popup_id, error);
+                                    Phase::Close
+                                }
+                                #[cfg(feature = "data-control")]
+                                Events::DataControl(data_control_event) => {
+                                    app.data_control_handle(data_control_event);
+                                    Phase::Clipboard
+                                }
+                                _ => continue,
+                            };
+                            recorder.record(phase, None, start);
+                        }
+                    }
+                    // Let's apply the changes requested through window proxies
+                    for (object_id, resized) in self.state.apply_window_commands() {
+                        if resized
+                            && let Some(window) = self
+                                .state
+                                .windows
+                                .get_by_object_id(&object_id)
+                                .filter(|window| window.configured)
+                        {
+                            app.resize_handle(
+                                window.get_id(),
+                                logical_to_physical_rounded(window.size, window.scale_factor),
+                            );
                         }
+                        redraw_req.insert(object_id);
                     }
                     for object_id in redraw_req {
-                        if let Some(window) = self.state.windows.get_mut_by_object_id(&object_id) {
+                        // A pending frame callback draws the window once it's done.
+                        if let Some(window) = self
+                            .state
+                            .windows
+                            .get_mut_by_object_id(&object_id)
+                            .filter(|window| window.configured && !window.frame_callback_pending)
+                        {
                             // TODO: Чтобы делать нормальный refresh frame, нужно вызывать draw_handle, а не запрос на перерисовку
+                            let start = recorder.start();
                             window.refresh_frame();
                             app.draw_handle(window.get_id(), window);
+                            // The application committed the window while drawing.
+                            window.latch_subsurface_positions();
+                            recorder.record(Phase::Draw, Some(window.get_id()), start);
+                        }
+                    }
+                    for object_id in close_req {
+                        if let Some(window) = self.state.windows.get_mut_by_object_id(&object_id) {
+                            let start = recorder.start();
+                            let window_id = window.get_id();
+                            if app.close_requested_handle(window_id, window) {
+                                self.state.windows.destroy_request.insert(object_id);
+                            }
+                            recorder.record(Phase::Close, Some(window_id), start);
                         }
                     }
-                    for object_id in close_req.iter() {
-                        app.close_handle(self.state.close_window(object_id));
+                    let destroy_req = mem::take(&mut self.state.windows.destroy_request);
+                    for object_id in destroy_req.iter() {
+                        for object_id in self.state.windows.with_popups(object_id) {
+                            let start = recorder.start();
+                            // The keys held on the window are released before it goes.
+                            if let Some(window_id) = self.state.windows.get_id(&object_id).copied()
+                            {
+                                for (kb_event, raw_key) in self.state.take_held_keys(window_id) {
+                                    app.raw_keyboard_handle(window_id, kb_event, raw_key);
+                                }
+                            }
+                            if let Some(window_id) = self.state.close_window(&object_id) {
+                                app.destroyed_handle(window_id);
+                                recorder.record(Phase::Destroy, Some(window_id), start);
+                            }
+                        }
                     }
                 }
                 Err(err) => {
@@ -284,6 +829,7 @@ where
                     return Err(String::from("Error dispatching event loop"));
                 }
             }
+            recorder.finish(self.instrumentation.as_deref_mut());
 
             // Let's handle all wayland state events and close an app, if we receive close request
             if self.state.windows.is_empty() || !LOOP_RUNNING.load(Ordering::Acquire) {
@@ -294,6 +840,134 @@ where
         Ok(())
     }
 
+    /// The window with the keyboard focus.
+    pub fn focused_window(&self) -> Option<WindowId> {
+        self.state.focused_window()
+    }
+
+    /// See [`WaylandState::active_touches`].
+    pub fn active_touches(
+        &self,
+        window_id: WindowId,
+    ) -> impl Iterator<Item = (PointerInfo, PointerState)> + '_ {
+        self.state.active_touches(window_id)
+    }
+
+    /// Load the cursors from the theme, or from the system one with `None`.
+    ///
+    /// The pointers of all seats are recreated, so they leave and enter the windows again.
+    pub fn set_cursor_theme(&mut self, theme: Option<CursorTheme>) {
+        self.state.set_cursor_theme(theme);
+    }
+
+    /// See [`WindowsRegistry::available_monitors`].
+    pub fn available_monitors(&self) -> &[Monitor] {
+        self.state.windows.available_monitors()
+    }
+
+    /// The `clockid_t` of the timestamps of [`PresentationEvent::Presented`], to compare them
+    /// with `clock_gettime`.
+    pub fn presentation_clock_id(&self) -> Option<u32> {
+        self.state
+            .presentation
+            .as_ref()
+            .and_then(|presentation| presentation.clock_id)
+    }
+
+    /// Whether the windows can allow tearing, see [`WaylandWindow::set_presentation_hint`].
+    pub fn tearing_control_supported(&self) -> bool {
+        self.state.tearing_control.is_some()
+    }
+
+    /// Whether the layer surfaces can be created, see [`LoopHandler::create_layer_surface`].
+    #[cfg(feature = "layer-shell")]
+    pub fn layer_shell_supported(&self) -> bool {
+        self.state.layer_shell.is_some()
+    }
+
+    /// Whether the windows can keep the screen from blanking, see
+    /// [`WaylandWindow::set_idle_inhibited`].
+    pub fn idle_inhibit_supported(&self) -> bool {
+        self.state.idle_inhibit.is_some()
+    }
+
+    /// Whether the dialogs can be marked as modal, see [`WaylandWindow::set_modal`].
+    pub fn dialog_supported(&self) -> bool {
+        self.state.dialog.is_some()
+    }
+
+    /// See [`WindowsRegistry::current_monitor`].
+    pub fn current_monitor(&self, window_id: &WindowId) -> Option<Monitor> {
+        self.state.windows.current_monitor(window_id)
+    }
+
+    /// See [`WindowsRegistry::immutable`].
+    pub fn window_immutable(&self, window_id: &WindowId) -> Option<Arc<WindowImmutable>> {
+        self.state.windows.immutable(window_id)
+    }
+
+    /// Handle to change the window from any handler callback.
+    pub fn window_proxy(&self, window_id: WindowId) -> WindowProxy {
+        self.state.window_proxy(window_id)
+    }
+
+    /// Put the content on the clipboard, or clear it with `None`.
+    ///
+    /// See [`WaylandState::set_clipboard`], handler callbacks use [`LoopHandler::set_clipboard`].
+    pub fn set_clipboard(
+        &mut self,
+        content: Option<ClipboardContent>,
+    ) -> Result<(), ClipboardError> {
+        self.state.set_clipboard(content)
+    }
+
+    /// See [`WaylandState::clipboard_mime_types`].
+    pub fn clipboard_mime_types(&self) -> Vec<String> {
+        self.state.clipboard_mime_types()
+    }
+
+    /// Read the clipboard in the MIME type, the data comes to
+    /// [`ApplicationHandler::clipboard_handle`].
+    ///
+    /// Handler callbacks use [`LoopHandler::read_clipboard`].
+    pub fn read_clipboard(&mut self, mime_type: &str) -> Result<ClipboardRequest, ClipboardError> {
+        self.state.read_clipboard(mime_type)
+    }
+
+    /// The selections without the keyboard focus, `None` if the compositor lacks the data control.
+    ///
+    /// Handler callbacks use [`LoopHandler::set_data_control`] and [`LoopHandler::read_data_control`].
+    #[cfg(feature = "data-control")]
+    pub fn data_control(&mut self) -> Option<DataControl<'_>> {
+        self.state.data_control.as_ref()?;
+        Some(DataControl {
+            state: &mut self.state,
+        })
+    }
+
+    /// Limit the size and the duration of the clipboard and drag and drop transfers.
+    pub fn set_transfer_limits(&mut self, limits: TransferLimits) {
+        self.state.transfer_limits = limits;
+    }
+
+    /// Focus the target window on behalf of the source one, which got the latest input.
+    ///
+    /// The compositor may decline, which is reported with
+    /// [`ApplicationHandler::activation_declined_handle`].
+    pub fn activate_window(&mut self, source: WindowId, target: WindowId) {
+        self.state.activate_window(source, target);
+    }
+
+    /// Mark the window as demanding the attention of the user, without focusing it.
+    pub fn request_user_attention(&mut self, window_id: WindowId) {
+        self.state.request_user_attention(window_id);
+    }
+
+    /// Handle to query the keymap from any handler callback.
+    pub fn keyboard_query(&self) -> KeyboardQuery {
+        KeyboardQuery::new(self.state.seat_state.keymaps.clone())
+    }
+
     pub fn send_event(&self, event: UserEvent) {
         if let Err(err) = self.event_sender.send(event) {
             error!("{err}");
@@ -301,16 +975,157 @@ where
     }
 }
 
+/// Lets the application pick the surface size after a scale factor change.
+///
+/// Without a request the window keeps its logical size, so the physical size follows the scale.
+#[derive(Debug)]
+pub struct SurfaceSizeWriter {
+    size: PhysicalSize<u32>,
+    requested: Option<PhysicalSize<u32>>,
+}
+
+impl SurfaceSizeWriter {
+    fn new(size: PhysicalSize<u32>) -> Self {
+        Self {
+            size,
+            requested: None,
+        }
+    }
+
+    /// Size the surface gets with the new scale factor unless another one is requested.
+    pub fn surface_size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Request a new surface size, e.g. the old physical size to keep the content crisp.
+    ///
+    /// Goes through the same path as [`WaylandWindow::request_inner_size`], so it is ignored
+    /// while the window is maximized, fullscreen or tiled.
+    pub fn request_surface_size(&mut self, size: PhysicalSize<u32>) {
+        self.requested = Some(size);
+    }
+}
+
 pub trait ApplicationHandler<UserEvent>
 where
     UserEvent: 'static + Send,
 {
+    /// Called once the first configure of a new window has been processed, before any other
+    /// callback for that window.
+    ///
+    /// This holds within a single iteration too, and for windows requested before
+    /// [`WlEventLoop::run`]: nothing is delivered for a window until it has been configured.
+    ///
+    /// `size` and `scale_factor` are the ones the window is going to be drawn with, so renderers
+    /// can create their surfaces right away.
+    fn create_window_handle(
+        &mut self,
+        _window_id: WindowId,
+        _window: Arc<WindowImmutable>,
+        _size: PhysicalSize<u32>,
+        _scale_factor: f64,
+    ) {
+    }
+    /// Never called before [`ApplicationHandler::create_window_handle`] for the window.
     fn draw_handle(&mut self, window_id: WindowId, window: &mut WaylandWindow);
+    /// The `repeat` flag is set on the presses of a held key, never on the first press or a release.
     fn keyboard_handle(&mut self, window_id: WindowId, keyboard_event: KeyboardEvent);
+    /// Keyboard event with its physical key, for bindings by the key position.
+    ///
+    /// Calls [`ApplicationHandler::keyboard_handle`] by default.
+    fn raw_keyboard_handle(
+        &mut self,
+        window_id: WindowId,
+        keyboard_event: KeyboardEvent,
+        _raw_key: RawKey,
+    ) {
+        self.keyboard_handle(window_id, keyboard_event);
+    }
+    /// Called when the modifiers change, also when a modifier is pressed alone.
+    ///
+    /// The modifiers include Caps Lock and Num Lock, whose changes come without a focused window
+    /// too.
+    fn modifiers_handle(&mut self, _window_id: Option<WindowId>, _modifiers: Modifiers) {}
+    /// Called as a dead-key or compose sequence progresses, to show the pending keys.
+    fn compose_handle(&mut self, _window_id: WindowId, _state: ComposeState) {}
+    /// Text from the input methods, once allowed with [`WaylandWindow::set_ime_allowed`].
+    fn ime_handle(&mut self, _window_id: WindowId, _ime_event: ImeEvent) {}
     fn pointer_handle(&mut self, window_id: WindowId, pointer_event: PointerEvent);
+    /// Input which does not fit into [`PointerEvent`].
+    fn gesture_handle(&mut self, _window_id: WindowId, _gesture_event: GestureEvent) {}
+    /// Called when a lock or a confinement of the pointer takes effect or is released.
+    fn pointer_constraint_handle(
+        &mut self,
+        _window_id: WindowId,
+        _constraint_event: PointerConstraintEvent,
+    ) {
+    }
+    /// Called when the keymap or its active layout changes, with the window focused by the
+    /// keyboard if any.
+    fn keymap_changed_handle(&mut self, _window_id: Option<WindowId>, _layout: KeyboardLayout) {}
     fn resize_handle(&mut self, window_id: WindowId, size: PhysicalSize<u32>);
     fn focus_handle(&mut self, window_id: WindowId, new_focus: bool);
-    fn rescale_handle(&mut self, window_id: WindowId, scale_factor: f64);
+    /// Called when the compositor grants or withdraws the inhibition requested with
+    /// [`WaylandWindow::set_shortcuts_inhibited`].
+    fn shortcuts_inhibited_handle(&mut self, _window_id: WindowId, _active: bool) {}
+    /// Drag and drop over the window, a drag is accepted with [`WaylandWindow::accept_drag`].
+    fn drag_handle(&mut self, _window_id: WindowId, _drag_event: DragEvent) {}
+    /// Called when a drag started with [`WaylandWindow::start_drag`] changes or ends.
+    fn drag_source_handle(&mut self, _window_id: WindowId, _source_event: DragSourceEvent) {}
+    /// Called when the compositor does not focus the window of [`WlEventLoop::activate_window`].
+    fn activation_declined_handle(&mut self, _window_id: WindowId) {}
+    /// Called when the compositor dismisses a popup of [`WaylandWindow::create_popup`], e.g.
+    /// after a click outside of it. The popup is destroyed right after.
+    fn popup_done_handle(&mut self, _popup_id: PopupId) {}
+    /// Called when a popup of [`WaylandWindow::create_popup`] could not be opened, it's never
+    /// announced.
+    fn popup_failed_handle(&mut self, _popup_id: PopupId, _error: PopupError) {}
+    /// Called with the placement the compositor chose for a popup, after its creation and after
+    /// the moves of [`WaylandWindow::reposition`]. A new size is also reported with
+    /// [`ApplicationHandler::resize_handle`].
+    fn popup_configure_handle(&mut self, _popup_id: PopupId, _placement: PopupPlacement) {}
+    /// Called when the clipboard changes hands and when the data of a read arrives.
+    fn clipboard_handle(&mut self, _clipboard_event: ClipboardEvent) {}
+    /// Called when a selection changes and when the data of a read through
+    /// [`WlEventLoop::data_control`] arrives.
+    #[cfg(feature = "data-control")]
+    fn data_control_handle(&mut self, _data_control_event: DataControlEvent) {}
+    /// Called when the scale factor of the window changes.
+    ///
+    /// The size picked through `surface_size` is applied right after, and is reported with
+    /// [`ApplicationHandler::resize_handle`] in the same iteration.
+    fn rescale_handle(
+        &mut self,
+        window_id: WindowId,
+        scale_factor: f64,
+        surface_size: &mut SurfaceSizeWriter,
+    );
+    /// Called when the primary monitor of the window changes.
+    ///
+    /// The primary monitor only changes once the window has left it, so a window dragged
+    /// across outputs does not flip between them. Within an iteration it is called after
+    /// [`ApplicationHandler::rescale_handle`] and before [`ApplicationHandler::resize_handle`].
+    fn monitor_changed_handle(&mut self, _window_id: WindowId, _monitor: Option<Monitor>) {}
+    /// Called when the transform of the output of the window changes, so the renderers may
+    /// rotate their projection and call [`WaylandWindow::set_buffer_transform`].
+    fn transform_changed_handle(&mut self, _window_id: WindowId, _transform: Transform) {}
+    /// Called when a frame of [`WaylandWindow::request_presentation_feedback`] was presented or
+    /// discarded.
+    fn presentation_handle(
+        &mut self,
+        _window_id: WindowId,
+        _presentation_event: PresentationEvent,
+    ) {
+    }
+    /// Called when an output is connected, changed or disconnected.
+    ///
+    /// [`WindowsRegistry::available_monitors`] is already up to date.
+    fn monitor_handle(&mut self, _monitor_event: MonitorEvent) {}
+    /// Called when fullscreen is requested again for a window in the kiosk mode.
+    ///
+    /// `attempt` counts the requests since [`WaylandWindow::set_kiosk`], so the application can
+    /// give up after a while by disabling the kiosk mode.
+    fn kiosk_recovery_handle(&mut self, _window_id: WindowId, _attempt: u32) {}
     fn user_signals_handle(&mut self, windows: &mut WindowsRegistry);
     fn user_events_handle(&mut self, event: UserEvent);
     fn accesskit_activate_handle(&self, window_id: WindowId, adapter: &mut Adapter);
@@ -322,6 +1137,20 @@ where
     );
     fn accesskit_deactivate_handle(&self, window_id: WindowId, adapter: &mut Adapter);
 
-    /// Do something before main event loop will be stopped: save state, etc.
-    fn close_handle(&mut self, window_id: WindowId);
+    /// Called when the compositor or the frame asks to close the window, which is still
+    /// registered and can be inspected.
+    ///
+    /// Return `false` to keep the window open.
+    fn close_requested_handle(
+        &mut self,
+        _window_id: WindowId,
+        _window: &mut WaylandWindow,
+    ) -> bool {
+        true
+    }
+
+    /// Called once the window is gone: save state, etc.
+    ///
+    /// It is the last callback for the window, no events are delivered for it afterwards.
+    fn destroyed_handle(&mut self, window_id: WindowId);
 }
@@ -0,0 +1,139 @@
+// Handling of the idle-inhibit-unstable-v1.
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::{
+        client::{
+            Connection, Dispatch, Proxy, QueueHandle, delegate_dispatch,
+            globals::{BindError, GlobalList},
+            protocol::wl_surface::WlSurface,
+        },
+        protocols::wp::idle_inhibit::zv1::client::{
+            zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1,
+            zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+        },
+    },
+};
+
+use crate::WaylandState;
+
+/// Inhibition of the screen blanking, for the video players.
+#[derive(Debug, Clone)]
+pub struct IdleInhibitState {
+    manager: ZwpIdleInhibitManagerV1,
+}
+
+impl IdleInhibitState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+}
+
+/// Inhibition requested by a window.
+#[derive(Debug, Default)]
+pub(crate) struct IdleInhibit {
+    pub(crate) requested: bool,
+    inhibitor: Option<ZwpIdleInhibitorV1>,
+}
+
+impl IdleInhibit {
+    /// Inhibit the idle while the surface is visible, unless it's already done.
+    pub(crate) fn inhibit(
+        &mut self,
+        state: &IdleInhibitState,
+        surface: &WlSurface,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) {
+        if !self.requested || self.inhibitor.is_some() {
+            return;
+        }
+        let inhibitor = state
+            .manager
+            .create_inhibitor(surface, queue_handle, GlobalData);
+        self.inhibitor = Some(inhibitor);
+    }
+
+    pub(crate) fn release(&mut self) {
+        if let Some(inhibitor) = self.inhibitor.take() {
+            inhibitor.destroy();
+        }
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitManagerV1, GlobalData, WaylandState> for IdleInhibitState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwpIdleInhibitManagerV1,
+        _: <ZwpIdleInhibitManagerV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitorV1, GlobalData, WaylandState> for IdleInhibitState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwpIdleInhibitorV1,
+        _: <ZwpIdleInhibitorV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+delegate_dispatch!(WaylandState: [ZwpIdleInhibitManagerV1: GlobalData] => IdleInhibitState);
+delegate_dispatch!(WaylandState: [ZwpIdleInhibitorV1: GlobalData] => IdleInhibitState);
+
+#[cfg(test)]
+mod tests {
+    use smithay_client_toolkit::compositor::CompositorState;
+
+    use super::*;
+    use crate::testing::{FakeCompositor, bound_global};
+
+    #[test]
+    fn released_inhibitor_leaves_no_object() {
+        let mut compositor =
+            FakeCompositor::new(&[("wl_compositor", 6), ("zwp_idle_inhibit_manager_v1", 1)]);
+        let queue_handle = compositor.queue_handle.clone();
+        let surface = CompositorState::bind(&compositor.globals, &queue_handle)
+            .unwrap()
+            .create_surface(&queue_handle);
+        let state = IdleInhibitState::new(&compositor.globals, &queue_handle).unwrap();
+
+        let mut idle_inhibit = IdleInhibit {
+            requested: true,
+            ..Default::default()
+        };
+        idle_inhibit.inhibit(&state, &surface, &queue_handle);
+        idle_inhibit.inhibit(&state, &surface, &queue_handle);
+        // What the drop of the window does.
+        idle_inhibit.release();
+
+        let requests = compositor.requests();
+        let manager = bound_global(&requests, 2).unwrap();
+        // zwp_idle_inhibit_manager_v1::create_inhibitor, the new id goes first.
+        let inhibitors: Vec<u32> = requests
+            .iter()
+            .filter(|request| request.object == manager && request.opcode == 1)
+            .map(|request| request.word(0))
+            .collect();
+        assert_eq!(inhibitors.len(), 1);
+        // zwp_idle_inhibitor_v1::destroy is the only request of the inhibitor.
+        let inhibitor_requests: Vec<u16> = requests
+            .iter()
+            .filter(|request| request.object == inhibitors[0])
+            .map(|request| request.opcode)
+            .collect();
+        assert_eq!(inhibitor_requests, [0]);
+    }
+}
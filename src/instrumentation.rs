@@ -0,0 +1,265 @@
+// Optional hooks to find out where the event loop spends its time.
+
+use std::{
+    cell::Cell,
+    convert::Infallible,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use smithay_client_toolkit::reexports::calloop::{
+    self, EventIterator, EventSource, Poll, PostAction, Readiness, Token, TokenFactory,
+};
+
+use crate::WindowId;
+
+/// Phase of an event loop iteration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Blocked until a Wayland or a channel event arrives.
+    Wait,
+    /// Processing the Wayland and channel events.
+    Dispatch,
+    UserEvents,
+    CreateWindow,
+    Rescale,
+    Monitor,
+    Resize,
+    UserSignals,
+    Accesskit,
+    Keyboard,
+    Pointer,
+    Gesture,
+    Focus,
+    Clipboard,
+    DragAndDrop,
+    Draw,
+    Close,
+    Destroy,
+}
+
+/// Time spent in one phase, or in one handler callback for the window.
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseRecord {
+    pub phase: Phase,
+    pub window_id: Option<WindowId>,
+    pub duration: Duration,
+}
+
+/// Queue lengths observed right after the dispatch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueueLengths {
+    pub user_events: usize,
+    pub events: usize,
+    pub accesskit_events: usize,
+    pub create_requests: usize,
+    pub rescale_requests: usize,
+    pub resize_requests: usize,
+    pub redraw_requests: usize,
+    pub close_requests: usize,
+}
+
+/// Everything recorded during a single event loop iteration.
+#[derive(Debug, Clone, Default)]
+pub struct IterationRecord {
+    pub phases: Vec<PhaseRecord>,
+    pub queues: QueueLengths,
+    /// Whole iteration time, dispatch included.
+    pub duration: Duration,
+}
+
+impl IterationRecord {
+    /// Time spent in the phase summed over all windows.
+    pub fn phase_duration(&self, phase: Phase) -> Duration {
+        self.phases
+            .iter()
+            .filter(|record| record.phase == phase)
+            .map(|record| record.duration)
+            .sum()
+    }
+}
+
+/// Receives a record for every event loop iteration.
+///
+/// Register it with [`crate::event_loop::WlEventLoop::set_instrumentation`].
+pub trait Instrumentation {
+    fn iteration(&mut self, record: &IterationRecord);
+}
+
+/// Emits every record as `tracing` events inside an `iteration` span.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingInstrumentation;
+
+impl Instrumentation for TracingInstrumentation {
+    fn iteration(&mut self, record: &IterationRecord) {
+        let span = tracing::trace_span!(
+            "iteration",
+            duration = ?record.duration,
+            events = record.queues.events,
+            user_events = record.queues.user_events,
+            redraw_requests = record.queues.redraw_requests,
+        );
+        let _entered = span.enter();
+        for phase in &record.phases {
+            tracing::trace!(
+                phase = ?phase.phase,
+                window_id = phase.window_id.map(WindowId::trace),
+                duration = ?phase.duration,
+            );
+        }
+    }
+}
+
+/// Collects the records, does nothing if no instrumentation is registered.
+pub(crate) struct Recorder {
+    iteration: Option<IterationRecord>,
+    started: Option<Instant>,
+}
+
+impl Recorder {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            iteration: enabled.then(IterationRecord::default),
+            started: enabled.then(Instant::now),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn start(&self) -> Option<Instant> {
+        self.iteration.as_ref().map(|_| Instant::now())
+    }
+
+    #[inline]
+    pub(crate) fn record(
+        &mut self,
+        phase: Phase,
+        window_id: Option<WindowId>,
+        start: Option<Instant>,
+    ) {
+        if let (Some(iteration), Some(start)) = (self.iteration.as_mut(), start) {
+            iteration.phases.push(PhaseRecord {
+                phase,
+                window_id,
+                duration: start.elapsed(),
+            });
+        }
+    }
+
+    /// Split the dispatch started at `start` into the wait and the processing.
+    pub(crate) fn record_dispatch(&mut self, start: Option<Instant>, woken: &WakeTime) {
+        let Some(start) = start else {
+            return;
+        };
+        // Without the wake up time the whole dispatch counts as processing.
+        let woken = woken.take().unwrap_or(start).max(start);
+        if let Some(iteration) = self.iteration.as_mut() {
+            iteration.phases.push(PhaseRecord {
+                phase: Phase::Wait,
+                window_id: None,
+                duration: woken - start,
+            });
+        }
+        self.record(Phase::Dispatch, None, Some(woken));
+    }
+
+    #[inline]
+    pub(crate) fn queues(&mut self, queues: impl FnOnce() -> QueueLengths) {
+        if let Some(iteration) = self.iteration.as_mut() {
+            iteration.queues = queues();
+        }
+    }
+
+    pub(crate) fn finish(self, instrumentation: Option<&mut (dyn Instrumentation + 'static)>) {
+        if let (Some(mut iteration), Some(started), Some(instrumentation)) =
+            (self.iteration, self.started, instrumentation)
+        {
+            iteration.duration = started.elapsed();
+            instrumentation.iteration(&iteration);
+        }
+    }
+}
+
+/// Time the event loop woke up at, the end of its wait.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WakeTime(Rc<Cell<Option<Instant>>>);
+
+impl WakeTime {
+    pub(crate) fn take(&self) -> Option<Instant> {
+        self.0.take()
+    }
+}
+
+/// Event source without events, notified by calloop right after the poll returns and before any
+/// event is processed.
+#[derive(Debug)]
+pub(crate) struct WakeMarker {
+    woken: WakeTime,
+}
+
+impl WakeMarker {
+    pub(crate) fn new() -> (Self, WakeTime) {
+        let woken = WakeTime::default();
+        (
+            Self {
+                woken: woken.clone(),
+            },
+            woken,
+        )
+    }
+}
+
+impl EventSource for WakeMarker {
+    type Event = ();
+    type Metadata = ();
+    type Ret = ();
+    type Error = Infallible;
+
+    const NEEDS_EXTRA_LIFECYCLE_EVENTS: bool = true;
+
+    fn process_events<F>(&mut self, _: Readiness, _: Token, _: F) -> Result<PostAction, Infallible>
+    where
+        F: FnMut((), &mut ()),
+    {
+        Ok(PostAction::Continue)
+    }
+
+    // Nothing to poll, only the lifecycle notifications are needed.
+    fn register(&mut self, _: &mut Poll, _: &mut TokenFactory) -> calloop::Result<()> {
+        Ok(())
+    }
+
+    fn reregister(&mut self, _: &mut Poll, _: &mut TokenFactory) -> calloop::Result<()> {
+        Ok(())
+    }
+
+    fn unregister(&mut self, _: &mut Poll) -> calloop::Result<()> {
+        Ok(())
+    }
+
+    fn before_handle_events(&mut self, _: EventIterator<'_>) {
+        self.woken.0.set(Some(Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch_is_split_at_the_wake_up() {
+        let mut recorder = Recorder::new(true);
+        let start = recorder.start();
+        let woken = WakeTime::default();
+        woken
+            .0
+            .set(start.map(|start| start + Duration::from_millis(5)));
+        recorder.record_dispatch(start, &woken);
+        let iteration = recorder.iteration.unwrap();
+        assert_eq!(
+            iteration.phase_duration(Phase::Wait),
+            Duration::from_millis(5)
+        );
+        assert_eq!(iteration.phases[1].phase, Phase::Dispatch);
+        assert_eq!(woken.take(), None);
+    }
+}
@@ -0,0 +1,330 @@
+// Handling of the wlr-layer-shell-unstable-v1, for the panels, the notifications and the
+// overlays.
+
+use dpi::LogicalSize;
+pub use smithay_client_toolkit::shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer};
+use smithay_client_toolkit::{
+    delegate_layer,
+    reexports::client::{Connection, Proxy, QueueHandle},
+    shell::{
+        WaylandSurface,
+        wlr_layer::{LayerShellHandler, LayerSurface, LayerSurfaceConfigure},
+    },
+};
+use tracing::warn;
+
+use crate::{
+    Decorations, Monitor, WaylandState, WaylandWindow, WindowAttributes, WindowId,
+    window::{DEFAULT_WINDOW_SIZE, ShellSurface},
+};
+
+/// Distance kept from the anchored edges, in logical pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Margins {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+/// The attributes of a layer surface, see
+/// [`crate::event_loop::LoopHandler::create_layer_surface`].
+#[derive(Debug, Clone)]
+pub struct LayerSurfaceAttributes {
+    pub layer: Layer,
+    /// Edges of the output the surface is attached to, it's centered without anchors.
+    pub anchor: Anchor,
+    /// A zero width or height stretches the surface between the left and right or the top and
+    /// bottom anchors, both must be set then.
+    pub size: LogicalSize<u32>,
+    /// Space reserved along the anchored edge, e.g. for a panel. `0` moves the surface out of the
+    /// way of the other exclusive zones, `-1` ignores them.
+    pub exclusive_zone: i32,
+    pub margins: Margins,
+    pub keyboard_interactivity: KeyboardInteractivity,
+    /// The output to show the surface on, the choice of the compositor if `None`.
+    pub output: Option<Monitor>,
+    /// The kind of surface, e.g. `"notifications"`, for the rules of the compositor.
+    pub namespace: String,
+}
+
+impl Default for LayerSurfaceAttributes {
+    fn default() -> Self {
+        Self {
+            layer: Layer::Top,
+            anchor: Anchor::empty(),
+            size: DEFAULT_WINDOW_SIZE.to_owned(),
+            exclusive_zone: 0,
+            margins: Margins::default(),
+            keyboard_interactivity: KeyboardInteractivity::None,
+            output: None,
+            namespace: String::new(),
+        }
+    }
+}
+
+impl LayerSurfaceAttributes {
+    #[inline]
+    pub fn with_layer(mut self, layer: Layer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    #[inline]
+    pub fn with_anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    #[inline]
+    pub fn with_size(mut self, size: LogicalSize<u32>) -> Self {
+        self.size = size;
+        self
+    }
+
+    #[inline]
+    pub fn with_exclusive_zone(mut self, exclusive_zone: i32) -> Self {
+        self.exclusive_zone = exclusive_zone;
+        self
+    }
+
+    #[inline]
+    pub fn with_margins(mut self, margins: Margins) -> Self {
+        self.margins = margins;
+        self
+    }
+
+    #[inline]
+    pub fn with_keyboard_interactivity(mut self, interactivity: KeyboardInteractivity) -> Self {
+        self.keyboard_interactivity = interactivity;
+        self
+    }
+
+    #[inline]
+    pub fn with_output(mut self, output: Option<Monitor>) -> Self {
+        self.output = output;
+        self
+    }
+
+    #[inline]
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Check the size against the anchors, the compositor kills the connection otherwise.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let horizontal = Anchor::LEFT | Anchor::RIGHT;
+        if self.size.width == 0 && !self.anchor.contains(horizontal) {
+            return Err(String::from(
+                "A layer surface with a zero width must be anchored to the left and the right",
+            ));
+        }
+        let vertical = Anchor::TOP | Anchor::BOTTOM;
+        if self.size.height == 0 && !self.anchor.contains(vertical) {
+            return Err(String::from(
+                "A layer surface with a zero height must be anchored to the top and the bottom",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The requests setting the surface up, in the order they are sent.
+    fn requests(&self) -> [LayerRequest; 5] {
+        let Margins {
+            top,
+            right,
+            bottom,
+            left,
+        } = self.margins;
+        [
+            LayerRequest::Size(self.size.width, self.size.height),
+            LayerRequest::Anchor(self.anchor),
+            LayerRequest::ExclusiveZone(self.exclusive_zone),
+            LayerRequest::Margin(top, right, bottom, left),
+            LayerRequest::KeyboardInteractivity(self.keyboard_interactivity),
+        ]
+    }
+}
+
+/// A request of the layer surface made from the attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LayerRequest {
+    Size(u32, u32),
+    Anchor(Anchor),
+    ExclusiveZone(i32),
+    Margin(i32, i32, i32, i32),
+    KeyboardInteractivity(KeyboardInteractivity),
+}
+
+impl LayerRequest {
+    fn send(self, layer: &LayerSurface) {
+        match self {
+            Self::Size(width, height) => layer.set_size(width, height),
+            Self::Anchor(anchor) => layer.set_anchor(anchor),
+            Self::ExclusiveZone(zone) => layer.set_exclusive_zone(zone),
+            Self::Margin(top, right, bottom, left) => layer.set_margin(top, right, bottom, left),
+            Self::KeyboardInteractivity(interactivity) => {
+                layer.set_keyboard_interactivity(interactivity)
+            }
+        }
+    }
+}
+
+impl WaylandState {
+    pub(crate) fn create_layer_surface(
+        &mut self,
+        id: WindowId,
+        attributes: LayerSurfaceAttributes,
+    ) {
+        let Some(layer_shell) = self.layer_shell.as_ref() else {
+            warn!("wlr-layer-shell is not supported, the layer surface is not created");
+            return;
+        };
+        if let Err(err) = attributes.validate() {
+            warn!("{err}, the layer surface is not created");
+            return;
+        }
+        let output = attributes.output.as_ref().and_then(Monitor::live_output);
+        let scale_factor = output
+            .and_then(|output| self.output_state.info(output))
+            .map_or_else(
+                || self.initial_scale_factor(),
+                |info| info.scale_factor as f64,
+            );
+        let surface = self.compositor_state.create_surface(&self.queue_handle);
+        let wl_id = surface.id();
+        let layer = layer_shell.create_layer_surface(
+            &self.queue_handle,
+            surface,
+            attributes.layer,
+            Some(attributes.namespace.clone()),
+            output,
+        );
+        for request in attributes.requests() {
+            request.send(&layer);
+        }
+        let window = WaylandWindow::new(
+            self,
+            ShellSurface::Layer(layer),
+            scale_factor,
+            id,
+            WindowAttributes {
+                surface_size: Some(attributes.size.into()),
+                decorations: Decorations::None,
+                ..Default::default()
+            },
+        );
+        // The compositor answers the initial commit with the configure mapping the surface.
+        window.immutable.window.wl_surface().commit();
+        self.windows.insert(id, wl_id, window);
+    }
+}
+
+impl LayerShellHandler for WaylandState {
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, layer: &LayerSurface) {
+        // The surface is not shown anymore, e.g. its output is gone.
+        let id = layer.wl_surface().id();
+        if self.windows.get_by_object_id(&id).is_some() {
+            self.windows.destroy_request.insert(id);
+        }
+    }
+
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        let id = layer.wl_surface().id();
+        let Some(window) = self.windows.get_mut_by_object_id(&id) else {
+            return;
+        };
+        // A zero is left to the application.
+        let (width, height) = configure.new_size;
+        let size = LogicalSize::new(
+            if width == 0 { window.size.width } else { width },
+            if height == 0 {
+                window.size.height
+            } else {
+                height
+            },
+        );
+        let first_configure = !window.configured;
+        window.configured = true;
+        // The inhibitors only take effect on the mapped surfaces, every configure maps the
+        // surface again after an unmap.
+        window.inhibit_idle();
+        let resize = size != window.size;
+        if first_configure || resize {
+            window.resize(size);
+        }
+        self.windows.request_configured(id, first_configure, resize);
+    }
+}
+
+delegate_layer!(WaylandState);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_attributes_are_valid() {
+        let attributes = LayerSurfaceAttributes::default();
+        assert!(attributes.size.width > 0 && attributes.size.height > 0);
+        assert_eq!(attributes.validate(), Ok(()));
+    }
+
+    #[test]
+    fn zero_size_needs_opposite_anchors() {
+        let bar = LayerSurfaceAttributes::default().with_size(LogicalSize::new(0, 30));
+        assert!(bar.clone().with_anchor(Anchor::TOP).validate().is_err());
+        assert!(
+            bar.clone()
+                .with_anchor(Anchor::TOP | Anchor::LEFT)
+                .validate()
+                .is_err()
+        );
+        assert_eq!(
+            bar.with_anchor(Anchor::TOP | Anchor::LEFT | Anchor::RIGHT)
+                .validate(),
+            Ok(())
+        );
+
+        let side = LayerSurfaceAttributes::default().with_size(LogicalSize::new(30, 0));
+        assert!(side.clone().with_anchor(Anchor::LEFT).validate().is_err());
+        assert_eq!(
+            side.with_anchor(Anchor::LEFT | Anchor::TOP | Anchor::BOTTOM)
+                .validate(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn attributes_map_to_requests() {
+        let attributes = LayerSurfaceAttributes::default()
+            .with_anchor(Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT)
+            .with_size(LogicalSize::new(0, 40))
+            .with_exclusive_zone(40)
+            .with_margins(Margins {
+                top: 1,
+                right: 2,
+                bottom: 3,
+                left: 4,
+            })
+            .with_keyboard_interactivity(KeyboardInteractivity::OnDemand);
+        assert_eq!(
+            attributes.requests(),
+            [
+                LayerRequest::Size(0, 40),
+                LayerRequest::Anchor(Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT),
+                LayerRequest::ExclusiveZone(40),
+                LayerRequest::Margin(1, 2, 3, 4),
+                LayerRequest::KeyboardInteractivity(KeyboardInteractivity::OnDemand),
+            ]
+        );
+    }
+}
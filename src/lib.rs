@@ -1,13 +1,66 @@
+pub mod activation;
+pub mod content_type;
+pub mod data_device;
+pub mod dialog;
 pub mod event_loop;
+pub mod idle_inhibit;
+pub mod instrumentation;
+#[cfg(feature = "layer-shell")]
+pub mod layer_shell;
+pub mod monitor;
+pub mod presentation;
 pub mod seat;
 pub mod state;
+pub mod tearing_control;
+#[cfg(test)]
+mod testing;
 pub mod viewporter;
 pub mod window;
 
-pub use event_loop::{AccesskitEvents, AccesskitHandler, ApplicationHandler, Events, LoopHandler};
+pub use content_type::{ContentType, ContentTypeState};
+#[cfg(feature = "data-control")]
+pub use data_device::data_control::{DataControl, DataControlEvent, SelectionKind};
+pub use data_device::{
+    TransferError, TransferLimits,
+    clipboard::{ClipboardContent, ClipboardError, ClipboardEvent, ClipboardRequest},
+    dnd::{DragAction, DragContent, DragEvent, DragIcon, DragSourceEvent},
+    uri_list::parse_uri_list,
+};
+pub use dialog::DialogState;
+pub use event_loop::{
+    AccesskitEvents, AccesskitHandler, ApplicationHandler, Events, LoopHandler, SurfaceSizeWriter,
+};
+pub use idle_inhibit::IdleInhibitState;
+pub use instrumentation::{Instrumentation, TracingInstrumentation};
+#[cfg(feature = "layer-shell")]
+pub use layer_shell::{LayerSurfaceAttributes, Margins};
+pub use monitor::{Monitor, MonitorEvent, MonitorMode};
+pub use presentation::{PresentationEvent, PresentationFlags};
+pub use seat::{
+    CursorTheme,
+    constraints::{ConfineRegion, ConstraintError, CursorGrabMode, PointerConstraintEvent},
+    gesture::{GestureEvent, ScrollSource},
+    keyboard::{ComposeState, KeyboardLayout, KeyboardQuery, RawKey},
+    text_input::{ImeEvent, ImePurpose},
+};
 pub use state::WaylandState;
-pub use viewporter::ViewporterState;
-pub use window::{WaylandWindow, WindowImmutable, attributes::*, registry::WindowsRegistry};
+pub use tearing_control::{PresentationHint, TearingControlState};
+pub use viewporter::{ViewportError, ViewporterState};
+pub use window::{
+    WaylandWindow, WindowImmutable,
+    attributes::*,
+    cursor::{CursorImage, CursorImageError, CustomCursor},
+    pending::PendingChanges,
+    popup::{PopupAttributes, PopupError, PopupId, PopupPlacement},
+    proxy::{WindowCommand, WindowProxy},
+    registry::WindowsRegistry,
+    subsurface::SubsurfaceHandle,
+};
+
+pub use sctk_adwaita::FrameConfig;
+pub use smithay_client_toolkit::{
+    reexports::client::protocol::wl_output::Transform, seat::keyboard::Keysym,
+};
 
 pub mod xdg {
     pub use smithay_client_toolkit::reexports::protocols::xdg::shell::client::xdg_toplevel::ResizeEdge;
@@ -0,0 +1,167 @@
+use dpi::{LogicalPosition, LogicalSize, PhysicalSize};
+use smithay_client_toolkit::{
+    output::{Mode, OutputInfo, OutputState},
+    reexports::client::{
+        Proxy,
+        protocol::wl_output::{Transform, WlOutput},
+    },
+};
+
+/// A compositor output the windows can be shown on.
+///
+/// The information is a snapshot taken when the handle was created, the handles of
+/// [`crate::WindowsRegistry::available_monitors`] are refreshed when the output changes.
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    output: WlOutput,
+    info: Option<OutputInfo>,
+}
+
+/// A video mode of a [`Monitor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorMode {
+    pub size: PhysicalSize<u32>,
+    /// Refresh rate in millihertz, `0` for the outputs without one, such as the virtual ones.
+    pub refresh_rate_millihertz: u32,
+    /// Whether the output runs in this mode.
+    pub current: bool,
+    /// Whether the output recommends this mode, usually its native resolution.
+    pub preferred: bool,
+}
+
+impl From<&Mode> for MonitorMode {
+    fn from(mode: &Mode) -> Self {
+        let (width, height) = mode.dimensions;
+        Self {
+            size: PhysicalSize::new(width.max(0) as u32, height.max(0) as u32),
+            refresh_rate_millihertz: mode.refresh_rate.max(0) as u32,
+            current: mode.current,
+            preferred: mode.preferred,
+        }
+    }
+}
+
+/// Changes of the outputs of the compositor, e.g. when a laptop is docked.
+#[derive(Debug, Clone)]
+pub enum MonitorEvent {
+    Connected(Monitor),
+    /// The mode, the refresh rate, the scale or the position of the output changed.
+    Changed(Monitor),
+    /// Carries the last information of the output.
+    Disconnected(Monitor),
+}
+
+impl Monitor {
+    pub(crate) fn new(output_state: &OutputState, output: WlOutput) -> Self {
+        Self {
+            info: output_state.info(&output),
+            output,
+        }
+    }
+
+    pub(crate) fn output(&self) -> &WlOutput {
+        &self.output
+    }
+
+    /// The output unless it was disconnected since the handle was taken.
+    pub(crate) fn live_output(&self) -> Option<&WlOutput> {
+        self.output.is_alive().then_some(&self.output)
+    }
+
+    /// Name of the output, e.g. `"HDMI-A-1"`, if the compositor advertises one.
+    pub fn name(&self) -> Option<&str> {
+        self.info.as_ref().and_then(|info| info.name.as_deref())
+    }
+
+    /// Human readable description of the output, if the compositor advertises one.
+    pub fn description(&self) -> Option<&str> {
+        self.info
+            .as_ref()
+            .and_then(|info| info.description.as_deref())
+    }
+
+    /// Manufacturer of the output.
+    pub fn make(&self) -> Option<&str> {
+        self.info.as_ref().map(|info| info.make.as_str())
+    }
+
+    /// Model name of the output.
+    pub fn model(&self) -> Option<&str> {
+        self.info.as_ref().map(|info| info.model.as_str())
+    }
+
+    /// Physical dimensions in millimeters, `None` for the outputs without one such as projectors.
+    pub fn physical_size(&self) -> Option<(u32, u32)> {
+        let (width, height) = self.info.as_ref()?.physical_size;
+        match (u32::try_from(width), u32::try_from(height)) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => Some((width, height)),
+            _ => None,
+        }
+    }
+
+    /// Integer scale factor of the output.
+    pub fn scale_factor(&self) -> i32 {
+        self.info
+            .as_ref()
+            .map(|info| info.scale_factor)
+            .unwrap_or(1)
+    }
+
+    /// Top-left corner of the output in the compositor space, from xdg-output when the
+    /// compositor has it.
+    ///
+    /// Compositors may hide the layout and report every output at the origin.
+    pub fn position(&self) -> Option<LogicalPosition<i32>> {
+        let info = self.info.as_ref()?;
+        let (x, y) = info.logical_position.unwrap_or(info.location);
+        Some(LogicalPosition::new(x, y))
+    }
+
+    /// Size of the output in the compositor space, together with [`Monitor::position`] it tells
+    /// which output a window is on.
+    ///
+    /// Without xdg-output it is derived from the current mode, the transform and the integer
+    /// scale, which misses the fractional scaling.
+    pub fn logical_size(&self) -> Option<LogicalSize<u32>> {
+        let info = self.info.as_ref()?;
+        if let Some((width, height)) = info.logical_size {
+            return Some(LogicalSize::new(width.max(0) as u32, height.max(0) as u32));
+        }
+        let size = self.current_mode()?.size;
+        let size = match info.transform {
+            Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270 => {
+                PhysicalSize::new(size.height, size.width)
+            }
+            _ => size,
+        };
+        Some(size.to_logical(self.scale_factor().max(1) as f64))
+    }
+
+    /// The mode the output currently runs in.
+    pub fn current_mode(&self) -> Option<MonitorMode> {
+        self.info
+            .as_ref()?
+            .modes
+            .iter()
+            .find(|mode| mode.current)
+            .map(MonitorMode::from)
+    }
+
+    /// The modes advertised by the output.
+    ///
+    /// Recent compositors only advertise the current mode.
+    pub fn modes(&self) -> Vec<MonitorMode> {
+        self.info
+            .as_ref()
+            .map(|info| info.modes.iter().map(MonitorMode::from).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl PartialEq for Monitor {
+    fn eq(&self, other: &Self) -> bool {
+        self.output == other.output
+    }
+}
+
+impl Eq for Monitor {}
@@ -0,0 +1,136 @@
+// Handling of the presentation-time.
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::{
+        client::{
+            Connection, Dispatch, Proxy, QueueHandle, WEnum,
+            backend::ObjectId,
+            delegate_dispatch,
+            globals::{BindError, GlobalList},
+            protocol::wl_surface::WlSurface,
+        },
+        protocols::wp::presentation_time::client::{
+            wp_presentation::{self, WpPresentation},
+            wp_presentation_feedback::{self, Kind, WpPresentationFeedback},
+        },
+    },
+};
+
+use crate::{Events, WaylandState};
+
+/// How a frame was presented, see the `kind` enum of the presentation-time protocol.
+pub type PresentationFlags = Kind;
+
+/// When a frame requested with [`crate::WaylandWindow::request_presentation_feedback`] reached
+/// the screen.
+#[derive(Debug, Clone, Copy)]
+pub enum PresentationEvent {
+    Presented {
+        /// Time of the presentation in the clock of
+        /// [`crate::event_loop::WlEventLoop::presentation_clock_id`].
+        tv_sec: u64,
+        tv_nsec: u32,
+        /// Nanoseconds until the next refresh, `0` for the outputs without a constant rate.
+        refresh_ns: u32,
+        /// Counter of the vertical retraces of the output, `0` without one.
+        seq: u64,
+        flags: PresentationFlags,
+    },
+    /// The content was never shown, e.g. it was replaced first or the window is hidden.
+    Discarded,
+}
+
+/// Presentation time.
+#[derive(Debug)]
+pub struct PresentationState {
+    presentation: WpPresentation,
+    /// The `clockid_t` of the timestamps, `None` until the compositor sends it.
+    pub(crate) clock_id: Option<u32>,
+}
+
+impl PresentationState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let presentation = globals.bind(queue_handle, 1..=2, GlobalData)?;
+        Ok(Self {
+            presentation,
+            clock_id: None,
+        })
+    }
+
+    pub(crate) fn presentation(&self) -> &WpPresentation {
+        &self.presentation
+    }
+}
+
+/// Ask for the feedback of the content of the next commit of the surface.
+pub(crate) fn request_feedback(
+    presentation: &WpPresentation,
+    surface: &WlSurface,
+    queue_handle: &QueueHandle<WaylandState>,
+) {
+    presentation.feedback(surface, queue_handle, surface.id());
+}
+
+impl Dispatch<WpPresentation, GlobalData, WaylandState> for PresentationState {
+    fn event(
+        state: &mut WaylandState,
+        _: &WpPresentation,
+        event: wp_presentation::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        if let wp_presentation::Event::ClockId { clk_id } = event
+            && let Some(presentation) = state.presentation.as_mut()
+        {
+            presentation.clock_id = Some(clk_id);
+        }
+    }
+}
+
+/// The feedback objects are destroyed by their last event, so the discarded frames leave
+/// nothing behind.
+impl Dispatch<WpPresentationFeedback, ObjectId, WaylandState> for PresentationState {
+    fn event(
+        state: &mut WaylandState,
+        _: &WpPresentationFeedback,
+        event: wp_presentation_feedback::Event,
+        surface_id: &ObjectId,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        let event = match event {
+            wp_presentation_feedback::Event::Presented {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+                refresh,
+                seq_hi,
+                seq_lo,
+                flags,
+            } => PresentationEvent::Presented {
+                tv_sec: (u64::from(tv_sec_hi) << 32) | u64::from(tv_sec_lo),
+                tv_nsec,
+                refresh_ns: refresh,
+                seq: (u64::from(seq_hi) << 32) | u64::from(seq_lo),
+                flags: match flags {
+                    WEnum::Value(flags) => flags,
+                    WEnum::Unknown(bits) => Kind::from_bits_truncate(bits),
+                },
+            },
+            wp_presentation_feedback::Event::Discarded => PresentationEvent::Discarded,
+            // The output is known from the window.
+            _ => return,
+        };
+        state
+            .events
+            .push_back(Events::Presentation(surface_id.clone(), event));
+    }
+}
+
+delegate_dispatch!(WaylandState: [WpPresentation: GlobalData] => PresentationState);
+delegate_dispatch!(WaylandState: [WpPresentationFeedback: ObjectId] => PresentationState);
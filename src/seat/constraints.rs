@@ -0,0 +1,205 @@
+use std::{error::Error, fmt};
+
+use dpi::{LogicalPosition, LogicalSize};
+use smithay_client_toolkit::{
+    compositor::{CompositorState, Region},
+    reexports::{
+        client::{
+            Connection, Proxy, QueueHandle,
+            protocol::{wl_pointer::WlPointer, wl_surface::WlSurface},
+        },
+        protocols::wp::pointer_constraints::zv1::client::{
+            zwp_confined_pointer_v1::ZwpConfinedPointerV1,
+            zwp_locked_pointer_v1::ZwpLockedPointerV1,
+        },
+    },
+    seat::pointer_constraints::PointerConstraintsHandler,
+};
+
+use ui_events::pointer::PointerInfo;
+
+use crate::{Events, WaylandState};
+
+/// Why a pointer constraint could not be set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintError {
+    /// The compositor does not support pointer constraints.
+    Unsupported,
+    /// Another kind of constraint is active on the window.
+    AlreadyConstrained,
+    /// The operation needs the pointer to be locked.
+    NotLocked,
+}
+
+impl fmt::Display for ConstraintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported => f.write_str("pointer constraints are not supported"),
+            Self::AlreadyConstrained => {
+                f.write_str("another pointer constraint is active on the window")
+            }
+            Self::NotLocked => f.write_str("the pointer is not locked to the window"),
+        }
+    }
+}
+
+impl Error for ConstraintError {}
+
+/// Changes of the constraints set on a pointer.
+#[derive(Debug, Clone)]
+pub enum PointerConstraintEvent {
+    /// The lock requested with [`crate::WaylandWindow::lock_pointer`] took effect.
+    Locked { pointer: PointerInfo },
+    /// The compositor released the lock, e.g. the window lost the focus. It's applied again
+    /// once possible until [`crate::WaylandWindow::unlock_pointer`] is called.
+    Unlocked { pointer: PointerInfo },
+    /// The confinement requested with [`crate::WaylandWindow::confine_pointer`] took effect.
+    Confined { pointer: PointerInfo },
+    /// The compositor released the confinement, it's applied again once possible.
+    Unconfined { pointer: PointerInfo },
+}
+
+/// Area of the window the pointer is confined to, in logical surface coordinates.
+///
+/// The parts outside of the surface are clipped whenever the window is resized.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfineRegion {
+    rects: Vec<(LogicalPosition<i32>, LogicalSize<i32>)>,
+}
+
+impl ConfineRegion {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rectangle to the region.
+    pub fn add(&mut self, position: LogicalPosition<i32>, size: LogicalSize<i32>) -> &mut Self {
+        self.rects.push((position, size));
+        self
+    }
+
+    /// Build the Wayland region for a surface of the given size.
+    pub(crate) fn to_region(
+        &self,
+        compositor: &CompositorState,
+        surface_size: LogicalSize<u32>,
+    ) -> Option<Region> {
+        let region = Region::new(compositor).ok()?;
+        let (width, height) = (surface_size.width as i32, surface_size.height as i32);
+        for (position, size) in &self.rects {
+            let (x, y) = (position.x.max(0), position.y.max(0));
+            let right = (position.x + size.width).min(width);
+            let bottom = (position.y + size.height).min(height);
+            if right > x && bottom > y {
+                region.add(x, y, right - x, bottom - y);
+            }
+        }
+        Some(region)
+    }
+}
+
+/// How the pointers over the window are grabbed, see [`crate::WaylandWindow::set_cursor_grab`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CursorGrabMode {
+    /// The pointers move freely.
+    #[default]
+    None,
+    /// The pointers can't leave the window.
+    Confined,
+    /// The pointers are locked in place, only relative motion is reported.
+    Locked,
+}
+
+/// The grab requested on the pointers of a window.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) enum PointerGrab {
+    #[default]
+    None,
+    Locked,
+    /// Confined to the region, or to the whole surface.
+    Confined(Option<ConfineRegion>),
+}
+
+/// A constraint set on one of the pointers of a window.
+#[derive(Debug)]
+pub(crate) enum PointerConstraint {
+    Locked(ZwpLockedPointerV1),
+    Confined(ZwpConfinedPointerV1),
+}
+
+impl PointerConstraint {
+    pub(crate) fn destroy(&self) {
+        match self {
+            Self::Locked(locked_pointer) => locked_pointer.destroy(),
+            Self::Confined(confined_pointer) => confined_pointer.destroy(),
+        }
+    }
+}
+
+impl WaylandState {
+    fn constraint_event(
+        &mut self,
+        surface: &WlSurface,
+        pointer: &WlPointer,
+        event: fn(PointerInfo) -> PointerConstraintEvent,
+    ) {
+        if let Some(info) = self.seat_state.pointers.info(pointer.id()) {
+            self.events
+                .push_back(Events::PointerConstraint(surface.id(), event(info)));
+        }
+    }
+}
+
+impl PointerConstraintsHandler for WaylandState {
+    fn confined(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _confined_pointer: &ZwpConfinedPointerV1,
+        surface: &WlSurface,
+        pointer: &WlPointer,
+    ) {
+        self.constraint_event(surface, pointer, |pointer| {
+            PointerConstraintEvent::Confined { pointer }
+        });
+    }
+
+    fn unconfined(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _confined_pointer: &ZwpConfinedPointerV1,
+        surface: &WlSurface,
+        pointer: &WlPointer,
+    ) {
+        self.constraint_event(surface, pointer, |pointer| {
+            PointerConstraintEvent::Unconfined { pointer }
+        });
+    }
+
+    fn locked(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _locked_pointer: &ZwpLockedPointerV1,
+        surface: &WlSurface,
+        pointer: &WlPointer,
+    ) {
+        self.constraint_event(surface, pointer, |pointer| PointerConstraintEvent::Locked {
+            pointer,
+        });
+    }
+
+    fn unlocked(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _locked_pointer: &ZwpLockedPointerV1,
+        surface: &WlSurface,
+        pointer: &WlPointer,
+    ) {
+        self.constraint_event(surface, pointer, |pointer| {
+            PointerConstraintEvent::Unlocked { pointer }
+        });
+    }
+}
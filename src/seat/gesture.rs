@@ -0,0 +1,457 @@
+// Handling of the pointer-gestures-unstable-v1.
+
+use std::sync::Mutex;
+
+use dpi::{LogicalPosition, PhysicalPosition};
+use smithay_client_toolkit::{
+    compositor::SurfaceData,
+    globals::GlobalData,
+    reexports::{
+        client::{
+            Connection, Dispatch, Proxy, QueueHandle,
+            backend::ObjectId,
+            delegate_dispatch,
+            globals::{BindError, GlobalList},
+            protocol::{
+                wl_pointer::{AxisSource, WlPointer},
+                wl_surface::WlSurface,
+            },
+        },
+        protocols::wp::pointer_gestures::zv1::client::{
+            zwp_pointer_gesture_hold_v1::{self, ZwpPointerGestureHoldV1},
+            zwp_pointer_gesture_pinch_v1::{self, ZwpPointerGesturePinchV1},
+            zwp_pointer_gesture_swipe_v1::{self, ZwpPointerGestureSwipeV1},
+            zwp_pointer_gestures_v1::ZwpPointerGesturesV1,
+        },
+    },
+};
+use ui_events::pointer::PointerInfo;
+
+use crate::{Events, WaylandState, seat::WlPointerId};
+
+/// Device a scroll comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollSource {
+    Wheel,
+    /// Fingers on a touchpad, kinetic scrolling starts once it stops.
+    Finger,
+    Continuous,
+    WheelTilt,
+}
+
+impl ScrollSource {
+    pub(crate) fn from_wayland(source: AxisSource) -> Option<Self> {
+        Some(match source {
+            AxisSource::Wheel => Self::Wheel,
+            AxisSource::Finger => Self::Finger,
+            AxisSource::Continuous => Self::Continuous,
+            AxisSource::WheelTilt => Self::WheelTilt,
+            _ => return None,
+        })
+    }
+}
+
+/// Input the pointer events don't cover.
+#[derive(Debug, Clone)]
+pub enum GestureEvent {
+    /// The scroll stopped, e.g. the fingers were lifted from the touchpad.
+    ScrollStop {
+        pointer: PointerInfo,
+        source: Option<ScrollSource>,
+        time: u64,
+    },
+    PinchBegin {
+        pointer: PointerInfo,
+        fingers: u32,
+        time: u64,
+    },
+    PinchUpdate {
+        pointer: PointerInfo,
+        time: u64,
+        /// Motion of the center of the fingers.
+        translation: PhysicalPosition<f64>,
+        /// Scale relative to the previous update.
+        scale_delta: f64,
+        /// Clockwise rotation in degrees relative to the previous update.
+        rotation_delta: f64,
+    },
+    /// The pinch ended, a cancelled one should be rolled back.
+    PinchEnd {
+        pointer: PointerInfo,
+        time: u64,
+        cancelled: bool,
+    },
+    SwipeBegin {
+        pointer: PointerInfo,
+        fingers: u32,
+        time: u64,
+    },
+    SwipeUpdate {
+        pointer: PointerInfo,
+        time: u64,
+        /// Motion of the center of the fingers.
+        delta: PhysicalPosition<f64>,
+    },
+    SwipeEnd {
+        pointer: PointerInfo,
+        time: u64,
+        cancelled: bool,
+    },
+    /// Fingers rest on the touchpad, e.g. to stop a kinetic scroll.
+    ///
+    /// Only sent by compositors supporting version 3 of the protocol.
+    HoldBegin {
+        pointer: PointerInfo,
+        fingers: u32,
+        time: u64,
+    },
+    /// The fingers were lifted, or the hold was cancelled by some other gesture.
+    HoldEnd {
+        pointer: PointerInfo,
+        time: u64,
+        cancelled: bool,
+    },
+}
+
+/// Pointer gestures.
+#[derive(Debug)]
+pub struct PointerGesturesState {
+    gestures: ZwpPointerGesturesV1,
+}
+
+impl PointerGesturesState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let gestures = globals.bind(queue_handle, 1..=3, GlobalData)?;
+        Ok(Self { gestures })
+    }
+
+    /// Create the gesture objects of the pointer.
+    pub(crate) fn get_gestures(
+        &self,
+        pointer: &WlPointer,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> PointerGestures {
+        PointerGestures {
+            pinch: self.gestures.get_pinch_gesture(
+                pointer,
+                queue_handle,
+                GestureData::new(pointer.id()),
+            ),
+            swipe: self.gestures.get_swipe_gesture(
+                pointer,
+                queue_handle,
+                GestureData::new(pointer.id()),
+            ),
+            // Hold gestures came with the version 3.
+            hold: (self.gestures.version() >= 3).then(|| {
+                self.gestures.get_hold_gesture(
+                    pointer,
+                    queue_handle,
+                    GestureData::new(pointer.id()),
+                )
+            }),
+        }
+    }
+}
+
+/// Gesture objects of a pointer.
+#[derive(Debug)]
+pub(crate) struct PointerGestures {
+    pinch: ZwpPointerGesturePinchV1,
+    swipe: ZwpPointerGestureSwipeV1,
+    hold: Option<ZwpPointerGestureHoldV1>,
+}
+
+impl PointerGestures {
+    /// The ends of the gestures in progress, for a pointer going away in the middle of them.
+    pub(crate) fn cancel(&self, pointer: PointerInfo) -> Vec<(ObjectId, GestureEvent)> {
+        let mut events = Vec::new();
+        if let Some((surface, time)) = take_target(&self.pinch) {
+            let event = GestureEvent::PinchEnd {
+                pointer,
+                time,
+                cancelled: true,
+            };
+            events.push((surface, event));
+        }
+        if let Some((surface, time)) = take_target(&self.swipe) {
+            let event = GestureEvent::SwipeEnd {
+                pointer,
+                time,
+                cancelled: true,
+            };
+            events.push((surface, event));
+        }
+        if let Some((surface, time)) = self.hold.as_ref().and_then(take_target) {
+            let event = GestureEvent::HoldEnd {
+                pointer,
+                time,
+                cancelled: true,
+            };
+            events.push((surface, event));
+        }
+        events
+    }
+
+    pub(crate) fn destroy(&self) {
+        self.pinch.destroy();
+        self.swipe.destroy();
+        if let Some(hold) = self.hold.as_ref() {
+            hold.destroy();
+        }
+    }
+}
+
+/// The pointer of a gesture and the window it goes to.
+#[derive(Debug)]
+pub struct GestureData {
+    pointer_id: WlPointerId,
+    target: Mutex<GestureTarget>,
+}
+
+#[derive(Debug, Default)]
+struct GestureTarget {
+    surface: Option<ObjectId>,
+    scale: f64,
+    /// Time of the latest event of the gesture.
+    time: u64,
+}
+
+/// The window of the gesture in progress with the time of its latest event, it's over after.
+fn take_target(gesture: &impl Proxy) -> Option<(ObjectId, u64)> {
+    let mut target = gesture.data::<GestureData>()?.target.lock().unwrap();
+    let surface = target.surface.take()?;
+    Some((surface, target.time))
+}
+
+impl GestureData {
+    fn new(pointer_id: WlPointerId) -> Self {
+        Self {
+            pointer_id,
+            target: Default::default(),
+        }
+    }
+}
+
+/// The window owning the surface, which may be a decorations subsurface.
+pub(crate) fn window_surface(surface: &WlSurface) -> ObjectId {
+    surface
+        .data::<SurfaceData>()
+        .and_then(|data| data.parent_surface().map(|s| s.id()))
+        .unwrap_or(surface.id())
+}
+
+impl Dispatch<ZwpPointerGesturesV1, GlobalData, WaylandState> for PointerGesturesState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwpPointerGesturesV1,
+        _: <ZwpPointerGesturesV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+impl Dispatch<ZwpPointerGesturePinchV1, GestureData, WaylandState> for PointerGesturesState {
+    fn event(
+        state: &mut WaylandState,
+        _: &ZwpPointerGesturePinchV1,
+        event: <ZwpPointerGesturePinchV1 as Proxy>::Event,
+        data: &GestureData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        let Some(pointer) = state.seat_state.pointers.info(data.pointer_id.clone()) else {
+            return;
+        };
+        let mut target = data.target.lock().unwrap();
+        let (surface, event) = match event {
+            zwp_pointer_gesture_pinch_v1::Event::Begin {
+                time,
+                surface,
+                fingers,
+                ..
+            } => {
+                let surface = window_surface(&surface);
+                *target = GestureTarget {
+                    surface: Some(surface.clone()),
+                    scale: 1.,
+                    time: time as u64,
+                };
+                let event = GestureEvent::PinchBegin {
+                    pointer,
+                    fingers,
+                    time: time as u64,
+                };
+                (surface, event)
+            }
+            zwp_pointer_gesture_pinch_v1::Event::Update {
+                time,
+                dx,
+                dy,
+                scale,
+                rotation,
+            } => {
+                let Some(surface) = target.surface.clone() else {
+                    return;
+                };
+                let Some(window) = state.windows.get_by_object_id(&surface) else {
+                    return;
+                };
+                let scale_delta = if target.scale != 0. {
+                    scale / target.scale
+                } else {
+                    1.
+                };
+                target.scale = scale;
+                target.time = time as u64;
+                let event = GestureEvent::PinchUpdate {
+                    pointer,
+                    time: time as u64,
+                    translation: LogicalPosition::new(dx, dy).to_physical(window.scale_factor),
+                    scale_delta,
+                    rotation_delta: rotation,
+                };
+                (surface, event)
+            }
+            zwp_pointer_gesture_pinch_v1::Event::End {
+                time, cancelled, ..
+            } => {
+                let Some(surface) = target.surface.take() else {
+                    return;
+                };
+                let event = GestureEvent::PinchEnd {
+                    pointer,
+                    time: time as u64,
+                    cancelled: cancelled != 0,
+                };
+                (surface, event)
+            }
+            _ => return,
+        };
+        state.events.push_back(Events::Gesture(surface, event));
+    }
+}
+
+impl Dispatch<ZwpPointerGestureSwipeV1, GestureData, WaylandState> for PointerGesturesState {
+    fn event(
+        state: &mut WaylandState,
+        _: &ZwpPointerGestureSwipeV1,
+        event: <ZwpPointerGestureSwipeV1 as Proxy>::Event,
+        data: &GestureData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        let Some(pointer) = state.seat_state.pointers.info(data.pointer_id.clone()) else {
+            return;
+        };
+        let mut target = data.target.lock().unwrap();
+        let (surface, event) = match event {
+            zwp_pointer_gesture_swipe_v1::Event::Begin {
+                time,
+                surface,
+                fingers,
+                ..
+            } => {
+                let surface = window_surface(&surface);
+                target.surface = Some(surface.clone());
+                target.time = time as u64;
+                let event = GestureEvent::SwipeBegin {
+                    pointer,
+                    fingers,
+                    time: time as u64,
+                };
+                (surface, event)
+            }
+            zwp_pointer_gesture_swipe_v1::Event::Update { time, dx, dy } => {
+                let Some(surface) = target.surface.clone() else {
+                    return;
+                };
+                let Some(window) = state.windows.get_by_object_id(&surface) else {
+                    return;
+                };
+                target.time = time as u64;
+                let event = GestureEvent::SwipeUpdate {
+                    pointer,
+                    time: time as u64,
+                    delta: LogicalPosition::new(dx, dy).to_physical(window.scale_factor),
+                };
+                (surface, event)
+            }
+            zwp_pointer_gesture_swipe_v1::Event::End {
+                time, cancelled, ..
+            } => {
+                let Some(surface) = target.surface.take() else {
+                    return;
+                };
+                let event = GestureEvent::SwipeEnd {
+                    pointer,
+                    time: time as u64,
+                    cancelled: cancelled != 0,
+                };
+                (surface, event)
+            }
+            _ => return,
+        };
+        state.events.push_back(Events::Gesture(surface, event));
+    }
+}
+
+impl Dispatch<ZwpPointerGestureHoldV1, GestureData, WaylandState> for PointerGesturesState {
+    fn event(
+        state: &mut WaylandState,
+        _: &ZwpPointerGestureHoldV1,
+        event: <ZwpPointerGestureHoldV1 as Proxy>::Event,
+        data: &GestureData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        let Some(pointer) = state.seat_state.pointers.info(data.pointer_id.clone()) else {
+            return;
+        };
+        let mut target = data.target.lock().unwrap();
+        let (surface, event) = match event {
+            zwp_pointer_gesture_hold_v1::Event::Begin {
+                time,
+                surface,
+                fingers,
+                ..
+            } => {
+                let surface = window_surface(&surface);
+                target.surface = Some(surface.clone());
+                target.time = time as u64;
+                let event = GestureEvent::HoldBegin {
+                    pointer,
+                    fingers,
+                    time: time as u64,
+                };
+                (surface, event)
+            }
+            zwp_pointer_gesture_hold_v1::Event::End {
+                time, cancelled, ..
+            } => {
+                let Some(surface) = target.surface.take() else {
+                    return;
+                };
+                let event = GestureEvent::HoldEnd {
+                    pointer,
+                    time: time as u64,
+                    cancelled: cancelled != 0,
+                };
+                (surface, event)
+            }
+            _ => return,
+        };
+        state.events.push_back(Events::Gesture(surface, event));
+    }
+}
+
+delegate_dispatch!(WaylandState: [ZwpPointerGesturesV1: GlobalData] => PointerGesturesState);
+delegate_dispatch!(WaylandState: [ZwpPointerGesturePinchV1: GestureData] => PointerGesturesState);
+delegate_dispatch!(WaylandState: [ZwpPointerGestureSwipeV1: GestureData] => PointerGesturesState);
+delegate_dispatch!(WaylandState: [ZwpPointerGestureHoldV1: GestureData] => PointerGesturesState);
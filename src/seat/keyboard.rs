@@ -1,34 +1,374 @@
+use std::{cell::RefCell, collections::HashMap, fmt, num::NonZeroU32, rc::Rc, time::Duration};
+
 use smithay_client_toolkit::{
-    reexports::client::{
-        Connection, Proxy, QueueHandle,
-        protocol::{wl_keyboard::WlKeyboard, wl_surface::WlSurface},
+    reexports::{
+        calloop::{
+            RegistrationToken,
+            timer::{TimeoutAction, Timer},
+        },
+        client::{
+            Connection, Proxy, QueueHandle,
+            protocol::{wl_keyboard::WlKeyboard, wl_surface::WlSurface},
+        },
     },
     seat::keyboard::{
-        KeyEvent, KeyboardHandler, Keysym, Modifiers as WaylandModifiers, RawModifiers,
+        KeyEvent, KeyboardData, KeyboardHandler, Keymap, Keysym, Modifiers as WaylandModifiers,
+        RawModifiers, RepeatInfo,
     },
 };
-use tracing::error;
+use tracing::{error, warn};
 use ui_events::keyboard::{Code, Key, KeyState, KeyboardEvent, Location, Modifiers, NamedKey};
+use xkbcommon::xkb;
+
+use crate::{
+    Events, WaylandState, WindowId,
+    seat::{WlSeatId, serial::SerialKind},
+};
+
+/// The active layout of a keyboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyboardLayout {
+    /// Index of the layout in the keymap, the xkb group.
+    pub index: u32,
+    /// Name given by the keymap, like `English (US)`.
+    pub name: Option<String>,
+}
+
+/// The physical key of a keyboard event, before the layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawKey {
+    /// Linux evdev code, as sent by `wl_keyboard`.
+    pub scancode: u32,
+    /// The keysym produced by the active layout.
+    pub keysym: Keysym,
+}
+
+impl RawKey {
+    /// The xkb keycode, it's the evdev code plus 8.
+    pub fn keycode(&self) -> u32 {
+        self.scancode + 8
+    }
+}
+
+/// Progress of a dead-key or compose sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComposeState {
+    /// Keys of the unfinished sequence, like the accent of a dead key.
+    Composing(String),
+    /// The sequence produced its text, it comes with the next keyboard event.
+    Composed,
+    /// The sequence was cancelled by Escape or by a key which does not continue it.
+    Cancelled,
+}
+
+/// Sequence followed alongside the compose state of sctk, which is not exposed.
+#[derive(Debug, Default)]
+pub(crate) struct PendingCompose {
+    text: String,
+    /// Started with the Compose key, so the plain keys continue it.
+    multi: bool,
+}
+
+impl PendingCompose {
+    fn push(&mut self, keysym: Keysym) {
+        if let Some(ch) = dead_key_char(keysym).or_else(|| keysym.key_char()) {
+            self.text.push(ch);
+        }
+    }
+}
+
+/// Keymaps of the seats, compiled again since sctk keeps its xkb state private.
+pub(crate) struct Keymaps {
+    context: xkb::Context,
+    seats: HashMap<WlSeatId, SeatKeymap>,
+    /// The seat whose keyboard focused a window last.
+    current: Option<WlSeatId>,
+}
+
+#[derive(Default)]
+struct SeatKeymap {
+    keymap: Option<xkb::Keymap>,
+    active: u32,
+    modifiers: RawModifiers,
+}
+
+impl SeatKeymap {
+    fn layout(&self) -> KeyboardLayout {
+        KeyboardLayout {
+            index: self.active,
+            name: self
+                .keymap
+                .as_ref()
+                .map(|keymap| keymap.layout_get_name(self.active))
+                .filter(|name| !name.is_empty())
+                .map(str::to_owned),
+        }
+    }
+}
+
+impl Keymaps {
+    pub(crate) fn new() -> Self {
+        Self {
+            context: xkb::Context::new(xkb::CONTEXT_NO_FLAGS),
+            seats: HashMap::new(),
+            current: None,
+        }
+    }
+
+    fn seat(&mut self, seat_id: WlSeatId) -> &mut SeatKeymap {
+        self.current.get_or_insert_with(|| seat_id.clone());
+        self.seats.entry(seat_id).or_default()
+    }
+
+    fn set_keymap(&mut self, seat_id: WlSeatId, keymap: String) -> KeyboardLayout {
+        let keymap = xkb::Keymap::new_from_string(
+            &self.context,
+            keymap,
+            xkb::KEYMAP_FORMAT_TEXT_V1,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        );
+        if keymap.is_none() {
+            warn!("Failed to compile the keymap of the seat");
+        }
+        let seat = self.seat(seat_id);
+        seat.keymap = keymap;
+        seat.layout()
+    }
+
+    /// The layout, when it changed.
+    fn set_active(&mut self, seat_id: WlSeatId, active: u32) -> Option<KeyboardLayout> {
+        let seat = self.seat(seat_id);
+        (seat.active != active).then(|| {
+            seat.active = active;
+            seat.layout()
+        })
+    }
+
+    fn set_modifiers(&mut self, seat_id: WlSeatId, modifiers: RawModifiers) {
+        self.seat(seat_id).modifiers = modifiers;
+    }
+
+    /// Whether the keymap marks the key as repeating, the modifiers do not repeat.
+    fn key_repeats(&self, seat_id: &WlSeatId, raw_code: u32) -> bool {
+        self.seats
+            .get(seat_id)
+            .and_then(|seat| seat.keymap.as_ref())
+            .is_some_and(|keymap| keymap.key_repeats(xkb::Keycode::new(raw_code + 8)))
+    }
+
+    /// The key with the keysym and the text of the current modifiers, they may have changed
+    /// since the key was pressed.
+    fn refresh_key(&self, seat_id: &WlSeatId, event: &mut KeyEvent) {
+        let Some((seat, keymap)) = self
+            .seats
+            .get(seat_id)
+            .and_then(|seat| Some((seat, seat.keymap.as_ref()?)))
+        else {
+            return;
+        };
+        let mut state = xkb::State::new(keymap);
+        let RawModifiers {
+            depressed,
+            latched,
+            locked,
+        } = seat.modifiers;
+        state.update_mask(depressed, latched, locked, 0, 0, seat.active);
+        let keycode = xkb::Keycode::new(event.raw_code + 8);
+        event.keysym = state.key_get_one_sym(keycode);
+        if event.utf8.is_some() {
+            event.utf8 = Some(state.key_get_utf8(keycode));
+        }
+    }
+
+    pub(crate) fn remove(&mut self, seat_id: &WlSeatId) {
+        self.seats.remove(seat_id);
+        if self.current.as_ref() == Some(seat_id) {
+            self.current = None;
+        }
+    }
 
-use crate::{Events, WaylandState};
+    fn current(&self) -> Option<&SeatKeymap> {
+        self.current.as_ref().and_then(|id| self.seats.get(id))
+    }
+}
+
+impl fmt::Debug for Keymaps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keymaps")
+            .field("seats", &self.seats.len())
+            .field("current", &self.current)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Keys held on the focused window by seat and evdev code, released when it loses the focus.
+#[derive(Debug, Default)]
+pub(crate) struct HeldKeys {
+    seats: HashMap<WlSeatId, HashMap<u32, (KeyboardEvent, RawKey)>>,
+}
+
+impl HeldKeys {
+    fn contains(&self, seat_id: &WlSeatId, raw_code: u32) -> bool {
+        self.seats
+            .get(seat_id)
+            .is_some_and(|keys| keys.contains_key(&raw_code))
+    }
+
+    fn press(&mut self, seat_id: WlSeatId, kb_event: KeyboardEvent, raw_key: RawKey) {
+        self.seats
+            .entry(seat_id)
+            .or_default()
+            .insert(raw_key.scancode, (kb_event, raw_key));
+    }
+
+    /// Returns whether the key was held.
+    fn release(&mut self, seat_id: &WlSeatId, raw_code: u32) -> bool {
+        self.seats
+            .get_mut(seat_id)
+            .is_some_and(|keys| keys.remove(&raw_code).is_some())
+    }
+
+    /// The key-ups of the keys held on the seat.
+    fn release_seat(&mut self, seat_id: &WlSeatId) -> Vec<(KeyboardEvent, RawKey)> {
+        self.seats
+            .remove(seat_id)
+            .into_iter()
+            .flat_map(HashMap::into_values)
+            .map(key_up)
+            .collect()
+    }
+
+    /// The key-ups of the keys held on all seats.
+    fn release_all(&mut self) -> Vec<(KeyboardEvent, RawKey)> {
+        self.seats
+            .drain()
+            .flat_map(|(_, keys)| keys.into_values())
+            .map(key_up)
+            .collect()
+    }
+}
+
+/// The release of a held key, sent when the key is not released on the window.
+fn key_up((mut kb_event, raw_key): (KeyboardEvent, RawKey)) -> (KeyboardEvent, RawKey) {
+    kb_event.state = KeyState::Up;
+    kb_event.repeat = false;
+    kb_event.is_composing = false;
+    (kb_event, raw_key)
+}
+
+/// The repeat of the key pressed last on a seat, driven by a timer on the event loop.
+///
+/// The repeat of sctk can't be stopped from the outside, so the synthetic releases would leave
+/// it running.
+#[derive(Debug)]
+pub(crate) struct KeyRepeat {
+    info: RepeatInfo,
+    /// The evdev code of the repeated key and its timer.
+    current: Option<(u32, RegistrationToken)>,
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        Self {
+            // The one of sctk for the keyboards without the repeat info.
+            info: RepeatInfo::Repeat {
+                rate: NonZeroU32::new(200).unwrap(),
+                delay: 200,
+            },
+            current: None,
+        }
+    }
+}
+
+impl KeyRepeat {
+    /// Time between two repeats, `None` when the repeat is disabled.
+    fn gap(&self) -> Option<Duration> {
+        match self.info {
+            RepeatInfo::Repeat { rate, .. } => {
+                Some(Duration::from_micros(1_000_000 / u64::from(rate.get())))
+            }
+            RepeatInfo::Disable => None,
+        }
+    }
+}
+
+/// Handle to query the keymap from any handler callback, it follows the latest keyboard events.
+///
+/// The keymap is the one of the seat whose keyboard focused a window last.
+#[derive(Debug, Clone)]
+pub struct KeyboardQuery {
+    keymaps: Rc<RefCell<Keymaps>>,
+}
+
+impl KeyboardQuery {
+    pub(crate) fn new(keymaps: Rc<RefCell<Keymaps>>) -> Self {
+        Self { keymaps }
+    }
+
+    /// The active layout.
+    pub fn layout(&self) -> Option<KeyboardLayout> {
+        self.keymaps.borrow().current().map(SeatKeymap::layout)
+    }
+
+    /// Names of the layouts of the keymap, by their index.
+    pub fn layouts(&self) -> Vec<String> {
+        let keymaps = self.keymaps.borrow();
+        let Some(keymap) = keymaps.current().and_then(|seat| seat.keymap.as_ref()) else {
+            return Vec::new();
+        };
+        (0..keymap.num_layouts())
+            .map(|index| keymap.layout_get_name(index).to_owned())
+            .collect()
+    }
+
+    /// The keysym of the key in the active layout, without modifiers other than Shift.
+    ///
+    /// The keycode is the xkb one, see [`RawKey::keycode`].
+    pub fn keysym_for_keycode(&self, keycode: u32, with_shift: bool) -> Option<Keysym> {
+        let keymaps = self.keymaps.borrow();
+        let seat = keymaps.current()?;
+        let keymap = seat.keymap.as_ref()?;
+        let mut state = xkb::State::new(keymap);
+        let shift = keymap.mod_get_index(xkb::MOD_NAME_SHIFT);
+        let depressed = if with_shift && shift < u32::BITS {
+            1 << shift
+        } else {
+            0
+        };
+        state.update_mask(depressed, 0, 0, 0, 0, seat.active);
+        let keysym = state.key_get_one_sym(xkb::Keycode::new(keycode));
+        (keysym != Keysym::NoSymbol).then_some(keysym)
+    }
+}
 
 impl KeyboardHandler for WaylandState {
     fn enter(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _keyboard: &WlKeyboard,
+        keyboard: &WlKeyboard,
         surface: &WlSurface,
-        _serial: u32,
+        serial: u32,
         _raw: &[u32],
         _keysyms: &[smithay_client_toolkit::seat::keyboard::Keysym],
     ) {
         let id = surface.id();
-        if self.windows.get_mut_by_object_id(&id).is_some() {
-            if let Err(err) = self.event_sender.send(Events::Focus(id.clone(), true)) {
+        if let Some(data) = keyboard.data::<KeyboardData<WaylandState>>() {
+            self.seat_state
+                .serials
+                .record(SerialKind::KeyboardEnter, data.seat(), serial);
+        }
+        if let Some(window) = self.windows.get_mut_by_object_id(&id)
+            && let Some(data) = keyboard.data::<KeyboardData<WaylandState>>()
+        {
+            window.focus_gained(data.seat().clone());
+            self.seat_state.keymaps.borrow_mut().current = Some(data.seat().id());
+        }
+        if let Some(window_id) = self.windows.get_id(&id).copied() {
+            if let Err(err) = self.event_sender.send(Events::Focus(id, true)) {
                 error!("{err}");
             }
-            self.seat_state.keyboard_focus = Some(id);
+            self.seat_state.set_keyboard_focus(Some(window_id));
         }
     }
 
@@ -36,70 +376,349 @@ impl KeyboardHandler for WaylandState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _keyboard: &WlKeyboard,
+        keyboard: &WlKeyboard,
         surface: &WlSurface,
         _serial: u32,
     ) {
         let id = surface.id();
+        if let Some(window) = self.windows.get_mut_by_object_id(&id) {
+            window.focus_lost();
+        }
         if let Err(err) = self.event_sender.send(Events::Focus(id, false)) {
             error!("{err}");
         }
-        self.seat_state.keyboard_focus = None;
+        if let Some(data) = keyboard.data::<KeyboardData<WaylandState>>() {
+            self.release_held_keys(&data.seat().id());
+        }
+        self.seat_state.set_keyboard_focus(None);
     }
 
     fn press_key(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _keyboard: &WlKeyboard,
-        _serial: u32,
+        keyboard: &WlKeyboard,
+        serial: u32,
         event: KeyEvent,
     ) {
-        let kb_event = self.translate_event(event, KeyState::Down, false);
-        self.events.push_back(Events::Keyboard(kb_event));
+        let Some(window_id) = self.seat_state.keyboard_focus else {
+            return;
+        };
+        let Some(data) = keyboard.data::<KeyboardData<WaylandState>>() else {
+            return;
+        };
+        let seat_id = data.seat().id();
+        self.seat_state
+            .serials
+            .record(SerialKind::KeyPress, data.seat(), serial);
+        // Some compositors repeat the keys themselves with more presses.
+        if self.seat_state.held_keys.contains(&seat_id, event.raw_code) {
+            repeat_key(self, keyboard, &seat_id, event);
+            return;
+        }
+        let is_composing = self.feed_compose(keyboard, &event);
+        let (mut kb_event, raw_key) =
+            self.translate_event(keyboard, event.clone(), KeyState::Down, false);
+        kb_event.is_composing = is_composing;
+        self.seat_state
+            .held_keys
+            .press(seat_id.clone(), kb_event.clone(), raw_key);
+        self.events
+            .push_back(Events::Keyboard(window_id, kb_event, raw_key));
+        self.start_repeat(keyboard, seat_id, event);
     }
 
     fn repeat_key(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _keyboard: &WlKeyboard,
+        keyboard: &WlKeyboard,
         _serial: u32,
         event: KeyEvent,
     ) {
-        let kb_event = self.translate_event(event, KeyState::Down, true);
-        self.events.push_back(Events::Keyboard(kb_event));
+        if let Some(data) = keyboard.data::<KeyboardData<WaylandState>>() {
+            repeat_key(self, keyboard, &data.seat().id(), event);
+        }
     }
 
     fn release_key(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _keyboard: &WlKeyboard,
+        keyboard: &WlKeyboard,
         _serial: u32,
         event: KeyEvent,
     ) {
-        let kb_event = self.translate_event(event, KeyState::Up, false);
-        self.events.push_back(Events::Keyboard(kb_event));
+        let Some(data) = keyboard.data::<KeyboardData<WaylandState>>() else {
+            return;
+        };
+        let seat_id = data.seat().id();
+        // Already released on the focus loss.
+        if !self.seat_state.held_keys.release(&seat_id, event.raw_code) {
+            return;
+        }
+        if self
+            .seat_state
+            .repeats
+            .get(&seat_id)
+            .and_then(|repeat| repeat.current)
+            .is_some_and(|(raw_code, _)| raw_code == event.raw_code)
+        {
+            self.stop_repeat(&seat_id);
+        }
+        let Some(window_id) = self.seat_state.keyboard_focus else {
+            return;
+        };
+        let (kb_event, raw_key) = self.translate_event(keyboard, event, KeyState::Up, false);
+        self.events
+            .push_back(Events::Keyboard(window_id, kb_event, raw_key));
     }
 
     fn update_modifiers(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _keyboard: &WlKeyboard,
+        keyboard: &WlKeyboard,
         _serial: u32,
-        _modifiers: WaylandModifiers,
+        wayland_modifiers: WaylandModifiers,
         raw_modifiers: RawModifiers,
-        _layout: u32,
+        layout: u32,
     ) {
-        let combined = raw_modifiers.depressed | raw_modifiers.latched | raw_modifiers.locked;
-        self.seat_state.modifiers = Modifiers::from_bits_truncate(combined);
+        if let Some(data) = keyboard.data::<KeyboardData<WaylandState>>() {
+            let seat_id = data.seat().id();
+            self.seat_state
+                .keymaps
+                .borrow_mut()
+                .set_modifiers(seat_id.clone(), raw_modifiers);
+            let modifiers = from_wayland_modifiers(wayland_modifiers);
+            // The locks change without a focused window too.
+            if self
+                .seat_state
+                .set_seat_modifiers(seat_id.clone(), modifiers)
+            {
+                let window_id = self.seat_state.keyboard_focus;
+                self.events
+                    .push_back(Events::Modifiers(window_id, modifiers));
+            }
+            let layout = self
+                .seat_state
+                .keymaps
+                .borrow_mut()
+                .set_active(seat_id, layout);
+            if let Some(layout) = layout {
+                self.events.push_back(Events::KeymapChanged(layout));
+            }
+        }
+    }
+
+    fn update_keymap(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        keyboard: &WlKeyboard,
+        keymap: Keymap<'_>,
+    ) {
+        let Some(data) = keyboard.data::<KeyboardData<WaylandState>>() else {
+            return;
+        };
+        let layout = self
+            .seat_state
+            .keymaps
+            .borrow_mut()
+            .set_keymap(data.seat().id(), keymap.as_string());
+        self.events.push_back(Events::KeymapChanged(layout));
+    }
+
+    fn update_repeat_info(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        keyboard: &WlKeyboard,
+        info: RepeatInfo,
+    ) {
+        // The repeated key follows the new rate from its next repeat.
+        if let Some(data) = keyboard.data::<KeyboardData<WaylandState>>() {
+            let seat_id = data.seat().id();
+            self.seat_state
+                .repeats
+                .entry(seat_id.clone())
+                .or_default()
+                .info = info;
+            if matches!(info, RepeatInfo::Disable) {
+                self.stop_repeat(&seat_id);
+            }
+        }
     }
 }
 
+/// Key repeated by the timer or the compositor, it's marked with the `repeat` flag.
+///
+/// Returns whether the key is still held on the focused window.
+fn repeat_key(
+    state: &mut WaylandState,
+    keyboard: &WlKeyboard,
+    seat_id: &WlSeatId,
+    mut event: KeyEvent,
+) -> bool {
+    let Some(window_id) = state.seat_state.keyboard_focus else {
+        return false;
+    };
+    if !state.seat_state.held_keys.contains(seat_id, event.raw_code) {
+        return false;
+    }
+    state
+        .seat_state
+        .keymaps
+        .borrow()
+        .refresh_key(seat_id, &mut event);
+    let (kb_event, raw_key) = state.translate_event(keyboard, event, KeyState::Down, true);
+    state
+        .events
+        .push_back(Events::Keyboard(window_id, kb_event, raw_key));
+    true
+}
+
 impl WaylandState {
-    fn translate_event(&self, event: KeyEvent, state: KeyState, repeat: bool) -> KeyboardEvent {
+    /// Repeat the pressed key after the delay, in place of the key repeated before.
+    fn start_repeat(&mut self, keyboard: &WlKeyboard, seat_id: WlSeatId, event: KeyEvent) {
+        // The modifiers keep the previous key repeating.
+        if !self
+            .seat_state
+            .keymaps
+            .borrow()
+            .key_repeats(&seat_id, event.raw_code)
+        {
+            return;
+        }
+        self.stop_repeat(&seat_id);
+        let repeat = self.seat_state.repeats.entry(seat_id.clone()).or_default();
+        let RepeatInfo::Repeat { delay, .. } = repeat.info else {
+            return;
+        };
+        let raw_code = event.raw_code;
+        let keyboard = keyboard.clone();
+        let timer_seat = seat_id.clone();
+        let mut event = event;
+        event.time = event.time.wrapping_add(delay);
+        let timer = Timer::from_duration(Duration::from_millis(u64::from(delay)));
+        let inserted = self.loop_handle.insert_source(timer, move |_, _, state| {
+            // The rate is read every time, the compositor may change it while the key is held.
+            let gap = state
+                .seat_state
+                .repeats
+                .get(&timer_seat)
+                .and_then(KeyRepeat::gap);
+            match gap {
+                Some(gap) if repeat_key(state, &keyboard, &timer_seat, event.clone()) => {
+                    event.time = event.time.wrapping_add(gap.as_millis() as u32);
+                    TimeoutAction::ToDuration(gap)
+                }
+                _ => {
+                    if let Some(repeat) = state.seat_state.repeats.get_mut(&timer_seat) {
+                        repeat.current = None;
+                    }
+                    TimeoutAction::Drop
+                }
+            }
+        });
+        match inserted {
+            Ok(token) => repeat.current = Some((raw_code, token)),
+            Err(err) => error!("Failed to repeat the key: {err}"),
+        }
+    }
+
+    /// Stop the key repeat of the seat.
+    pub(crate) fn stop_repeat(&mut self, seat_id: &WlSeatId) {
+        let current = self
+            .seat_state
+            .repeats
+            .get_mut(seat_id)
+            .and_then(|repeat| repeat.current.take());
+        if let Some((_, token)) = current {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    /// Release the keys held on the focused window by the seat, for the focus loss.
+    pub(crate) fn release_held_keys(&mut self, seat_id: &WlSeatId) {
+        self.stop_repeat(seat_id);
+        let released = self.seat_state.held_keys.release_seat(seat_id);
+        let Some(window_id) = self.seat_state.keyboard_focus else {
+            return;
+        };
+        for (kb_event, raw_key) in released {
+            self.events
+                .push_back(Events::Keyboard(window_id, kb_event, raw_key));
+        }
+    }
+
+    /// Release the keys held on the window about to be destroyed, they are given right away
+    /// since the events can't reach the window anymore.
+    pub(crate) fn take_held_keys(&mut self, window_id: WindowId) -> Vec<(KeyboardEvent, RawKey)> {
+        if self.seat_state.keyboard_focus != Some(window_id) {
+            return Vec::new();
+        }
+        let seats: Vec<_> = self.seat_state.repeats.keys().cloned().collect();
+        for seat_id in seats {
+            self.stop_repeat(&seat_id);
+        }
+        self.seat_state.held_keys.release_all()
+    }
+
+    /// Follows the compose sequence of a pressed key, returns whether the key is part of one.
+    ///
+    /// sctk only gives the text once the sequence is done, so the pending keys are tracked here.
+    fn feed_compose(&mut self, keyboard: &WlKeyboard, event: &KeyEvent) -> bool {
+        let Some(data) = keyboard.data::<KeyboardData<WaylandState>>() else {
+            return false;
+        };
+        let seat_id = data.seat().id();
+        let keysym = event.keysym;
+        let starts = is_dead_key(keysym) || keysym == Keysym::Multi_key;
+        let state = match self.seat_state.compose.get_mut(&seat_id) {
+            // Without a compose table the dead keys give their text right away.
+            None if !starts || event.utf8.is_some() => return false,
+            None => {
+                let mut pending = PendingCompose {
+                    multi: keysym == Keysym::Multi_key,
+                    ..Default::default()
+                };
+                pending.push(keysym);
+                let state = ComposeState::Composing(pending.text.clone());
+                self.seat_state.compose.insert(seat_id, pending);
+                state
+            }
+            Some(_) if keysym.is_modifier_key() => return true,
+            Some(_) if event.utf8.is_some() => {
+                self.seat_state.compose.remove(&seat_id);
+                ComposeState::Composed
+            }
+            Some(pending) if (starts || pending.multi) && keysym != Keysym::Escape => {
+                pending.push(keysym);
+                ComposeState::Composing(pending.text.clone())
+            }
+            Some(_) => {
+                self.seat_state.compose.remove(&seat_id);
+                ComposeState::Cancelled
+            }
+        };
+        if let Some(window_id) = self.seat_state.keyboard_focus {
+            self.events.push_back(Events::Compose(window_id, state));
+        }
+        true
+    }
+
+    fn translate_event(
+        &self,
+        keyboard: &WlKeyboard,
+        event: KeyEvent,
+        state: KeyState,
+        repeat: bool,
+    ) -> (KeyboardEvent, RawKey) {
+        let raw_key = RawKey {
+            scancode: event.raw_code,
+            keysym: event.keysym,
+        };
         let key = if let Some(key) = event.utf8 {
             Key::Character(key)
         } else {
@@ -117,18 +736,34 @@ impl WaylandState {
             _ => Location::Standard,
         };
 
-        KeyboardEvent {
+        let kb_event = KeyboardEvent {
             state,
             key,
             code,
             location,
-            modifiers: self.seat_state.modifiers,
+            modifiers: keyboard
+                .data::<KeyboardData<WaylandState>>()
+                .map(|data| self.seat_state.seat_modifiers(&data.seat().id()))
+                .unwrap_or_default(),
             repeat,
             is_composing: false,
-        }
+        };
+        (kb_event, raw_key)
     }
 }
 
+/// The effective modifiers, so the latched and locked ones are included.
+fn from_wayland_modifiers(modifiers: WaylandModifiers) -> Modifiers {
+    let mut result = Modifiers::empty();
+    result.set(Modifiers::CONTROL, modifiers.ctrl);
+    result.set(Modifiers::ALT, modifiers.alt);
+    result.set(Modifiers::SHIFT, modifiers.shift);
+    result.set(Modifiers::META, modifiers.logo);
+    result.set(Modifiers::CAPS_LOCK, modifiers.caps_lock);
+    result.set(Modifiers::NUM_LOCK, modifiers.num_lock);
+    result
+}
+
 #[inline(always)]
 fn from_wayland_key(keysym: Keysym) -> NamedKey {
     match keysym {
@@ -330,10 +965,35 @@ fn from_wayland_key(keysym: Keysym) -> NamedKey {
         Keysym::F34 => NamedKey::F34,
         Keysym::F35 => NamedKey::F35,
 
+        keysym if is_dead_key(keysym) => NamedKey::Dead,
         _ => NamedKey::Unidentified,
     }
 }
 
+fn is_dead_key(keysym: Keysym) -> bool {
+    (Keysym::dead_grave.raw()..=Keysym::dead_longsolidusoverlay.raw()).contains(&keysym.raw())
+}
+
+/// Spacing form of the accent of a dead key, to show the pending sequence.
+fn dead_key_char(keysym: Keysym) -> Option<char> {
+    Some(match keysym {
+        Keysym::dead_grave => '`',
+        Keysym::dead_acute => '´',
+        Keysym::dead_circumflex => '^',
+        Keysym::dead_tilde => '~',
+        Keysym::dead_macron => '¯',
+        Keysym::dead_breve => '˘',
+        Keysym::dead_abovedot => '˙',
+        Keysym::dead_diaeresis => '¨',
+        Keysym::dead_abovering => '˚',
+        Keysym::dead_doubleacute => '˝',
+        Keysym::dead_caron => 'ˇ',
+        Keysym::dead_cedilla => '¸',
+        Keysym::dead_ogonek => '˛',
+        _ => return None,
+    })
+}
+
 #[inline(always)]
 fn from_wayland_code(code: u32) -> Code {
     match code {
@@ -422,16 +1082,16 @@ fn from_wayland_code(code: u32) -> Code {
         82 => Code::Numpad0,
         83 => Code::NumpadDecimal,
 
-        // 85 => Code::ZenkakuHankaku,
+        85 => Code::Lang5,
         86 => Code::IntlBackslash,
         87 => Code::F11,
         88 => Code::F12,
-        // 89 => Code::Ro,
+        89 => Code::IntlRo,
         90 => Code::Katakana,
         91 => Code::Hiragana,
-        // 92 => Code::Henkan,
-        // 93 => Code::KatakanaHiragana,
-        // 94 => Code::Muhenkan,
+        92 => Code::Convert,
+        93 => Code::KanaMode,
+        94 => Code::NonConvert,
         95 => Code::NumpadComma,
         96 => Code::NumpadEnter,
         97 => Code::ControlRight,
@@ -451,21 +1111,22 @@ fn from_wayland_code(code: u32) -> Code {
         111 => Code::Delete,
 
         // 112 => Code::Macro,
-        // 113 => Code::Mute,
-        // 114 => Code::VolumeDown,
-        // 115 => Code::VolumeUp,
+        113 => Code::AudioVolumeMute,
+        114 => Code::AudioVolumeDown,
+        115 => Code::AudioVolumeUp,
         116 => Code::Power,
         117 => Code::NumpadEqual,
         // 118 => Code::NumpadPlusMinus,
         119 => Code::Pause,
-        // 120 => Code::Scale,
+        120 => Code::ShowAllWindows,
+        121 => Code::NumpadComma,
 
-        // 122 => Code::Hangeul,
-        // 123 => Code::Hanja,
-        // 124 => Code::Yen,
+        122 => Code::Lang1,
+        123 => Code::Lang2,
+        124 => Code::IntlYen,
         125 => Code::MetaLeft,
         126 => Code::MetaRight,
-        // 127 => Code::Compose,
+        127 => Code::ContextMenu,
 
         // 128 => Code::Stop,
         129 => Code::Again,
@@ -478,8 +1139,8 @@ fn from_wayland_code(code: u32) -> Code {
         136 => Code::Find,
         137 => Code::Cut,
         138 => Code::Help,
-        139 => Code::ContextMenu,
-        // 140 => Code::Calculator,
+        // 139 => Code::Menu, the context menu key of the PC keyboards is KEY_COMPOSE above.
+        140 => Code::LaunchApp2,
         // 141 => Code::Setup,
         142 => Code::Sleep,
         143 => Code::WakeUp,
@@ -494,25 +1155,25 @@ fn from_wayland_code(code: u32) -> Code {
         // 152 => Code::Coffee,
         // 153 => Code::RotateDisplay,
         // 154 => Code::CycleWindows,
-        // 155 => Code::Mail,
-        // 156 => Code::Bookmarks,
-        // 157 => Code::Computer,
-        // 158 => Code::Back,
-        // 159 => Code::Forward,
+        155 => Code::LaunchMail,
+        156 => Code::BrowserFavorites,
+        157 => Code::LaunchApp1,
+        158 => Code::BrowserBack,
+        159 => Code::BrowserForward,
         // 160 => Code::CloseCD,
-        // 161 => Code::EjectCD,
-        // 162 => Code::EjectCloseCD,
-        // 163 => Code::NextSong,
-        // 164 => Code::PlayPause,
-        // 165 => Code::PreviousSong,
-        // 166 => Code::StopCD,
-        // 167 => Code::Record,
-        // 168 => Code::Rewind,
+        161 => Code::Eject,
+        162 => Code::Eject,
+        163 => Code::MediaTrackNext,
+        164 => Code::MediaPlayPause,
+        165 => Code::MediaTrackPrevious,
+        166 => Code::MediaStop,
+        167 => Code::MediaRecord,
+        168 => Code::MediaRewind,
         // 169 => Code::Phone,
         // 170 => Code::ISO,
         // 171 => Code::Config,
-        // 172 => Code::HomePage,
-        // 173 => Code::Refresh,
+        172 => Code::BrowserHome,
+        173 => Code::BrowserRefresh,
         // 174 => Code::Exit,
         // 175 => Code::Move,
         // 176 => Code::Edit,
@@ -535,15 +1196,15 @@ fn from_wayland_code(code: u32) -> Code {
         193 => Code::F23,
         194 => Code::F24,
 
-        // 200 => Code::PlayCD,
-        // 201 => Code::PauseCD,
+        200 => Code::MediaPlay,
+        201 => Code::MediaPause,
         // 202 => Code::Prog3,
         // 203 => Code::Prog4,
         // 204 => Code::AllApplications,
         205 => Code::Suspend,
         // 206 => Code::Close,
         // 207 => Code::Play,
-        // 208 => Code::FastForward,
+        208 => Code::MediaFastForward,
         // 209 => Code::BassBoost,
         // 210 => Code::Print,
         // 211 => Code::Hp,
@@ -552,23 +1213,23 @@ fn from_wayland_code(code: u32) -> Code {
         // 214 => Code::Question,
         // 215 => Code::Email,
         // 216 => Code::Chat,
-        // 217 => Code::Search,
+        217 => Code::BrowserSearch,
         // 218 => Code::Connect,
         // 219 => Code::Finance,
         // 220 => Code::Sport,
         // 221 => Code::Shop,
         // 222 => Code::AltErase,
-        // 223 => Code::Cancel,
+        223 => Code::Abort,
         224 => Code::BrightnessDown,
         225 => Code::BrightnessUp,
-        // 226 => Code::Media,
-        // 227 => Code::SwitchVideoMode,
-        // 228 => Code::KbdIllumToggle,
+        226 => Code::MediaSelect,
+        227 => Code::DisplayToggleIntExt,
+        228 => Code::KeyboardBacklightToggle,
         // 229 => Code::KbdIllumDown,
         // 230 => Code::KbdIllumUp,
-        // 231 => Code::Send,
-        // 232 => Code::Reply,
-        // 233 => Code::ForwardMail,
+        231 => Code::MailSend,
+        232 => Code::MailReply,
+        233 => Code::MailForward,
         // 234 => Code::Save,
         // 235 => Code::Documents,
         // 236 => Code::Battery,
@@ -584,7 +1245,109 @@ fn from_wayland_code(code: u32) -> Code {
         // 245 => Code::DisplayOff,
         // 246 => Code::WWAN,
         // 247 => Code::RfKill,
-        // 248 => Code::MicMute,
+        248 => Code::MicrophoneMuteToggle,
+        353 => Code::Select,
+        420 => Code::ZoomToggle,
+        464 => Code::Fn,
+        579 => Code::LaunchControlPanel,
+        581 => Code::LaunchScreenSaver,
+        583 => Code::LaunchAssistant,
+        584 => Code::KeyboardLayoutSelect,
+        633 => Code::PrivacyScreenToggle,
         _ => Code::Unidentified,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use smithay_client_toolkit::reexports::client::backend::ObjectId;
+
+    use super::*;
+
+    fn key_down(scancode: u32, keysym: Keysym) -> (KeyboardEvent, RawKey) {
+        let kb_event = KeyboardEvent {
+            state: KeyState::Down,
+            key: Key::Character(String::from("a")),
+            code: from_wayland_code(scancode),
+            location: Location::Standard,
+            modifiers: Modifiers::empty(),
+            repeat: true,
+            is_composing: true,
+        };
+        (kb_event, RawKey { scancode, keysym })
+    }
+
+    /// The lowest evdev code of the code, the codes of the aliases are not found.
+    fn to_wayland_code(code: Code) -> Option<u32> {
+        (0..=KEY_MAX).find(|evdev| from_wayland_code(*evdev) == code)
+    }
+
+    const KEY_MAX: u32 = 0x2ff;
+
+    #[test]
+    fn evdev_codes_round_trip() {
+        for evdev in [
+            1, 14, 28, 30, 42, 57, 59, 71, 86, 87, 89, 96, 100, 103, 113, 119, 124, 125, 127, 130,
+            138, 155, 164, 183, 224, 248, 464, 633,
+        ] {
+            let code = from_wayland_code(evdev);
+            assert_ne!(code, Code::Unidentified, "evdev code {evdev}");
+            assert_eq!(to_wayland_code(code), Some(evdev), "{code:?}");
+        }
+    }
+
+    #[test]
+    fn evdev_codes_are_distinct() {
+        // The keys which are the same for the applications.
+        let aliases = [(121, Code::NumpadComma), (162, Code::Eject)];
+        for evdev in 0..=KEY_MAX {
+            let code = from_wayland_code(evdev);
+            if code == Code::Unidentified || aliases.contains(&(evdev, code)) {
+                continue;
+            }
+            assert_eq!(to_wayland_code(code), Some(evdev), "{code:?}");
+        }
+    }
+
+    #[test]
+    fn repeat_gap_follows_the_rate() {
+        let repeat = KeyRepeat {
+            info: RepeatInfo::Repeat {
+                rate: NonZeroU32::new(25).unwrap(),
+                delay: 600,
+            },
+            current: None,
+        };
+        assert_eq!(repeat.gap(), Some(Duration::from_millis(40)));
+        let disabled = KeyRepeat {
+            info: RepeatInfo::Disable,
+            current: None,
+        };
+        assert_eq!(disabled.gap(), None);
+    }
+
+    #[test]
+    fn focus_loss_releases_the_held_keys() {
+        let seat = ObjectId::null();
+        let mut held = HeldKeys::default();
+        for (scancode, keysym) in [(30, Keysym::a), (31, Keysym::s)] {
+            let (kb_event, raw_key) = key_down(scancode, keysym);
+            held.press(seat.clone(), kb_event, raw_key);
+        }
+        assert!(held.release(&seat, 31));
+        assert!(!held.release(&seat, 31));
+
+        let released = held.release_seat(&seat);
+        assert_eq!(released.len(), 1);
+        let (kb_event, raw_key) = &released[0];
+        assert_eq!(raw_key.scancode, 30);
+        assert_eq!(kb_event.state, KeyState::Up);
+        assert!(!kb_event.repeat);
+        assert!(!kb_event.is_composing);
+
+        // The compositor releases the key later, it was already released.
+        assert!(!held.contains(&seat, 30));
+        assert!(!held.release(&seat, 30));
+        assert!(held.release_all().is_empty());
+    }
+}
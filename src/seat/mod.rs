@@ -1,63 +1,152 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc, sync::atomic::Ordering};
 
 use cursor_icon::CursorIcon;
 use smithay_client_toolkit::{
-    reexports::client::{
-        Connection, Proxy, QueueHandle,
-        backend::ObjectId,
-        protocol::{wl_keyboard::WlKeyboard, wl_seat::WlSeat, wl_touch::WlTouch},
+    data_device_manager::data_device::DataDevice,
+    reexports::{
+        client::{
+            Connection, Proxy, QueueHandle,
+            backend::ObjectId,
+            protocol::{
+                wl_keyboard::WlKeyboard, wl_seat::WlSeat, wl_surface::WlSurface, wl_touch::WlTouch,
+            },
+        },
+        protocols::wp::{
+            tablet::zv2::client::zwp_tablet_tool_v2::ZwpTabletToolV2,
+            text_input::zv3::client::zwp_text_input_v3::ZwpTextInputV3,
+        },
     },
     seat::{
         Capability, SeatHandler, SeatState as WlSeatState,
-        pointer::{PointerData, ThemeSpec, ThemedPointer},
+        pointer::{PointerData, PointerThemeError, ThemeSpec, ThemedPointer},
         touch::TouchData,
     },
 };
 use tracing::{error, warn};
 use ui_events::{
     keyboard::Modifiers,
-    pointer::{PointerEvent, PointerId, PointerInfo, PointerType},
+    pointer::{
+        PersistentDeviceId, PointerButtons, PointerEvent, PointerId, PointerInfo, PointerState,
+        PointerType,
+    },
 };
 
-use crate::{Events, WaylandState};
+use crate::{
+    Events, WaylandState, WindowId,
+    event_loop::FOCUSED_WINDOW,
+    seat::{
+        gesture::PointerGestures,
+        keyboard::{HeldKeys, KeyRepeat, Keymaps, PendingCompose},
+        serial::SerialTracker,
+        tablet::{TabletDevices, TabletToolData},
+    },
+};
 
+pub mod constraints;
+pub mod gesture;
 pub mod keyboard;
 pub mod pointer;
+pub mod serial;
+pub mod shortcuts_inhibit;
+pub mod tablet;
+pub mod text_input;
 pub mod touch;
 
 pub type WlSeatId = ObjectId;
 pub type WlPointerId = ObjectId;
+pub type WlTouchId = ObjectId;
+
+/// Pressure of a contact from a device without a pressure axis, as in the web pointer events.
+pub(crate) const DEFAULT_PRESSURE: f32 = 0.5;
+
+/// Cursor theme replacing the one from the `XCURSOR_THEME` and `XCURSOR_SIZE` variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorTheme {
+    pub name: String,
+    /// Size at the scale factor 1, it's multiplied on HiDPI outputs.
+    pub size: u32,
+}
 
 #[derive(Debug)]
 pub struct SeatState {
     /// The seat state responsible for all sorts of input.
     pub seat: WlSeatState,
-    pub modifiers: Modifiers,
+    /// Keyboard modifiers of every seat.
+    pub modifiers: HashMap<WlSeatId, Modifiers>,
     pub pointers: PointerRegistry,
-    pub keyboard: Option<WlKeyboard>,
-    pub keyboard_focus: Option<ObjectId>,
+    pub keyboards: HashMap<WlSeatId, WlKeyboard>,
+    /// The window with the keyboard focus.
+    pub(crate) keyboard_focus: Option<WindowId>,
+    /// The theme of the pointers, the system one when `None`.
+    pub(crate) cursor_theme: Option<CursorTheme>,
+    pub(crate) tablets: TabletDevices,
+    /// Keymaps of every seat, shared with the [`keyboard::KeyboardQuery`] handles.
+    pub(crate) keymaps: Rc<RefCell<Keymaps>>,
+    /// Unfinished compose sequence of every seat.
+    pub(crate) compose: HashMap<WlSeatId, PendingCompose>,
+    pub(crate) text_inputs: HashMap<WlSeatId, ZwpTextInputV3>,
+    pub(crate) held_keys: HeldKeys,
+    /// Key repeat of every seat.
+    pub(crate) repeats: HashMap<WlSeatId, KeyRepeat>,
+    pub(crate) data_devices: HashMap<WlSeatId, DataDevice>,
+    /// The window under the drag of every seat.
+    pub(crate) drags: HashMap<WlSeatId, ObjectId>,
+    /// Latest serials of the inputs, for the requests which need one.
+    pub(crate) serials: SerialTracker,
 }
 
 impl SeatState {
     pub fn new(state: WlSeatState) -> Self {
         Self {
             seat: state,
-            modifiers: Modifiers::default(),
+            modifiers: HashMap::new(),
             pointers: PointerRegistry::default(),
-            keyboard: None,
+            keyboards: HashMap::new(),
             keyboard_focus: None,
+            cursor_theme: None,
+            tablets: TabletDevices::default(),
+            keymaps: Rc::new(RefCell::new(Keymaps::new())),
+            compose: HashMap::new(),
+            text_inputs: HashMap::new(),
+            held_keys: HeldKeys::default(),
+            repeats: HashMap::new(),
+            data_devices: HashMap::new(),
+            drags: HashMap::new(),
+            serials: SerialTracker::default(),
         }
     }
+
+    pub(crate) fn set_keyboard_focus(&mut self, window_id: Option<WindowId>) {
+        self.keyboard_focus = window_id;
+        FOCUSED_WINDOW.store(window_id.map_or(0, |id| id.0.get()), Ordering::Release);
+    }
+
+    /// Modifiers currently active on the given seat.
+    pub fn seat_modifiers(&self, seat_id: &WlSeatId) -> Modifiers {
+        self.modifiers.get(seat_id).copied().unwrap_or_default()
+    }
+
+    /// Record the modifiers of the seat, the events of its devices carry them from now on.
+    ///
+    /// Returns whether they changed.
+    pub(crate) fn set_seat_modifiers(&mut self, seat_id: WlSeatId, modifiers: Modifiers) -> bool {
+        self.modifiers
+            .insert(seat_id, modifiers)
+            .unwrap_or_default()
+            != modifiers
+    }
 }
 
 #[derive(Debug)]
 pub enum PointerKind {
     Mouse(ThemedPointer),
     Touch(WlTouch),
+    /// A tablet tool, there may be several of them on a seat.
+    Pen(ZwpTabletToolV2),
 }
 
 impl PointerKind {
-    pub fn set(&self) -> Result<(), String> {
+    pub fn hide_cursor(&self) -> Result<(), String> {
         match self {
             PointerKind::Mouse(themed_pointer) => {
                 themed_pointer.hide_cursor().map_err(|err| err.to_string())
@@ -66,13 +155,18 @@ impl PointerKind {
         }
     }
 
+    /// Set the icon, or the closest one the theme has.
     pub fn set_cursor(&self, conn: &Connection, icon: CursorIcon) -> Result<(), String> {
-        match self {
-            PointerKind::Mouse(themed_pointer) => themed_pointer
-                .set_cursor(conn, icon)
-                .map_err(|err| err.to_string()),
-            _ => Err(String::from("Icons unsupported for touch")),
+        let PointerKind::Mouse(themed_pointer) = self else {
+            return Err(String::from("Icons unsupported for touch"));
+        };
+        for icon in cursor_fallbacks(icon) {
+            match themed_pointer.set_cursor(conn, icon) {
+                Err(PointerThemeError::CursorNotFound) => continue,
+                result => return result.map_err(|err| err.to_string()),
+            }
         }
+        Err(PointerThemeError::CursorNotFound.to_string())
     }
 
     pub fn latest_serial(&self) -> Option<u32> {
@@ -85,6 +179,10 @@ impl PointerKind {
                 let data = wl_touch.data::<TouchData>()?;
                 data.latest_down_serial()
             }
+            PointerKind::Pen(tool) => {
+                let data = tool.data::<TabletToolData>()?;
+                data.latest_down_serial()
+            }
         }
     }
 
@@ -98,14 +196,124 @@ impl PointerKind {
                 let data = wl_touch.data::<TouchData>()?;
                 Some(data.seat())
             }
+            PointerKind::Pen(tool) => {
+                let data = tool.data::<TabletToolData>()?;
+                data.seat()
+            }
         }
     }
 }
 
+/// Icons tried in order when the theme lacks the key one, before the default icon.
+const CURSOR_FALLBACKS: &[(CursorIcon, &[CursorIcon])] = &[
+    (
+        CursorIcon::NwseResize,
+        &[CursorIcon::SeResize, CursorIcon::NwResize],
+    ),
+    (
+        CursorIcon::NeswResize,
+        &[CursorIcon::NeResize, CursorIcon::SwResize],
+    ),
+    (
+        CursorIcon::EwResize,
+        &[
+            CursorIcon::EResize,
+            CursorIcon::WResize,
+            CursorIcon::ColResize,
+        ],
+    ),
+    (
+        CursorIcon::NsResize,
+        &[
+            CursorIcon::NResize,
+            CursorIcon::SResize,
+            CursorIcon::RowResize,
+        ],
+    ),
+    (
+        CursorIcon::ColResize,
+        &[CursorIcon::EwResize, CursorIcon::EResize],
+    ),
+    (
+        CursorIcon::RowResize,
+        &[CursorIcon::NsResize, CursorIcon::SResize],
+    ),
+    (CursorIcon::NResize, &[CursorIcon::NsResize]),
+    (CursorIcon::SResize, &[CursorIcon::NsResize]),
+    (CursorIcon::EResize, &[CursorIcon::EwResize]),
+    (CursorIcon::WResize, &[CursorIcon::EwResize]),
+    (CursorIcon::NeResize, &[CursorIcon::NeswResize]),
+    (CursorIcon::SwResize, &[CursorIcon::NeswResize]),
+    (CursorIcon::NwResize, &[CursorIcon::NwseResize]),
+    (CursorIcon::SeResize, &[CursorIcon::NwseResize]),
+    (CursorIcon::AllScroll, &[CursorIcon::Move]),
+    (
+        CursorIcon::Move,
+        &[CursorIcon::AllScroll, CursorIcon::Grabbing],
+    ),
+    (CursorIcon::Grab, &[CursorIcon::Pointer]),
+    (CursorIcon::Grabbing, &[CursorIcon::Grab, CursorIcon::Move]),
+    (CursorIcon::Progress, &[CursorIcon::Wait]),
+    (CursorIcon::Wait, &[CursorIcon::Progress]),
+    (CursorIcon::NoDrop, &[CursorIcon::NotAllowed]),
+    (CursorIcon::NotAllowed, &[CursorIcon::NoDrop]),
+    (CursorIcon::DndAsk, &[CursorIcon::Copy]),
+    (CursorIcon::Alias, &[CursorIcon::Copy]),
+    (CursorIcon::VerticalText, &[CursorIcon::Text]),
+    (CursorIcon::Cell, &[CursorIcon::Crosshair]),
+    (CursorIcon::Help, &[CursorIcon::Pointer]),
+];
+
+/// The icon followed by its fallbacks, ending with the default icon.
+fn cursor_fallbacks(icon: CursorIcon) -> impl Iterator<Item = CursorIcon> {
+    let fallbacks = CURSOR_FALLBACKS
+        .iter()
+        .find(|(key, _)| *key == icon)
+        .map_or(&[][..], |(_, fallbacks)| *fallbacks);
+    std::iter::once(icon)
+        .chain(fallbacks.iter().copied())
+        .chain((icon != CursorIcon::Default).then_some(CursorIcon::Default))
+}
+
+/// A touch point which is currently down.
+#[derive(Debug, Clone)]
+pub(crate) struct TouchPoint {
+    /// The window surface the touch went down on.
+    pub(crate) surface: ObjectId,
+    pub(crate) info: PointerInfo,
+    pub(crate) state: PointerState,
+    /// Position of the subsurface the touch went down on, the positions are moved to the window.
+    pub(crate) offset: (f64, f64),
+    /// Set when the touch went down on the decorations, it's not reported to the application.
+    pub(crate) frame: Option<FrameTouch>,
+}
+
+/// A touch point on the decorations frame.
+#[derive(Debug, Clone)]
+pub(crate) struct FrameTouch {
+    /// The decorations subsurface.
+    pub(crate) surface: ObjectId,
+    /// Last position relative to the subsurface.
+    pub(crate) position: (f64, f64),
+    /// Serial and position of the press on the titlebar, the move starts once the finger is
+    /// dragged away.
+    pub(crate) pending_move: Option<(u32, (f64, f64))>,
+    /// Time of the touch down.
+    pub(crate) time: u32,
+}
+
 #[derive(Debug, Default)]
 pub struct PointerRegistry {
-    by_seat: HashMap<WlSeatId, (WlPointerId, Rc<PointerKind>)>,
+    /// The mice and the touch devices of the seats, a seat may have one of each.
+    devices: HashMap<WlPointerId, Rc<PointerKind>>,
     by_pointer: HashMap<WlPointerId, (WlSeatId, PointerInfo)>,
+    /// Touch points by touch device and touch id.
+    pub(crate) touches: HashMap<(WlTouchId, i32), TouchPoint>,
+    /// Tablet tools, they come and go independently of the seat capabilities.
+    tools: HashMap<WlPointerId, Rc<PointerKind>>,
+    gestures: HashMap<WlPointerId, PointerGestures>,
+    /// Buttons held on the window under each pointer.
+    buttons: HashMap<WlPointerId, PointerButtons>,
 }
 
 impl PointerRegistry {
@@ -116,30 +324,159 @@ impl PointerRegistry {
         pointer: PointerKind,
         info: PointerInfo,
     ) {
-        self.by_seat
-            .insert(seat_id.clone(), (pointer_id.clone(), Rc::new(pointer)));
-        self.by_pointer.insert(pointer_id.clone(), (seat_id, info));
-    }
-
-    pub fn remove(&mut self, seat_id: ObjectId) -> Option<PointerInfo> {
-        let pointer = self.by_seat.remove(&seat_id);
-        if let Some((id, pointer)) = pointer {
-            let _ = self.by_pointer.remove(&id);
-            match pointer.as_ref() {
-                PointerKind::Mouse(wl_pointer) => {
-                    wl_pointer.pointer().release();
-                    // TODO: do we need destroy pointer surface this way?
-                    wl_pointer.surface().destroy();
-                }
-                PointerKind::Touch(wl_touch) => wl_touch.release(),
+        self.devices.insert(pointer_id.clone(), Rc::new(pointer));
+        self.by_pointer.insert(pointer_id, (seat_id, info));
+    }
+
+    /// Release the mouse or the touch device, returning it with its info.
+    pub fn remove(&mut self, pointer_id: &WlPointerId) -> Option<(Rc<PointerKind>, PointerInfo)> {
+        let pointer = self.devices.remove(pointer_id)?;
+        let info = self.by_pointer.remove(pointer_id).map(|(_, info)| info);
+        self.buttons.remove(pointer_id);
+        if let Some(gestures) = self.gestures.remove(pointer_id) {
+            gestures.destroy();
+        }
+        match pointer.as_ref() {
+            PointerKind::Mouse(wl_pointer) => {
+                wl_pointer.pointer().release();
+                // TODO: do we need destroy pointer surface this way?
+                wl_pointer.surface().destroy();
             }
-            return self.by_pointer.get(&id).map(|(_, info)| info).copied();
+            PointerKind::Touch(wl_touch) => wl_touch.release(),
+            PointerKind::Pen(tool) => tool.destroy(),
+        }
+        info.map(|info| (pointer, info))
+    }
+
+    /// The device of the seat behind the capability, its mouse or its touch device.
+    pub(crate) fn seat_device(
+        &self,
+        seat_id: &WlSeatId,
+        capability: Capability,
+    ) -> Option<WlPointerId> {
+        self.devices
+            .iter()
+            .find(|(id, pointer)| {
+                let kind_matches = match pointer.as_ref() {
+                    PointerKind::Mouse(_) => capability == Capability::Pointer,
+                    PointerKind::Touch(_) => capability == Capability::Touch,
+                    PointerKind::Pen(_) => false,
+                };
+                kind_matches
+                    && self
+                        .by_pointer
+                        .get(*id)
+                        .is_some_and(|(seat, _)| seat == seat_id)
+            })
+            .map(|(id, _)| id.clone())
+    }
+
+    pub(crate) fn add_tool(
+        &mut self,
+        seat_id: WlSeatId,
+        tool_id: WlPointerId,
+        tool: PointerKind,
+        info: PointerInfo,
+    ) {
+        self.tools.insert(tool_id.clone(), Rc::new(tool));
+        self.by_pointer.insert(tool_id, (seat_id, info));
+    }
+
+    /// Forget the tablet tool, returning it with its info.
+    pub(crate) fn remove_tool(
+        &mut self,
+        tool_id: &WlPointerId,
+    ) -> Option<(Rc<PointerKind>, PointerInfo)> {
+        let tool = self.tools.remove(tool_id)?;
+        self.buttons.remove(tool_id);
+        if let PointerKind::Pen(wl_tool) = tool.as_ref() {
+            wl_tool.destroy();
+        }
+        let (_, info) = self.by_pointer.remove(tool_id)?;
+        Some((tool, info))
+    }
+
+    pub(crate) fn tool(&self, tool_id: &WlPointerId) -> Option<Rc<PointerKind>> {
+        self.tools.get(tool_id).cloned()
+    }
+
+    /// The tablet tools of the seat.
+    pub(crate) fn seat_tools(&self, seat_id: &WlSeatId) -> Vec<WlPointerId> {
+        self.tools
+            .keys()
+            .filter(|id| {
+                self.by_pointer
+                    .get(*id)
+                    .is_some_and(|(seat, _)| seat == seat_id)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The mouse or the touch device.
+    pub fn kind(&self, pointer_id: &WlPointerId) -> Option<Rc<PointerKind>> {
+        self.devices.get(pointer_id).cloned()
+    }
+
+    pub(crate) fn buttons(&self, pointer_id: &WlPointerId) -> PointerButtons {
+        self.buttons.get(pointer_id).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn set_buttons(&mut self, pointer_id: WlPointerId, buttons: PointerButtons) {
+        if buttons.is_empty() {
+            self.buttons.remove(&pointer_id);
+        } else {
+            self.buttons.insert(pointer_id, buttons);
         }
-        None
     }
 
-    pub fn kind(&self, seat_id: ObjectId) -> Option<Rc<PointerKind>> {
-        self.by_seat.get(&seat_id).map(|k| k.1.clone())
+    pub(crate) fn add_gestures(&mut self, pointer_id: WlPointerId, gestures: PointerGestures) {
+        self.gestures.insert(pointer_id, gestures);
+    }
+
+    pub(crate) fn gestures(&self, pointer_id: &WlPointerId) -> Option<&PointerGestures> {
+        self.gestures.get(pointer_id)
+    }
+
+    /// The touch point on the window, decoration touches excluded.
+    pub(crate) fn touch_point_mut(&mut self, touch: &WlTouch, id: i32) -> Option<&mut TouchPoint> {
+        self.touches
+            .get_mut(&(touch.id(), id))
+            .filter(|point| point.frame.is_none())
+    }
+
+    /// Remove the touch points of the touch device.
+    pub(crate) fn take_touches(&mut self, touch_id: &WlTouchId) -> Vec<TouchPoint> {
+        let ids: Vec<_> = self
+            .touches
+            .keys()
+            .filter(|(device, _)| device == touch_id)
+            .cloned()
+            .collect();
+        ids.iter()
+            .filter_map(|id| self.touches.remove(id))
+            .collect()
+    }
+
+    /// The seats having a mouse pointer.
+    pub(crate) fn mouse_seats(&self) -> Vec<WlSeat> {
+        self.devices
+            .values()
+            .filter(|pointer| matches!(pointer.as_ref(), PointerKind::Mouse(_)))
+            .filter_map(|pointer| pointer.seat().cloned())
+            .collect()
+    }
+
+    /// The pointer showing its cursor on the surface.
+    pub(crate) fn by_cursor_surface(&self, surface: &WlSurface) -> Option<Rc<PointerKind>> {
+        self.devices
+            .values()
+            .find_map(|pointer| match pointer.as_ref() {
+                PointerKind::Mouse(themed_pointer) if themed_pointer.surface() == surface => {
+                    Some(pointer.clone())
+                }
+                _ => None,
+            })
     }
 
     pub fn info(&self, pointer_id: ObjectId) -> Option<PointerInfo> {
@@ -150,12 +487,104 @@ impl PointerRegistry {
     }
 }
 
+impl WaylandState {
+    /// Stable identifier of the device behind a seat capability.
+    ///
+    /// The id is derived from the seat name and the capability, so it stays the same when the
+    /// capability is removed and re-added for the same seat, and across sessions as long as the
+    /// compositor keeps the seat name. Seats without a name fall back to the seat object, which
+    /// is only stable for the lifetime of the connection.
+    pub(crate) fn persistent_device_id(
+        &self,
+        seat: &WlSeat,
+        capability: impl Display,
+    ) -> Option<PersistentDeviceId> {
+        let seat_name = self
+            .seat_state
+            .seat
+            .info(seat)
+            .and_then(|info| info.name)
+            .unwrap_or_else(|| seat.id().protocol_id().to_string());
+
+        seat_device_id(&seat_name, capability)
+    }
+
+    /// Recreate the pointers of all seats with the theme.
+    pub fn set_cursor_theme(&mut self, theme: Option<CursorTheme>) {
+        if self.seat_state.cursor_theme == theme {
+            return;
+        }
+        self.seat_state.cursor_theme = theme;
+        let (conn, qh) = (self.conn.clone(), self.queue_handle.clone());
+        for seat in self.seat_state.pointers.mouse_seats() {
+            self.remove_capability(&conn, &qh, seat.clone(), Capability::Pointer);
+            self.new_capability(&conn, &qh, seat, Capability::Pointer);
+        }
+    }
+
+    /// Set the cursor again on the surface scale change, the theme is loaded per scale.
+    pub(crate) fn reload_cursor(&self, surface: &WlSurface) {
+        let Some(pointer) = self.seat_state.pointers.by_cursor_surface(surface) else {
+            return;
+        };
+        for window in self.windows.windows.values() {
+            if window.has_pointer(&pointer) {
+                window.show_cursor(&pointer);
+            }
+        }
+    }
+}
+
+/// Device id hashed from the key.
+pub(crate) fn device_hash(key: &str) -> Option<PersistentDeviceId> {
+    // FNV-1a, the std hasher is not guaranteed to be stable between releases.
+    let hash = key.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    PersistentDeviceId::new(hash)
+}
+
+/// Device id of the capability of the seat, the same whenever the capability comes back.
+fn seat_device_id(seat_name: &str, capability: impl Display) -> Option<PersistentDeviceId> {
+    device_hash(&format!("{seat_name}/{capability}"))
+}
+
+/// The info of the mouse or of the touch device of the capability, the pointer events of the
+/// device all carry its type.
+fn device_info(
+    capability: Capability,
+    protocol_id: u32,
+    persistent_device_id: Option<PersistentDeviceId>,
+) -> PointerInfo {
+    PointerInfo {
+        pointer_id: PointerId::new(protocol_id as u64),
+        persistent_device_id,
+        pointer_type: match capability {
+            Capability::Touch => PointerType::Touch,
+            _ => PointerType::Mouse,
+        },
+    }
+}
+
 impl SeatHandler for WaylandState {
     fn seat_state(&mut self) -> &mut WlSeatState {
         &mut self.seat_state.seat
     }
 
-    fn new_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: WlSeat) {}
+    fn new_seat(&mut self, _conn: &Connection, qh: &QueueHandle<Self>, seat: WlSeat) {
+        if let Some(tablet_manager) = self.tablet_manager.as_ref() {
+            let tablet_seat = tablet_manager.get_tablet_seat(&seat, qh);
+            self.seat_state.tablets.add_seat(seat.id(), tablet_seat);
+        }
+        if let Some(manager) = self.data_device_manager.as_ref() {
+            let device = manager.get_data_device(qh, &seat);
+            self.seat_state.data_devices.insert(seat.id(), device);
+        }
+        #[cfg(feature = "data-control")]
+        if let Some(data_control) = self.data_control.as_mut() {
+            data_control.add_seat(&seat, qh);
+        }
+    }
 
     fn new_capability(
         &mut self,
@@ -167,21 +596,34 @@ impl SeatHandler for WaylandState {
         match capability {
             Capability::Pointer => {
                 let surface = self.compositor_state.create_surface(qh);
+                let theme = match self.seat_state.cursor_theme.as_ref() {
+                    Some(theme) => ThemeSpec::Named {
+                        name: &theme.name,
+                        size: theme.size,
+                    },
+                    None => ThemeSpec::System,
+                };
 
                 if let Ok(pointer) = self.seat_state.seat.get_pointer_with_theme_and_data(
                     qh,
                     &seat,
                     self.shm.wl_shm(),
                     surface,
-                    ThemeSpec::System,
+                    theme,
                     PointerData::new(seat.clone()),
                 ) {
                     let pointer_id = pointer.pointer().id();
-                    let info = PointerInfo {
-                        pointer_id: Some(PointerId::new(pointer_id.protocol_id() as u64).unwrap()),
-                        persistent_device_id: None,
-                        pointer_type: PointerType::Mouse,
-                    };
+                    if let Some(gestures) = self.pointer_gestures.as_ref() {
+                        self.seat_state.pointers.add_gestures(
+                            pointer_id.clone(),
+                            gestures.get_gestures(pointer.pointer(), qh),
+                        );
+                    }
+                    let info = device_info(
+                        capability,
+                        pointer_id.protocol_id(),
+                        self.persistent_device_id(&seat, capability),
+                    );
                     self.seat_state.pointers.add(
                         seat.id(),
                         pointer_id,
@@ -193,11 +635,11 @@ impl SeatHandler for WaylandState {
             Capability::Touch => {
                 if let Ok(touch) = self.seat_state.seat.get_touch(qh, &seat) {
                     let touch_id = touch.id();
-                    let info = PointerInfo {
-                        pointer_id: Some(PointerId::new(touch_id.protocol_id() as u64).unwrap()),
-                        persistent_device_id: None,
-                        pointer_type: PointerType::Mouse,
-                    };
+                    let info = device_info(
+                        capability,
+                        touch_id.protocol_id(),
+                        self.persistent_device_id(&seat, capability),
+                    );
                     self.seat_state.pointers.add(
                         seat.id(),
                         touch_id,
@@ -206,9 +648,16 @@ impl SeatHandler for WaylandState {
                     );
                 }
             }
-            Capability::Keyboard if self.seat_state.keyboard.is_none() => {
+            Capability::Keyboard if !self.seat_state.keyboards.contains_key(&seat.id()) => {
+                // The keys are repeated by a timer of ours, following the repeat info of the
+                // compositor.
                 if let Ok(keyboard) = self.seat_state.seat.get_keyboard(qh, &seat, None) {
-                    self.seat_state.keyboard = Some(keyboard);
+                    self.seat_state.keyboards.insert(seat.id(), keyboard);
+                }
+                // The text input follows the keyboard focus.
+                if let Some(text_input) = self.text_input.as_ref() {
+                    let text_input = text_input.get_text_input(&seat, qh);
+                    self.seat_state.text_inputs.insert(seat.id(), text_input);
                 }
             }
             _ => {
@@ -225,17 +674,61 @@ impl SeatHandler for WaylandState {
         capability: Capability,
     ) {
         match capability {
-            Capability::Keyboard if self.seat_state.keyboard.is_some() => {
-                if let Some(id) = self.seat_state.keyboard_focus.take() {
-                    if let Err(err) = self.event_sender.send(Events::Focus(id, false)) {
+            Capability::Keyboard if self.seat_state.keyboards.contains_key(&seat.id()) => {
+                self.release_held_keys(&seat.id());
+                self.seat_state.repeats.remove(&seat.id());
+                if let Some(window_id) = self.seat_state.keyboard_focus {
+                    self.seat_state.set_keyboard_focus(None);
+                    if let Some(window) = self.windows.get_mut(&window_id) {
+                        window.focus_lost();
+                    }
+                    if let Some(id) = self.windows.id_converter.get(&window_id)
+                        && let Err(err) = self.event_sender.send(Events::Focus(id.clone(), false))
+                    {
                         error!("{err}");
-                    };
+                    }
+                }
+                self.seat_state.modifiers.remove(&seat.id());
+                self.seat_state.keymaps.borrow_mut().remove(&seat.id());
+                self.seat_state.compose.remove(&seat.id());
+                if let Some(text_input) = self.seat_state.text_inputs.remove(&seat.id()) {
+                    for window in self.windows.windows.values_mut() {
+                        window.text_inputs.retain(|input| *input != text_input);
+                    }
+                    text_input.destroy();
                 }
-                self.seat_state.keyboard.take().unwrap().release()
+                self.seat_state
+                    .keyboards
+                    .remove(&seat.id())
+                    .unwrap()
+                    .release()
             }
             Capability::Pointer | Capability::Touch => {
-                if let Some(info) = self.seat_state.pointers.remove(seat.id()) {
-                    for (id, _) in &self.windows.windows {
+                // The other device of the seat stays.
+                let Some(device_id) = self.seat_state.pointers.seat_device(&seat.id(), capability)
+                else {
+                    warn!("Could not remote unknown capability for {}", seat.id());
+                    return;
+                };
+                if capability == Capability::Touch {
+                    self.cancel_touches(&device_id);
+                }
+                if let (Some(gestures), Some(info)) = (
+                    self.seat_state.pointers.gestures(&device_id),
+                    self.seat_state.pointers.info(device_id.clone()),
+                ) {
+                    for (surface, event) in gestures.cancel(info) {
+                        self.events.push_back(Events::Gesture(surface, event));
+                    }
+                }
+                if let Some((pointer, info)) = self.seat_state.pointers.remove(&device_id) {
+                    // Only the windows under the pointer may have something in progress.
+                    for (id, window) in self.windows.windows.iter_mut() {
+                        if !window.has_pointer(&pointer) {
+                            continue;
+                        }
+                        window.pointer_leave(pointer.clone());
+                        window.drop_constraints(&pointer);
                         if let Err(err) = self
                             .event_sender
                             .send(Events::Pointer(id.clone(), PointerEvent::Cancel(info)))
@@ -243,13 +736,173 @@ impl SeatHandler for WaylandState {
                             error!("Failed to remove capability for window: {id}\n{err}");
                         }
                     }
-                } else {
-                    warn!("Could not remote unknown capability for {}", seat.id());
                 }
             }
             _ => {}
         }
     }
 
-    fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: WlSeat) {}
+    fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, seat: WlSeat) {
+        for tool_id in self.seat_state.pointers.seat_tools(&seat.id()) {
+            self.remove_tablet_tool(&tool_id);
+        }
+        self.seat_state.tablets.remove_seat(&seat.id());
+        self.seat_state.data_devices.remove(&seat.id());
+        self.seat_state.drags.remove(&seat.id());
+        #[cfg(feature = "data-control")]
+        if let Some(data_control) = self.data_control.as_mut() {
+            data_control.remove_seat(&seat.id());
+        }
+        self.seat_state.serials.remove_seat(&seat.id());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use smithay_client_toolkit::seat::keyboard::{
+        KeyboardHandler, Modifiers as WaylandModifiers, RawModifiers,
+    };
+
+    use super::*;
+    use crate::{
+        WindowAttributes,
+        testing::{FakeCompositor, fixed},
+    };
+
+    /// `wl_seat::capabilities`, with the pointer and keyboard bits.
+    const SEAT_CAPABILITIES: u16 = 0;
+    const POINTER_AND_KEYBOARD: u32 = 1 | 2;
+    /// `wl_pointer::enter`, `wl_pointer::button` and `wl_pointer::frame`.
+    const POINTER_ENTER: u16 = 0;
+    const POINTER_BUTTON: u16 = 3;
+    const POINTER_FRAME: u16 = 5;
+    const BTN_LEFT: u32 = 0x110;
+    const PRESSED: u32 = 1;
+
+    fn touch_point(surface: ObjectId) -> TouchPoint {
+        TouchPoint {
+            surface,
+            info: device_info(Capability::Touch, 1, None),
+            state: PointerState::default(),
+            offset: (0., 0.),
+            frame: None,
+        }
+    }
+
+    #[test]
+    fn device_id_survives_replug() {
+        let pointer = seat_device_id("seat0", Capability::Pointer);
+        assert_eq!(pointer, seat_device_id("seat0", Capability::Pointer));
+        // Stable across the releases too, it's FNV-1a of "seat0/pointer".
+        assert_eq!(pointer, PersistentDeviceId::new(0xa0de9e3197a4d32a));
+
+        assert_ne!(pointer, seat_device_id("seat0", Capability::Touch));
+        assert_ne!(pointer, seat_device_id("seat1", Capability::Pointer));
+    }
+
+    #[test]
+    fn touch_cancel_stays_on_its_seat() {
+        let compositor = FakeCompositor::new(&[("wl_compositor", 6)]);
+        let [first, second, surface]: [ObjectId; 3] = compositor.object_ids(3).try_into().unwrap();
+        let mut pointers = PointerRegistry::default();
+        for (device, id) in [(&first, 0), (&first, 1), (&second, 0)] {
+            pointers
+                .touches
+                .insert((device.clone(), id), touch_point(surface.clone()));
+        }
+
+        assert_eq!(pointers.take_touches(&first).len(), 2);
+        assert!(pointers.take_touches(&first).is_empty());
+        // The touches of the other seat go on.
+        assert!(pointers.touches.contains_key(&(second.clone(), 0)));
+        assert_eq!(pointers.take_touches(&second).len(), 1);
+    }
+
+    #[test]
+    fn pointer_events_carry_the_modifiers_of_their_seat() {
+        let (mut compositor, mut state, mut event_loop) = FakeCompositor::with_state(&[
+            ("wl_compositor", 6),
+            ("wl_shm", 1),
+            ("xdg_wm_base", 6),
+            ("wl_seat", 7),
+            ("wl_seat", 7),
+        ]);
+        let seats: Vec<WlSeat> = state.seat_state.seat.seats().collect();
+        assert_eq!(seats.len(), 2);
+        for seat in &seats {
+            compositor.send(seat, SEAT_CAPABILITIES, &[POINTER_AND_KEYBOARD]);
+        }
+        event_loop.dispatch(Duration::ZERO, &mut state).unwrap();
+
+        // Ctrl is held on the first seat only. The keyboards have no keymap to go through, so
+        // the handler gets the modifiers right away.
+        let keyboard = state.seat_state.keyboards[&seats[0].id()].clone();
+        let (conn, queue_handle) = (compositor.conn.clone(), state.queue_handle.clone());
+        let ctrl = WaylandModifiers {
+            ctrl: true,
+            ..Default::default()
+        };
+        state.update_modifiers(
+            &conn,
+            &queue_handle,
+            &keyboard,
+            0,
+            ctrl,
+            RawModifiers::default(),
+            0,
+        );
+
+        // Each seat clicks on a window of its own.
+        let windows = [WindowId::next(), WindowId::next()];
+        let mut surfaces = Vec::new();
+        for (seat, window_id) in seats.iter().zip(windows) {
+            state.create_window((window_id, WindowAttributes::default()));
+            let surface = state
+                .windows
+                .get(&window_id)
+                .unwrap()
+                .get_surface_id()
+                .clone();
+            let pointer_id = state
+                .seat_state
+                .pointers
+                .seat_device(&seat.id(), Capability::Pointer)
+                .unwrap();
+            let kind = state.seat_state.pointers.kind(&pointer_id).unwrap();
+            let PointerKind::Mouse(pointer) = kind.as_ref() else {
+                panic!("{kind:?} is not a mouse");
+            };
+            let enter = [1, surface.protocol_id(), fixed(10.), fixed(10.)];
+            compositor.send(pointer.pointer(), POINTER_ENTER, &enter);
+            compositor.send(
+                pointer.pointer(),
+                POINTER_BUTTON,
+                &[2, 0, BTN_LEFT, PRESSED],
+            );
+            compositor.send(pointer.pointer(), POINTER_FRAME, &[]);
+            surfaces.push(surface);
+        }
+        state.events.clear();
+        event_loop.dispatch(Duration::ZERO, &mut state).unwrap();
+
+        let presses: Vec<(ObjectId, Modifiers)> = state
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                Events::Pointer(surface, PointerEvent::Down { state, .. }) => {
+                    Some((surface.clone(), state.modifiers))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            presses,
+            [
+                (surfaces[0].clone(), Modifiers::CONTROL),
+                (surfaces[1].clone(), Modifiers::empty()),
+            ]
+        );
+    }
 }
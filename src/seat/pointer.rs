@@ -1,18 +1,22 @@
-use std::time::Duration;
+use std::{collections::VecDeque, time::Duration};
 
 use dpi::LogicalPosition;
 use smithay_client_toolkit::{
     compositor::SurfaceData,
     reexports::{
-        client::{Connection, Proxy, QueueHandle, protocol::wl_pointer::WlPointer},
+        client::{
+            Connection, Proxy, QueueHandle, backend::ObjectId, protocol::wl_pointer::WlPointer,
+        },
         csd_frame::{DecorationsFrame, FrameClick},
     },
-    seat::pointer::{PointerEvent as WlPointerEvent, PointerEventKind, PointerHandler},
+    seat::pointer::{
+        AxisScroll, PointerData, PointerEvent as WlPointerEvent, PointerEventKind, PointerHandler,
+    },
 };
 use tracing::error;
-use ui_events::pointer::{PointerButton, PointerEvent, PointerState, PointerUpdate};
+use ui_events::pointer::{PointerButton, PointerEvent, PointerState, PointerUpdate, ScrollDelta};
 
-use crate::{Events, WaylandState};
+use crate::{Events, GestureEvent, ScrollSource, WaylandState, seat::serial::SerialKind};
 
 impl PointerHandler for WaylandState {
     fn pointer_frame(
@@ -23,51 +27,82 @@ impl PointerHandler for WaylandState {
         events: &[WlPointerEvent],
     ) {
         if let Some(mouse) = self.seat_state.pointers.info(pointer.id()) {
+            let mut buttons = self.seat_state.pointers.buttons(&pointer.id());
+            let modifiers = pointer
+                .data::<PointerData>()
+                .map(|data| self.seat_state.seat_modifiers(&data.seat().id()))
+                .unwrap_or_default();
             for event in events {
                 let surface = &event.surface;
                 let id = surface.id();
+                if let Some(data) = pointer.data::<PointerData>() {
+                    let serials = &mut self.seat_state.serials;
+                    match event.kind {
+                        PointerEventKind::Press { serial, .. } => {
+                            serials.record(SerialKind::PointerButton, data.seat(), serial)
+                        }
+                        PointerEventKind::Enter { serial } => {
+                            serials.record(SerialKind::PointerEnter, data.seat(), serial)
+                        }
+                        _ => {}
+                    }
+                }
 
                 let parent_id = surface
                     .data::<SurfaceData>()
                     .and_then(|data| data.parent_surface().map(|s| s.id()))
                     .unwrap_or(id.clone());
+                // The subsurfaces of the application are part of the window, unlike the ones of
+                // the decorations.
+                let subsurface_position = self
+                    .windows
+                    .get_by_object_id(&parent_id)
+                    .filter(|_| parent_id != id)
+                    .and_then(|window| window.subsurface_position(&id));
+                let decoration = parent_id != id && subsurface_position.is_none();
 
                 let pointer_kind = match event.kind {
                     PointerEventKind::Enter { .. } | PointerEventKind::Leave { .. } => {
                         self.pointer_kind(pointer)
                     }
                     PointerEventKind::Press { .. } | PointerEventKind::Release { .. }
-                        if parent_id != id =>
+                        if decoration =>
                     {
                         self.windows.redraw_request.insert(parent_id.clone());
                         None
                     }
+                    PointerEventKind::Press { .. } | PointerEventKind::Release { .. } => {
+                        self.pointer_kind(pointer)
+                    }
                     _ => None,
                 };
                 if let Some(window) = self.windows.get_mut_by_object_id(&parent_id) {
-                    let position = LogicalPosition::<f64>::from(event.position);
+                    let mut position = LogicalPosition::<f64>::from(event.position);
+                    if let Some(offset) = subsurface_position {
+                        position.x += f64::from(offset.x);
+                        position.y += f64::from(offset.y);
+                    }
                     let mut state = PointerState {
-                        position: position.to_physical(window.scale_factor as f64),
-                        modifiers: self.seat_state.modifiers,
+                        position: position.to_physical(window.scale_factor),
+                        buttons,
+                        modifiers,
                         ..Default::default()
                     };
-                    if parent_id != id {
+                    if decoration {
                         // Decoration events
                         match event.kind {
                             PointerEventKind::Enter { .. } | PointerEventKind::Motion { .. } => {
                                 if let (Some(frame), Some(pointer_kind)) =
                                     (window.window_frame.as_mut(), pointer_kind)
-                                {
-                                    if let Some(icon) = frame.click_point_moved(
+                                    && let Some(icon) = frame.click_point_moved(
                                         Duration::ZERO,
                                         &surface.id(),
                                         event.position.0,
                                         event.position.1,
-                                    ) {
-                                        if let Err(err) = pointer_kind.set_cursor(conn, icon) {
-                                            error!("{err}");
-                                        }
-                                    }
+                                    )
+                                    && let Err(err) = pointer_kind.set_cursor(conn, icon)
+                                {
+                                    error!("{err}");
                                 }
                             }
                             PointerEventKind::Leave { .. } => {
@@ -101,7 +136,10 @@ impl PointerHandler for WaylandState {
                                         )
                                     })
                                 {
-                                    if window.frame_action(pointer, serial, action) {
+                                    let seat = pointer.data::<PointerData>().map(PointerData::seat);
+                                    if seat.is_some_and(|seat| {
+                                        window.frame_action(seat, serial, action)
+                                    }) {
                                         self.windows.close_request.insert(parent_id.to_owned());
                                     }
                                 }
@@ -113,11 +151,7 @@ impl PointerHandler for WaylandState {
                         match event.kind {
                             PointerEventKind::Enter { .. } => {
                                 if let Some(pointer_kind) = pointer_kind {
-                                    if let Err(err) =
-                                        pointer_kind.set_cursor(conn, window.selected_cursor)
-                                    {
-                                        error!("{err}");
-                                    }
+                                    window.show_cursor(&pointer_kind);
                                     window.pointer_enter(pointer_kind);
                                 }
                                 self.events.push_back(Events::Pointer(
@@ -129,6 +163,8 @@ impl PointerHandler for WaylandState {
                                 if let Some(pointer_kind) = pointer_kind {
                                     window.pointer_leave(pointer_kind);
                                 }
+                                // The release outside of the window is never reported.
+                                buttons.clear();
                                 self.events.push_back(Events::Pointer(
                                     parent_id,
                                     PointerEvent::Leave(mouse),
@@ -136,19 +172,31 @@ impl PointerHandler for WaylandState {
                             }
                             PointerEventKind::Motion { time } => {
                                 state.time = time as u64;
-                                self.events.push_back(Events::Pointer(
+                                push_move(
+                                    &mut self.events,
                                     parent_id,
-                                    PointerEvent::Move(PointerUpdate {
+                                    PointerUpdate {
                                         pointer: mouse,
                                         current: state,
                                         coalesced: Vec::new(),
                                         predicted: Vec::new(),
-                                    }),
-                                ));
+                                    },
+                                );
                             }
-                            PointerEventKind::Press { time, button, .. } => {
+                            PointerEventKind::Press {
+                                time,
+                                button,
+                                serial,
+                            } => {
+                                if let Some(pointer_kind) = pointer_kind {
+                                    window.pointer_pressed(&pointer_kind, button, serial);
+                                }
                                 state.time = time as u64;
                                 let button = try_from_button(button);
+                                if let Some(button) = button {
+                                    buttons.insert(button);
+                                    state.buttons = buttons;
+                                }
                                 self.events.push_back(Events::Pointer(
                                     parent_id.clone(),
                                     PointerEvent::Down {
@@ -159,8 +207,15 @@ impl PointerHandler for WaylandState {
                                 ))
                             }
                             PointerEventKind::Release { time, button, .. } => {
+                                if let Some(pointer_kind) = pointer_kind {
+                                    window.pointer_released(&pointer_kind, button);
+                                }
                                 state.time = time as u64;
                                 let button = try_from_button(button);
+                                if let Some(button) = button {
+                                    buttons.remove(button);
+                                    state.buttons = buttons;
+                                }
                                 self.events.push_back(Events::Pointer(
                                     parent_id,
                                     PointerEvent::Up {
@@ -170,16 +225,94 @@ impl PointerHandler for WaylandState {
                                     },
                                 ))
                             }
-                            PointerEventKind::Axis { .. } => {}
+                            PointerEventKind::Axis {
+                                time,
+                                horizontal,
+                                vertical,
+                                source,
+                            } => {
+                                state.time = time as u64;
+                                // A stop may come alone.
+                                if has_scroll(&horizontal) || has_scroll(&vertical) {
+                                    let delta =
+                                        scroll_delta(&horizontal, &vertical, window.scale_factor);
+                                    self.events.push_back(Events::Pointer(
+                                        parent_id.clone(),
+                                        PointerEvent::Scroll {
+                                            pointer: mouse,
+                                            delta,
+                                            state,
+                                        },
+                                    ));
+                                }
+                                if horizontal.stop || vertical.stop {
+                                    self.events.push_back(Events::Gesture(
+                                        parent_id,
+                                        GestureEvent::ScrollStop {
+                                            pointer: mouse,
+                                            source: source.and_then(ScrollSource::from_wayland),
+                                            time: time as u64,
+                                        },
+                                    ));
+                                }
+                            }
                         }
                     }
                 }
             }
+            self.seat_state.pointers.set_buttons(pointer.id(), buttons);
         }
     }
 }
 
-fn try_from_button(code: u32) -> Option<PointerButton> {
+/// Queue the motion, merging it into the pending motion of the same pointer.
+///
+/// The samples of the merged motions are kept in `coalesced`, the oldest first. Only the
+/// motions of other pointers may be in between, so the order of the other events is kept.
+pub(crate) fn push_move(events: &mut VecDeque<Events>, surface: ObjectId, update: PointerUpdate) {
+    for event in events.iter_mut().rev() {
+        let Events::Pointer(id, PointerEvent::Move(pending)) = event else {
+            break;
+        };
+        if pending.pointer.pointer_id != update.pointer.pointer_id {
+            continue;
+        }
+        if *id != surface {
+            break;
+        }
+        let current = std::mem::replace(&mut pending.current, update.current);
+        pending.coalesced.push(current);
+        pending.coalesced.extend(update.coalesced);
+        pending.predicted = update.predicted;
+        return;
+    }
+    events.push_back(Events::Pointer(surface, PointerEvent::Move(update)));
+}
+
+fn has_scroll(axis: &AxisScroll) -> bool {
+    axis.absolute != 0. || axis.discrete != 0 || axis.value120 != 0
+}
+
+/// Wheels scroll by lines, touchpads and other continuous sources by pixels.
+///
+/// Positive values scroll down and to the right, like in Wayland.
+fn scroll_delta(horizontal: &AxisScroll, vertical: &AxisScroll, scale_factor: f64) -> ScrollDelta {
+    if horizontal.value120 != 0 || vertical.value120 != 0 {
+        ScrollDelta::LineDelta(
+            horizontal.value120 as f32 / 120.,
+            vertical.value120 as f32 / 120.,
+        )
+    } else if horizontal.discrete != 0 || vertical.discrete != 0 {
+        // Older compositors only send the steps.
+        ScrollDelta::LineDelta(horizontal.discrete as f32, vertical.discrete as f32)
+    } else {
+        ScrollDelta::PixelDelta(
+            LogicalPosition::new(horizontal.absolute, vertical.absolute).to_physical(scale_factor),
+        )
+    }
+}
+
+pub(crate) fn try_from_button(code: u32) -> Option<PointerButton> {
     Some(match code {
         // Основные кнопки мыши
         0x110 => PointerButton::Primary,
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use smithay_client_toolkit::reexports::client::{Proxy, protocol::wl_seat::WlSeat};
+
+use crate::seat::WlSeatId;
+
+/// The input events whose serials the requests to the compositor refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum SerialKind {
+    PointerButton,
+    PointerEnter,
+    TouchDown,
+    KeyPress,
+    KeyboardEnter,
+}
+
+#[derive(Debug)]
+struct Recorded {
+    seat: WlSeat,
+    serial: u32,
+    /// Serials may wrap around, so the order of the records is kept apart.
+    order: u64,
+}
+
+/// The latest serial of every kind of input, with its seat.
+#[derive(Debug, Default)]
+pub(crate) struct SerialTracker {
+    serials: HashMap<SerialKind, Recorded>,
+    next_order: u64,
+}
+
+impl SerialTracker {
+    pub(crate) fn record(&mut self, kind: SerialKind, seat: &WlSeat, serial: u32) {
+        let order = self.next_order;
+        self.next_order += 1;
+        self.serials.insert(
+            kind,
+            Recorded {
+                seat: seat.clone(),
+                serial,
+                order,
+            },
+        );
+    }
+
+    pub(crate) fn remove_seat(&mut self, seat_id: &WlSeatId) {
+        self.serials
+            .retain(|_, recorded| recorded.seat.id() != *seat_id);
+    }
+
+    /// The latest of the serials of the given kinds.
+    pub(crate) fn latest(&self, kinds: &[SerialKind]) -> Option<(&WlSeat, u32)> {
+        kinds
+            .iter()
+            .filter_map(|kind| self.serials.get(kind))
+            .max_by_key(|recorded| recorded.order)
+            .map(|recorded| (&recorded.seat, recorded.serial))
+    }
+
+    /// The latest button press or touch, which can start a move, a resize, a drag or a popup grab.
+    pub(crate) fn latest_implicit_grab_serial(&self) -> Option<(&WlSeat, u32)> {
+        self.latest(&[SerialKind::PointerButton, SerialKind::TouchDown])
+    }
+
+    /// The latest input of the user, for the clipboard and the activation.
+    pub(crate) fn latest_focus_serial(&self) -> Option<(&WlSeat, u32)> {
+        self.latest(&[
+            SerialKind::PointerButton,
+            SerialKind::TouchDown,
+            SerialKind::KeyPress,
+            SerialKind::KeyboardEnter,
+        ])
+    }
+
+    /// The seat of the latest input of the user.
+    pub(crate) fn latest_seat(&self) -> Option<WlSeatId> {
+        self.latest_focus_serial().map(|(seat, _)| seat.id())
+    }
+}
@@ -0,0 +1,119 @@
+// Handling of the keyboard-shortcuts-inhibit-unstable-v1.
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::{
+        client::{
+            Connection, Dispatch, Proxy, QueueHandle,
+            backend::ObjectId,
+            delegate_dispatch,
+            globals::{BindError, GlobalList},
+            protocol::{wl_seat::WlSeat, wl_surface::WlSurface},
+        },
+        protocols::wp::keyboard_shortcuts_inhibit::zv1::client::{
+            zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1,
+            zwp_keyboard_shortcuts_inhibitor_v1::{self, ZwpKeyboardShortcutsInhibitorV1},
+        },
+    },
+};
+
+use crate::{Events, WaylandState, seat::WlSeatId};
+
+/// Inhibition of the compositor shortcuts, for terminal emulators and virtual machines.
+#[derive(Debug, Clone)]
+pub struct ShortcutsInhibitState {
+    manager: ZwpKeyboardShortcutsInhibitManagerV1,
+}
+
+impl ShortcutsInhibitState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+}
+
+/// Inhibition requested by a window.
+#[derive(Debug, Default)]
+pub(crate) struct ShortcutsInhibit {
+    pub(crate) requested: bool,
+    inhibitor: Option<(ZwpKeyboardShortcutsInhibitorV1, WlSeatId)>,
+    /// The compositor withdrew the inhibition, it's asked again on the next focus.
+    pub(crate) deactivated: bool,
+}
+
+impl ShortcutsInhibit {
+    /// Inhibit the shortcuts of the seat focusing the surface, unless it's already done.
+    pub(crate) fn inhibit(
+        &mut self,
+        state: &ShortcutsInhibitState,
+        surface: &WlSurface,
+        seat: &WlSeat,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) {
+        if !self.requested
+            || self
+                .inhibitor
+                .as_ref()
+                .is_some_and(|(_, seat_id)| !self.deactivated && *seat_id == seat.id())
+        {
+            return;
+        }
+        // A surface can only have one inhibitor per seat.
+        self.release();
+        let inhibitor = state
+            .manager
+            .inhibit_shortcuts(surface, seat, queue_handle, surface.id());
+        self.inhibitor = Some((inhibitor, seat.id()));
+    }
+
+    pub(crate) fn release(&mut self) {
+        if let Some((inhibitor, _)) = self.inhibitor.take() {
+            inhibitor.destroy();
+        }
+        self.deactivated = false;
+    }
+}
+
+impl Dispatch<ZwpKeyboardShortcutsInhibitManagerV1, GlobalData, WaylandState>
+    for ShortcutsInhibitState
+{
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwpKeyboardShortcutsInhibitManagerV1,
+        _: <ZwpKeyboardShortcutsInhibitManagerV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+impl Dispatch<ZwpKeyboardShortcutsInhibitorV1, ObjectId, WaylandState> for ShortcutsInhibitState {
+    fn event(
+        state: &mut WaylandState,
+        _: &ZwpKeyboardShortcutsInhibitorV1,
+        event: <ZwpKeyboardShortcutsInhibitorV1 as Proxy>::Event,
+        surface_id: &ObjectId,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        let active = match event {
+            zwp_keyboard_shortcuts_inhibitor_v1::Event::Active => true,
+            zwp_keyboard_shortcuts_inhibitor_v1::Event::Inactive => false,
+            _ => return,
+        };
+        if let Some(window) = state.windows.get_mut_by_object_id(surface_id) {
+            window.shortcuts_inhibit.deactivated = !active;
+        }
+        state
+            .events
+            .push_back(Events::ShortcutsInhibited(surface_id.clone(), active));
+    }
+}
+
+delegate_dispatch!(WaylandState: [ZwpKeyboardShortcutsInhibitManagerV1: GlobalData] => ShortcutsInhibitState);
+delegate_dispatch!(WaylandState: [ZwpKeyboardShortcutsInhibitorV1: ObjectId] => ShortcutsInhibitState);
@@ -0,0 +1,647 @@
+// Handling of the tablet-v2.
+
+use std::{
+    collections::HashMap,
+    f64::consts::{FRAC_PI_2, TAU},
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use dpi::LogicalPosition;
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::{
+        client::{
+            Connection, Dispatch, Proxy, QueueHandle, WEnum,
+            backend::ObjectId,
+            delegate_dispatch, event_created_child,
+            globals::{BindError, GlobalList},
+            protocol::{wl_seat::WlSeat, wl_surface::WlSurface},
+        },
+        csd_frame::{DecorationsFrame, FrameClick},
+        protocols::wp::tablet::zv2::client::{
+            zwp_tablet_manager_v2::ZwpTabletManagerV2,
+            zwp_tablet_pad_group_v2::{self, ZwpTabletPadGroupV2},
+            zwp_tablet_pad_ring_v2::ZwpTabletPadRingV2,
+            zwp_tablet_pad_strip_v2::ZwpTabletPadStripV2,
+            zwp_tablet_pad_v2::{self, ZwpTabletPadV2},
+            zwp_tablet_seat_v2::{self, ZwpTabletSeatV2},
+            zwp_tablet_tool_v2::{self, ButtonState, Capability, Type, ZwpTabletToolV2},
+            zwp_tablet_v2::{self, ZwpTabletV2},
+        },
+    },
+};
+use ui_events::pointer::{
+    PointerButton, PointerEvent, PointerId, PointerInfo, PointerState, PointerType, PointerUpdate,
+};
+
+use crate::{
+    Events, WaylandState,
+    seat::{
+        DEFAULT_PRESSURE, PointerKind, WlPointerId, WlSeatId, device_hash,
+        gesture::window_surface,
+        pointer::{push_move, try_from_button},
+    },
+};
+
+/// The evdev code of the contact of the tip, it has no button of its own.
+const BTN_TOUCH: u32 = 0x14a;
+
+/// Graphics tablets.
+#[derive(Debug)]
+pub struct TabletManagerState {
+    manager: ZwpTabletManagerV2,
+}
+
+impl TabletManagerState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    /// Start tracking the tablets of the seat.
+    pub(crate) fn get_tablet_seat(
+        &self,
+        seat: &WlSeat,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> ZwpTabletSeatV2 {
+        self.manager
+            .get_tablet_seat(seat, queue_handle, TabletSeatData { seat: seat.clone() })
+    }
+}
+
+/// Tablets and pads of all seats, the tools are in the [`super::PointerRegistry`].
+#[derive(Debug, Default)]
+pub struct TabletDevices {
+    seats: HashMap<WlSeatId, ZwpTabletSeatV2>,
+    tablets: HashMap<ObjectId, ZwpTabletV2>,
+    pads: HashMap<ObjectId, ZwpTabletPadV2>,
+}
+
+impl TabletDevices {
+    pub(crate) fn add_seat(&mut self, seat_id: WlSeatId, tablet_seat: ZwpTabletSeatV2) {
+        self.seats.insert(seat_id, tablet_seat);
+    }
+
+    pub(crate) fn remove_seat(&mut self, seat_id: &WlSeatId) {
+        if let Some(tablet_seat) = self.seats.remove(seat_id) {
+            tablet_seat.destroy();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TabletSeatData {
+    seat: WlSeat,
+}
+
+/// State of a tablet tool, such as a pen or an eraser.
+#[derive(Debug, Default)]
+pub struct TabletToolData {
+    /// Set once the tool is announced by its seat.
+    seat: OnceLock<WlSeat>,
+    inner: Mutex<ToolInner>,
+}
+
+#[derive(Debug, Default)]
+struct ToolInner {
+    hardware_serial: u64,
+    latest_down_serial: Option<u32>,
+    description: ToolDescription,
+    /// The surface the tool is in proximity of.
+    focus: Option<WlSurface>,
+    axes: ToolAxes,
+    /// Events waiting for the frame.
+    pending: Vec<zwp_tablet_tool_v2::Event>,
+}
+
+/// What the tool is and can report.
+#[derive(Debug, Default, Clone, Copy)]
+struct ToolDescription {
+    eraser: bool,
+    has_pressure: bool,
+    has_tilt: bool,
+}
+
+/// The latest values of the tool axes.
+#[derive(Debug, Default, Clone, Copy)]
+struct ToolAxes {
+    /// Position relative to the surface.
+    position: (f64, f64),
+    /// Pressure from 0 to 1.
+    pressure: f32,
+    /// Tilt in degrees along the X and Y axes.
+    tilt: (f64, f64),
+    tip_down: bool,
+}
+
+impl ToolAxes {
+    fn pressure(&self, description: &ToolDescription) -> f32 {
+        match (self.tip_down, description.has_pressure) {
+            (false, _) => 0.,
+            (true, true) => self.pressure,
+            (true, false) => DEFAULT_PRESSURE,
+        }
+    }
+}
+
+/// Convert the tilt angles in degrees to the altitude and the azimuth in radians.
+fn tilt_to_spherical((tilt_x, tilt_y): (f64, f64)) -> (f32, f32) {
+    let (tan_x, tan_y) = (tilt_x.to_radians().tan(), tilt_y.to_radians().tan());
+    if tan_x == 0. && tan_y == 0. {
+        return (FRAC_PI_2 as f32, 0.);
+    }
+    let altitude = (1. / tan_x.hypot(tan_y)).atan();
+    let azimuth = tan_y.atan2(tan_x).rem_euclid(TAU);
+    (altitude as f32, azimuth as f32)
+}
+
+impl TabletToolData {
+    pub fn seat(&self) -> Option<&WlSeat> {
+        self.seat.get()
+    }
+
+    /// Serial of the latest tip down, like the one of a button press.
+    pub fn latest_down_serial(&self) -> Option<u32> {
+        self.inner.lock().unwrap().latest_down_serial
+    }
+}
+
+/// Groups of buttons, rings and strips of a pad.
+#[derive(Debug, Default)]
+pub struct TabletPadData {
+    groups: Mutex<Vec<ZwpTabletPadGroupV2>>,
+}
+
+#[derive(Debug, Default)]
+pub struct TabletPadGroupData {
+    rings: Mutex<Vec<ZwpTabletPadRingV2>>,
+    strips: Mutex<Vec<ZwpTabletPadStripV2>>,
+}
+
+impl WaylandState {
+    /// Register the tool once it's fully described.
+    fn add_tablet_tool(&mut self, tool: &ZwpTabletToolV2, data: &TabletToolData) {
+        let Some(seat) = data.seat() else {
+            return;
+        };
+        let hardware_serial = data.inner.lock().unwrap().hardware_serial;
+        // The serial identifies the physical tool, if the tablet reports it.
+        let persistent_device_id = if hardware_serial != 0 {
+            device_hash(&format!("tablet-tool/{hardware_serial:x}"))
+        } else {
+            self.persistent_device_id(seat, "tablet-tool")
+        };
+        let info = PointerInfo {
+            pointer_id: PointerId::new(tool.id().protocol_id() as u64),
+            persistent_device_id,
+            pointer_type: PointerType::Pen,
+        };
+        self.seat_state.pointers.add_tool(
+            seat.id(),
+            tool.id(),
+            PointerKind::Pen(tool.clone()),
+            info,
+        );
+    }
+
+    /// Forget the tool, cancelling what it does on the windows.
+    pub(crate) fn remove_tablet_tool(&mut self, tool_id: &WlPointerId) {
+        let Some((tool, info)) = self.seat_state.pointers.remove_tool(tool_id) else {
+            return;
+        };
+        for (id, window) in self.windows.windows.iter_mut() {
+            if !window.has_pointer(&tool) {
+                continue;
+            }
+            window.pointer_leave(tool.clone());
+            self.events
+                .push_back(Events::Pointer(id.clone(), PointerEvent::Cancel(info)));
+        }
+    }
+
+    fn tablet_tool_frame(&mut self, tool: &ZwpTabletToolV2, data: &TabletToolData, time: u32) {
+        let (Some(info), Some(kind)) = (
+            self.seat_state.pointers.info(tool.id()),
+            self.seat_state.pointers.tool(&tool.id()),
+        ) else {
+            return;
+        };
+        let Some(seat) = data.seat() else {
+            return;
+        };
+        let (events, mut focus, mut axes, description) = {
+            let mut inner = data.inner.lock().unwrap();
+            (
+                std::mem::take(&mut inner.pending),
+                inner.focus.take(),
+                inner.axes,
+                inner.description,
+            )
+        };
+        // The events of a frame happen at once, so the axes are the same for all of them.
+        for event in &events {
+            match event {
+                zwp_tablet_tool_v2::Event::Motion { x, y } => axes.position = (*x, *y),
+                zwp_tablet_tool_v2::Event::Pressure { pressure } => {
+                    axes.pressure = *pressure as f32 / 65535.;
+                }
+                zwp_tablet_tool_v2::Event::Tilt { tilt_x, tilt_y } => {
+                    axes.tilt = (*tilt_x, *tilt_y);
+                }
+                _ => {}
+            }
+        }
+        // The eraser end erases instead of drawing.
+        let tip = if description.eraser {
+            PointerButton::PenEraser
+        } else {
+            PointerButton::Primary
+        };
+        let mut buttons = self.seat_state.pointers.buttons(&tool.id());
+        let modifiers = self.seat_state.seat_modifiers(&seat.id());
+        let mut down_serial = None;
+        let mut moved = false;
+        for event in events {
+            if let zwp_tablet_tool_v2::Event::ProximityIn { surface, .. } = &event {
+                focus = Some(surface.clone());
+            }
+            let Some(surface) = focus.as_ref() else {
+                continue;
+            };
+            let id = surface.id();
+            let parent_id = window_surface(surface);
+            let Some(window) = self.windows.get_mut_by_object_id(&parent_id) else {
+                if matches!(event, zwp_tablet_tool_v2::Event::ProximityOut) {
+                    focus = None;
+                }
+                continue;
+            };
+
+            if parent_id != id {
+                // The tip acts like the primary button on the decorations.
+                let Some(frame) = window.window_frame.as_mut() else {
+                    continue;
+                };
+                let action = match event {
+                    zwp_tablet_tool_v2::Event::Motion { x, y } => {
+                        frame.click_point_moved(Duration::ZERO, &id, x, y);
+                        None
+                    }
+                    zwp_tablet_tool_v2::Event::Down { serial } => {
+                        down_serial = Some(serial);
+                        axes.tip_down = true;
+                        frame
+                            .on_click(Duration::from_millis(time as u64), FrameClick::Normal, true)
+                            .map(|action| (serial, action))
+                    }
+                    zwp_tablet_tool_v2::Event::Up => {
+                        axes.tip_down = false;
+                        frame
+                            .on_click(
+                                Duration::from_millis(time as u64),
+                                FrameClick::Normal,
+                                false,
+                            )
+                            .zip(down_serial.or(data.latest_down_serial()))
+                            .map(|(action, serial)| (serial, action))
+                    }
+                    zwp_tablet_tool_v2::Event::ProximityOut => {
+                        frame.click_point_left();
+                        focus = None;
+                        axes.tip_down = false;
+                        None
+                    }
+                    _ => None,
+                };
+                let dirty = frame.is_dirty();
+                if action.is_some_and(|(serial, action)| window.frame_action(seat, serial, action))
+                {
+                    self.windows.close_request.insert(parent_id.clone());
+                }
+                if dirty {
+                    self.windows.redraw_request.insert(parent_id);
+                }
+                continue;
+            }
+
+            match event {
+                zwp_tablet_tool_v2::Event::Down { .. } => axes.tip_down = true,
+                zwp_tablet_tool_v2::Event::Up | zwp_tablet_tool_v2::Event::ProximityOut => {
+                    axes.tip_down = false
+                }
+                _ => {}
+            }
+            let mut state = PointerState {
+                time: time as u64,
+                position: LogicalPosition::<f64>::from(axes.position)
+                    .to_physical(window.scale_factor),
+                buttons,
+                modifiers,
+                pressure: axes.pressure(&description),
+                ..Default::default()
+            };
+            if description.has_tilt {
+                let (altitude, azimuth) = tilt_to_spherical(axes.tilt);
+                state.orientation.altitude = altitude;
+                state.orientation.azimuth = azimuth;
+            }
+            let event = match event {
+                zwp_tablet_tool_v2::Event::ProximityIn { .. } => {
+                    window.pointer_enter(kind.clone());
+                    PointerEvent::Enter(info)
+                }
+                zwp_tablet_tool_v2::Event::ProximityOut => {
+                    focus = None;
+                    window.pointer_leave(kind.clone());
+                    // The release out of proximity is never reported.
+                    buttons.clear();
+                    PointerEvent::Leave(info)
+                }
+                zwp_tablet_tool_v2::Event::Motion { .. }
+                | zwp_tablet_tool_v2::Event::Pressure { .. }
+                | zwp_tablet_tool_v2::Event::Tilt { .. } => {
+                    if moved {
+                        continue;
+                    }
+                    moved = true;
+                    let update = PointerUpdate {
+                        pointer: info,
+                        current: state,
+                        coalesced: Vec::new(),
+                        predicted: Vec::new(),
+                    };
+                    push_move(&mut self.events, parent_id, update);
+                    continue;
+                }
+                zwp_tablet_tool_v2::Event::Down { serial } => {
+                    down_serial = Some(serial);
+                    window.pointer_pressed(&kind, BTN_TOUCH, serial);
+                    buttons.insert(tip);
+                    state.buttons = buttons;
+                    PointerEvent::Down {
+                        button: Some(tip),
+                        pointer: info,
+                        state,
+                    }
+                }
+                zwp_tablet_tool_v2::Event::Up => {
+                    window.pointer_released(&kind, BTN_TOUCH);
+                    buttons.remove(tip);
+                    state.buttons = buttons;
+                    PointerEvent::Up {
+                        button: Some(tip),
+                        pointer: info,
+                        state,
+                    }
+                }
+                zwp_tablet_tool_v2::Event::Button {
+                    serial,
+                    button,
+                    state: button_state,
+                } => {
+                    let code = button;
+                    let button = try_from_button(button);
+                    if button_state == WEnum::Value(ButtonState::Pressed) {
+                        window.pointer_pressed(&kind, code, serial);
+                        if let Some(button) = button {
+                            buttons.insert(button);
+                        }
+                        state.buttons = buttons;
+                        PointerEvent::Down {
+                            button,
+                            pointer: info,
+                            state,
+                        }
+                    } else {
+                        window.pointer_released(&kind, code);
+                        if let Some(button) = button {
+                            buttons.remove(button);
+                        }
+                        state.buttons = buttons;
+                        PointerEvent::Up {
+                            button,
+                            pointer: info,
+                            state,
+                        }
+                    }
+                }
+                _ => continue,
+            };
+            self.events.push_back(Events::Pointer(parent_id, event));
+        }
+        self.seat_state.pointers.set_buttons(tool.id(), buttons);
+        let mut inner = data.inner.lock().unwrap();
+        inner.focus = focus;
+        inner.axes = axes;
+        if down_serial.is_some() {
+            inner.latest_down_serial = down_serial;
+        }
+    }
+}
+
+impl Dispatch<ZwpTabletManagerV2, GlobalData, WaylandState> for TabletManagerState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwpTabletManagerV2,
+        _: <ZwpTabletManagerV2 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+impl Dispatch<ZwpTabletSeatV2, TabletSeatData, WaylandState> for TabletManagerState {
+    fn event(
+        state: &mut WaylandState,
+        _: &ZwpTabletSeatV2,
+        event: <ZwpTabletSeatV2 as Proxy>::Event,
+        data: &TabletSeatData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        match event {
+            zwp_tablet_seat_v2::Event::TabletAdded { id } => {
+                state.seat_state.tablets.tablets.insert(id.id(), id);
+            }
+            zwp_tablet_seat_v2::Event::ToolAdded { id } => {
+                // Registered on the done event, once the tool is described.
+                if let Some(tool_data) = id.data::<TabletToolData>() {
+                    let _ = tool_data.seat.set(data.seat.clone());
+                }
+            }
+            zwp_tablet_seat_v2::Event::PadAdded { id } => {
+                state.seat_state.tablets.pads.insert(id.id(), id);
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(WaylandState, ZwpTabletSeatV2, [
+        zwp_tablet_seat_v2::EVT_TABLET_ADDED_OPCODE => (ZwpTabletV2, ()),
+        zwp_tablet_seat_v2::EVT_TOOL_ADDED_OPCODE => (ZwpTabletToolV2, TabletToolData::default()),
+        zwp_tablet_seat_v2::EVT_PAD_ADDED_OPCODE => (ZwpTabletPadV2, TabletPadData::default()),
+    ]);
+}
+
+impl Dispatch<ZwpTabletV2, (), WaylandState> for TabletManagerState {
+    fn event(
+        state: &mut WaylandState,
+        tablet: &ZwpTabletV2,
+        event: <ZwpTabletV2 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        if let zwp_tablet_v2::Event::Removed = event {
+            state.seat_state.tablets.tablets.remove(&tablet.id());
+            tablet.destroy();
+        }
+    }
+}
+
+impl Dispatch<ZwpTabletToolV2, TabletToolData, WaylandState> for TabletManagerState {
+    fn event(
+        state: &mut WaylandState,
+        tool: &ZwpTabletToolV2,
+        event: <ZwpTabletToolV2 as Proxy>::Event,
+        data: &TabletToolData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        match event {
+            zwp_tablet_tool_v2::Event::HardwareSerial {
+                hardware_serial_hi,
+                hardware_serial_lo,
+            } => {
+                data.inner.lock().unwrap().hardware_serial =
+                    ((hardware_serial_hi as u64) << 32) | hardware_serial_lo as u64;
+            }
+            zwp_tablet_tool_v2::Event::Done => state.add_tablet_tool(tool, data),
+            zwp_tablet_tool_v2::Event::Removed => state.remove_tablet_tool(&tool.id()),
+            zwp_tablet_tool_v2::Event::Frame { time } => state.tablet_tool_frame(tool, data, time),
+            zwp_tablet_tool_v2::Event::Type { tool_type } => {
+                data.inner.lock().unwrap().description.eraser =
+                    tool_type == WEnum::Value(Type::Eraser);
+            }
+            zwp_tablet_tool_v2::Event::Capability { capability } => {
+                let description = &mut data.inner.lock().unwrap().description;
+                match capability {
+                    WEnum::Value(Capability::Pressure) => description.has_pressure = true,
+                    WEnum::Value(Capability::Tilt) => description.has_tilt = true,
+                    _ => {}
+                }
+            }
+            zwp_tablet_tool_v2::Event::HardwareIdWacom { .. } => {}
+            event => data.inner.lock().unwrap().pending.push(event),
+        }
+    }
+}
+
+impl Dispatch<ZwpTabletPadV2, TabletPadData, WaylandState> for TabletManagerState {
+    fn event(
+        state: &mut WaylandState,
+        pad: &ZwpTabletPadV2,
+        event: <ZwpTabletPadV2 as Proxy>::Event,
+        data: &TabletPadData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        match event {
+            zwp_tablet_pad_v2::Event::Group { pad_group } => {
+                data.groups.lock().unwrap().push(pad_group);
+            }
+            zwp_tablet_pad_v2::Event::Removed => {
+                state.seat_state.tablets.pads.remove(&pad.id());
+                for group in data.groups.lock().unwrap().drain(..) {
+                    if let Some(group_data) = group.data::<TabletPadGroupData>() {
+                        group_data
+                            .rings
+                            .lock()
+                            .unwrap()
+                            .drain(..)
+                            .for_each(|ring| ring.destroy());
+                        group_data
+                            .strips
+                            .lock()
+                            .unwrap()
+                            .drain(..)
+                            .for_each(|strip| strip.destroy());
+                    }
+                    group.destroy();
+                }
+                pad.destroy();
+            }
+            // The pad buttons are not reported yet.
+            _ => {}
+        }
+    }
+
+    event_created_child!(WaylandState, ZwpTabletPadV2, [
+        zwp_tablet_pad_v2::EVT_GROUP_OPCODE => (ZwpTabletPadGroupV2, TabletPadGroupData::default()),
+    ]);
+}
+
+impl Dispatch<ZwpTabletPadGroupV2, TabletPadGroupData, WaylandState> for TabletManagerState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwpTabletPadGroupV2,
+        event: <ZwpTabletPadGroupV2 as Proxy>::Event,
+        data: &TabletPadGroupData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        match event {
+            zwp_tablet_pad_group_v2::Event::Ring { ring } => data.rings.lock().unwrap().push(ring),
+            zwp_tablet_pad_group_v2::Event::Strip { strip } => {
+                data.strips.lock().unwrap().push(strip)
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(WaylandState, ZwpTabletPadGroupV2, [
+        zwp_tablet_pad_group_v2::EVT_RING_OPCODE => (ZwpTabletPadRingV2, ()),
+        zwp_tablet_pad_group_v2::EVT_STRIP_OPCODE => (ZwpTabletPadStripV2, ()),
+    ]);
+}
+
+impl Dispatch<ZwpTabletPadRingV2, (), WaylandState> for TabletManagerState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwpTabletPadRingV2,
+        _: <ZwpTabletPadRingV2 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // The rings are not reported yet.
+    }
+}
+
+impl Dispatch<ZwpTabletPadStripV2, (), WaylandState> for TabletManagerState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwpTabletPadStripV2,
+        _: <ZwpTabletPadStripV2 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // The strips are not reported yet.
+    }
+}
+
+delegate_dispatch!(WaylandState: [ZwpTabletManagerV2: GlobalData] => TabletManagerState);
+delegate_dispatch!(WaylandState: [ZwpTabletSeatV2: TabletSeatData] => TabletManagerState);
+delegate_dispatch!(WaylandState: [ZwpTabletV2: ()] => TabletManagerState);
+delegate_dispatch!(WaylandState: [ZwpTabletToolV2: TabletToolData] => TabletManagerState);
+delegate_dispatch!(WaylandState: [ZwpTabletPadV2: TabletPadData] => TabletManagerState);
+delegate_dispatch!(WaylandState: [ZwpTabletPadGroupV2: TabletPadGroupData] => TabletManagerState);
+delegate_dispatch!(WaylandState: [ZwpTabletPadRingV2: ()] => TabletManagerState);
+delegate_dispatch!(WaylandState: [ZwpTabletPadStripV2: ()] => TabletManagerState);
@@ -0,0 +1,338 @@
+// Handling of the text-input-unstable-v3, the input methods.
+
+use std::{mem, sync::Mutex};
+
+use dpi::{LogicalPosition, LogicalSize, Position, Size};
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::{
+        client::{
+            Connection, Dispatch, Proxy, QueueHandle, delegate_dispatch,
+            globals::{BindError, GlobalList},
+            protocol::{wl_seat::WlSeat, wl_surface::WlSurface},
+        },
+        protocols::wp::text_input::zv3::client::{
+            zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+            zwp_text_input_v3::{self, ContentHint, ContentPurpose, ZwpTextInputV3},
+        },
+    },
+};
+
+use crate::{Events, WaylandState};
+
+/// Input of the text from the input methods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImeEvent {
+    /// The input method is ready to send text to the window.
+    Enabled,
+    /// Text being composed, replacing the previous one, empty when the composition ends.
+    ///
+    /// The cursor is a range of bytes in the text, `None` when it should be hidden.
+    Preedit(String, Option<(usize, usize)>),
+    /// Text to insert at the cursor.
+    Commit(String),
+    /// Bytes to delete around the cursor, it comes before the commit of the same change.
+    DeleteSurrounding { before: usize, after: usize },
+    /// The input method stopped sending text to the window.
+    Disabled,
+}
+
+/// Kind of the field receiving the text, it lets the input method adapt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ImePurpose {
+    #[default]
+    Normal,
+    /// The text is hidden and not learned by the input method.
+    Password,
+    /// A hidden numeric code.
+    Pin,
+    Terminal,
+    Digits,
+    Number,
+    Phone,
+    Url,
+    Email,
+    Name,
+}
+
+impl ImePurpose {
+    fn content_type(self) -> (ContentHint, ContentPurpose) {
+        let sensitive = ContentHint::SensitiveData | ContentHint::HiddenText;
+        match self {
+            Self::Normal => (ContentHint::None, ContentPurpose::Normal),
+            Self::Password => (sensitive, ContentPurpose::Password),
+            Self::Pin => (sensitive, ContentPurpose::Pin),
+            Self::Terminal => (ContentHint::None, ContentPurpose::Terminal),
+            Self::Digits => (ContentHint::None, ContentPurpose::Digits),
+            Self::Number => (ContentHint::None, ContentPurpose::Number),
+            Self::Phone => (ContentHint::None, ContentPurpose::Phone),
+            Self::Url => (ContentHint::None, ContentPurpose::Url),
+            Self::Email => (ContentHint::None, ContentPurpose::Email),
+            Self::Name => (ContentHint::None, ContentPurpose::Name),
+        }
+    }
+}
+
+/// Input methods.
+#[derive(Debug)]
+pub struct TextInputState {
+    manager: ZwpTextInputManagerV3,
+}
+
+impl TextInputState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    pub(crate) fn get_text_input(
+        &self,
+        seat: &WlSeat,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> ZwpTextInputV3 {
+        self.manager
+            .get_text_input(seat, queue_handle, TextInputData::default())
+    }
+}
+
+/// The state of the text input requested by a window.
+#[derive(Debug, Default)]
+pub(crate) struct ImeState {
+    pub(crate) allowed: bool,
+    /// Area of the caret, where the candidates are shown.
+    pub(crate) cursor_area: Option<(Position, Size)>,
+    pub(crate) purpose: ImePurpose,
+}
+
+impl ImeState {
+    /// The cursor area in the logical coordinates of the surface, it changes with the scale when
+    /// the area was given in physical pixels.
+    fn cursor_rectangle(&self, scale_factor: f64) -> Option<(i32, i32, i32, i32)> {
+        let (position, size) = self.cursor_area?;
+        let position: LogicalPosition<i32> = position.to_logical(scale_factor);
+        let size: LogicalSize<i32> = size.to_logical(scale_factor);
+        Some((position.x, position.y, size.width, size.height))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TextInputData {
+    inner: Mutex<TextInputInner>,
+}
+
+#[derive(Debug, Default)]
+struct TextInputInner {
+    /// The surface with the text input focus.
+    surface: Option<WlSurface>,
+    /// Whether the compositor was told to enable the text input.
+    enabled: bool,
+    /// Number of commit requests, the done events carry it back.
+    commits: u32,
+    pending: PendingInput,
+    /// The preedit shown by the window.
+    preedit: Option<(String, Option<(usize, usize)>)>,
+}
+
+/// Double-buffered changes, applied on done.
+#[derive(Debug, Default)]
+struct PendingInput {
+    preedit: Option<(String, Option<(usize, usize)>)>,
+    commit: Option<String>,
+    delete: Option<(u32, u32)>,
+}
+
+impl TextInputInner {
+    fn commit(&mut self, text_input: &ZwpTextInputV3) {
+        text_input.commit();
+        self.commits = self.commits.wrapping_add(1);
+    }
+}
+
+/// Send the state of the window to the text input, it's committed right away.
+pub(crate) fn send_ime_state(text_input: &ZwpTextInputV3, ime: &ImeState, scale_factor: f64) {
+    let Some(data) = text_input.data::<TextInputData>() else {
+        return;
+    };
+    let mut inner = data.inner.lock().unwrap();
+    if inner.surface.is_none() {
+        return;
+    }
+    if ime.allowed != inner.enabled {
+        inner.enabled = ime.allowed;
+        if ime.allowed {
+            text_input.enable();
+        } else {
+            text_input.disable();
+        }
+    } else if !ime.allowed {
+        return;
+    }
+    // Enabling resets the state, so all of it is sent every time.
+    if ime.allowed {
+        let (hint, purpose) = ime.purpose.content_type();
+        text_input.set_content_type(hint, purpose);
+        if let Some((x, y, width, height)) = ime.cursor_rectangle(scale_factor) {
+            text_input.set_cursor_rectangle(x, y, width, height);
+        }
+    }
+    inner.commit(text_input);
+}
+
+impl Dispatch<ZwpTextInputManagerV3, GlobalData, WaylandState> for TextInputState {
+    fn event(
+        _: &mut WaylandState,
+        _: &ZwpTextInputManagerV3,
+        _: <ZwpTextInputManagerV3 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+impl Dispatch<ZwpTextInputV3, TextInputData, WaylandState> for TextInputState {
+    fn event(
+        state: &mut WaylandState,
+        text_input: &ZwpTextInputV3,
+        event: <ZwpTextInputV3 as Proxy>::Event,
+        data: &TextInputData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        let mut inner = data.inner.lock().unwrap();
+        match event {
+            zwp_text_input_v3::Event::Enter { surface } => {
+                inner.surface = Some(surface.clone());
+                drop(inner);
+                let id = surface.id();
+                if let Some(window) = state.windows.get_mut_by_object_id(&id) {
+                    window.text_inputs.push(text_input.clone());
+                    if window.ime.allowed {
+                        send_ime_state(text_input, &window.ime, window.scale_factor);
+                        state.events.push_back(Events::Ime(id, ImeEvent::Enabled));
+                    }
+                }
+            }
+            zwp_text_input_v3::Event::Leave { surface } => {
+                inner.surface = None;
+                inner.pending = PendingInput::default();
+                let had_preedit = inner.preedit.take().is_some();
+                if inner.enabled {
+                    inner.enabled = false;
+                    text_input.disable();
+                    inner.commit(text_input);
+                }
+                drop(inner);
+                let id = surface.id();
+                if let Some(window) = state.windows.get_mut_by_object_id(&id) {
+                    window.text_inputs.retain(|input| input != text_input);
+                    if window.ime.allowed {
+                        if had_preedit {
+                            let preedit = ImeEvent::Preedit(String::new(), None);
+                            state.events.push_back(Events::Ime(id.clone(), preedit));
+                        }
+                        state.events.push_back(Events::Ime(id, ImeEvent::Disabled));
+                    }
+                }
+            }
+            zwp_text_input_v3::Event::PreeditString {
+                text,
+                cursor_begin,
+                cursor_end,
+            } => {
+                let cursor = (cursor_begin >= 0 && cursor_end >= 0)
+                    .then_some((cursor_begin as usize, cursor_end as usize));
+                inner.pending.preedit = text.map(|text| (text, cursor));
+            }
+            zwp_text_input_v3::Event::CommitString { text } => {
+                inner.pending.commit = text;
+            }
+            zwp_text_input_v3::Event::DeleteSurroundingText {
+                before_length,
+                after_length,
+            } => {
+                inner.pending.delete = Some((before_length, after_length));
+            }
+            zwp_text_input_v3::Event::Done { serial } => {
+                let pending = mem::take(&mut inner.pending);
+                let Some(id) = inner.surface.as_ref().map(Proxy::id) else {
+                    return;
+                };
+                // The changes were made for an older state of the window, e.g. before the
+                // cursor moved, they are dropped.
+                if serial != inner.commits {
+                    return;
+                }
+                // The order of the protocol: the preedit is removed, the surrounding text
+                // deleted, the commit inserted and the new preedit shown.
+                let mut events = Vec::new();
+                let mut shown = inner.preedit.take();
+                if shown.is_some() && (pending.delete.is_some() || pending.commit.is_some()) {
+                    events.push(ImeEvent::Preedit(String::new(), None));
+                    shown = None;
+                }
+                if let Some((before, after)) = pending.delete {
+                    events.push(ImeEvent::DeleteSurrounding {
+                        before: before as usize,
+                        after: after as usize,
+                    });
+                }
+                if let Some(text) = pending.commit {
+                    events.push(ImeEvent::Commit(text));
+                }
+                if pending.preedit != shown {
+                    let (text, cursor) = pending.preedit.clone().unwrap_or_default();
+                    events.push(ImeEvent::Preedit(text, cursor));
+                }
+                inner.preedit = pending.preedit;
+                drop(inner);
+                for event in events {
+                    state.events.push_back(Events::Ime(id.clone(), event));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+delegate_dispatch!(WaylandState: [ZwpTextInputManagerV3: GlobalData] => TextInputState);
+delegate_dispatch!(WaylandState: [ZwpTextInputV3: TextInputData] => TextInputState);
+
+#[cfg(test)]
+mod tests {
+    use dpi::{PhysicalPosition, PhysicalSize};
+
+    use super::*;
+
+    #[test]
+    fn cursor_rectangle_follows_the_scale() {
+        let ime = ImeState {
+            allowed: true,
+            cursor_area: Some((
+                PhysicalPosition::new(200, 100).into(),
+                PhysicalSize::new(20, 40).into(),
+            )),
+            purpose: ImePurpose::Normal,
+        };
+        assert_eq!(ime.cursor_rectangle(1.0), Some((200, 100, 20, 40)));
+        assert_eq!(ime.cursor_rectangle(2.0), Some((100, 50, 10, 20)));
+    }
+
+    #[test]
+    fn logical_cursor_rectangle_ignores_the_scale() {
+        let ime = ImeState {
+            allowed: true,
+            cursor_area: Some((
+                LogicalPosition::new(10, 20).into(),
+                LogicalSize::new(5, 15).into(),
+            )),
+            purpose: ImePurpose::Normal,
+        };
+        assert_eq!(ime.cursor_rectangle(1.0), ime.cursor_rectangle(2.0));
+        assert_eq!(ImeState::default().cursor_rectangle(2.0), None);
+    }
+}
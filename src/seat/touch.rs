@@ -1,72 +1,387 @@
-use dpi::LogicalPosition;
+use std::time::Duration;
+
+use dpi::{LogicalPosition, LogicalSize};
 use smithay_client_toolkit::{
-    reexports::client::{
-        Connection, QueueHandle,
-        protocol::{wl_surface::WlSurface, wl_touch::WlTouch},
+    compositor::SurfaceData,
+    reexports::{
+        client::{
+            Connection, Proxy, QueueHandle,
+            backend::ObjectId,
+            protocol::{wl_surface::WlSurface, wl_touch::WlTouch},
+        },
+        csd_frame::{DecorationsFrame, FrameAction, FrameClick},
     },
-    seat::touch::TouchHandler,
+    seat::touch::{TouchData, TouchHandler},
+};
+use ui_events::pointer::{
+    PointerButton, PointerEvent, PointerId, PointerInfo, PointerState, PointerUpdate,
 };
 
-use crate::WaylandState;
+use crate::{
+    Events, WaylandState, WindowId,
+    seat::{
+        DEFAULT_PRESSURE, FrameTouch, TouchPoint, WlTouchId, pointer::push_move, serial::SerialKind,
+    },
+};
+
+/// Distance in logical pixels a finger travels on the titlebar before the window moves.
+const TOUCH_MOVE_THRESHOLD: f64 = 4.;
+
+/// Every contact is a pointer of its own, with the type of its touch device.
+fn contact_info(device: PointerInfo, touch_id: u32, id: i32) -> PointerInfo {
+    PointerInfo {
+        pointer_id: PointerId::new(((touch_id as u64) << 32) | id as u32 as u64),
+        ..device
+    }
+}
 
 impl TouchHandler for WaylandState {
     fn down(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _touch: &WlTouch,
-        _serial: u32,
-        _time: u32,
-        _surface: WlSurface,
-        _id: i32,
+        touch: &WlTouch,
+        serial: u32,
+        time: u32,
+        surface: WlSurface,
+        id: i32,
         position: (f64, f64),
     ) {
-        let _position = LogicalPosition::<f64>::from(position);
+        let (Some(data), Some(info)) = (
+            touch.data::<TouchData>(),
+            self.seat_state.pointers.info(touch.id()),
+        ) else {
+            return;
+        };
+        let seat_id = data.seat().id();
+        let surface_id = surface.id();
+        self.seat_state
+            .serials
+            .record(SerialKind::TouchDown, data.seat(), serial);
+        let info = contact_info(info, touch.id().protocol_id(), id);
+        let parent_id = surface
+            .data::<SurfaceData>()
+            .and_then(|data| data.parent_surface().map(|s| s.id()))
+            .unwrap_or(surface_id.clone());
+        let Some(window) = self.windows.get_mut_by_object_id(&parent_id) else {
+            return;
+        };
+        if let Some(touch_kind) = self.seat_state.pointers.kind(&touch.id()) {
+            window.pointer_pressed(&touch_kind, id as u32, serial);
+        }
+        // The subsurfaces of the application are part of the window, unlike the ones of the
+        // decorations.
+        let offset = match window.subsurface_position(&surface_id) {
+            Some(offset) => (f64::from(offset.x), f64::from(offset.y)),
+            None if parent_id != surface_id => {
+                // Decoration touches act like the primary button.
+                let action = window.window_frame.as_mut().and_then(|frame| {
+                    frame.click_point_moved(Duration::ZERO, &surface_id, position.0, position.1);
+                    frame.on_click(Duration::from_millis(time as u64), FrameClick::Normal, true)
+                });
+                // The frame only sees the presses, so the double taps are checked against the
+                // completed taps instead.
+                let action = match action {
+                    Some(FrameAction::Maximize | FrameAction::UnMaximize)
+                        if !window.titlebar_double_tap(time, position) =>
+                    {
+                        Some(FrameAction::Move)
+                    }
+                    action => action,
+                };
+                // A tap on the titlebar is not a move, so the taps are left to the frame.
+                let pending_move =
+                    matches!(action, Some(FrameAction::Move)).then_some((serial, position));
+                let action = action.filter(|_| pending_move.is_none());
+                if action.is_some_and(|action| window.frame_action(data.seat(), serial, action)) {
+                    self.windows.close_request.insert(parent_id.clone());
+                }
+                self.windows.redraw_request.insert(parent_id.clone());
+                self.seat_state.pointers.touches.insert(
+                    (touch.id(), id),
+                    TouchPoint {
+                        surface: parent_id,
+                        info,
+                        state: PointerState::default(),
+                        offset: (0.0, 0.0),
+                        frame: Some(FrameTouch {
+                            surface: surface_id,
+                            position,
+                            pending_move,
+                            time,
+                        }),
+                    },
+                );
+                return;
+            }
+            None => (0.0, 0.0),
+        };
+        let position = (position.0 + offset.0, position.1 + offset.1);
+
+        let state = PointerState {
+            time: time as u64,
+            position: LogicalPosition::<f64>::from(position).to_physical(window.scale_factor),
+            modifiers: self.seat_state.seat_modifiers(&seat_id),
+            // Touch screens don't report the pressure.
+            pressure: DEFAULT_PRESSURE,
+            ..Default::default()
+        };
+        self.seat_state.pointers.touches.insert(
+            (touch.id(), id),
+            TouchPoint {
+                surface: parent_id.clone(),
+                info,
+                state: state.clone(),
+                offset,
+                frame: None,
+            },
+        );
+        self.events.push_back(Events::Pointer(
+            parent_id,
+            PointerEvent::Down {
+                button: Some(PointerButton::Primary),
+                pointer: info,
+                state,
+            },
+        ));
     }
 
     fn up(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _touch: &WlTouch,
-        _serial: u32,
-        _time: u32,
-        _id: i32,
+        touch: &WlTouch,
+        serial: u32,
+        time: u32,
+        id: i32,
     ) {
+        let Some(data) = touch.data::<TouchData>() else {
+            return;
+        };
+        let touch_id = touch.id();
+        if let Some(mut point) = self
+            .seat_state
+            .pointers
+            .touches
+            .remove(&(touch_id.clone(), id))
+        {
+            if let (Some(window), Some(touch_kind)) = (
+                self.windows.get_mut_by_object_id(&point.surface),
+                self.seat_state.pointers.kind(&touch_id),
+            ) {
+                window.pointer_released(&touch_kind, id as u32);
+            }
+            if let Some(frame_touch) = point.frame {
+                self.frame_touch_up(data, serial, time, point.surface, frame_touch);
+                return;
+            }
+            point.state.time = time as u64;
+            point.state.pressure = 0.0;
+            self.events.push_back(Events::Pointer(
+                point.surface,
+                PointerEvent::Up {
+                    button: Some(PointerButton::Primary),
+                    pointer: point.info,
+                    state: point.state,
+                },
+            ));
+        }
     }
 
     fn motion(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _touch: &WlTouch,
-        _time: u32,
-        _id: i32,
-        _position: (f64, f64),
+        touch: &WlTouch,
+        time: u32,
+        id: i32,
+        position: (f64, f64),
     ) {
-        // let position = LogicalPosition::<f64>::from(position);
+        let Some(data) = touch.data::<TouchData>() else {
+            return;
+        };
+        let Some(point) = self.seat_state.pointers.touches.get_mut(&(touch.id(), id)) else {
+            return;
+        };
+        if let Some(frame_touch) = point.frame.as_mut() {
+            frame_touch.position = position;
+            let Some(window) = self.windows.get_mut_by_object_id(&point.surface) else {
+                return;
+            };
+            let dirty = window.window_frame.as_mut().is_some_and(|frame| {
+                frame.click_point_moved(
+                    Duration::ZERO,
+                    &frame_touch.surface,
+                    position.0,
+                    position.1,
+                );
+                frame.is_dirty()
+            });
+            if let Some((serial, start)) = frame_touch.pending_move
+                && (position.0 - start.0).hypot(position.1 - start.1) >= TOUCH_MOVE_THRESHOLD
+            {
+                frame_touch.pending_move = None;
+                window.frame_action(data.seat(), serial, FrameAction::Move);
+            }
+            if dirty {
+                self.windows.redraw_request.insert(point.surface.clone());
+            }
+            return;
+        }
+        let Some(window) = self.windows.get_by_object_id(&point.surface) else {
+            return;
+        };
+
+        point.state.time = time as u64;
+        let position = (position.0 + point.offset.0, position.1 + point.offset.1);
+        point.state.position =
+            LogicalPosition::<f64>::from(position).to_physical(window.scale_factor);
+        push_move(
+            &mut self.events,
+            point.surface.clone(),
+            PointerUpdate {
+                pointer: point.info,
+                current: point.state.clone(),
+                coalesced: Vec::new(),
+                predicted: Vec::new(),
+            },
+        );
     }
 
     fn shape(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _touch: &WlTouch,
-        _id: i32,
-        _major: f64,
-        _minor: f64,
+        touch: &WlTouch,
+        id: i32,
+        major: f64,
+        minor: f64,
     ) {
+        let Some(point) = self.seat_state.pointers.touch_point_mut(touch, id) else {
+            return;
+        };
+        if let Some(window) = self.windows.get_by_object_id(&point.surface) {
+            point.state.contact_geometry =
+                LogicalSize::new(major, minor).to_physical(window.scale_factor);
+        }
     }
 
     fn orientation(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _touch: &WlTouch,
-        _id: i32,
-        _orientation: f64,
+        touch: &WlTouch,
+        id: i32,
+        orientation: f64,
+    ) {
+        if let Some(point) = self.seat_state.pointers.touch_point_mut(touch, id) {
+            // Angle of the major axis from the surface y axis, clockwise.
+            point.state.orientation.azimuth = orientation.to_radians() as f32;
+        }
+    }
+
+    fn cancel(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, touch: &WlTouch) {
+        // The cancel only applies to the touch sequence of the device sending it.
+        self.cancel_touches(&touch.id());
+    }
+}
+
+impl WaylandState {
+    /// The touch points down on the window, as last reported to the application.
+    ///
+    /// Touches on the decorations are not included.
+    pub fn active_touches(
+        &self,
+        window_id: WindowId,
+    ) -> impl Iterator<Item = (PointerInfo, PointerState)> + '_ {
+        let surface = self.windows.id_converter.get(&window_id);
+        self.seat_state
+            .pointers
+            .touches
+            .values()
+            .filter(move |point| point.frame.is_none() && Some(&point.surface) == surface)
+            .map(|point| (point.info, point.state.clone()))
+    }
+
+    /// Drop the touch points of the device, notifying the windows they were on.
+    pub(crate) fn cancel_touches(&mut self, touch_id: &WlTouchId) {
+        for point in self.seat_state.pointers.take_touches(touch_id) {
+            if point.frame.is_some() {
+                if let Some(frame) = self
+                    .windows
+                    .get_mut_by_object_id(&point.surface)
+                    .and_then(|window| window.window_frame.as_mut())
+                {
+                    frame.click_point_left();
+                }
+                continue;
+            }
+            self.events.push_back(Events::Pointer(
+                point.surface,
+                PointerEvent::Cancel(point.info),
+            ));
+        }
+    }
+
+    /// Release the decorations under the lifted finger.
+    fn frame_touch_up(
+        &mut self,
+        data: &TouchData,
+        serial: u32,
+        time: u32,
+        window_id: ObjectId,
+        frame_touch: FrameTouch,
     ) {
+        let Some(window) = self.windows.get_mut_by_object_id(&window_id) else {
+            return;
+        };
+        // A press on the titlebar which was not dragged is a tap.
+        if let Some((_, position)) = frame_touch.pending_move {
+            window.last_titlebar_tap = Some((frame_touch.time, position));
+        }
+        let (x, y) = frame_touch.position;
+        let action = window.window_frame.as_mut().and_then(|frame| {
+            // Off the pressed button the release does nothing, so dragging away cancels.
+            frame.click_point_moved(Duration::ZERO, &frame_touch.surface, x, y);
+            let action = frame.on_click(
+                Duration::from_millis(time as u64),
+                FrameClick::Normal,
+                false,
+            );
+            // Nothing hovers the frame once the finger is lifted.
+            frame.click_point_left();
+            action
+        });
+        if action.is_some_and(|action| window.frame_action(data.seat(), serial, action)) {
+            self.windows.close_request.insert(window_id.clone());
+        }
+        self.windows.redraw_request.insert(window_id);
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay_client_toolkit::seat::Capability;
+    use ui_events::pointer::PointerType;
+
+    use super::*;
+    use crate::seat::{device_hash, device_info};
 
-    fn cancel(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _touch: &WlTouch) {}
+    #[test]
+    fn touch_contacts_are_touch_pointers() {
+        let device = device_info(Capability::Touch, 7, device_hash("seat0/touch"));
+        assert_eq!(device.pointer_type, PointerType::Touch);
+
+        let first = contact_info(device, 7, 0);
+        let second = contact_info(device, 7, 1);
+        for contact in [first, second] {
+            assert_eq!(contact.pointer_type, PointerType::Touch);
+            assert_eq!(contact.persistent_device_id, device.persistent_device_id);
+            assert_ne!(contact.pointer_id, device.pointer_id);
+        }
+        assert_ne!(first.pointer_id, second.pointer_id);
+
+        let mouse = device_info(Capability::Pointer, 8, device_hash("seat0/pointer"));
+        assert_eq!(mouse.pointer_type, PointerType::Mouse);
+    }
 }
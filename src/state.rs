@@ -1,22 +1,33 @@
-use std::{collections::VecDeque, rc::Rc, sync::Arc};
+use std::{
+    collections::VecDeque,
+    env,
+    num::NonZeroU32,
+    rc::Rc,
+    sync::{Arc, mpsc},
+    time::Duration,
+};
 
-use accesskit_unix::Adapter;
 use dpi::{LogicalSize, PhysicalSize};
 use sctk_adwaita::AdwaitaFrame;
 use smithay_client_toolkit::{
-    activation::{ActivationHandler as WlActivationHandler, ActivationState, RequestData},
-    compositor::{CompositorHandler, CompositorState, Region},
-    delegate_activation, delegate_compositor, delegate_keyboard, delegate_output, delegate_pointer,
-    delegate_registry, delegate_seat, delegate_shm, delegate_subcompositor, delegate_touch,
-    delegate_xdg_shell, delegate_xdg_window,
+    activation::{ActivationState, RequestData},
+    compositor::{CompositorHandler, CompositorState, SurfaceData},
+    data_device_manager::{DataDeviceManagerState, data_source::CopyPasteSource},
+    delegate_compositor, delegate_data_device, delegate_keyboard, delegate_output,
+    delegate_pointer, delegate_pointer_constraints, delegate_registry, delegate_seat, delegate_shm,
+    delegate_subcompositor, delegate_touch, delegate_xdg_shell, delegate_xdg_window,
     output::{OutputHandler, OutputState},
     reexports::{
-        calloop::{self, EventLoop, LoopHandle, RegistrationToken, channel::Sender as WlSender},
+        calloop::{
+            self, EventLoop, LoopHandle, RegistrationToken,
+            channel::Sender as WlSender,
+            timer::{TimeoutAction, Timer},
+        },
         calloop_wayland_source::WaylandSource,
         client::{
-            Connection, Proxy, QueueHandle,
+            Connection, EventQueue, Proxy, QueueHandle,
             backend::ObjectId,
-            globals::registry_queue_init,
+            globals::{GlobalList, registry_queue_init},
             protocol::{
                 wl_output::{Transform, WlOutput},
                 wl_pointer::WlPointer,
@@ -27,7 +38,7 @@ use smithay_client_toolkit::{
     },
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
-    seat::{SeatState as WlSeatState, pointer::PointerData},
+    seat::{SeatState as WlSeatState, pointer_constraints::PointerConstraintsState},
     shell::{
         WaylandSurface,
         xdg::{
@@ -38,13 +49,105 @@ use smithay_client_toolkit::{
     shm::{Shm, ShmHandler},
     subcompositor::SubcompositorState,
 };
-use tracing::error;
+use tracing::{debug, error, warn};
 
+#[cfg(feature = "data-control")]
+use crate::data_device::data_control::DataControlState;
 use crate::{
-    AccesskitEvents, AccesskitHandler, Events, ViewporterState, WaylandWindow, WindowAttributes,
-    WindowId, WindowsRegistry,
-    seat::{PointerKind, SeatState},
+    AccesskitEvents, ClipboardContent, Decorations, Events, Monitor, MonitorEvent, ViewporterState,
+    WaylandWindow, WindowAttributes, WindowId, WindowsRegistry,
+    activation::ActivationRequest,
+    content_type::ContentTypeState,
+    data_device::{TransferLimits, dnd::OutgoingDrag},
+    dialog::DialogState,
+    idle_inhibit::IdleInhibitState,
+    presentation::PresentationState,
+    seat::{
+        PointerKind, SeatState, gesture::PointerGesturesState,
+        shortcuts_inhibit::ShortcutsInhibitState, tablet::TabletManagerState,
+        text_input::TextInputState,
+    },
+    tearing_control::TearingControlState,
+    window::{
+        DEFAULT_SCALE_FACTOR, ShellSurface, frame_hidden, min_surface_size,
+        proxy::{CommandSender, WindowCommand, WindowProxy},
+    },
 };
+#[cfg(feature = "layer-shell")]
+use smithay_client_toolkit::shell::wlr_layer::LayerShell;
+
+/// Time the initial fullscreen waits for its named output to be connected.
+const FULLSCREEN_OUTPUT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A request setting a toplevel up, in the order they are sent.
+#[derive(Debug, Clone, PartialEq)]
+enum ToplevelRequest {
+    Title(String),
+    AppId(String),
+    DecorationMode(DecorationMode),
+    MinSize(LogicalSize<u32>),
+    MaxSize(Option<LogicalSize<u32>>),
+    Maximized,
+    Fullscreen(Option<WlOutput>),
+    /// In order for the window to be mapped, we need to perform an initial commit with no
+    /// attached buffer. For more info, see WaylandSurface::commit
+    ///
+    /// The compositor will respond with an initial configure that we can then use to present to
+    /// the window with the correct options.
+    InitialCommit,
+}
+
+impl ToplevelRequest {
+    fn send(self, toplevel: &Window) {
+        match self {
+            Self::Title(title) => toplevel.set_title(title),
+            Self::AppId(app_id) => toplevel.set_app_id(app_id),
+            Self::DecorationMode(mode) => toplevel.request_decoration_mode(Some(mode)),
+            Self::MinSize(size) => toplevel.set_min_size(Some(size.into())),
+            Self::MaxSize(size) => toplevel.set_max_size(size.map(Into::into)),
+            Self::Maximized => toplevel.set_maximized(),
+            Self::Fullscreen(output) => toplevel.set_fullscreen(output.as_ref()),
+            Self::InitialCommit => toplevel.wl_surface().commit(),
+        }
+    }
+}
+
+/// The requests of a new toplevel. The app id, the size limits and the states are set before the
+/// initial commit, as the compositors match their window rules on the first state.
+fn toplevel_requests(attributes: &WindowAttributes, scale_factor: f64) -> Vec<ToplevelRequest> {
+    let mut requests = vec![ToplevelRequest::Title(attributes.title.clone())];
+    if let Some(name) = attributes.app_name.as_ref() {
+        requests.push(ToplevelRequest::AppId(name.general.clone()));
+    }
+    match attributes.decorations {
+        Decorations::Server => {
+            requests.push(ToplevelRequest::DecorationMode(DecorationMode::Server))
+        }
+        // Keep the compositor from drawing anything around the surface.
+        Decorations::None => requests.push(ToplevelRequest::DecorationMode(DecorationMode::Client)),
+        Decorations::Client => (),
+    }
+    let min_size = attributes
+        .min_surface_size
+        .map(|size| size.to_logical(scale_factor));
+    requests.push(ToplevelRequest::MinSize(min_surface_size(min_size)));
+    requests.push(ToplevelRequest::MaxSize(
+        attributes
+            .max_surface_size
+            .map(|size| size.to_logical(scale_factor)),
+    ));
+    if attributes.maximized {
+        requests.push(ToplevelRequest::Maximized);
+    }
+    if attributes.fullscreen {
+        let monitor = attributes.fullscreen_monitor.as_ref();
+        requests.push(ToplevelRequest::Fullscreen(
+            monitor.and_then(Monitor::live_output).cloned(),
+        ));
+    }
+    requests.push(ToplevelRequest::InitialCommit);
+    requests
+}
 
 pub struct WaylandState {
     pub conn: Connection,
@@ -61,6 +164,24 @@ pub struct WaylandState {
     pub subcompositor_state: Option<Arc<SubcompositorState>>,
 
     pub viewport_state: Option<ViewporterState>,
+    pub pointer_gestures: Option<PointerGesturesState>,
+    pub pointer_constraints: Arc<PointerConstraintsState>,
+    pub tablet_manager: Option<TabletManagerState>,
+    pub shortcuts_inhibit: Option<ShortcutsInhibitState>,
+    pub presentation: Option<PresentationState>,
+    pub tearing_control: Option<TearingControlState>,
+    pub content_type: Option<ContentTypeState>,
+    pub idle_inhibit: Option<IdleInhibitState>,
+    pub dialog: Option<DialogState>,
+    pub text_input: Option<TextInputState>,
+    pub data_device_manager: Option<DataDeviceManagerState>,
+    #[cfg(feature = "data-control")]
+    pub data_control: Option<DataControlState>,
+    /// The clipboard content offered by the application.
+    pub(crate) selection: Option<(CopyPasteSource, ClipboardContent)>,
+    /// The drag started by the application.
+    pub(crate) drag_source: Option<OutgoingDrag>,
+    pub(crate) transfer_limits: TransferLimits,
 
     /// The WlRegistry.
     pub registry_state: RegistryState,
@@ -77,22 +198,31 @@ pub struct WaylandState {
 
     /// The XDG shell that is used for windows.
     pub xdg_shell: XdgShell,
+    /// The shell of the panels and the overlays.
+    #[cfg(feature = "layer-shell")]
+    pub layer_shell: Option<LayerShell>,
 
     // TODO: внедрить поле surface
     // surfaces: HashMap<HandleId, RenderSurface<'a>>,
     pub windows: WindowsRegistry,
     pub activation_state: Option<ActivationState>,
+    /// The token of the launcher, used by the first window.
+    pub(crate) activation_token: Option<String>,
 
     pub accesskit_events: VecDeque<AccesskitEvents>,
     pub events: VecDeque<Events>,
 
+    /// Commands sent through window proxies, queued right away to be applied before the draws.
+    pub(crate) window_command_sender: CommandSender,
+    window_commands: mpsc::Receiver<(WindowId, WindowCommand)>,
+
     /// Loop handle to re-register event sources, such as keyboard repeat.
     /// Also need to close app correctly, if user event source is used.
     // pub loop_handle: LoopHandle<'static, Self>,
 
     /// Queue handle
     pub queue_handle: QueueHandle<Self>,
-    loop_handle: LoopHandle<'static, Self>,
+    pub(crate) loop_handle: LoopHandle<'static, Self>,
 
     // Client side decorations
     pub csd_fails: bool,
@@ -108,6 +238,15 @@ impl WaylandState {
 
         // Enumerate the list of globals to get the protocols the server implements.
         let (globals, event_queue) = registry_queue_init(&conn).unwrap();
+        Self::with_globals(conn, &globals, event_queue)
+    }
+
+    /// The state of a connection whose globals are already listed.
+    pub(crate) fn with_globals(
+        conn: Connection,
+        globals: &GlobalList,
+        event_queue: EventQueue<Self>,
+    ) -> (Self, EventLoop<'static, WaylandState>) {
         let queue_handle = event_queue.handle();
         let event_loop: EventLoop<'static, WaylandState> =
             EventLoop::try_new().expect("Failed to initialize the event loop!");
@@ -118,23 +257,56 @@ impl WaylandState {
         // The compositor (not to be confused with the server which is commonly called the compositor) allows
         // configuring surfaces to be presented.
         let compositor =
-            CompositorState::bind(&globals, &queue_handle).expect("wl_compositor not available");
+            CompositorState::bind(globals, &queue_handle).expect("wl_compositor not available");
         let subcompositor =
-            SubcompositorState::bind(compositor.wl_compositor().clone(), &globals, &queue_handle)
-                .map(|sbcr| Arc::new(sbcr))
+            SubcompositorState::bind(compositor.wl_compositor().clone(), globals, &queue_handle)
+                .map(Arc::new)
                 .ok();
         // For desktop platforms, the XDG shell is the standard protocol for creating desktop windows.
-        let xdg_shell =
-            XdgShell::bind(&globals, &queue_handle).expect("xdg shell is not available");
+        let xdg_shell = XdgShell::bind(globals, &queue_handle).expect("xdg shell is not available");
         // Since we are not using the GPU in this example, we use wl_shm to allow software rendering to a buffer
         // we share with the compositor process.
-        let shm = Shm::bind(&globals, &queue_handle).expect("wl shm is not available.");
+        let shm = Shm::bind(globals, &queue_handle).expect("wl shm is not available.");
+        #[cfg(feature = "layer-shell")]
+        let layer_shell = LayerShell::bind(globals, &queue_handle).ok();
         // If the compositor supports xdg-activation it probably wants us to use it to get focus
-        let activation_state = ActivationState::bind(&globals, &queue_handle).ok();
+        let activation_state = ActivationState::bind(globals, &queue_handle).ok();
+        // The token is meant for this process only. Changing the environment is unsound with
+        // other threads around, so it's left to the application to keep it from the children.
+        let activation_token = env::var("XDG_ACTIVATION_TOKEN").ok();
         // Suggest min allocation for our app.
         // let image_pool = SlotPool::new(2, &shm).expect("Failed to create pool");
-        let seat_state = WlSeatState::new(&globals, &queue_handle);
-        let viewport_state = ViewporterState::new(&globals, &queue_handle).ok();
+        let seat_state = WlSeatState::new(globals, &queue_handle);
+        let viewport_state = ViewporterState::new(globals, &queue_handle).ok();
+        let pointer_gestures = PointerGesturesState::new(globals, &queue_handle).ok();
+        let pointer_constraints = PointerConstraintsState::bind(globals, &queue_handle);
+        let tablet_manager = TabletManagerState::new(globals, &queue_handle).ok();
+        let shortcuts_inhibit = ShortcutsInhibitState::new(globals, &queue_handle).ok();
+        let presentation = PresentationState::new(globals, &queue_handle).ok();
+        let tearing_control = TearingControlState::new(globals, &queue_handle).ok();
+        let content_type = ContentTypeState::new(globals, &queue_handle).ok();
+        let idle_inhibit = IdleInhibitState::new(globals, &queue_handle).ok();
+        let dialog = DialogState::new(globals, &queue_handle).ok();
+        let text_input = TextInputState::new(globals, &queue_handle).ok();
+        let data_device_manager = DataDeviceManagerState::bind(globals, &queue_handle).ok();
+        #[cfg(feature = "data-control")]
+        let mut data_control = DataControlState::new(globals, &queue_handle).ok();
+        let mut seats = SeatState::new(seat_state);
+        // The seats found on startup are not announced by the seat handler.
+        for seat in seats.seat.seats() {
+            if let Some(tablet_manager) = tablet_manager.as_ref() {
+                let tablet_seat = tablet_manager.get_tablet_seat(&seat, &queue_handle);
+                seats.tablets.add_seat(seat.id(), tablet_seat);
+            }
+            if let Some(manager) = data_device_manager.as_ref() {
+                let device = manager.get_data_device(&queue_handle, &seat);
+                seats.data_devices.insert(seat.id(), device);
+            }
+            #[cfg(feature = "data-control")]
+            if let Some(data_control) = data_control.as_mut() {
+                data_control.add_seat(&seat, &queue_handle);
+            }
+        }
         let (event_sender, events_channel) = calloop::channel::channel();
         let event_source_token: RegistrationToken = event_loop
             .handle()
@@ -153,26 +325,59 @@ impl WaylandState {
                 }
             })
             .expect("Faild to insert accesskit events into calloop channel");
+        // The commands are drained after the handler callbacks, the ping only wakes the loop up.
+        let (window_command_sender, window_commands) = mpsc::channel();
+        let (ping, ping_source) =
+            calloop::ping::make_ping().expect("Failed to create the window commands ping");
+        let commands_source_token: RegistrationToken = event_loop
+            .handle()
+            .insert_source(ping_source, |_, _, _| {})
+            .expect("Faild to insert window commands ping into calloop");
         (
             Self {
                 conn,
                 event_sender,
                 accesskit_event_sender,
-                event_source_token: vec![event_source_token, accesskit_source_token],
+                event_source_token: vec![
+                    event_source_token,
+                    accesskit_source_token,
+                    commands_source_token,
+                ],
                 running: false,
                 compositor_state: Arc::new(compositor),
                 subcompositor_state: subcompositor,
                 viewport_state,
-                registry_state: RegistryState::new(&globals),
-                seat_state: SeatState::new(seat_state),
+                pointer_gestures,
+                pointer_constraints: Arc::new(pointer_constraints),
+                tablet_manager,
+                shortcuts_inhibit,
+                presentation,
+                tearing_control,
+                content_type,
+                idle_inhibit,
+                dialog,
+                text_input,
+                data_device_manager,
+                #[cfg(feature = "data-control")]
+                data_control,
+                selection: None,
+                drag_source: None,
+                transfer_limits: TransferLimits::default(),
+                registry_state: RegistryState::new(globals),
+                seat_state: seats,
                 last_output: None,
-                output_state: OutputState::new(&globals, &queue_handle),
+                output_state: OutputState::new(globals, &queue_handle),
                 shm,
                 xdg_shell,
+                #[cfg(feature = "layer-shell")]
+                layer_shell,
                 windows: WindowsRegistry::default(),
                 activation_state,
+                activation_token,
                 accesskit_events: VecDeque::new(),
                 events: VecDeque::new(),
+                window_command_sender: CommandSender::new(window_command_sender, ping),
+                window_commands,
                 queue_handle,
                 loop_handle: event_loop.handle(),
                 csd_fails: true,
@@ -182,16 +387,12 @@ impl WaylandState {
         )
     }
 
-    pub fn create_window(&mut self, (id, new_window): (WindowId, WindowAttributes)) {
+    pub fn create_window(&mut self, (id, mut new_window): (WindowId, WindowAttributes)) {
         let surface = self.compositor_state.create_surface(&self.queue_handle);
-        let viewport = self
-            .viewport_state
-            .as_ref()
-            .map(|v| v.get_viewport(&surface, &self.queue_handle));
         let wl_id = surface.id();
         let decorations = match new_window.decorations {
-            true => WindowDecorations::RequestServer,
-            false => WindowDecorations::RequestClient,
+            Decorations::Server => WindowDecorations::RequestServer,
+            Decorations::Client | Decorations::None => WindowDecorations::RequestClient,
         };
 
         // Здесь окно сразу оборачивается в toplevel
@@ -199,67 +400,185 @@ impl WaylandState {
             .xdg_shell
             .create_window(surface, decorations, &self.queue_handle);
 
-        let accesskit = AccesskitHandler::new(wl_id.clone(), self.accesskit_event_sender.clone());
-
-        let accesskit_adapter = Adapter::new(accesskit.clone(), accesskit.clone(), accesskit);
+        // A token given to the application focuses the window right away, otherwise one is
+        // requested. The token of the launcher is only meant for the first window, even when
+        // that one has its own token.
+        let launcher_token = self.activation_token.take();
+        let token = new_window.activation_token.take().or(launcher_token);
+        let app_id = new_window.app_id.clone();
+        // The named output is resolved now, or the fullscreen waits for it.
+        let mut pending_fullscreen = None;
+        if let Some(name) = new_window
+            .fullscreen_output_name
+            .take()
+            .filter(|_| new_window.fullscreen)
+        {
+            match self
+                .windows
+                .monitors
+                .iter()
+                .find(|monitor| monitor.name() == Some(name.as_str()))
+            {
+                Some(monitor) => new_window.fullscreen_monitor = Some(monitor.clone()),
+                None => {
+                    new_window.fullscreen = false;
+                    pending_fullscreen = Some(name);
+                }
+            }
+        }
 
-        window.set_title(&new_window.title);
-        // In order for the window to be mapped, we need to perform an initial commit with no attached buffer.
-        // For more info, see WaylandSurface::commit
-        //
-        // The compositor will respond with an initial configure that we can then use to present to the window with
-        // the correct options.
-        window.commit();
+        let scale_factor = self.initial_scale_factor();
+        let requests = toplevel_requests(&new_window, scale_factor);
+        let window = WaylandWindow::new(
+            self,
+            ShellSurface::Toplevel(window),
+            scale_factor,
+            id,
+            new_window,
+        );
+        let xdg_window = &window.immutable.window;
+        if let Some(toplevel) = xdg_window.toplevel() {
+            for request in requests {
+                request.send(toplevel);
+            }
+        }
 
-        // To request focus, we first need to request a token. When we create a window it should
-        // catch a focus, or this block should be deleted
         if let Some(activation) = self.activation_state.as_ref() {
-            activation.request_token(
-                &self.queue_handle,
-                RequestData {
-                    seat_and_serial: None,
-                    surface: Some(window.wl_surface().clone()),
-                    app_id: Some(new_window.app_id.clone()),
-                },
-            )
+            match token {
+                Some(token) => activation.activate::<WaylandState>(xdg_window.wl_surface(), token),
+                None => activation.request_token_with_data::<WaylandState, _>(
+                    &self.queue_handle,
+                    ActivationRequest::new(RequestData {
+                        seat_and_serial: self
+                            .seat_state
+                            .serials
+                            .latest_focus_serial()
+                            .map(|(seat, serial)| (seat.clone(), serial)),
+                        surface: Some(xdg_window.wl_surface().clone()),
+                        app_id: Some(app_id),
+                    }),
+                ),
+            }
         }
 
-        self.windows.insert(
-            id,
-            wl_id,
-            WaylandWindow::new(
-                window,
-                self.last_output.as_ref(),
-                id,
-                new_window,
-                self.conn.display(),
-                self.event_sender.clone(),
-                accesskit_adapter,
-                Region::new(&*self.compositor_state).ok(),
-                viewport,
-            ),
-        );
+        self.windows.insert(id, wl_id.clone(), window);
+        if let Some(name) = pending_fullscreen {
+            self.defer_fullscreen(wl_id, name);
+        }
+    }
+
+    /// Make the window fullscreen once the named output is connected, or on the choice of the
+    /// compositor after a timeout.
+    fn defer_fullscreen(&mut self, id: ObjectId, name: String) {
+        let timer = Timer::from_duration(FULLSCREEN_OUTPUT_TIMEOUT);
+        let timer_id = id.clone();
+        let inserted = self.loop_handle.insert_source(timer, move |_, _, state| {
+            if let Some(window) = state.windows.get_mut_by_object_id(&timer_id)
+                && let Some((name, _)) = window.pending_fullscreen.take()
+            {
+                warn!("Output {name} is not connected, the compositor picks the fullscreen one");
+                window.set_fullscreen(None);
+            }
+            TimeoutAction::Drop
+        });
+        match (inserted, self.windows.get_mut_by_object_id(&id)) {
+            (Ok(token), Some(window)) => window.pending_fullscreen = Some((name, token)),
+            (Ok(_), None) => {}
+            (Err(err), window) => {
+                error!("Failed to wait for output {name}: {err}");
+                if let Some(window) = window {
+                    window.set_fullscreen(None);
+                }
+            }
+        }
     }
 
-    pub fn close_window(&mut self, id: &ObjectId) -> WindowId {
-        // Panic, if there is no windows to remove
-        let id = self.windows.remove(&id);
+    /// Returns whether the surface is the one of a window.
+    fn set_scale_factor(&mut self, id: ObjectId, new_factor: f64) -> bool {
+        let Some(window) = self.windows.get_mut_by_object_id(&id) else {
+            return false;
+        };
+        let changed = window.scale_factor != new_factor;
+        window.scale_factor = new_factor;
+        window.apply_pending_physical_size();
+        if changed {
+            window.reload_confine_region();
+            window.reload_viewport();
+            if window.ime.cursor_area.is_some() {
+                window.send_ime_state();
+            }
+            self.windows.rescale_request.insert(id);
+        }
+        true
+    }
+
+    /// Scale of the output the new windows likely map on, so the first frame is drawn at the
+    /// right scale.
+    pub(crate) fn initial_scale_factor(&self) -> f64 {
+        self.last_output
+            .clone()
+            .or_else(|| self.output_state.outputs().next())
+            .and_then(|output| self.output_state.info(&output))
+            .map_or(DEFAULT_SCALE_FACTOR, |info| info.scale_factor as f64)
+    }
+
+    /// Destroy the window of the surface, `None` if it's already gone.
+    pub fn close_window(&mut self, id: &ObjectId) -> Option<WindowId> {
+        self.seat_state
+            .pointers
+            .touches
+            .retain(|_, point| &point.surface != id);
+        // The protocol objects of the window are destroyed when it's dropped.
+        let id = self.windows.remove(id)?;
+        if self.seat_state.keyboard_focus == Some(id) {
+            // The event loop gave the releases already.
+            self.take_held_keys(id);
+            self.seat_state.set_keyboard_focus(None);
+        }
         if self.windows.is_empty() {
             // Free event sources to close an app properly
             for token in self.event_source_token.drain(..) {
                 self.loop_handle.remove(token);
             }
         }
-        id
+        Some(id)
     }
 
-    pub(crate) fn pointer_kind(&self, pointer: &WlPointer) -> Option<Rc<PointerKind>> {
-        if let Some(data) = pointer.data::<PointerData>() {
-            if let Some(pointer) = self.seat_state.pointers.kind(data.seat().id()) {
-                return Some(pointer.clone());
+    /// The window with the keyboard focus.
+    pub fn focused_window(&self) -> Option<WindowId> {
+        self.seat_state.keyboard_focus
+    }
+
+    /// Handle to change the window from any handler callback.
+    pub fn window_proxy(&self, window_id: WindowId) -> WindowProxy {
+        WindowProxy::new(window_id, self.window_command_sender.clone())
+    }
+
+    /// Apply the queued window proxy commands in order.
+    ///
+    /// Returns the surfaces of the changed windows and whether their size changed.
+    pub(crate) fn apply_window_commands(&mut self) -> Vec<(ObjectId, bool)> {
+        let mut changed = Vec::new();
+        while let Ok((window_id, command)) = self.window_commands.try_recv() {
+            match self.windows.get_mut(&window_id) {
+                Some(_) if matches!(command, WindowCommand::Close) => {
+                    self.windows.close(&window_id);
+                }
+                Some(window) => {
+                    let resized = window.apply_command(command);
+                    changed.push((window.get_surface_id().clone(), resized));
+                }
+                None => debug!(
+                    window_id = window_id.trace(),
+                    "Dropping {command:?} for the closed window"
+                ),
             }
         }
-        None
+        changed
+    }
+
+    pub(crate) fn pointer_kind(&self, pointer: &WlPointer) -> Option<Rc<PointerKind>> {
+        self.seat_state.pointers.kind(&pointer.id())
     }
 }
 
@@ -281,6 +600,39 @@ fn is_stateless(configure: &WindowConfigure) -> bool {
 }
 // END: Code from winit
 
+/// The surface size for a configure, and whether the window picked it and has to keep it in the
+/// bounds.
+///
+/// The size kept while the window is stateless comes back once it leaves fullscreen, the
+/// maximized or the tiled state.
+fn configure_size(
+    new_size: (Option<NonZeroU32>, Option<NonZeroU32>),
+    stateless: bool,
+    stateless_size: LogicalSize<u32>,
+    size: LogicalSize<u32>,
+) -> (LogicalSize<u32>, bool) {
+    match new_size {
+        (Some(width), Some(height)) => ((width.get(), height.get()).into(), false),
+        _ if stateless => (stateless_size, true),
+        _ => (size, true),
+    }
+}
+
+/// Keep the size picked by the window in the suggested bounds, without the frame.
+fn constrain_to_bounds(
+    size: LogicalSize<u32>,
+    bounds: (Option<NonZeroU32>, Option<NonZeroU32>),
+) -> LogicalSize<u32> {
+    LogicalSize::new(
+        bounds
+            .0
+            .map_or(size.width, |bound| size.width.min(bound.get())),
+        bounds
+            .1
+            .map_or(size.height, |bound| size.height.min(bound.get())),
+    )
+}
+
 impl CompositorHandler for WaylandState {
     fn scale_factor_changed(
         &mut self,
@@ -289,10 +641,8 @@ impl CompositorHandler for WaylandState {
         surface: &WlSurface,
         new_factor: i32,
     ) {
-        let id = surface.id();
-        if let Some(window) = self.windows.get_mut_by_object_id(&id) {
-            window.scale_factor = new_factor;
-            self.windows.rescale_request.insert(id);
+        if !self.set_scale_factor(surface.id(), new_factor as f64) {
+            self.reload_cursor(surface);
         }
     }
 
@@ -300,9 +650,15 @@ impl CompositorHandler for WaylandState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &WlSurface,
-        _new_transform: Transform,
+        surface: &WlSurface,
+        new_transform: Transform,
     ) {
+        let id = surface.id();
+        if let Some(window) = self.windows.get_mut_by_object_id(&id) {
+            window.preferred_transform = new_transform;
+            self.events
+                .push_back(Events::TransformChanged(id, new_transform));
+        }
     }
 
     fn frame(
@@ -313,6 +669,9 @@ impl CompositorHandler for WaylandState {
         _time: u32,
     ) {
         let id = surface.id();
+        if let Some(window) = self.windows.get_mut_by_object_id(&id) {
+            window.frame_callback_pending = false;
+        }
         self.windows.redraw_request.insert(id);
     }
 
@@ -325,9 +684,16 @@ impl CompositorHandler for WaylandState {
     ) {
         self.last_output = Some(output.clone());
         tracing::debug!("Last output");
-        if let Some(window) = self.windows.get_mut_by_object_id(&surface.id()) {
-            window.output = Some(output.to_owned());
+        let id = surface.id();
+        if let Some(window) = self.windows.get_mut_by_object_id(&id) {
+            window.output_enter(output);
             tracing::debug!("Window output");
+            self.windows.monitor_request.insert(id.clone());
+            // The toolkit only reports the scales differing from `1`, which the initial guess
+            // may not be.
+            if let Some(data) = surface.data::<SurfaceData>() {
+                self.set_scale_factor(id, data.scale_factor() as f64);
+            }
         }
     }
 
@@ -335,9 +701,24 @@ impl CompositorHandler for WaylandState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &WlSurface,
-        _output: &WlOutput,
+        surface: &WlSurface,
+        output: &WlOutput,
     ) {
+        let id = surface.id();
+        let mut remaining = None;
+        if let Some(window) = self.windows.get_mut_by_object_id(&id) {
+            window.output_leave(output);
+            remaining = window.output.clone();
+            self.windows.monitor_request.insert(id.clone());
+            // The scale of the surface is the largest one of the outputs it's still on, or the
+            // preferred one of the compositor.
+            if let Some(data) = surface.data::<SurfaceData>() {
+                self.set_scale_factor(id, data.scale_factor() as f64);
+            }
+        }
+        if self.last_output.as_ref() == Some(output) {
+            self.last_output = remaining;
+        }
     }
 }
 
@@ -346,11 +727,55 @@ impl OutputHandler for WaylandState {
         &mut self.output_state
     }
 
-    fn new_output(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _output: WlOutput) {}
+    fn new_output(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, output: WlOutput) {
+        let monitor = Monitor::new(&self.output_state, output);
+        self.windows.monitors.push(monitor.clone());
+        for window in self.windows.windows.values_mut() {
+            if let Some((_, timeout)) = window
+                .pending_fullscreen
+                .take_if(|(name, _)| monitor.name() == Some(name.as_str()))
+            {
+                self.loop_handle.remove(timeout);
+                window.set_fullscreen(Some(&monitor));
+            }
+        }
+        self.events
+            .push_back(Events::Monitor(MonitorEvent::Connected(monitor)));
+    }
 
-    fn update_output(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _output: WlOutput) {}
+    fn update_output(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, output: WlOutput) {
+        let monitor = Monitor::new(&self.output_state, output);
+        match self.windows.monitors.iter_mut().find(|m| **m == monitor) {
+            Some(known) => *known = monitor.clone(),
+            None => self.windows.monitors.push(monitor.clone()),
+        }
+        self.events
+            .push_back(Events::Monitor(MonitorEvent::Changed(monitor)));
+    }
 
-    fn output_destroyed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _output: WlOutput) {
+    fn output_destroyed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, output: WlOutput) {
+        // The surfaces are not always left before the output goes away.
+        for (id, window) in self.windows.windows.iter_mut() {
+            if window.outputs.contains(&output) {
+                window.output_leave(&output);
+                self.windows.monitor_request.insert(id.clone());
+            }
+            if let Some(kiosk) = window
+                .kiosk
+                .as_mut()
+                .filter(|kiosk| kiosk.output.as_ref() == Some(&output))
+            {
+                kiosk.output = None;
+            }
+        }
+        if self.last_output.as_ref() == Some(&output) {
+            self.last_output = None;
+        }
+        // The information is still there until the handler returns.
+        let monitor = Monitor::new(&self.output_state, output);
+        self.windows.monitors.retain(|known| *known != monitor);
+        self.events
+            .push_back(Events::Monitor(MonitorEvent::Disconnected(monitor)));
     }
 }
 
@@ -370,29 +795,32 @@ impl WindowHandler for WaylandState {
         &mut self,
         _conn: &Connection,
         qh: &QueueHandle<Self>,
-        window: &Window,
+        xdg_window: &Window,
         configure: WindowConfigure,
         _serial: u32,
     ) {
-        let id = window.wl_surface().id();
+        let id = xdg_window.wl_surface().id();
+        let mut first_configure = false;
         let mut resize = false;
+        let mut kiosk_recovery = false;
         if let Some(window) = self.windows.get_mut_by_object_id(&id) {
             if configure.decoration_mode == DecorationMode::Client
+                && window.decorations != Decorations::None
                 && window.window_frame.is_none()
-                && self.subcompositor_state.is_some()
                 && self.csd_fails
+                && let Some(subcompositor) = self.subcompositor_state.clone()
             {
                 match AdwaitaFrame::new(
-                    &window.immutable.window,
+                    xdg_window,
                     &self.shm,
                     self.compositor_state.clone(),
-                    self.subcompositor_state.as_ref().unwrap().clone(),
+                    subcompositor,
                     qh.clone(),
                     window.frame_config(),
                 ) {
                     Ok(mut frame) => {
                         frame.set_title(&window.title);
-                        frame.set_scaling_factor(window.scale_factor as f64);
+                        frame.set_scaling_factor(window.scale_factor);
                         // Hide the frame if we were asked to not decorate.
                         frame.set_hidden(!window.decorate);
                         window.window_frame = Some(frame);
@@ -402,18 +830,30 @@ impl WindowHandler for WaylandState {
                         self.csd_fails = true;
                     }
                 }
-            } else if configure.decoration_mode == DecorationMode::Server {
+            } else if configure.decoration_mode == DecorationMode::Server
+                || window.decorations == Decorations::None
+            {
                 // Drop the frame for server side decorations to save resources.
                 window.window_frame = None;
             }
 
             window.stateless = is_stateless(&configure);
+            if !window.configured {
+                // The scale is known by now, later changes keep the logical size.
+                window.apply_pending_physical_size();
+                window.pending_physical_size = None;
+            }
 
             let (mut new_size, constrain) = if let Some(frame) = window.window_frame.as_mut() {
                 // Configure the window states.
                 frame.update_state(configure.state);
                 frame.update_wm_capabilities(configure.capabilities);
 
+                let hidden = frame_hidden(window.decorate, configure.state);
+                if frame.is_hidden() != hidden {
+                    frame.set_hidden(hidden);
+                }
+
                 match configure.new_size {
                     (Some(width), Some(height)) => {
                         let (width, height) = frame.subtract_borders(width, height);
@@ -425,24 +865,18 @@ impl WindowHandler for WaylandState {
                     _ => (window.size, true),
                 }
             } else {
-                match configure.new_size {
-                    (Some(width), Some(height)) => ((width.get(), height.get()).into(), false),
-                    _ if window.stateless => (window.stateless_size, true),
-                    _ => (window.size, true),
-                }
+                configure_size(
+                    configure.new_size,
+                    window.stateless,
+                    window.stateless_size,
+                    window.size,
+                )
             };
 
             // Apply configure bounds only when compositor let the user decide what size to pick.
+            // This also covers the initial configure, where the size comes from the attributes.
             if constrain {
-                let bounds = window.surface_size_bounds(&configure);
-                new_size.width = bounds
-                    .0
-                    .map(|bound_w| new_size.width.min(bound_w.get()))
-                    .unwrap_or(new_size.width);
-                new_size.height = bounds
-                    .1
-                    .map(|bound_h| new_size.height.min(bound_h.get()))
-                    .unwrap_or(new_size.height);
+                new_size = constrain_to_bounds(new_size, window.surface_size_bounds(&configure));
             }
 
             let new_state = configure.state;
@@ -456,29 +890,42 @@ impl WindowHandler for WaylandState {
             // NOTE: Set the configure before doing a resize, since we query it during it.
             window.state = new_state;
 
+            // A window shown again after being suspended is mapped anew, so is its inhibitor.
+            if old_state.contains(WindowState::SUSPENDED)
+                && !new_state.contains(WindowState::SUSPENDED)
+            {
+                window.idle_inhibit.release();
+            }
+
+            // Take the fullscreen back in the kiosk mode.
+            if let Some(kiosk) = window.kiosk.as_mut().filter(|_| {
+                old_state.contains(WindowState::FULLSCREEN) && !configure.is_fullscreen()
+            }) {
+                kiosk.attempts += 1;
+                xdg_window.set_fullscreen(kiosk.output.as_ref().or(window.output.as_ref()));
+                kiosk_recovery = true;
+            }
+
             resize = state_change_requires_resize || new_size != window.size;
-            if resize {
+            first_configure = !window.configured;
+            window.configured = true;
+            // The inhibitors only take effect on the mapped surfaces, every configure maps the
+            // window again after an unmap.
+            window.inhibit_idle();
+            // The first configure has to set up the geometry anyway.
+            if first_configure || resize {
                 window.resize(new_size);
             }
         }
-        if resize {
-            self.windows.resize_request.insert(id.clone());
+        self.windows
+            .request_configured(id.clone(), first_configure, resize);
+        if kiosk_recovery {
+            self.windows.kiosk_request.insert(id.clone());
         }
         self.windows.redraw_request.insert(id);
     }
 }
 
-impl WlActivationHandler for WaylandState {
-    type RequestData = RequestData;
-
-    fn new_token(&mut self, token: String, data: &Self::RequestData) {
-        self.activation_state
-            .as_ref()
-            .unwrap()
-            .activate::<WaylandState>(data.surface.as_ref().unwrap(), token);
-    }
-}
-
 delegate_compositor!(WaylandState);
 delegate_subcompositor!(WaylandState);
 delegate_output!(WaylandState);
@@ -488,10 +935,11 @@ delegate_seat!(WaylandState);
 delegate_keyboard!(WaylandState);
 delegate_pointer!(WaylandState);
 delegate_touch!(WaylandState);
+delegate_pointer_constraints!(WaylandState);
+delegate_data_device!(WaylandState);
 
 delegate_xdg_shell!(WaylandState);
 delegate_xdg_window!(WaylandState);
-delegate_activation!(WaylandState);
 
 delegate_registry!(WaylandState);
 
@@ -501,3 +949,123 @@ impl ProvidesRegistryState for WaylandState {
     }
     registry_handlers![OutputState, WlSeatState,];
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{WindowAttributesExtWayland, testing::FakeCompositor};
+
+    #[test]
+    fn toplevel_is_set_up_before_the_initial_commit() {
+        let attributes = WindowAttributes::default()
+            .with_title("Editor")
+            .with_name("org.example.Editor", "editor")
+            .with_decoration_mode(Decorations::None)
+            .with_min_surface_size(LogicalSize::new(200, 100))
+            .with_max_surface_size(LogicalSize::new(800, 600))
+            .with_maximized(true)
+            .with_fullscreen(true);
+        assert_eq!(
+            toplevel_requests(&attributes, 2.0),
+            [
+                ToplevelRequest::Title(String::from("Editor")),
+                ToplevelRequest::AppId(String::from("org.example.Editor")),
+                ToplevelRequest::DecorationMode(DecorationMode::Client),
+                ToplevelRequest::MinSize(LogicalSize::new(200, 100)),
+                ToplevelRequest::MaxSize(Some(LogicalSize::new(800, 600))),
+                ToplevelRequest::Maximized,
+                ToplevelRequest::Fullscreen(None),
+                ToplevelRequest::InitialCommit,
+            ]
+        );
+    }
+
+    #[test]
+    fn initial_commit_goes_last() {
+        let requests = toplevel_requests(&WindowAttributes::default(), 1.0);
+        assert_eq!(
+            requests.first(),
+            Some(&ToplevelRequest::Title(String::from("Wayland window")))
+        );
+        assert_eq!(requests.last(), Some(&ToplevelRequest::InitialCommit));
+        assert_eq!(
+            requests
+                .iter()
+                .filter(|request| **request == ToplevelRequest::InitialCommit)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn leaving_fullscreen_restores_the_size() {
+        let windowed = LogicalSize::new(800, 600);
+        let fullscreen = (NonZeroU32::new(1920), NonZeroU32::new(1080));
+        // Fullscreen is not stateless, the resize keeps the stateless size.
+        let (size, constrain) = configure_size(fullscreen, false, windowed, windowed);
+        assert_eq!(size, LogicalSize::new(1920, 1080));
+        assert!(!constrain);
+
+        // Leaving fullscreen lets the window pick its size.
+        assert_eq!(
+            configure_size((None, None), true, windowed, size),
+            (windowed, true)
+        );
+        // The maximized and tiled windows without a size keep the current one.
+        assert_eq!(
+            configure_size((None, None), false, windowed, size),
+            (size, true)
+        );
+    }
+
+    #[test]
+    fn initial_size_is_kept_in_the_bounds() {
+        // The size of the attributes is the stateless size until the first configure.
+        let requested = LogicalSize::new(3000, 2000);
+        let (size, constrain) = configure_size((None, None), true, requested, requested);
+        assert!(constrain);
+        assert_eq!(
+            constrain_to_bounds(size, (NonZeroU32::new(1920), NonZeroU32::new(1080))),
+            LogicalSize::new(1920, 1080)
+        );
+        // A missing bound leaves the dimension alone.
+        assert_eq!(
+            constrain_to_bounds(size, (None, NonZeroU32::new(1080))),
+            LogicalSize::new(3000, 1080)
+        );
+        // A smaller size is kept.
+        assert_eq!(
+            constrain_to_bounds(
+                LogicalSize::new(800, 600),
+                (NonZeroU32::new(1920), NonZeroU32::new(1080))
+            ),
+            LogicalSize::new(800, 600)
+        );
+    }
+
+    #[test]
+    fn commands_of_a_callback_are_applied_before_the_draws() {
+        let (_compositor, mut state, mut event_loop) =
+            FakeCompositor::with_state(&[("wl_compositor", 6), ("wl_shm", 1), ("xdg_wm_base", 6)]);
+        let window_id = WindowId::next();
+        state.create_window((window_id, WindowAttributes::default()));
+        let surface = state
+            .windows
+            .get(&window_id)
+            .unwrap()
+            .get_surface_id()
+            .clone();
+
+        // A handler callback renames the window while the event loop dispatches.
+        let proxy = state.window_proxy(window_id);
+        event_loop
+            .handle()
+            .insert_idle(move |_| proxy.set_title("Renamed"));
+        event_loop.dispatch(Duration::ZERO, &mut state).unwrap();
+
+        // The command is there right after the callbacks, without another dispatch.
+        assert_eq!(state.apply_window_commands(), [(surface, false)]);
+        assert_eq!(state.windows.get(&window_id).unwrap().title, "Renamed");
+        assert!(state.apply_window_commands().is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+// Handling of the tearing-control-v1.
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::{
+        client::{
+            Connection, Dispatch, Proxy, QueueHandle, delegate_dispatch,
+            globals::{BindError, GlobalList},
+            protocol::wl_surface::WlSurface,
+        },
+        protocols::wp::tearing_control::v1::client::{
+            wp_tearing_control_manager_v1::WpTearingControlManagerV1,
+            wp_tearing_control_v1::{self, WpTearingControlV1},
+        },
+    },
+};
+
+use crate::WaylandState;
+
+/// How the frames of a window are presented.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PresentationHint {
+    /// Wait for the vertical blank, without tearing.
+    #[default]
+    Vsync,
+    /// Show the frames as soon as possible, tearing if needed, for the latency-sensitive games.
+    AllowTearing,
+}
+
+impl From<PresentationHint> for wp_tearing_control_v1::PresentationHint {
+    fn from(hint: PresentationHint) -> Self {
+        match hint {
+            PresentationHint::Vsync => Self::Vsync,
+            PresentationHint::AllowTearing => Self::Async,
+        }
+    }
+}
+
+/// Tearing control.
+#[derive(Debug, Clone)]
+pub struct TearingControlState {
+    manager: WpTearingControlManagerV1,
+}
+
+impl TearingControlState {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    /// A surface can only have one tearing control, it must be destroyed with the surface.
+    pub(crate) fn get_tearing_control(
+        &self,
+        surface: &WlSurface,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> WpTearingControlV1 {
+        self.manager
+            .get_tearing_control(surface, queue_handle, GlobalData)
+    }
+}
+
+impl Dispatch<WpTearingControlManagerV1, GlobalData, WaylandState> for TearingControlState {
+    fn event(
+        _: &mut WaylandState,
+        _: &WpTearingControlManagerV1,
+        _: <WpTearingControlManagerV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+impl Dispatch<WpTearingControlV1, GlobalData, WaylandState> for TearingControlState {
+    fn event(
+        _: &mut WaylandState,
+        _: &WpTearingControlV1,
+        _: <WpTearingControlV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<WaylandState>,
+    ) {
+        // No events.
+    }
+}
+
+delegate_dispatch!(WaylandState: [WpTearingControlManagerV1: GlobalData] => TearingControlState);
+delegate_dispatch!(WaylandState: [WpTearingControlV1: GlobalData] => TearingControlState);
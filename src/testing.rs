@@ -0,0 +1,179 @@
+// A fake compositor on the other end of a socket pair, for the tests of the protocol objects.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::unix::net::UnixStream,
+};
+
+use smithay_client_toolkit::{
+    compositor::CompositorState,
+    reexports::{
+        calloop::EventLoop,
+        client::{
+            Connection, EventQueue, Proxy, QueueHandle,
+            backend::ObjectId,
+            globals::{GlobalList, registry_queue_init},
+        },
+    },
+};
+
+use crate::WaylandState;
+
+/// The registry is the first object created by the client.
+const REGISTRY: u32 = 2;
+/// The callback of the initial roundtrip comes right after it.
+const ROUNDTRIP_CALLBACK: u32 = 3;
+
+/// A request received by the compositor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Request {
+    pub(crate) object: u32,
+    pub(crate) opcode: u16,
+    pub(crate) args: Vec<u8>,
+}
+
+impl Request {
+    /// The argument at the index, the strings span several words.
+    pub(crate) fn word(&self, index: usize) -> u32 {
+        word(&self.args, index)
+    }
+}
+
+/// A compositor answering the initial roundtrip and recording the requests, it sends nothing
+/// else unless asked to.
+pub(crate) struct FakeCompositor {
+    pub(crate) conn: Connection,
+    pub(crate) globals: GlobalList,
+    pub(crate) queue_handle: QueueHandle<WaylandState>,
+    /// Kept here unless a [`WaylandState`] dispatches it.
+    _queue: Option<EventQueue<WaylandState>>,
+    server: UnixStream,
+}
+
+impl FakeCompositor {
+    /// Connect to a compositor advertising the globals, by interface and version. They are
+    /// named from 1 in their order.
+    pub(crate) fn new(globals: &[(&str, u32)]) -> Self {
+        let (mut compositor, queue) = Self::connect(globals);
+        compositor._queue = Some(queue);
+        compositor
+    }
+
+    /// Same as [`FakeCompositor::new`], with the state of the connection. Its event loop
+    /// dispatches the events sent with [`FakeCompositor::send`].
+    pub(crate) fn with_state(
+        globals: &[(&str, u32)],
+    ) -> (Self, WaylandState, EventLoop<'static, WaylandState>) {
+        let (compositor, queue) = Self::connect(globals);
+        let (state, event_loop) =
+            WaylandState::with_globals(compositor.conn.clone(), &compositor.globals, queue);
+        (compositor, state, event_loop)
+    }
+
+    fn connect(globals: &[(&str, u32)]) -> (Self, EventQueue<WaylandState>) {
+        let (client, mut server) = UnixStream::pair().unwrap();
+        let mut answers = Vec::new();
+        for (name, (interface, version)) in (1..).zip(globals) {
+            answers.extend(message(REGISTRY, 0, &global(name, interface, *version)));
+        }
+        answers.extend(message(ROUNDTRIP_CALLBACK, 0, &0u32.to_ne_bytes()));
+        server.write_all(&answers).unwrap();
+        server.set_nonblocking(true).unwrap();
+
+        let conn = Connection::from_socket(client).unwrap();
+        let (globals, queue) = registry_queue_init::<WaylandState>(&conn).unwrap();
+        let compositor = Self {
+            conn,
+            globals,
+            queue_handle: queue.handle(),
+            _queue: None,
+            server,
+        };
+        (compositor, queue)
+    }
+
+    /// Send an event of the object, with its arguments as words.
+    pub(crate) fn send(&mut self, object: &impl Proxy, opcode: u16, args: &[u32]) {
+        let args: Vec<u8> = args.iter().flat_map(|arg| arg.to_ne_bytes()).collect();
+        let object = object.id().protocol_id();
+        self.server
+            .write_all(&message(object, opcode, &args))
+            .unwrap();
+    }
+
+    /// Objects with ids of their own, e.g. to stand for several devices. The compositor must
+    /// advertise `wl_compositor`.
+    pub(crate) fn object_ids(&self, count: usize) -> Vec<ObjectId> {
+        let compositor = CompositorState::bind(&self.globals, &self.queue_handle).unwrap();
+        (0..count)
+            .map(|_| compositor.create_surface(&self.queue_handle).id())
+            .collect()
+    }
+
+    /// The requests sent since the last call.
+    pub(crate) fn requests(&mut self) -> Vec<Request> {
+        self.conn.flush().unwrap();
+        let mut wire = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            match self.server.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => wire.extend_from_slice(&buffer[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => panic!("{err}"),
+            }
+        }
+        let mut requests = Vec::new();
+        let mut bytes = &wire[..];
+        while bytes.len() >= 8 {
+            let header = word(bytes, 1);
+            let size = (header >> 16) as usize;
+            requests.push(Request {
+                object: word(bytes, 0),
+                opcode: header as u16,
+                args: bytes[8..size].to_vec(),
+            });
+            bytes = &bytes[size..];
+        }
+        requests
+    }
+}
+
+/// The object bound to the global of the name, `wl_registry::bind` gives the new id last.
+pub(crate) fn bound_global(requests: &[Request], name: u32) -> Option<u32> {
+    requests
+        .iter()
+        .find(|request| {
+            request.object == REGISTRY && request.opcode == 0 && request.word(0) == name
+        })
+        .map(|request| request.word(request.args.len() / 4 - 1))
+}
+
+/// The word of a fixed point argument, with 8 bits of fraction.
+pub(crate) fn fixed(value: f64) -> u32 {
+    (value * 256.0).round() as i32 as u32
+}
+
+/// A message of the wire protocol, with its arguments already encoded.
+fn message(object: u32, opcode: u16, args: &[u8]) -> Vec<u8> {
+    let size = 8 + args.len() as u32;
+    let mut message = object.to_ne_bytes().to_vec();
+    message.extend_from_slice(&((size << 16) | opcode as u32).to_ne_bytes());
+    message.extend_from_slice(args);
+    message
+}
+
+/// The arguments of `wl_registry::global`.
+fn global(name: u32, interface: &str, version: u32) -> Vec<u8> {
+    let mut args = name.to_ne_bytes().to_vec();
+    args.extend_from_slice(&(interface.len() as u32 + 1).to_ne_bytes());
+    args.extend_from_slice(interface.as_bytes());
+    args.push(0);
+    args.resize(args.len().next_multiple_of(4), 0);
+    args.extend_from_slice(&version.to_ne_bytes());
+    args
+}
+
+fn word(bytes: &[u8], index: usize) -> u32 {
+    u32::from_ne_bytes(bytes[index * 4..index * 4 + 4].try_into().unwrap())
+}
@@ -2,6 +2,8 @@
 // Code from winit (https://github.com/rust-windowing/winit)
 // Copyright (c) 2015-2024 The winit Contributors
 
+use std::{error::Error, fmt};
+
 use smithay_client_toolkit::{
     globals::GlobalData,
     reexports::{
@@ -16,8 +18,34 @@ use smithay_client_toolkit::{
 
 use crate::WaylandState;
 
+/// Why the viewport of a window could not be set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewportError {
+    /// The compositor does not support wp-viewporter.
+    Unsupported,
+    /// The source has a negative position or a size that is not positive.
+    InvalidSource,
+    /// The source is not within the buffer.
+    OutOfBuffer,
+    /// The destination has a zero size or a size too large for the protocol.
+    InvalidDestination,
+}
+
+impl fmt::Display for ViewportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported => f.write_str("wp-viewporter is not supported"),
+            Self::InvalidSource => f.write_str("the viewport source is not a valid rectangle"),
+            Self::OutOfBuffer => f.write_str("the viewport source is outside of the buffer"),
+            Self::InvalidDestination => f.write_str("the viewport destination is not a valid size"),
+        }
+    }
+}
+
+impl Error for ViewportError {}
+
 /// Viewporter.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ViewporterState {
     viewporter: WpViewporter,
 }
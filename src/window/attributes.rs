@@ -4,8 +4,11 @@ use std::{
 };
 
 use dpi::Size;
+use sctk_adwaita::FrameConfig;
 use tracing::field::DisplayValue;
 
+use crate::{ContentType, Monitor};
+
 /// A unique and persistent identifier for a window.
 ///
 /// [`MasonryState`] internally maps these to winit window ids ([`winit::window::WindowId`]).
@@ -32,6 +35,26 @@ impl WindowId {
     }
 }
 
+/// An axis-aligned rectangle, the units are given by its use.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rect<T> {
+    pub x: T,
+    pub y: T,
+    pub width: T,
+    pub height: T,
+}
+
+impl<T> Rect<T> {
+    pub fn new(x: T, y: T, width: T, height: T) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ApplicationName {
     pub general: String,
@@ -44,6 +67,24 @@ impl ApplicationName {
     }
 }
 
+/// Who draws the decorations of the window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Decorations {
+    /// Ask the compositor to draw them, falling back to the client side frame.
+    #[default]
+    Server,
+    /// Draw the client side frame.
+    Client,
+    /// No decorations at all, the surface is the whole window.
+    ///
+    /// Moving and resizing is left to the application through [`WaylandWindow::drag_window`]
+    /// and [`WaylandWindow::drag_resize_window`].
+    ///
+    /// [`WaylandWindow::drag_window`]: crate::WaylandWindow::drag_window
+    /// [`WaylandWindow::drag_resize_window`]: crate::WaylandWindow::drag_resize_window
+    None,
+}
+
 #[derive(Debug, Clone)]
 pub struct WindowAttributes {
     pub title: String,
@@ -57,13 +98,24 @@ pub struct WindowAttributes {
     pub resizable: bool,
     // TODO
     pub fullscreen: bool,
+    /// The monitor of the initial fullscreen, the compositor chooses when `None`.
+    pub fullscreen_monitor: Option<Monitor>,
+    /// Name of the output of the initial fullscreen, e.g. `"HDMI-A-1"`.
+    pub fullscreen_output_name: Option<String>,
     pub maximized: bool,
     pub hide_titlebar: bool,
-    pub decorations: bool,
+    pub decorations: Decorations,
     pub light_theme: Option<bool>,
+    /// Overrides the frame config computed from `light_theme` and `hide_titlebar`.
+    pub frame_config: Option<FrameConfig>,
     pub transparent: bool,
+    /// Toggle the maximization with a double tap on the titlebar.
+    pub titlebar_double_tap: bool,
     // TODO: consider to use as app_id
     pub app_name: Option<ApplicationName>,
+    /// The xdg-activation token focusing the window, in place of the one of the launcher.
+    pub activation_token: Option<String>,
+    pub content_type: ContentType,
 }
 
 impl Default for WindowAttributes {
@@ -77,12 +129,18 @@ impl Default for WindowAttributes {
             max_surface_size: None,
             resizable: Default::default(),
             fullscreen: false,
+            fullscreen_monitor: None,
+            fullscreen_output_name: None,
             maximized: false,
             hide_titlebar: false,
-            decorations: true,
+            decorations: Decorations::Server,
             light_theme: None,
+            frame_config: None,
             transparent: false,
+            titlebar_double_tap: true,
             app_name: Default::default(),
+            activation_token: None,
+            content_type: ContentType::None,
         }
     }
 }
@@ -165,13 +223,36 @@ impl WindowAttributes {
     ///
     /// The default is `None`.
     ///
-    /// See [`Window::set_fullscreen`] for details.
+    /// See [`WaylandWindow::set_fullscreen`] for details.
+    ///
+    /// [`WaylandWindow::set_fullscreen`]: crate::WaylandWindow::set_fullscreen
     #[inline]
     pub fn with_fullscreen(mut self, fullscreen: bool) -> Self {
         self.fullscreen = fullscreen;
         self
     }
 
+    /// Sets the monitor the window is made fullscreen on upon creation.
+    ///
+    /// The default is `None`, the compositor chooses.
+    #[inline]
+    pub fn with_fullscreen_monitor(mut self, monitor: Option<Monitor>) -> Self {
+        self.fullscreen_monitor = monitor;
+        self
+    }
+
+    /// Puts the window into fullscreen upon creation on the output with the name, e.g.
+    /// `"HDMI-A-1"`.
+    ///
+    /// When no output has the name yet, the fullscreen waits a few seconds for it to be
+    /// connected, then the compositor chooses the monitor.
+    #[inline]
+    pub fn with_fullscreen_on(mut self, output_name: impl Into<String>) -> Self {
+        self.fullscreen = true;
+        self.fullscreen_output_name = Some(output_name.into());
+        self
+    }
+
     /// Sets whether the window should have a border, a title bar, etc.
     ///
     /// The default is `true`.
@@ -179,9 +260,80 @@ impl WindowAttributes {
     /// See [`Window::set_decorations`] for details.
     #[inline]
     pub fn with_decorations(mut self, decorations: bool) -> Self {
+        self.decorations = match decorations {
+            true => Decorations::Server,
+            false => Decorations::Client,
+        };
+        self
+    }
+
+    /// Sets who draws the decorations of the window.
+    ///
+    /// The default is [`Decorations::Server`].
+    ///
+    /// See [`WaylandWindow::set_decoration_mode`] for details.
+    ///
+    /// [`WaylandWindow::set_decoration_mode`]: crate::WaylandWindow::set_decoration_mode
+    #[inline]
+    pub fn with_decoration_mode(mut self, decorations: Decorations) -> Self {
         self.decorations = decorations;
         self
     }
+
+    /// Sets whether a double tap on the titlebar of the client side frame toggles the
+    /// maximization.
+    ///
+    /// The default is `true`, kiosk applications may want to disable it.
+    ///
+    /// See [`WaylandWindow::set_titlebar_double_tap`] for details.
+    ///
+    /// [`WaylandWindow::set_titlebar_double_tap`]: crate::WaylandWindow::set_titlebar_double_tap
+    #[inline]
+    pub fn with_titlebar_double_tap(mut self, enabled: bool) -> Self {
+        self.titlebar_double_tap = enabled;
+        self
+    }
+
+    /// Sets the config of the client side decorations frame.
+    ///
+    /// It takes precedence over the light/dark theme, so the frame can match the theme of the
+    /// application exactly.
+    ///
+    /// See [`WaylandWindow::set_frame_config`] for details.
+    ///
+    /// [`WaylandWindow::set_frame_config`]: crate::WaylandWindow::set_frame_config
+    #[inline]
+    pub fn with_frame_config(mut self, frame_config: FrameConfig) -> Self {
+        self.frame_config = Some(frame_config);
+        self
+    }
+
+    /// Sets the xdg-activation token which focuses the window once created.
+    ///
+    /// It's for the tokens received otherwise than by the environment, such as with a D-Bus
+    /// activation. The `XDG_ACTIVATION_TOKEN` of the launcher is used by the first window when it
+    /// has no token of its own.
+    ///
+    /// The variable is left in the environment. The child processes must not inherit it, e.g.
+    /// remove it with [`std::process::Command::env_remove`] when spawning them.
+    #[inline]
+    pub fn with_activation_token(mut self, token: String) -> Self {
+        self.activation_token = Some(token);
+        self
+    }
+
+    /// Sets the kind of content of the window, sent before the initial commit.
+    ///
+    /// The default is [`ContentType::None`].
+    ///
+    /// See [`WaylandWindow::set_content_type`] for details.
+    ///
+    /// [`WaylandWindow::set_content_type`]: crate::WaylandWindow::set_content_type
+    #[inline]
+    pub fn with_content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = content_type;
+        self
+    }
 }
 
 /// Additional methods on [`WindowAttributes`] that are specific to Wayland.
@@ -0,0 +1,219 @@
+use std::{error::Error, fmt, time::Duration};
+
+use smithay_client_toolkit::{
+    reexports::{
+        calloop::RegistrationToken,
+        client::{Proxy, protocol::wl_shm::Format},
+    },
+    seat::pointer::PointerData,
+    shm::slot::{Buffer, SlotPool},
+};
+
+use crate::seat::PointerKind;
+
+/// Why a cursor image was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorImageError {
+    /// The width or the height is zero.
+    Empty,
+    /// The length of the data is not `width * height * 4`.
+    BadLength { expected: usize, got: usize },
+    /// The hotspot is outside of the image.
+    HotspotOutside,
+}
+
+impl fmt::Display for CursorImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("the cursor image is empty"),
+            Self::BadLength { expected, got } => {
+                write!(f, "expected {expected} bytes of RGBA data, got {got}")
+            }
+            Self::HotspotOutside => f.write_str("the cursor hotspot is outside of the image"),
+        }
+    }
+}
+
+impl Error for CursorImageError {}
+
+/// RGBA image of a custom cursor, shown at one image pixel per logical pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorImage {
+    rgba: Vec<u8>,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+    pub(crate) hotspot_x: u16,
+    pub(crate) hotspot_y: u16,
+}
+
+impl CursorImage {
+    pub fn from_rgba(
+        rgba: Vec<u8>,
+        width: u16,
+        height: u16,
+        hotspot_x: u16,
+        hotspot_y: u16,
+    ) -> Result<Self, CursorImageError> {
+        if width == 0 || height == 0 {
+            return Err(CursorImageError::Empty);
+        }
+        let expected = width as usize * height as usize * 4;
+        if rgba.len() != expected {
+            return Err(CursorImageError::BadLength {
+                expected,
+                got: rgba.len(),
+            });
+        }
+        if hotspot_x >= width || hotspot_y >= height {
+            return Err(CursorImageError::HotspotOutside);
+        }
+        Ok(Self {
+            rgba,
+            width,
+            height,
+            hotspot_x,
+            hotspot_y,
+        })
+    }
+
+    /// Upload the image to a buffer of the pool.
+    pub(crate) fn create_buffer(&self, pool: &mut SlotPool) -> Result<Buffer, String> {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let (buffer, canvas) = pool
+            .create_buffer(width, height, width * 4, Format::Argb8888)
+            .map_err(|err| err.to_string())?;
+        // Premultiplied little-endian ARGB.
+        for (dst, src) in canvas.chunks_exact_mut(4).zip(self.rgba.chunks_exact(4)) {
+            let alpha = src[3] as u16;
+            let premultiply = |channel: u8| ((channel as u16 * alpha) / 255) as u8;
+            dst.copy_from_slice(&[
+                premultiply(src[2]),
+                premultiply(src[1]),
+                premultiply(src[0]),
+                src[3],
+            ]);
+        }
+        Ok(buffer)
+    }
+}
+
+/// Shortest time a frame of an animated cursor is shown.
+const MIN_FRAME_DURATION: Duration = Duration::from_millis(1);
+
+/// A cursor drawn by the application, animated when it has several frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomCursor {
+    frames: Vec<(CursorImage, Duration)>,
+}
+
+impl CustomCursor {
+    pub fn new(image: CursorImage) -> Self {
+        Self {
+            frames: vec![(image, Duration::ZERO)],
+        }
+    }
+
+    /// Frames shown in a loop, each one for its duration.
+    ///
+    /// The durations are at least a millisecond, a zero one would spin the event loop.
+    /// Returns `None` without frames.
+    pub fn animated(mut frames: Vec<(CursorImage, Duration)>) -> Option<Self> {
+        for (_, duration) in &mut frames {
+            *duration = (*duration).max(MIN_FRAME_DURATION);
+        }
+        (!frames.is_empty()).then_some(Self { frames })
+    }
+}
+
+struct CursorFrame {
+    buffer: Buffer,
+    width: i32,
+    height: i32,
+    hotspot: (i32, i32),
+    duration: Duration,
+}
+
+/// A custom cursor uploaded to the compositor.
+pub(crate) struct CursorAnimation {
+    frames: Vec<CursorFrame>,
+    /// The frame shown now, kept while the animation is paused.
+    pub(crate) current: usize,
+    pub(crate) timer: Option<RegistrationToken>,
+}
+
+impl CursorAnimation {
+    pub(crate) fn new(pool: &mut SlotPool, cursor: &CustomCursor) -> Result<Self, String> {
+        let mut frames = Vec::with_capacity(cursor.frames.len());
+        for (image, duration) in &cursor.frames {
+            frames.push(CursorFrame {
+                buffer: image.create_buffer(pool)?,
+                width: image.width as i32,
+                height: image.height as i32,
+                hotspot: (image.hotspot_x as i32, image.hotspot_y as i32),
+                duration: *duration,
+            });
+        }
+        Ok(Self {
+            frames,
+            current: 0,
+            timer: None,
+        })
+    }
+
+    pub(crate) fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    /// How long the current frame stays.
+    pub(crate) fn duration(&self) -> Duration {
+        self.frames[self.current].duration
+    }
+
+    pub(crate) fn advance(&mut self) {
+        self.current = (self.current + 1) % self.frames.len();
+    }
+
+    /// Show the current frame on the pointer.
+    pub(crate) fn show(&self, pointer: &PointerKind) -> Result<(), String> {
+        let PointerKind::Mouse(themed_pointer) = pointer else {
+            return Err(String::from("Icons unsupported for touch"));
+        };
+        let serial = themed_pointer
+            .pointer()
+            .data::<PointerData>()
+            .and_then(PointerData::latest_enter_serial)
+            .ok_or_else(|| String::from("The pointer has not entered a surface"))?;
+        let frame = &self.frames[self.current];
+        let surface = themed_pointer.surface();
+        surface.set_buffer_scale(1);
+        // The frames are never redrawn, so they are attached to every pointer as is.
+        surface.attach(Some(frame.buffer.wl_buffer()), 0, 0);
+        surface.damage_buffer(0, 0, frame.width, frame.height);
+        surface.commit();
+        themed_pointer.pointer().set_cursor(
+            serial,
+            Some(surface),
+            frame.hotspot.0,
+            frame.hotspot.1,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_frame_durations_are_clamped() {
+        let image = CursorImage::from_rgba(vec![0; 4], 1, 1, 0, 0).unwrap();
+        let cursor = CustomCursor::animated(vec![
+            (image.clone(), Duration::ZERO),
+            (image, Duration::from_millis(50)),
+        ])
+        .unwrap();
+        assert_eq!(cursor.frames[0].1, MIN_FRAME_DURATION);
+        assert_eq!(cursor.frames[1].1, Duration::from_millis(50));
+        assert!(CustomCursor::animated(Vec::new()).is_none());
+    }
+}
@@ -1,82 +1,257 @@
 pub mod attributes;
+pub mod cursor;
+pub mod pending;
+pub mod popup;
+pub mod proxy;
 pub mod registry;
+pub mod subsurface;
 
 use std::{
     num::NonZeroU32,
     rc::{Rc, Weak},
-    sync::{Arc, LazyLock},
+    sync::{self, Arc, LazyLock},
 };
 
 use accesskit_unix::Adapter;
 use cursor_icon::CursorIcon;
-use dpi::{LogicalPosition, LogicalSize, PhysicalSize, Position};
+use dpi::{LogicalPosition, LogicalSize, PhysicalSize, Position, Size};
 
 use raw_window_handle::{
     DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
     RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle, WindowHandle,
 };
 use sctk_adwaita::{AdwaitaFrame, FrameConfig};
+#[cfg(feature = "layer-shell")]
+use smithay_client_toolkit::shell::wlr_layer::LayerSurface;
 use smithay_client_toolkit::{
-    compositor::Region,
+    compositor::{CompositorState, Region},
+    globals::ProvidesBoundGlobal,
     reexports::{
-        client::protocol::{wl_display::WlDisplay, wl_output::WlOutput, wl_pointer::WlPointer},
+        calloop::{
+            LoopHandle, RegistrationToken,
+            timer::{TimeoutAction, Timer},
+        },
+        client::{
+            Connection,
+            protocol::{
+                wl_display::WlDisplay,
+                wl_output::{Transform, WlOutput},
+                wl_seat::WlSeat,
+                wl_surface::WlSurface,
+            },
+        },
         csd_frame::{FrameAction, ResizeEdge},
-        protocols::wp::viewporter::client::wp_viewport::WpViewport,
+        protocols::wp::{
+            content_type::v1::client::wp_content_type_v1::WpContentTypeV1,
+            pointer_constraints::zv1::client::zwp_pointer_constraints_v1::Lifetime,
+            presentation_time::client::wp_presentation::WpPresentation,
+            tearing_control::v1::client::wp_tearing_control_v1::WpTearingControlV1,
+            text_input::zv3::client::zwp_text_input_v3::ZwpTextInputV3,
+            viewporter::client::wp_viewport::WpViewport,
+        },
     },
-    seat::pointer::PointerData,
+    seat::pointer_constraints::PointerConstraintsState,
     shell::xdg::{
         XdgSurface,
+        popup::Popup,
         window::{DecorationMode, Window},
     },
+    shm::{Shm, slot::SlotPool},
+    subcompositor::SubcompositorState,
 };
 use smithay_client_toolkit::{
     reexports::{
-        calloop::channel::Sender as WlSender, client::backend::ObjectId,
-        protocols::xdg::shell::client::xdg_toplevel::ResizeEdge as XdgResizeEdge,
+        calloop::channel::Sender as WlSender,
+        client::backend::ObjectId,
+        protocols::xdg::{
+            dialog::v1::client::xdg_dialog_v1::XdgDialogV1,
+            shell::client::{xdg_surface, xdg_toplevel::ResizeEdge as XdgResizeEdge},
+        },
     },
     shell::WaylandSurface,
 };
 use smithay_client_toolkit::{
     reexports::{
-        client::Proxy,
+        client::{Proxy, QueueHandle},
         csd_frame::{DecorationsFrame, WindowState},
     },
     shell::xdg::window::WindowConfigure,
 };
-use tracing::error;
+use tracing::{error, warn};
 
 use crate::{
-    Events, WaylandState, WindowAttributes, WindowId, seat::PointerKind,
+    AccesskitHandler, ContentType, Decorations, Events, Monitor, Rect, ViewportError,
+    ViewporterState, WaylandState, WindowAttributes, WindowId,
+    content_type::ContentTypeState,
+    data_device::dnd::{DragAction, DragContent, DragEvent, DragIcon, DropTarget},
+    dialog::DialogState,
+    idle_inhibit::{IdleInhibit, IdleInhibitState},
+    presentation::request_feedback,
+    seat::{
+        PointerKind,
+        constraints::{
+            ConfineRegion, ConstraintError, CursorGrabMode, PointerConstraint, PointerGrab,
+        },
+        shortcuts_inhibit::{ShortcutsInhibit, ShortcutsInhibitState},
+        text_input::{ImeEvent, ImePurpose, ImeState, send_ime_state},
+    },
     state::logical_to_physical_rounded,
+    tearing_control::{PresentationHint, TearingControlState},
+    window::{
+        cursor::{CursorAnimation, CustomCursor},
+        pending::PendingChanges,
+        proxy::{CommandSender, WindowCommand, WindowProxy},
+        subsurface::Subsurface,
+    },
 };
 
 pub(crate) static DEFAULT_WINDOW_SIZE: LazyLock<LogicalSize<u32>> =
     LazyLock::new(|| LogicalSize::from((256, 256)));
 
-pub(crate) const DEFAULT_SCALE_FACTOR: i32 = 1;
+pub(crate) const DEFAULT_SCALE_FACTOR: f64 = 1.0;
 
 // Minimum window surface size.
 const MIN_WINDOW_SIZE: LogicalSize<u32> = LogicalSize::new(2, 1);
 
+/// Time in milliseconds between the touches of a double tap, the same as for the double clicks
+/// of the frame.
+const DOUBLE_TAP_DURATION: u32 = 400;
+
+/// Distance in logical pixels between the touches of a double tap.
+const DOUBLE_TAP_DISTANCE: f64 = 16.;
+
+/// The cursor showing the resize from the edge.
+fn resize_cursor(edge: XdgResizeEdge) -> Option<CursorIcon> {
+    Some(match edge {
+        XdgResizeEdge::Top => CursorIcon::NResize,
+        XdgResizeEdge::Bottom => CursorIcon::SResize,
+        XdgResizeEdge::Left => CursorIcon::WResize,
+        XdgResizeEdge::Right => CursorIcon::EResize,
+        XdgResizeEdge::TopLeft => CursorIcon::NwResize,
+        XdgResizeEdge::TopRight => CursorIcon::NeResize,
+        XdgResizeEdge::BottomLeft => CursorIcon::SwResize,
+        XdgResizeEdge::BottomRight => CursorIcon::SeResize,
+        _ => return None,
+    })
+}
+
+/// The minimum surface size requested, without the borders of the frame.
+pub(crate) fn min_surface_size(size: Option<LogicalSize<u32>>) -> LogicalSize<u32> {
+    let size = size.unwrap_or(MIN_WINDOW_SIZE);
+    LogicalSize::new(
+        size.width.max(MIN_WINDOW_SIZE.width),
+        size.height.max(MIN_WINDOW_SIZE.height),
+    )
+}
+
+/// Whether the pointer is among the ones over the window, only these windows may have something
+/// in progress with it.
+fn is_over(pointers: &[Weak<PointerKind>], pointer: &Rc<PointerKind>) -> bool {
+    let pointer = Rc::downgrade(pointer);
+    pointers.iter().any(|p| p.ptr_eq(&pointer))
+}
+
+/// Whether the decorations frame is hidden, it's not a part of the window while it's
+/// fullscreen.
+pub(crate) fn frame_hidden(decorate: bool, state: WindowState) -> bool {
+    !decorate || state.contains(WindowState::FULLSCREEN)
+}
+
+/// The size of the buffers rendered with the transform for a surface of the size, the quarter
+/// turns swap the dimensions.
+fn transformed_size(size: PhysicalSize<u32>, transform: Transform) -> PhysicalSize<u32> {
+    match transform {
+        Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270 => {
+            PhysicalSize::new(size.height, size.width)
+        }
+        _ => size,
+    }
+}
+
+/// The shell object giving the surface of a window its role.
+#[derive(Debug, Clone)]
+pub(crate) enum ShellSurface {
+    Toplevel(Window),
+    Popup(Popup),
+    #[cfg(feature = "layer-shell")]
+    Layer(LayerSurface),
+}
+
+impl ShellSurface {
+    pub(crate) fn wl_surface(&self) -> &WlSurface {
+        match self {
+            Self::Toplevel(window) => window.wl_surface(),
+            Self::Popup(popup) => popup.wl_surface(),
+            #[cfg(feature = "layer-shell")]
+            Self::Layer(layer) => layer.wl_surface(),
+        }
+    }
+
+    /// The layer surfaces have no window geometry.
+    pub(crate) fn xdg_surface(&self) -> Option<&xdg_surface::XdgSurface> {
+        match self {
+            Self::Toplevel(window) => Some(window.xdg_surface()),
+            Self::Popup(popup) => Some(popup.xdg_surface()),
+            #[cfg(feature = "layer-shell")]
+            Self::Layer(_) => None,
+        }
+    }
+
+    /// The toplevel, the requests about the window state are ignored for the popups.
+    pub(crate) fn toplevel(&self) -> Option<&Window> {
+        match self {
+            Self::Toplevel(window) => Some(window),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn popup(&self) -> Option<&Popup> {
+        match self {
+            Self::Popup(popup) => Some(popup),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "layer-shell")]
+    pub(crate) fn layer(&self) -> Option<&LayerSurface> {
+        match self {
+            Self::Layer(layer) => Some(layer),
+            _ => None,
+        }
+    }
+}
+
 pub struct WindowImmutable {
     pub(crate) object_id: ObjectId,
     pub(crate) window_id: WindowId,
-    pub(crate) window: Window,
+    pub(crate) window: ShellSurface,
     /// The wayland display used solely for raw window handle.
     #[allow(dead_code)]
     display: WlDisplay,
+    commands: CommandSender,
 }
 
 impl WindowImmutable {
-    pub fn new(window: Window, display: WlDisplay, id: WindowId) -> Self {
+    pub(crate) fn new(
+        window: ShellSurface,
+        display: WlDisplay,
+        id: WindowId,
+        commands: CommandSender,
+    ) -> Self {
         Self {
             object_id: window.wl_surface().id(),
             window_id: id,
             window,
             display,
+            commands,
         }
     }
 
+    /// Handle to change the window from any handler callback.
+    pub fn proxy(&self) -> WindowProxy {
+        WindowProxy::new(self.window_id, self.commands.clone())
+    }
+
     #[inline]
     pub fn raw_window_handle_rwh_06(&self) -> Result<RawWindowHandle, HandleError> {
         Ok(WaylandWindowHandle::new({
@@ -96,117 +271,302 @@ impl WindowImmutable {
     }
 }
 
+/// Kiosk mode of a window.
+pub(crate) struct Kiosk {
+    /// The output to stay fullscreen on, the current one if `None`.
+    pub(crate) output: Option<WlOutput>,
+    /// Fullscreen requests issued after the compositor took it away.
+    pub(crate) attempts: u32,
+    /// Decorations before entering the kiosk mode.
+    decorate: bool,
+}
+
 /// New window
 pub struct WaylandWindow {
     pub immutable: Arc<WindowImmutable>,
+    /// The window or the popup a popup is attached to.
+    pub(crate) parent: Option<ObjectId>,
+    /// Whether the popup took the keyboard and the pointer.
+    pub(crate) grabbing: bool,
     pub(crate) title: String,
-    pub(crate) visible: bool,
     pub(crate) resizable: bool,
     pub(crate) hide_titlebar: bool,
-    pub(crate) decorations: bool,
+    pub(crate) decorations: Decorations,
     pub(crate) transparent: bool,
     pub(crate) light_theme: Option<bool>,
+    pub(crate) custom_frame_config: Option<FrameConfig>,
     pub(crate) state: WindowState,
     pub(crate) window_frame: Option<AdwaitaFrame<WaylandState>>,
+    /// The primary output of the window.
     pub(crate) output: Option<WlOutput>,
+    /// Outputs the surface is currently on, in the order it entered them.
+    pub(crate) outputs: Vec<WlOutput>,
+    /// The primary output last reported to the application.
+    pub(crate) reported_output: Option<WlOutput>,
     pub(crate) viewport: Option<WpViewport>,
+    /// The part of the buffers shown, set by the application.
+    pub(crate) viewport_source: Option<Rect<f64>>,
+    /// The size the surface is scaled to in place of the window size, set by the application.
+    pub(crate) viewport_destination: Option<LogicalSize<u32>>,
+    viewporter_state: Option<ViewporterState>,
+    subcompositor_state: Option<Arc<SubcompositorState>>,
+    /// The subsurfaces of [`WaylandWindow::create_subsurface`], alive while their handle is.
+    subsurfaces: Vec<sync::Weak<Subsurface>>,
     pub(crate) size: LogicalSize<u32>,
     /// Min size.
     pub(crate) min_surface_size: LogicalSize<u32>,
     pub(crate) max_surface_size: Option<LogicalSize<u32>>,
     pub(crate) stateless_size: LogicalSize<u32>,
-    pub scale_factor: i32,
+    /// Initial size requested in physical pixels, converted once the real scale is known.
+    pub(crate) pending_physical_size: Option<PhysicalSize<u32>>,
+    /// Scale of the surface, fractional scales are kept as they are for the size math.
+    pub scale_factor: f64,
+    /// The scale factor last reported to the application.
+    pub(crate) reported_scale_factor: f64,
+    /// The transform of the output, which the buffers may be rendered with.
+    pub(crate) preferred_transform: Transform,
+    pub(crate) buffer_transform: Transform,
     pub(crate) event_sender: WlSender<Events>,
+    pub(crate) queue_handle: QueueHandle<WaylandState>,
+    /// Whether a frame callback requested with `pre_present_notify` is pending.
+    pub(crate) frame_callback_pending: bool,
     pub accesskit_adapter: Adapter,
     pub(crate) decorate: bool,
     pub(crate) region: Option<Region>,
     pub(crate) stateless: bool,
+    /// Whether the first configure has been processed.
+    pub(crate) configured: bool,
     /// The pointers observed on the window.
     pub(crate) pointers: Vec<Weak<PointerKind>>,
+    /// The presses still held on the window with their button and their serial, the latest last.
+    pub(crate) presses: Vec<(Weak<PointerKind>, u32, u32)>,
+    pub(crate) pointer_constraints: Arc<PointerConstraintsState>,
+    pub(crate) compositor: Arc<CompositorState>,
+    /// The grab to apply to the pointers over the window.
+    pub(crate) pointer_grab: PointerGrab,
+    pub(crate) release_grab_on_focus_loss: bool,
+    /// The constraints set on the pointers which entered the window.
+    pub(crate) constraints: Vec<(Weak<PointerKind>, PointerConstraint)>,
     pub(crate) selected_cursor: CursorIcon,
+    /// The custom cursor replacing `selected_cursor`.
+    pub(crate) custom_cursor: Option<CursorAnimation>,
+    pub(crate) cursor_pool: Option<SlotPool>,
+    pub(crate) shm: Shm,
+    /// Needed to load the cursor themes.
+    pub(crate) conn: Connection,
+    pub(crate) loop_handle: LoopHandle<'static, WaylandState>,
     /// Whether the cursor is visible.
     pub(crate) cursor_visible: bool,
+    pub(crate) kiosk: Option<Kiosk>,
+    /// The output of the initial fullscreen which is not connected yet, with its timeout.
+    pub(crate) pending_fullscreen: Option<(String, RegistrationToken)>,
+    pub(crate) titlebar_double_tap: bool,
+    /// Time and position of the latest tap on the titlebar.
+    pub(crate) last_titlebar_tap: Option<(u32, (f64, f64))>,
+    pub(crate) shortcuts_inhibit_state: Option<ShortcutsInhibitState>,
+    pub(crate) shortcuts_inhibit: ShortcutsInhibit,
+    pub(crate) presentation: Option<WpPresentation>,
+    pub(crate) tearing_control_state: Option<TearingControlState>,
+    /// Created with the first hint, a surface can't have a second one.
+    pub(crate) tearing_control: Option<WpTearingControlV1>,
+    pub(crate) presentation_hint: PresentationHint,
+    pub(crate) content_type_state: Option<ContentTypeState>,
+    pub(crate) content_type_object: Option<WpContentTypeV1>,
+    pub(crate) content_type: ContentType,
+    pub(crate) idle_inhibit_state: Option<IdleInhibitState>,
+    pub(crate) idle_inhibit: IdleInhibit,
+    dialog_state: Option<DialogState>,
+    dialog: Option<XdgDialogV1>,
+    modal: bool,
+    /// The toplevel of [`WaylandWindow::set_parent`].
+    toplevel_parent: Option<ObjectId>,
+    /// The seat whose keyboard focuses the window.
+    pub(crate) keyboard_seat: Option<WlSeat>,
+    pub(crate) ime: ImeState,
+    /// The text inputs focusing the window.
+    pub(crate) text_inputs: Vec<ZwpTextInputV3>,
+    pub(crate) text_input_supported: bool,
+    pub(crate) drop_target: Option<DropTarget>,
 }
 
 impl WaylandWindow {
+    /// The window of the shell surface, with the objects of the protocols of the compositor.
     pub(crate) fn new(
-        window: Window,
-        last_output: Option<&WlOutput>,
+        wayland: &WaylandState,
+        window: ShellSurface,
+        scale_factor: f64,
         window_id: WindowId,
         attr: WindowAttributes,
-        display: WlDisplay,
-        event_sender: WlSender<Events>,
-        accesskit_adapter: Adapter,
-        region: Option<Region>,
-        viewport: Option<WpViewport>,
     ) -> Self {
-        // Set the app_id.
-        if let Some(name) = attr.app_name.map(|name| name.general) {
-            window.set_app_id(name);
-        }
-
-        if attr.maximized {
-            window.set_maximized();
-        }
-
-        if attr.fullscreen {
-            window.set_fullscreen(last_output);
-        }
-
+        let surface = window.wl_surface();
+        let viewport = wayland
+            .viewport_state
+            .as_ref()
+            .map(|v| v.get_viewport(surface, &wayland.queue_handle));
+        let accesskit = AccesskitHandler::new(surface.id(), wayland.accesskit_event_sender.clone());
+        let accesskit_adapter = Adapter::new(accesskit.clone(), accesskit.clone(), accesskit);
         let mut state = Self {
-            immutable: Arc::new(WindowImmutable::new(window, display, window_id)),
+            immutable: Arc::new(WindowImmutable::new(
+                window,
+                wayland.conn.display(),
+                window_id,
+                wayland.window_command_sender.clone(),
+            )),
+            parent: None,
+            grabbing: false,
             state: WindowState::empty(),
             window_frame: None,
             output: None,
+            outputs: Vec::new(),
+            reported_output: None,
             viewport,
+            viewport_source: None,
+            viewport_destination: None,
+            viewporter_state: wayland.viewport_state.clone(),
+            subcompositor_state: wayland.subcompositor_state.clone(),
+            subsurfaces: Vec::new(),
             size: DEFAULT_WINDOW_SIZE.to_owned(),
             stateless_size: DEFAULT_WINDOW_SIZE.to_owned(),
-            scale_factor: DEFAULT_SCALE_FACTOR,
-            event_sender,
+            pending_physical_size: None,
+            scale_factor,
+            reported_scale_factor: scale_factor,
+            preferred_transform: Transform::Normal,
+            buffer_transform: Transform::Normal,
+            event_sender: wayland.event_sender.clone(),
+            queue_handle: wayland.queue_handle.clone(),
+            frame_callback_pending: false,
             accesskit_adapter,
             decorate: true,
-            region,
+            region: Region::new(&*wayland.compositor_state).ok(),
             transparent: false,
             stateless: false,
+            configured: false,
             pointers: Vec::new(),
+            presses: Vec::new(),
+            pointer_constraints: wayland.pointer_constraints.clone(),
+            compositor: wayland.compositor_state.clone(),
+            pointer_grab: PointerGrab::None,
+            release_grab_on_focus_loss: false,
+            constraints: Vec::new(),
             selected_cursor: Default::default(),
+            custom_cursor: None,
+            cursor_pool: None,
+            shm: Shm::from(wayland.shm.wl_shm().clone()),
+            conn: wayland.conn.clone(),
+            loop_handle: wayland.loop_handle.clone(),
             cursor_visible: true,
+            kiosk: None,
+            pending_fullscreen: None,
+            titlebar_double_tap: attr.titlebar_double_tap,
+            last_titlebar_tap: None,
+            shortcuts_inhibit_state: wayland.shortcuts_inhibit.clone(),
+            shortcuts_inhibit: ShortcutsInhibit::default(),
+            presentation: wayland
+                .presentation
+                .as_ref()
+                .map(|presentation| presentation.presentation().clone()),
+            tearing_control_state: wayland.tearing_control.clone(),
+            tearing_control: None,
+            presentation_hint: PresentationHint::Vsync,
+            content_type_state: wayland.content_type.clone(),
+            content_type_object: None,
+            content_type: ContentType::None,
+            idle_inhibit_state: wayland.idle_inhibit.clone(),
+            idle_inhibit: IdleInhibit::default(),
+            dialog_state: wayland.dialog.clone(),
+            dialog: None,
+            modal: false,
+            toplevel_parent: None,
+            keyboard_seat: None,
+            ime: ImeState::default(),
+            text_inputs: Vec::new(),
+            text_input_supported: wayland.text_input.is_some(),
+            drop_target: None,
             title: attr.title,
-            visible: attr.visible,
             resizable: attr.resizable,
             hide_titlebar: attr.hide_titlebar,
             decorations: attr.decorations,
             light_theme: attr.light_theme,
+            custom_frame_config: attr.frame_config,
             min_surface_size: MIN_WINDOW_SIZE,
             max_surface_size: None,
         };
 
-        if state.decorations {
-            // TODO: do we need to make this request or not?
-            state
-                .immutable
-                .window
-                .request_decoration_mode(Some(DecorationMode::Server));
-        }
-
-        state.set_min_surface_size(attr.min_surface_size.map(|s| s.to_logical(1.0)));
-        state.set_max_surface_size(attr.max_surface_size.map(|s| s.to_logical(1.0)));
+        // The toplevels send their limits with the initial commit, see `create_window`.
+        state.min_surface_size =
+            min_surface_size(attr.min_surface_size.map(|s| s.to_logical(scale_factor)));
+        state.max_surface_size = attr.max_surface_size.map(|s| s.to_logical(scale_factor));
 
         state.size = attr
             .surface_size
-            .map(|s| s.to_logical(DEFAULT_SCALE_FACTOR as f64))
+            .map(|s| s.to_logical(scale_factor))
             .unwrap_or(DEFAULT_WINDOW_SIZE.to_owned())
             .max(state.min_surface_size);
+        // The first configure picks the stateless size when the compositor lets us decide, so
+        // the requested size gets clamped to the suggested bounds there.
+        state.stateless_size = state.size;
+        // The scale is only a guess until the surface enters an output.
+        if let Some(Size::Physical(size)) = attr.surface_size {
+            state.pending_physical_size = Some(size);
+        }
+        if attr.content_type != ContentType::None {
+            state.set_content_type(attr.content_type);
+        }
 
         state
     }
 
+    fn toplevel(&self) -> Option<&Window> {
+        self.immutable.window.toplevel()
+    }
+
+    /// Integer scale for `wl_surface::set_buffer_scale`, for the renderers drawing without a
+    /// viewport.
+    pub fn buffer_scale(&self) -> i32 {
+        self.scale_factor.ceil() as i32
+    }
+
+    /// The transform of the output the window is on, e.g. `_90` for a rotated monitor.
+    ///
+    /// Renderers drawing the buffers already transformed save the compositor the rotation, see
+    /// [`WaylandWindow::set_buffer_transform`].
+    pub fn preferred_transform(&self) -> Transform {
+        self.preferred_transform
+    }
+
+    /// Tell the compositor the buffers are rendered with the transform, from the next commit.
+    ///
+    /// With `_90` and `_270` the buffers are as wide as the surface is high, the surface size
+    /// stays the same.
+    pub fn set_buffer_transform(&mut self, transform: Transform) {
+        if self.buffer_transform != transform {
+            self.buffer_transform = transform;
+            self.immutable
+                .window
+                .wl_surface()
+                .set_buffer_transform(transform);
+        }
+    }
+
+    pub fn buffer_transform(&self) -> Transform {
+        self.buffer_transform
+    }
+
+    /// Re-derive the initial physical size with the current scale factor, the first configure
+    /// settles it.
+    pub(crate) fn apply_pending_physical_size(&mut self) {
+        if let Some(size) = self.pending_physical_size {
+            let size = size
+                .to_logical(self.scale_factor)
+                .max(self.min_surface_size);
+            self.stateless_size = size;
+            self.size = size;
+        }
+    }
+
     /// Set maximum inner window size.
     pub fn set_min_surface_size(&mut self, size: Option<LogicalSize<u32>>) {
-        // Ensure that the window has the right minimum size.
-        let mut size = size.unwrap_or(MIN_WINDOW_SIZE);
-        size.width = size.width.max(MIN_WINDOW_SIZE.width);
-        size.height = size.height.max(MIN_WINDOW_SIZE.height);
+        let size = min_surface_size(size);
 
         // Add the borders.
         let size = self
@@ -216,7 +576,9 @@ impl WaylandWindow {
             .unwrap_or(size);
 
         self.min_surface_size = size;
-        self.immutable.window.set_min_size(Some(size.into()));
+        if let Some(toplevel) = self.toplevel() {
+            toplevel.set_min_size(Some(size.into()));
+        }
     }
 
     /// Set maximum inner window size.
@@ -229,10 +591,15 @@ impl WaylandWindow {
         });
 
         self.max_surface_size = size;
-        self.immutable.window.set_max_size(size.map(Into::into));
+        if let Some(toplevel) = self.toplevel() {
+            toplevel.set_max_size(size.map(Into::into));
+        }
     }
 
     pub fn frame_config(&self) -> FrameConfig {
+        if let Some(config) = self.custom_frame_config.as_ref() {
+            return config.clone();
+        }
         let config = match self.light_theme {
             Some(true) => FrameConfig::light(),
             Some(false) => FrameConfig::dark(),
@@ -241,6 +608,30 @@ impl WaylandWindow {
         config.hide_titlebar(self.hide_titlebar)
     }
 
+    /// Override the config of the client side decorations frame.
+    ///
+    /// `None` restores the config computed from the theme.
+    pub fn set_frame_config(&mut self, frame_config: Option<FrameConfig>) {
+        self.custom_frame_config = frame_config;
+        self.reload_frame_config();
+    }
+
+    /// Switch between the light, the dark or the system theme of the frame.
+    ///
+    /// Has no visible effect while a custom frame config is set.
+    pub fn set_theme(&mut self, light_theme: Option<bool>) {
+        self.light_theme = light_theme;
+        self.reload_frame_config();
+    }
+
+    fn reload_frame_config(&mut self) {
+        let config = self.frame_config();
+        if let Some(frame) = self.window_frame.as_mut() {
+            frame.set_config(config);
+            self.redraw_request();
+        }
+    }
+
     /// Create a new [`WindowAttributes`] which allows modifying the window's attributes before
     /// creation.
     #[inline]
@@ -265,31 +656,378 @@ impl WaylandWindow {
         }
     }
 
+    /// Request a frame callback, call it right before presenting a buffer.
+    ///
+    /// Meant for renderers committing the surface on their own. Redraw requests made while the
+    /// callback is pending are delayed until the compositor is ready for a new frame, and the
+    /// callback itself triggers a draw, so calling it on every present redraws continuously at
+    /// the pace of the compositor.
+    pub fn pre_present_notify(&mut self) {
+        self.latch_subsurface_positions();
+        if self.frame_callback_pending {
+            return;
+        }
+        let surface = self.immutable.window.wl_surface();
+        surface.frame(&self.queue_handle, surface.clone());
+        self.frame_callback_pending = true;
+    }
+
+    /// Ask when the content of the next commit reaches the screen, call it right before
+    /// presenting a buffer.
+    ///
+    /// The answer comes with [`crate::ApplicationHandler::presentation_handle`]. Returns `false`
+    /// when the compositor has no presentation-time.
+    pub fn request_presentation_feedback(&self) -> bool {
+        let Some(presentation) = self.presentation.as_ref() else {
+            return false;
+        };
+        request_feedback(
+            presentation,
+            self.immutable.window.wl_surface(),
+            &self.queue_handle,
+        );
+        true
+    }
+
+    /// Tell the compositor which parts of the next buffer changed, in buffer pixels, so it only
+    /// recomposites them.
+    ///
+    /// Call it before the commit of the renderer, usually together with
+    /// [`WaylandWindow::pre_present_notify`]. The damage is applied with that commit and is
+    /// reset after it, so every frame reports its own. Renderers like the ones of wgpu damage the
+    /// whole buffer themselves, this is for the shm and dmabuf renderers committing on their own.
+    ///
+    /// The parts outside of the buffer are ignored by the compositor. Compositors without
+    /// `damage_buffer` get the whole surface damaged instead, as the buffer may not match the
+    /// surface exactly. Does nothing before the window is configured, as no buffer can be
+    /// attached yet.
+    pub fn damage(&self, rects: &[Rect<i32>]) {
+        if !self.configured {
+            return;
+        }
+        let surface = self.immutable.window.wl_surface();
+        // `damage_buffer` comes with the version 4 of the compositor.
+        if surface.version() < 4 {
+            if !rects.is_empty() {
+                surface.damage(0, 0, i32::MAX, i32::MAX);
+            }
+            return;
+        }
+        for rect in rects {
+            let (x, y) = (rect.x.max(0), rect.y.max(0));
+            let right = rect.x.saturating_add(rect.width);
+            let bottom = rect.y.saturating_add(rect.height);
+            if right <= x || bottom <= y {
+                continue;
+            }
+            surface.damage_buffer(x, y, right - x, bottom - y);
+        }
+    }
+
+    /// Damage the whole buffer, see [`WaylandWindow::damage`].
+    pub fn damage_full(&self) {
+        self.damage(&[Rect::new(0, 0, i32::MAX, i32::MAX)]);
+    }
+
+    /// Whether the compositor supports [`WaylandWindow::set_presentation_hint`].
+    #[inline]
+    pub fn tearing_control_supported(&self) -> bool {
+        self.tearing_control_state.is_some()
+    }
+
+    /// Let the compositor show the frames as soon as they are committed, tearing if needed, or
+    /// wait for the vertical blank again.
+    ///
+    /// Applied with the next commit. Compositors usually only allow the tearing of fullscreen
+    /// windows.
+    pub fn set_presentation_hint(&mut self, hint: PresentationHint) {
+        let Some(state) = self.tearing_control_state.as_ref() else {
+            warn!("The compositor does not support tearing control");
+            return;
+        };
+        if self.presentation_hint == hint {
+            return;
+        }
+        self.presentation_hint = hint;
+        let tearing_control = self.tearing_control.get_or_insert_with(|| {
+            state.get_tearing_control(self.immutable.window.wl_surface(), &self.queue_handle)
+        });
+        tearing_control.set_presentation_hint(hint.into());
+    }
+
+    #[inline]
+    pub fn presentation_hint(&self) -> PresentationHint {
+        self.presentation_hint
+    }
+
+    fn destroy_tearing_control(&mut self) {
+        if let Some(tearing_control) = self.tearing_control.take() {
+            tearing_control.destroy();
+        }
+    }
+
+    /// Tell the compositor what the window shows, e.g. to drop the animations of the games or to
+    /// match the refresh rate of the videos.
+    ///
+    /// Applied with the next commit. Does nothing when the compositor has no content-type.
+    pub fn set_content_type(&mut self, content_type: ContentType) {
+        let Some(state) = self.content_type_state.as_ref() else {
+            warn!("The compositor does not support the content type hint");
+            return;
+        };
+        if self.content_type == content_type {
+            return;
+        }
+        self.content_type = content_type;
+        let object = self.content_type_object.get_or_insert_with(|| {
+            state.get_surface_content_type(self.immutable.window.wl_surface(), &self.queue_handle)
+        });
+        object.set_content_type(content_type.into());
+    }
+
+    #[inline]
+    pub fn content_type(&self) -> ContentType {
+        self.content_type
+    }
+
+    fn destroy_content_type(&mut self) {
+        if let Some(object) = self.content_type_object.take() {
+            object.destroy();
+        }
+    }
+
     #[inline]
     pub fn set_cursor(&mut self, cursor: CursorIcon) {
         self.selected_cursor = cursor;
+        self.drop_custom_cursor();
+        self.apply_on_pointer(|pointer| self.show_cursor(&pointer));
+    }
+
+    /// Replace the cursor with the images drawn by the application.
+    ///
+    /// Animated cursors only run while a pointer is over the window and the cursor is visible.
+    pub fn set_custom_cursor(&mut self, cursor: &CustomCursor) {
+        if self.cursor_pool.is_none() {
+            match SlotPool::new(1, &self.shm) {
+                Ok(pool) => self.cursor_pool = Some(pool),
+                Err(err) => {
+                    error!("{err}");
+                    return;
+                }
+            }
+        }
+        let Some(pool) = self.cursor_pool.as_mut() else {
+            return;
+        };
+        match CursorAnimation::new(pool, cursor) {
+            Ok(animation) => {
+                self.drop_custom_cursor();
+                self.custom_cursor = Some(animation);
+                self.apply_on_pointer(|pointer| self.show_cursor(&pointer));
+                self.update_cursor_animation();
+            }
+            Err(err) => error!("{err}"),
+        }
+    }
+
+    /// Set the cursor of the window on the pointer, or hide it.
+    pub(crate) fn show_cursor(&self, pointer: &PointerKind) {
+        // The tablet tools keep the cursor of the compositor.
+        if !matches!(pointer, PointerKind::Mouse(_)) {
+            return;
+        }
+        let result = match self.custom_cursor.as_ref() {
+            _ if !self.cursor_visible => pointer.hide_cursor(),
+            Some(animation) => animation.show(pointer),
+            None => pointer.set_cursor(&self.conn, self.selected_cursor),
+        };
+        if let Err(err) = result {
+            error!("{err}");
+        }
+    }
+
+    fn drop_custom_cursor(&mut self) {
+        if let Some(token) = self
+            .custom_cursor
+            .take()
+            .and_then(|animation| animation.timer)
+        {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    /// Run the cursor animation only while somebody can see it.
+    pub(crate) fn update_cursor_animation(&mut self) {
+        let run = self.cursor_visible
+            && self
+                .pointers
+                .iter()
+                .any(|pointer| pointer.strong_count() > 0);
+        let window_id = self.get_id();
+        let Some(animation) = self.custom_cursor.as_mut() else {
+            return;
+        };
+        match (run && animation.is_animated(), animation.timer) {
+            (true, None) => {
+                let timer = Timer::from_duration(animation.duration());
+                match self.loop_handle.insert_source(timer, move |_, _, state| {
+                    match state.windows.get_mut(&window_id) {
+                        Some(window) => window.next_cursor_frame(),
+                        None => TimeoutAction::Drop,
+                    }
+                }) {
+                    Ok(token) => animation.timer = Some(token),
+                    Err(err) => error!("{err}"),
+                }
+            }
+            (false, Some(token)) => {
+                self.loop_handle.remove(token);
+                animation.timer = None;
+            }
+            _ => (),
+        }
+    }
+
+    fn next_cursor_frame(&mut self) -> TimeoutAction {
+        let Some(animation) = self.custom_cursor.as_mut() else {
+            return TimeoutAction::Drop;
+        };
+        animation.advance();
+        let duration = animation.duration();
+        self.apply_on_pointer(|pointer| self.show_cursor(&pointer));
+        TimeoutAction::ToDuration(duration)
     }
 
     /// Whether show or hide client side decorations.
     #[inline]
     pub fn set_decorate(&mut self, decorate: bool) {
-        if decorate == self.decorate {
+        if self.apply_decorate(decorate) {
+            // Force the resize.
+            self.resize(self.size);
+        }
+    }
+
+    /// Change who draws the decorations of the window.
+    ///
+    /// With [`Decorations::None`] the frame is dropped and the window geometry matches the
+    /// surface. Switching back creates the frame on the next configure.
+    pub fn set_decoration_mode(&mut self, decorations: Decorations) {
+        if decorations == self.decorations {
+            return;
+        }
+        self.decorations = decorations;
+
+        let mode = match decorations {
+            Decorations::Server => DecorationMode::Server,
+            Decorations::Client | Decorations::None => DecorationMode::Client,
+        };
+        if let Some(toplevel) = self.toplevel() {
+            toplevel.request_decoration_mode(Some(mode));
+        }
+
+        match decorations {
+            Decorations::None if self.window_frame.take().is_some() => {
+                // Force the resize.
+                self.resize(self.size);
+            }
+            // The compositor sees the client mode in both cases, it may not configure again.
+            Decorations::Client if self.window_frame.is_none() && self.configured => {
+                self.create_frame();
+                self.resize(self.size);
+            }
+            _ => (),
+        }
+        self.redraw_request();
+    }
+
+    /// Draw the decorations from now on, without waiting for a configure.
+    fn create_frame(&mut self) {
+        let (Some(toplevel), Some(subcompositor)) =
+            (self.toplevel(), self.subcompositor_state.clone())
+        else {
             return;
+        };
+        match AdwaitaFrame::new(
+            toplevel,
+            &self.shm,
+            self.compositor.clone(),
+            subcompositor,
+            self.queue_handle.clone(),
+            self.frame_config(),
+        ) {
+            Ok(mut frame) => {
+                frame.set_title(&self.title);
+                frame.set_scaling_factor(self.scale_factor);
+                frame.update_state(self.state);
+                // The frame is not a part of the window while it's fullscreen.
+                frame.set_hidden(!self.decorate || self.state.contains(WindowState::FULLSCREEN));
+                self.window_frame = Some(frame);
+            }
+            Err(err) => error!("Failed to create client side decorations frame: {err}"),
+        }
+    }
+
+    /// Update the decorations returning whether the geometry has to be recomputed.
+    fn apply_decorate(&mut self, decorate: bool) -> bool {
+        if decorate == self.decorate {
+            return false;
         }
 
         self.decorate = decorate;
 
-        if self.decorate {
-            self.immutable
-                .window
-                .request_decoration_mode(Some(DecorationMode::Server));
+        if let Some(toplevel) = self.toplevel().filter(|_| self.decorate) {
+            toplevel.request_decoration_mode(Some(DecorationMode::Server));
         }
 
         if let Some(frame) = self.window_frame.as_mut() {
-            frame.set_hidden(!decorate);
-            // Force the resize.
-            self.resize(self.size);
+            frame.set_hidden(frame_hidden(decorate, self.state));
+            return true;
+        }
+        false
+    }
+
+    /// Apply several changes at once.
+    ///
+    /// Unlike calling the setters one by one, the geometry is recomputed and the frame is
+    /// refreshed only once, on the next draw.
+    pub fn update(&mut self, f: impl FnOnce(&mut PendingChanges)) {
+        let mut changes = PendingChanges::default();
+        f(&mut changes);
+        let scale_factor = self.scale_factor;
+        let mut geometry = false;
+
+        if let Some(title) = changes.title {
+            self.set_title(title);
+        }
+        if let Some(decorate) = changes.decorate {
+            geometry |= self.apply_decorate(decorate);
+        }
+        // Borders depend on the decorations, so the limits go after them.
+        if let Some(size) = changes.min_surface_size {
+            self.set_min_surface_size(size.map(|s| s.to_logical(scale_factor)));
         }
+        if let Some(size) = changes.max_surface_size {
+            self.set_max_surface_size(size.map(|s| s.to_logical(scale_factor)));
+        }
+        if let Some(transparent) = changes.transparent {
+            // The hint is reloaded together with the geometry.
+            self.transparent = transparent;
+            geometry = true;
+        }
+        let old_size = self.size;
+        match changes.surface_size.filter(|_| self.stateless) {
+            Some(size) => self.resize(size.to_logical(scale_factor)),
+            None if geometry => self.resize(self.size),
+            None => (),
+        }
+        if self.size != old_size {
+            // The application hears of the new size like after a configure.
+            let surface_id = self.immutable.object_id.clone();
+            self.loop_handle.insert_idle(move |state| {
+                state.windows.resize_request.insert(surface_id);
+            });
+        }
+        self.redraw_request();
     }
 
     /// Set the window title to a new value.
@@ -311,7 +1049,9 @@ impl WaylandWindow {
             frame.set_title(&title);
         }
 
-        self.immutable.window.set_title(&title);
+        if let Some(toplevel) = self.toplevel() {
+            toplevel.set_title(&title);
+        }
         self.title = title;
     }
 
@@ -322,13 +1062,68 @@ impl WaylandWindow {
         self.reload_transparency_hint();
     }
 
+    /// Size of the window in physical pixels, the size of the buffers drawn for it without a
+    /// buffer transform.
+    #[inline]
+    pub fn surface_size(&self) -> PhysicalSize<u32> {
+        logical_to_physical_rounded(self.size, self.scale_factor)
+    }
+
+    /// Size of the buffers drawn for the window with its buffer transform, see
+    /// [`WaylandWindow::set_buffer_transform`].
+    #[inline]
+    pub fn buffer_size(&self) -> PhysicalSize<u32> {
+        transformed_size(self.surface_size(), self.buffer_transform)
+    }
+
     /// Try to resize the window when the user can do so.
     pub fn request_inner_size(&mut self, inner_size: PhysicalSize<u32>) -> PhysicalSize<u32> {
         if self.stateless {
-            self.resize(inner_size.to_logical(self.scale_factor as f64))
+            self.resize(inner_size.to_logical(self.scale_factor))
         }
 
-        logical_to_physical_rounded(self.size, self.scale_factor as f64)
+        logical_to_physical_rounded(self.size, self.scale_factor)
+    }
+
+    /// Apply a command sent through a [`WindowProxy`], returning whether the size changed.
+    pub(crate) fn apply_command(&mut self, command: WindowCommand) -> bool {
+        let size = self.size;
+        let scale_factor = self.scale_factor;
+        match command {
+            WindowCommand::SetTitle(title) => self.set_title(title),
+            WindowCommand::SetCursor(cursor) => self.set_cursor(cursor),
+            WindowCommand::SetCustomCursor(cursor) => self.set_custom_cursor(&cursor),
+            WindowCommand::SetCursorVisible(visible) => self.set_cursor_visible(visible),
+            WindowCommand::RequestSurfaceSize(size) => {
+                self.request_inner_size(size.to_physical(scale_factor));
+            }
+            WindowCommand::SetMinSurfaceSize(size) => {
+                self.set_min_surface_size(size.map(|s| s.to_logical(scale_factor)))
+            }
+            WindowCommand::SetMaxSurfaceSize(size) => {
+                self.set_max_surface_size(size.map(|s| s.to_logical(scale_factor)))
+            }
+            WindowCommand::SetDecorate(decorate) => self.set_decorate(decorate),
+            WindowCommand::SetDecorationMode(decorations) => self.set_decoration_mode(decorations),
+            WindowCommand::SetTransparent(transparent) => self.set_transparent(transparent),
+            WindowCommand::SetMaximized(maximized) => self.set_maximized(maximized),
+            WindowCommand::SetMinimized => self.set_minimized(),
+            WindowCommand::SetFullscreen(monitor) => self.set_fullscreen(monitor.as_ref()),
+            WindowCommand::UnsetFullscreen => self.unset_fullscreen(),
+            WindowCommand::DragWindow => {
+                self.drag_window();
+            }
+            WindowCommand::AcceptDrag(mime_type, preferred) => {
+                self.accept_drag(mime_type, preferred)
+            }
+            WindowCommand::ChooseDragAction(action) => self.choose_drag_action(action),
+            WindowCommand::StartDrag(content, icon) => {
+                self.start_drag(content, icon);
+            }
+            // Handled by the registry.
+            WindowCommand::RedrawRequest | WindowCommand::Close => (),
+        }
+        size != self.size
     }
 
     pub fn apply_on_pointer(&self, f: impl Fn(Rc<PointerKind>)) {
@@ -340,42 +1135,155 @@ impl WaylandWindow {
             });
     }
 
-    /// Start the window drag.
-    pub fn drag_window(&self) {
-        let xdg_toplevel = self.immutable.window.xdg_toplevel();
-        self.apply_on_pointer(|pointer| {
-            if let (Some(serial), Some(seat)) = (pointer.latest_serial(), pointer.seat()) {
-                xdg_toplevel._move(seat, serial);
-            }
-        });
+    /// The latest press still held on the window.
+    ///
+    /// Compositors ignore the serials of released buttons, so only these can start a grab.
+    fn grab(&self) -> Option<(Rc<PointerKind>, u32)> {
+        self.presses
+            .iter()
+            .rev()
+            .find_map(|(pointer, _, serial)| Some((pointer.upgrade()?, *serial)))
     }
 
-    /// Start interacting drag resize.
-    pub fn drag_resize_window(&self, direction: XdgResizeEdge) {
-        let xdg_toplevel = self.immutable.window.xdg_toplevel();
+    /// Start the window drag.
+    ///
+    /// Returns `false` if no button or touch is held on the window.
+    pub fn drag_window(&self) -> bool {
+        let Some(toplevel) = self.toplevel() else {
+            return false;
+        };
+        let xdg_toplevel = toplevel.xdg_toplevel();
+        if let Some((pointer, serial)) = self.grab()
+            && let Some(seat) = pointer.seat()
+        {
+            xdg_toplevel._move(seat, serial);
+            return true;
+        }
+        false
+    }
 
-        self.apply_on_pointer(|pointer| {
-            if let (Some(serial), Some(seat)) = (pointer.latest_serial(), pointer.seat()) {
-                xdg_toplevel.resize(seat, serial, direction);
-            }
+    /// Start dragging the content out of the window, the end comes as a [`DragSourceEvent`].
+    ///
+    /// Returns `false` if no button or touch is held on the window.
+    ///
+    /// [`DragSourceEvent`]: crate::DragSourceEvent
+    pub fn start_drag(&self, content: DragContent, icon: Option<DragIcon>) -> bool {
+        let Some((seat, serial)) = self
+            .grab()
+            .and_then(|(pointer, serial)| Some((pointer.seat()?.clone(), serial)))
+        else {
+            return false;
+        };
+        let surface_id = self.immutable.object_id.clone();
+        // The data devices are in the state.
+        self.loop_handle.insert_idle(move |state| {
+            state.start_drag(surface_id, seat, serial, content, icon);
         });
+        true
     }
 
-    pub fn show_window_menu(&self, position: impl Into<Position>) {
-        let position: Position = position.into();
-        let position: LogicalPosition<u32> = position.to_logical(self.scale_factor as f64);
-        self.apply_on_pointer(|pointer| {
-            if let (Some(serial), Some(seat)) = (pointer.latest_serial(), pointer.seat()) {
-                self.immutable
-                    .window
-                    .show_window_menu(seat, serial, position.into());
-            }
-        });
+    /// Start interacting drag resize.
+    ///
+    /// Returns `false` if no button or touch is held on the window.
+    pub fn drag_resize_window(&self, direction: XdgResizeEdge) -> bool {
+        let Some(toplevel) = self.toplevel() else {
+            return false;
+        };
+        let xdg_toplevel = toplevel.xdg_toplevel();
+        if let Some((pointer, serial)) = self.grab()
+            && let Some(seat) = pointer.seat()
+        {
+            xdg_toplevel.resize(seat, serial, direction);
+            return true;
+        }
+        false
     }
 
-    #[inline]
+    /// The edge of the window under the position, for the windows drawing their own borders.
+    ///
+    /// The borders are `border_width` logical pixels wide, the windows smaller than two borders
+    /// split them in the middle. Returns `None` away from the borders, or if the window can't be
+    /// resized by dragging: it's not resizable, maximized, fullscreen or tiled.
+    pub fn resize_edge_at(
+        &self,
+        position: impl Into<Position>,
+        border_width: u32,
+    ) -> Option<XdgResizeEdge> {
+        if !self.resizable
+            || self
+                .state
+                .intersects(WindowState::MAXIMIZED | WindowState::FULLSCREEN | WindowState::TILED)
+        {
+            return None;
+        }
+        let position: LogicalPosition<f64> = position.into().to_logical(self.scale_factor);
+        let (width, height) = (self.size.width as f64, self.size.height as f64);
+        if !(0. ..width).contains(&position.x) || !(0. ..height).contains(&position.y) {
+            return None;
+        }
+        let border_x = (border_width as f64).min(width / 2.);
+        let border_y = (border_width as f64).min(height / 2.);
+        let left = position.x < border_x;
+        let right = !left && position.x >= width - border_x;
+        let top = position.y < border_y;
+        let bottom = !top && position.y >= height - border_y;
+        Some(match (left, right, top, bottom) {
+            (true, _, true, _) => XdgResizeEdge::TopLeft,
+            (_, true, true, _) => XdgResizeEdge::TopRight,
+            (true, _, _, true) => XdgResizeEdge::BottomLeft,
+            (_, true, _, true) => XdgResizeEdge::BottomRight,
+            (true, ..) => XdgResizeEdge::Left,
+            (_, true, ..) => XdgResizeEdge::Right,
+            (_, _, true, _) => XdgResizeEdge::Top,
+            (.., true) => XdgResizeEdge::Bottom,
+            _ => return None,
+        })
+    }
+
+    /// Start the resize if the position is on a border, see [`Self::resize_edge_at`].
+    ///
+    /// The cursor is set to the one of the edge. Returns `false` if the position is not on a
+    /// border, or if no button or touch is held on the window.
+    pub fn begin_interactive_resize_if_edge(
+        &mut self,
+        position: impl Into<Position>,
+        border_width: u32,
+    ) -> bool {
+        let Some(edge) = self.resize_edge_at(position, border_width) else {
+            return false;
+        };
+        if let Some(icon) = resize_cursor(edge) {
+            self.set_cursor(icon);
+        }
+        self.drag_resize_window(edge)
+    }
+
+    /// Show the window menu of the compositor.
+    ///
+    /// Returns `false` if no button or touch is held on the window, or in the kiosk mode.
+    pub fn show_window_menu(&self, position: impl Into<Position>) -> bool {
+        let Some(toplevel) = self.toplevel().filter(|_| self.kiosk.is_none()) else {
+            return false;
+        };
+        let position: Position = position.into();
+        let position: LogicalPosition<u32> = position.to_logical(self.scale_factor);
+        if let Some((pointer, serial)) = self.grab()
+            && let Some(seat) = pointer.seat()
+        {
+            toplevel.show_window_menu(seat, serial, position.into());
+            return true;
+        }
+        false
+    }
+
+    #[inline]
     pub fn set_cursor_visible(&mut self, visible: bool) {
+        if self.cursor_visible == visible {
+            return;
+        }
         self.cursor_visible = visible;
+        self.apply_on_pointer(|pointer| self.show_cursor(&pointer));
+        self.update_cursor_animation();
     }
 
     #[inline]
@@ -389,10 +1297,82 @@ impl WaylandWindow {
         self.state.contains(WindowState::FULLSCREEN)
     }
 
-    /// Is [`WindowState::FULLSCREEN`] state is set.
+    /// Request fullscreen on the monitor, which the window does not need to be on.
+    ///
+    /// The compositor chooses the monitor when `None`, or when the monitor was disconnected.
+    #[inline]
+    pub fn set_fullscreen(&self, monitor: Option<&Monitor>) {
+        if let Some(toplevel) = self.toplevel() {
+            toplevel.set_fullscreen(monitor.and_then(Monitor::live_output));
+        }
+    }
+
+    /// Leave fullscreen, the window gets the size it had before on the next configure.
+    ///
+    /// It also leaves the kiosk mode, which would take the fullscreen back.
+    pub fn unset_fullscreen(&mut self) {
+        if self.kiosk.is_some() {
+            self.set_kiosk(false, None);
+        } else if let Some(toplevel) = self.toplevel() {
+            toplevel.unset_fullscreen();
+        }
+    }
+
+    /// Keep the window fullscreen on the monitor, or on the current one if `None`.
+    ///
+    /// Whenever the compositor takes the fullscreen away, it's requested again and
+    /// [`crate::ApplicationHandler::kiosk_recovery_handle`] is called. The window menu and the
+    /// decorations are disabled meanwhile. Disabling the mode leaves fullscreen, so the window
+    /// gets its previous size back.
+    pub fn set_kiosk(&mut self, kiosk: bool, monitor: Option<Monitor>) {
+        let Some(toplevel) = self.immutable.window.toplevel().cloned() else {
+            return;
+        };
+        if kiosk {
+            let decorate = self
+                .kiosk
+                .as_ref()
+                .map_or(self.decorate, |kiosk| kiosk.decorate);
+            let output = monitor.and_then(|monitor| monitor.live_output().cloned());
+            toplevel.set_fullscreen(output.as_ref().or(self.output.as_ref()));
+            self.kiosk = Some(Kiosk {
+                output,
+                attempts: 0,
+                decorate,
+            });
+            if self.apply_decorate(false) {
+                self.resize(self.size);
+            }
+        } else if let Some(kiosk) = self.kiosk.take() {
+            toplevel.unset_fullscreen();
+            if self.apply_decorate(kiosk.decorate) {
+                self.resize(self.size);
+            }
+        }
+    }
+
     #[inline]
-    pub fn set_fullscreen(&self) {
-        self.immutable.window.set_fullscreen(self.output.as_ref());
+    pub fn is_kiosk(&self) -> bool {
+        self.kiosk.is_some()
+    }
+
+    /// Toggle the maximization with a double tap on the titlebar of the client side frame.
+    ///
+    /// Double clicks with a mouse are left to the frame.
+    pub fn set_titlebar_double_tap(&mut self, enabled: bool) {
+        self.titlebar_double_tap = enabled;
+        self.last_titlebar_tap = None;
+    }
+
+    /// Whether the touch down on the titlebar completes a double tap.
+    pub(crate) fn titlebar_double_tap(&mut self, time: u32, position: (f64, f64)) -> bool {
+        let Some((last_time, last_position)) = self.last_titlebar_tap.take() else {
+            return false;
+        };
+        self.titlebar_double_tap
+            && time.wrapping_sub(last_time) < DOUBLE_TAP_DURATION
+            && (position.0 - last_position.0).hypot(position.1 - last_position.1)
+                < DOUBLE_TAP_DISTANCE
     }
 
     #[inline]
@@ -404,7 +1384,9 @@ impl WaylandWindow {
     #[inline]
     pub fn set_minimized(&self) {
         // You can't unminimize the window on Wayland.
-        self.immutable.window.set_minimized();
+        if let Some(toplevel) = self.toplevel() {
+            toplevel.set_minimized();
+        }
     }
 
     #[inline]
@@ -414,35 +1396,516 @@ impl WaylandWindow {
 
     #[inline]
     pub fn set_maximized(&self, maximized: bool) {
-        if maximized {
-            self.immutable.window.set_maximized()
-        } else {
-            self.immutable.window.unset_maximized()
+        match self.toplevel() {
+            Some(toplevel) if maximized => toplevel.set_maximized(),
+            Some(toplevel) => toplevel.unset_maximized(),
+            None => (),
+        }
+    }
+
+    pub(crate) fn output_enter(&mut self, output: &WlOutput) {
+        if !self.outputs.contains(output) {
+            self.outputs.push(output.clone());
+        }
+        // Keep the primary output until the surface leaves it, so it does not flip while the
+        // window is dragged across outputs.
+        if self.output.is_none() {
+            self.output = Some(output.clone());
+        }
+    }
+
+    pub(crate) fn output_leave(&mut self, output: &WlOutput) {
+        self.outputs.retain(|o| o != output);
+        if self.output.as_ref() == Some(output) {
+            self.output = self.outputs.first().cloned();
         }
     }
 
     pub fn pointer_enter(&mut self, pointer: Rc<PointerKind>) {
         self.pointers.push(Rc::downgrade(&pointer));
+        self.update_cursor_animation();
+        if let Err(err) = self.constrain(&pointer) {
+            error!("{err}");
+        }
+    }
+
+    /// Grab the pointers over the window, replacing the current grab.
+    ///
+    /// The mode is applied again to the pointers entering the window later, including the ones of
+    /// newly plugged seats.
+    pub fn set_cursor_grab(&mut self, mode: CursorGrabMode) -> Result<(), ConstraintError> {
+        let grab = match mode {
+            CursorGrabMode::None => PointerGrab::None,
+            CursorGrabMode::Confined => PointerGrab::Confined(None),
+            CursorGrabMode::Locked => PointerGrab::Locked,
+        };
+        if grab == self.pointer_grab {
+            return Ok(());
+        }
+        self.clear_constraints();
+        self.set_pointer_grab(grab)
+    }
+
+    /// The grab currently requested on the window.
+    pub fn cursor_grab(&self) -> CursorGrabMode {
+        match self.pointer_grab {
+            PointerGrab::None => CursorGrabMode::None,
+            PointerGrab::Locked => CursorGrabMode::Locked,
+            PointerGrab::Confined(_) => CursorGrabMode::Confined,
+        }
+    }
+
+    /// Release the grab when the window loses the keyboard focus.
+    #[inline]
+    pub fn set_release_grab_on_focus_loss(&mut self, release: bool) {
+        self.release_grab_on_focus_loss = release;
+    }
+
+    pub(crate) fn focus_lost(&mut self) {
+        self.keyboard_seat = None;
+        if self.release_grab_on_focus_loss {
+            self.clear_constraints();
+        }
+    }
+
+    pub(crate) fn focus_gained(&mut self, seat: WlSeat) {
+        self.keyboard_seat = Some(seat);
+        self.inhibit_shortcuts();
+    }
+
+    /// Ask the compositor to pass its own shortcuts, like Alt+Tab, to the window while it's
+    /// focused.
+    ///
+    /// [`crate::ApplicationHandler::shortcuts_inhibited_handle`] tells whether the compositor
+    /// granted it. The inhibition is asked again when the window regains the focus after the
+    /// compositor withdrew it.
+    pub fn set_shortcuts_inhibited(&mut self, inhibited: bool) {
+        if self.shortcuts_inhibit_state.is_none() {
+            warn!("The compositor does not support inhibiting keyboard shortcuts");
+            return;
+        }
+        self.shortcuts_inhibit.requested = inhibited;
+        if inhibited {
+            self.inhibit_shortcuts();
+        } else {
+            self.shortcuts_inhibit.release();
+        }
+    }
+
+    #[inline]
+    pub fn shortcuts_inhibited(&self) -> bool {
+        self.shortcuts_inhibit.requested
+    }
+
+    /// Whether the compositor supports [`WaylandWindow::set_idle_inhibited`], the applications
+    /// may fall back to the screensaver inhibition of D-Bus.
+    #[inline]
+    pub fn idle_inhibit_supported(&self) -> bool {
+        self.idle_inhibit_state.is_some()
+    }
+
+    /// Keep the screen from blanking or locking while the window is visible, e.g. during a video.
+    pub fn set_idle_inhibited(&mut self, inhibited: bool) {
+        if self.idle_inhibit_state.is_none() {
+            warn!("The compositor does not support inhibiting the idle");
+            return;
+        }
+        self.idle_inhibit.requested = inhibited;
+        if inhibited {
+            self.inhibit_idle();
+        } else {
+            self.idle_inhibit.release();
+        }
+    }
+
+    #[inline]
+    pub fn idle_inhibited(&self) -> bool {
+        self.idle_inhibit.requested
+    }
+
+    /// Create the inhibitor once the window is mapped, unless it already has one.
+    pub(crate) fn inhibit_idle(&mut self) {
+        if let Some(state) = self.idle_inhibit_state.as_ref().filter(|_| self.configured) {
+            self.idle_inhibit.inhibit(
+                state,
+                self.immutable.window.wl_surface(),
+                &self.queue_handle,
+            );
+        }
+    }
+
+    /// Stack the window above its parent, e.g. for a dialog, or drop the parent with `None`.
+    ///
+    /// Both must be toplevels.
+    pub fn set_parent(&mut self, parent: Option<&WaylandWindow>) {
+        let Some(toplevel) = self.immutable.window.toplevel() else {
+            return;
+        };
+        let parent = match parent.map(|parent| (parent, parent.immutable.window.toplevel())) {
+            Some((parent, Some(parent_toplevel))) => Some((parent, parent_toplevel)),
+            Some((_, None)) => {
+                warn!("Only a toplevel can be the parent of a window");
+                return;
+            }
+            None => None,
+        };
+        toplevel.set_parent(parent.map(|(_, parent_toplevel)| parent_toplevel));
+        self.toplevel_parent = parent.map(|(parent, _)| parent.immutable.object_id.clone());
+        if self.toplevel_parent.is_none() {
+            self.unset_modal();
+        }
+    }
+
+    /// Forget the parent once it's destroyed, the compositor does the same.
+    pub(crate) fn parent_destroyed(&mut self, parent: &ObjectId) {
+        if self.toplevel_parent.as_ref() == Some(parent) {
+            self.toplevel_parent = None;
+            self.unset_modal();
+        }
+    }
+
+    /// A window without a parent can't stay modal.
+    fn unset_modal(&mut self) {
+        if let Some(dialog) = self.dialog.as_ref().filter(|_| self.modal) {
+            dialog.unset_modal();
+        }
+        self.modal = false;
+    }
+
+    /// Whether the compositor supports [`WaylandWindow::set_modal`], the applications may block
+    /// the input of the parent on their own otherwise.
+    #[inline]
+    pub fn dialog_supported(&self) -> bool {
+        self.dialog_state.is_some()
+    }
+
+    /// Mark the window as a modal dialog of its parent, the compositor may dim or block the
+    /// parent. The applications still have to ignore the input of the parent themselves.
+    ///
+    /// Only a window with a parent of [`WaylandWindow::set_parent`] can be modal. Does nothing
+    /// when the compositor has no xdg-dialog-v1.
+    pub fn set_modal(&mut self, modal: bool) {
+        let Some(toplevel) = self.immutable.window.toplevel() else {
+            return;
+        };
+        let Some(state) = self.dialog_state.as_ref() else {
+            warn!("The compositor does not support the dialogs");
+            return;
+        };
+        if modal && self.toplevel_parent.is_none() {
+            warn!("Only a window with a parent can be modal");
+            return;
+        }
+        if self.modal == modal {
+            return;
+        }
+        self.modal = modal;
+        let dialog = self.dialog.get_or_insert_with(|| {
+            state.get_xdg_dialog(toplevel.xdg_toplevel(), &self.queue_handle)
+        });
+        if modal {
+            dialog.set_modal();
+        } else {
+            dialog.unset_modal();
+        }
+    }
+
+    #[inline]
+    pub fn is_modal(&self) -> bool {
+        self.modal
+    }
+
+    /// Let the input methods send text to the window, through
+    /// [`crate::ApplicationHandler::ime_handle`].
+    pub fn set_ime_allowed(&mut self, allowed: bool) {
+        if self.ime.allowed == allowed {
+            return;
+        }
+        self.ime.allowed = allowed;
+        self.send_ime_state();
+        if !self.text_inputs.is_empty() {
+            let event = if allowed {
+                ImeEvent::Enabled
+            } else {
+                ImeEvent::Disabled
+            };
+            if let Err(err) = self
+                .event_sender
+                .send(Events::Ime(self.immutable.object_id.clone(), event))
+            {
+                error!("{err}");
+            }
+        }
+    }
+
+    #[inline]
+    pub fn ime_allowed(&self) -> bool {
+        self.ime.allowed
+    }
+
+    /// Tell the input methods where the caret is, so their candidates are shown next to it.
+    pub fn set_ime_cursor_area(&mut self, position: impl Into<Position>, size: impl Into<Size>) {
+        if !self.text_input_supported {
+            warn!("The compositor does not support text-input-v3");
+            return;
+        }
+        self.ime.cursor_area = Some((position.into(), size.into()));
+        self.send_ime_state();
+    }
+
+    /// Tell the input methods what kind of text the focused field takes.
+    pub fn set_ime_purpose(&mut self, purpose: ImePurpose) {
+        if !self.text_input_supported {
+            warn!("The compositor does not support text-input-v3");
+            return;
+        }
+        if self.ime.purpose != purpose {
+            self.ime.purpose = purpose;
+            self.send_ime_state();
+        }
+    }
+
+    /// Send the state to the text inputs focusing the window, at the current scale.
+    pub(crate) fn send_ime_state(&self) {
+        for text_input in &self.text_inputs {
+            send_ime_state(text_input, &self.ime, self.scale_factor);
+        }
+    }
+
+    /// Accept the drag over the window in the MIME type, or reject it with `None`.
+    ///
+    /// The compositor picks the action from the preferred one and the ones of the source, it's
+    /// reported with [`DragEvent::Action`]. The data of the MIME type comes with the drop.
+    pub fn accept_drag(&mut self, mime_type: Option<String>, preferred: DragAction) {
+        if let Some(target) = self.drop_target.as_mut() {
+            target.accept(mime_type, preferred);
+        }
+    }
+
+    /// Pick the action of a drag dropped with [`DragAction::Ask`], or cancel the drop with `None`.
+    pub fn choose_drag_action(&mut self, action: Option<DragAction>) {
+        let Some(target) = self.drop_target.as_ref() else {
+            return;
+        };
+        let surface_id = self.immutable.object_id.clone();
+        if target.choose_action(&self.loop_handle, surface_id.clone(), action) {
+            self.drop_target = None;
+            if let Err(err) = self
+                .event_sender
+                .send(Events::Drag(surface_id, DragEvent::Left))
+            {
+                error!("{err}");
+            }
+        }
+    }
+
+    fn inhibit_shortcuts(&mut self) {
+        if let (Some(state), Some(seat)) = (&self.shortcuts_inhibit_state, &self.keyboard_seat) {
+            self.shortcuts_inhibit.inhibit(
+                state,
+                self.immutable.window.wl_surface(),
+                seat,
+                &self.queue_handle,
+            );
+        }
+    }
+
+    /// Lock the pointers in place while they are over the window.
+    ///
+    /// The lock takes effect once [`crate::PointerConstraintEvent::Locked`] is delivered, and is
+    /// applied again to the pointers entering the window later.
+    pub fn lock_pointer(&mut self) -> Result<(), ConstraintError> {
+        match self.pointer_grab {
+            PointerGrab::Locked => Ok(()),
+            PointerGrab::Confined(_) => Err(ConstraintError::AlreadyConstrained),
+            PointerGrab::None => self.set_pointer_grab(PointerGrab::Locked),
+        }
+    }
+
+    pub fn unlock_pointer(&mut self) {
+        if self.pointer_grab == PointerGrab::Locked {
+            self.clear_constraints();
+        }
+    }
+
+    /// Hint the compositor where to put the locked pointers once the lock is released.
+    ///
+    /// Wayland doesn't allow warping the pointer, so this only works while the pointer is locked.
+    pub fn set_cursor_position(&self, position: Position) -> Result<(), ConstraintError> {
+        if self.pointer_grab != PointerGrab::Locked || self.constraints.is_empty() {
+            return Err(ConstraintError::NotLocked);
+        }
+        let position: LogicalPosition<f64> = position.to_logical(self.scale_factor);
+        for (_, constraint) in &self.constraints {
+            if let PointerConstraint::Locked(locked_pointer) = constraint {
+                locked_pointer.set_cursor_position_hint(position.x, position.y);
+            }
+        }
+        // The hint is double-buffered state of the surface.
+        self.immutable.window.wl_surface().commit();
+        self.latch_subsurface_positions();
+        Ok(())
+    }
+
+    /// Confine the pointers to the region of the window, or to the whole window with `None`.
+    ///
+    /// Calling it again while confined only replaces the region.
+    pub fn confine_pointer(
+        &mut self,
+        region: Option<ConfineRegion>,
+    ) -> Result<(), ConstraintError> {
+        match self.pointer_grab {
+            PointerGrab::Locked => Err(ConstraintError::AlreadyConstrained),
+            PointerGrab::Confined(_) => {
+                self.pointer_grab = PointerGrab::Confined(region);
+                self.reload_confine_region();
+                // The region is double-buffered state of the surface.
+                self.immutable.window.wl_surface().commit();
+                self.latch_subsurface_positions();
+                Ok(())
+            }
+            PointerGrab::None => self.set_pointer_grab(PointerGrab::Confined(region)),
+        }
+    }
+
+    pub fn unconfine_pointer(&mut self) {
+        if matches!(self.pointer_grab, PointerGrab::Confined(_)) {
+            self.clear_constraints();
+        }
+    }
+
+    fn set_pointer_grab(&mut self, grab: PointerGrab) -> Result<(), ConstraintError> {
+        if grab != PointerGrab::None && self.pointer_constraints.bound_global().is_err() {
+            return Err(ConstraintError::Unsupported);
+        }
+        self.pointer_grab = grab;
+        let pointers: Vec<_> = self.pointers.iter().filter_map(Weak::upgrade).collect();
+        for pointer in pointers {
+            if let Err(err) = self.constrain(&pointer) {
+                self.clear_constraints();
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the requested grab to the pointer.
+    fn constrain(&mut self, pointer: &Rc<PointerKind>) -> Result<(), ConstraintError> {
+        let PointerKind::Mouse(themed_pointer) = pointer.as_ref() else {
+            return Ok(());
+        };
+        let weak = Rc::downgrade(pointer);
+        // The constraint outlives the pointer leaving the window.
+        if self.constraints.iter().any(|(p, _)| p.ptr_eq(&weak)) {
+            return Ok(());
+        }
+        let surface = self.immutable.window.wl_surface();
+        let constraint = match &self.pointer_grab {
+            PointerGrab::None => return Ok(()),
+            PointerGrab::Locked => self
+                .pointer_constraints
+                .lock_pointer(
+                    surface,
+                    themed_pointer.pointer(),
+                    None,
+                    Lifetime::Persistent,
+                    &self.queue_handle,
+                )
+                .map(PointerConstraint::Locked),
+            PointerGrab::Confined(region) => {
+                let region = region
+                    .as_ref()
+                    .and_then(|region| region.to_region(&self.compositor, self.size));
+                self.pointer_constraints
+                    .confine_pointer(
+                        surface,
+                        themed_pointer.pointer(),
+                        region.as_ref().map(Region::wl_region),
+                        Lifetime::Persistent,
+                        &self.queue_handle,
+                    )
+                    .map(PointerConstraint::Confined)
+            }
+        }
+        .map_err(|_| ConstraintError::Unsupported)?;
+        self.constraints.push((weak, constraint));
+        Ok(())
+    }
+
+    /// Rebuild the confinement region for the current surface size, applied on the next commit.
+    pub(crate) fn reload_confine_region(&self) {
+        let PointerGrab::Confined(region) = &self.pointer_grab else {
+            return;
+        };
+        let region = region
+            .as_ref()
+            .and_then(|region| region.to_region(&self.compositor, self.size));
+        for (_, constraint) in &self.constraints {
+            if let PointerConstraint::Confined(confined_pointer) = constraint {
+                confined_pointer.set_region(region.as_ref().map(Region::wl_region));
+            }
+        }
+    }
+
+    /// Release the grab and destroy all the constraints of the window.
+    pub(crate) fn clear_constraints(&mut self) {
+        self.pointer_grab = PointerGrab::None;
+        for (_, constraint) in self.constraints.drain(..) {
+            constraint.destroy();
+        }
+    }
+
+    /// Drop the constraints of a pointer going away.
+    pub(crate) fn drop_constraints(&mut self, pointer: &Rc<PointerKind>) {
+        let pointer = Rc::downgrade(pointer);
+        self.constraints.retain(|(p, constraint)| {
+            if p.ptr_eq(&pointer) {
+                constraint.destroy();
+                return false;
+            }
+            true
+        });
     }
 
     pub fn pointer_leave(&mut self, pointer: Rc<PointerKind>) {
         self.pointers
             .retain(|p| !p.ptr_eq(&Rc::downgrade(&pointer)));
+        self.presses
+            .retain(|(p, _, _)| !p.ptr_eq(&Rc::downgrade(&pointer)));
+        self.update_cursor_animation();
+    }
+
+    pub(crate) fn has_pointer(&self, pointer: &Rc<PointerKind>) -> bool {
+        is_over(&self.pointers, pointer)
+    }
+
+    /// The `button` is the code of the button, or the id of the touch point.
+    pub(crate) fn pointer_pressed(&mut self, pointer: &Rc<PointerKind>, button: u32, serial: u32) {
+        self.pointer_released(pointer, button);
+        self.presses.push((Rc::downgrade(pointer), button, serial));
     }
 
-    pub fn frame_action(&mut self, pointer: &WlPointer, serial: u32, action: FrameAction) -> bool {
-        let pointer_data = pointer.data::<PointerData>().unwrap();
-        let seat = pointer_data.seat();
+    /// Forget the press of the button, the other buttons of the pointer are still held.
+    pub(crate) fn pointer_released(&mut self, pointer: &Rc<PointerKind>, button: u32) {
+        let pointer = Rc::downgrade(pointer);
+        self.presses
+            .retain(|(p, b, _)| !(p.ptr_eq(&pointer) && *b == button));
+    }
+
+    pub fn frame_action(&mut self, seat: &WlSeat, serial: u32, action: FrameAction) -> bool {
         // TODO: remove tracing
         tracing::debug!("Frame action: {:?}", action);
+        // Only the toplevels have a frame.
+        let Some(toplevel) = self.toplevel() else {
+            return false;
+        };
         match action {
             FrameAction::Close => return true,
-            FrameAction::Minimize => self.immutable.window.set_minimized(),
-            FrameAction::Maximize => self.immutable.window.set_maximized(),
-            FrameAction::UnMaximize => self.immutable.window.unset_maximized(),
-            FrameAction::ShowMenu(x, y) => {
-                self.immutable.window.show_window_menu(seat, serial, (x, y))
-            }
+            FrameAction::Minimize => toplevel.set_minimized(),
+            FrameAction::Maximize => toplevel.set_maximized(),
+            FrameAction::UnMaximize => toplevel.unset_maximized(),
+            FrameAction::ShowMenu(_, _) if self.kiosk.is_some() => (),
+            FrameAction::ShowMenu(x, y) => toplevel.show_window_menu(seat, serial, (x, y)),
             FrameAction::Resize(edge) => {
                 let edge = match edge {
                     ResizeEdge::None => XdgResizeEdge::None,
@@ -456,9 +1919,9 @@ impl WaylandWindow {
                     ResizeEdge::BottomRight => XdgResizeEdge::BottomRight,
                     _ => return false,
                 };
-                self.immutable.window.resize(seat, serial, edge);
+                toplevel.resize(seat, serial, edge);
             }
-            FrameAction::Move => self.immutable.window.move_(seat, serial),
+            FrameAction::Move => toplevel.move_(seat, serial),
             _ => (),
         }
         false
@@ -498,7 +1961,7 @@ impl WaylandWindow {
         }
 
         // Update the inner frame.
-        let ((x, y), outer_size) = if let Some(frame) = self.window_frame.as_mut() {
+        if let Some(frame) = self.window_frame.as_mut() {
             // Resize only visible frame.
             if !frame.is_hidden() {
                 frame.resize(
@@ -506,30 +1969,116 @@ impl WaylandWindow {
                     NonZeroU32::new(self.size.height).unwrap(),
                 );
             }
-
-            (
-                frame.location(),
-                frame.add_borders(self.size.width, self.size.height).into(),
-            )
-        } else {
-            ((0, 0), self.size)
-        };
+        }
+        let ((x, y), outer_size) = self.window_geometry();
 
         // Reload the hint.
         self.reload_transparency_hint();
+        self.reload_confine_region();
 
         // Set the window geometry.
-        self.immutable.window.xdg_surface().set_window_geometry(
-            x,
-            y,
-            outer_size.width as i32,
-            outer_size.height as i32,
-        );
+        if let Some(xdg_surface) = self.immutable.window.xdg_surface() {
+            xdg_surface.set_window_geometry(
+                x,
+                y,
+                outer_size.width as i32,
+                outer_size.height as i32,
+            );
+        }
+
+        self.reload_viewport();
+    }
+
+    /// Show only a part of the buffers, scaled to the window or to the viewport destination,
+    /// e.g. to zoom in a video without drawing it again.
+    ///
+    /// The rectangle is in the pixels of buffers drawn at [`WaylandWindow::surface_size`] with
+    /// the buffer scale 1. When the window shrinks the source is clipped to the new buffers.
+    pub fn set_viewport_source(&mut self, source: Option<Rect<f64>>) -> Result<(), ViewportError> {
+        if self.viewport.is_none() {
+            return Err(ViewportError::Unsupported);
+        }
+        if let Some(rect) = source {
+            let finite = [rect.x, rect.y, rect.width, rect.height]
+                .iter()
+                .all(|value| value.is_finite());
+            if !finite || rect.x < 0. || rect.y < 0. || rect.width <= 0. || rect.height <= 0. {
+                return Err(ViewportError::InvalidSource);
+            }
+            let buffer = self.surface_size();
+            if rect.x + rect.width > buffer.width as f64
+                || rect.y + rect.height > buffer.height as f64
+            {
+                return Err(ViewportError::OutOfBuffer);
+            }
+        }
+        self.viewport_source = source;
+        self.reload_viewport();
+        Ok(())
+    }
 
-        // Update the target viewport, this is used if and only if fractional scaling is in use.
-        if let Some(viewport) = self.viewport.as_ref() {
-            // Set surface size without the borders.
-            viewport.set_destination(self.size.width as _, self.size.height as _);
+    /// Scale the surface to the size in place of the window size, `None` restores the window
+    /// size.
+    ///
+    /// The window geometry keeps the window size.
+    pub fn set_viewport_destination(
+        &mut self,
+        destination: Option<LogicalSize<u32>>,
+    ) -> Result<(), ViewportError> {
+        if self.viewport.is_none() {
+            return Err(ViewportError::Unsupported);
+        }
+        if let Some(size) = destination {
+            let valid = |value: u32| value > 0 && i32::try_from(value).is_ok();
+            if !valid(size.width) || !valid(size.height) {
+                return Err(ViewportError::InvalidDestination);
+            }
+        }
+        self.viewport_destination = destination;
+        self.reload_viewport();
+        Ok(())
+    }
+
+    #[inline]
+    pub fn viewport_source(&self) -> Option<Rect<f64>> {
+        self.viewport_source
+    }
+
+    #[inline]
+    pub fn viewport_destination(&self) -> Option<LogicalSize<u32>> {
+        self.viewport_destination
+    }
+
+    /// Reissue the viewport, which scales the buffers drawn for the fractional scales to the
+    /// window size unless the application set its own values.
+    pub(crate) fn reload_viewport(&self) {
+        let Some(viewport) = self.viewport.as_ref() else {
+            return;
+        };
+        // Set surface size without the borders.
+        let size = self.viewport_destination.unwrap_or(self.size);
+        viewport.set_destination(size.width as _, size.height as _);
+        // A source outside of the buffer is a protocol error, so it follows the buffer size.
+        let buffer = self.surface_size();
+        let source = self.viewport_source.and_then(|rect| {
+            let width = rect.width.min(buffer.width as f64 - rect.x);
+            let height = rect.height.min(buffer.height as f64 - rect.y);
+            (width > 0. && height > 0.).then_some(Rect::new(rect.x, rect.y, width, height))
+        });
+        match source {
+            Some(rect) => viewport.set_source(rect.x, rect.y, rect.width, rect.height),
+            None => viewport.set_source(-1., -1., -1., -1.),
+        }
+    }
+
+    /// Origin of the window geometry in the surface coordinates, with its size.
+    pub(crate) fn window_geometry(&self) -> ((i32, i32), LogicalSize<u32>) {
+        match self.window_frame.as_ref() {
+            Some(frame) => (
+                frame.location(),
+                frame.add_borders(self.size.width, self.size.height).into(),
+            ),
+            None => ((0, 0), self.size),
         }
     }
 
@@ -549,15 +2098,47 @@ impl WaylandWindow {
 
     /// Refresh the decorations frame if it's present returning whether the client should redraw.
     pub fn refresh_frame(&mut self) -> bool {
-        if let Some(frame) = self.window_frame.as_mut() {
-            if !frame.is_hidden() && frame.is_dirty() {
-                return frame.draw();
-            }
+        if let Some(frame) = self.window_frame.as_mut()
+            && !frame.is_hidden()
+            && frame.is_dirty()
+        {
+            return frame.draw();
         }
         false
     }
 }
 
+/// Destroy the objects of the window, the compositor keeps them until the client disconnects
+/// otherwise.
+///
+/// The toplevel, the xdg surface and the surface are destroyed in the protocol order by
+/// [`Window`] when its last handle, including the ones of the [`WindowImmutable`] given to the
+/// application, is dropped. The renderers may still draw to the surface until then.
+impl Drop for WaylandWindow {
+    fn drop(&mut self) {
+        // Don't leave the constraints of the closed surface to the compositor.
+        self.clear_constraints();
+        self.shortcuts_inhibit.release();
+        self.idle_inhibit.release();
+        self.destroy_tearing_control();
+        self.destroy_content_type();
+        if let Some(dialog) = self.dialog.take() {
+            dialog.destroy();
+        }
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+        self.drop_custom_cursor();
+        if let Some((_, token)) = self.pending_fullscreen.take() {
+            self.loop_handle.remove(token);
+        }
+        // The subsurfaces of the frame go before their parent surface.
+        self.window_frame = None;
+        // The region is destroyed by its own drop.
+        self.region = None;
+    }
+}
+
 impl HasWindowHandle for WindowImmutable {
     fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
         let raw = self.raw_window_handle_rwh_06()?;
@@ -577,3 +2158,234 @@ impl HasDisplayHandle for WindowImmutable {
         Ok(unsafe { DisplayHandle::borrow_raw(raw) })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use smithay_client_toolkit::{
+        compositor::CompositorHandler,
+        seat::{Capability, SeatState, touch::TouchData},
+    };
+
+    use super::*;
+    use crate::{
+        WindowAttributes,
+        testing::{FakeCompositor, Request, fixed},
+    };
+
+    /// `xdg_surface::set_window_geometry`.
+    const SET_WINDOW_GEOMETRY: u16 = 3;
+    /// `xdg_toplevel::configure` and `xdg_surface::configure`.
+    const TOPLEVEL_CONFIGURE: u16 = 0;
+    const SURFACE_CONFIGURE: u16 = 0;
+    /// `xdg_toplevel::move` and `xdg_toplevel::resize`.
+    const MOVE: u16 = 5;
+    const RESIZE: u16 = 6;
+    /// `wl_seat::capabilities`, with the pointer bit.
+    const SEAT_CAPABILITIES: u16 = 0;
+    const POINTER: u32 = 1;
+    /// `wl_pointer::enter`, `wl_pointer::button` and `wl_pointer::frame`.
+    const POINTER_ENTER: u16 = 0;
+    const POINTER_BUTTON: u16 = 3;
+    const POINTER_FRAME: u16 = 5;
+    const BTN_LEFT: u32 = 0x110;
+    const RELEASED: u32 = 0;
+    const PRESSED: u32 = 1;
+
+    #[test]
+    fn quarter_turns_swap_the_buffer_size() {
+        let size = PhysicalSize::new(1920, 1080);
+        for transform in [
+            Transform::_90,
+            Transform::_270,
+            Transform::Flipped90,
+            Transform::Flipped270,
+        ] {
+            assert_eq!(
+                transformed_size(size, transform),
+                PhysicalSize::new(1080, 1920)
+            );
+        }
+        for transform in [
+            Transform::Normal,
+            Transform::_180,
+            Transform::Flipped,
+            Transform::Flipped180,
+        ] {
+            assert_eq!(transformed_size(size, transform), size);
+        }
+    }
+
+    #[test]
+    fn fullscreen_hides_the_frame() {
+        let fullscreen = WindowState::FULLSCREEN | WindowState::ACTIVATED;
+        assert!(frame_hidden(true, fullscreen));
+        assert!(!frame_hidden(true, WindowState::ACTIVATED));
+        assert!(!frame_hidden(true, WindowState::MAXIMIZED));
+        // Leaving fullscreen keeps the frame hidden without the decorations.
+        assert!(frame_hidden(false, WindowState::empty()));
+    }
+
+    #[test]
+    fn cancel_goes_to_the_windows_under_the_pointer() {
+        let compositor = FakeCompositor::new(&[("wl_seat", 9)]);
+        let queue_handle = &compositor.queue_handle;
+        let seat = SeatState::new(&compositor.globals, queue_handle)
+            .seats()
+            .next()
+            .unwrap();
+        let touch = || {
+            Rc::new(PointerKind::Touch(
+                seat.get_touch(queue_handle, TouchData::new(seat.clone())),
+            ))
+        };
+        let (removed, other) = (touch(), touch());
+        let windows = [
+            vec![Rc::downgrade(&removed)],
+            vec![Rc::downgrade(&other)],
+            vec![Rc::downgrade(&other), Rc::downgrade(&removed)],
+            Vec::new(),
+        ];
+
+        let cancelled: Vec<bool> = windows
+            .iter()
+            .map(|pointers| is_over(pointers, &removed))
+            .collect();
+        assert_eq!(cancelled, [true, false, true, false]);
+    }
+
+    #[test]
+    fn update_sets_the_geometry_and_redraws_once() {
+        let (mut compositor, mut state, mut event_loop) =
+            FakeCompositor::with_state(&[("wl_compositor", 6), ("wl_shm", 1), ("xdg_wm_base", 6)]);
+        let window_id = WindowId::next();
+        state.create_window((window_id, WindowAttributes::default()));
+        event_loop.dispatch(Duration::ZERO, &mut state).unwrap();
+        state.events.clear();
+        compositor.requests();
+
+        let window = state.windows.get_mut(&window_id).unwrap();
+        // A floating window, as after its first configure.
+        window.stateless = true;
+        let xdg_surface = window.immutable.window.xdg_surface().unwrap().id();
+        window.update(|changes| {
+            changes
+                .set_title("Batched")
+                .request_surface_size(LogicalSize::new(640, 480))
+                .set_min_surface_size(Some(LogicalSize::new(200, 100).into()))
+                .set_max_surface_size(Some(LogicalSize::new(1600, 1200).into()))
+                .set_decorate(false)
+                .set_transparent(true);
+        });
+        event_loop.dispatch(Duration::ZERO, &mut state).unwrap();
+
+        let geometry = compositor
+            .requests()
+            .into_iter()
+            .filter(|request| {
+                request.object == xdg_surface.protocol_id() && request.opcode == SET_WINDOW_GEOMETRY
+            })
+            .count();
+        assert_eq!(geometry, 1);
+        let redraws = state
+            .events
+            .iter()
+            .filter(|event| matches!(event, Events::RedrawRequest(_)))
+            .count();
+        assert_eq!(redraws, 1);
+        assert_eq!(
+            state.windows.get(&window_id).unwrap().size,
+            LogicalSize::new(640, 480)
+        );
+    }
+
+    #[test]
+    fn physical_initial_size_follows_the_scale_of_the_output() {
+        let (mut compositor, mut state, mut event_loop) =
+            FakeCompositor::with_state(&[("wl_compositor", 6), ("wl_shm", 1), ("xdg_wm_base", 6)]);
+        let window_id = WindowId::next();
+        let attributes = WindowAttributes::default().with_size(PhysicalSize::new(1600, 1200));
+        state.create_window((window_id, attributes));
+        let window = state.windows.get(&window_id).unwrap();
+        assert_eq!(window.size, LogicalSize::new(1600, 1200));
+        let xdg_window = window.toplevel().unwrap().clone();
+        let surface = xdg_window.wl_surface().clone();
+        let (conn, queue_handle) = (compositor.conn.clone(), state.queue_handle.clone());
+        let size = |state: &WaylandState| state.windows.get(&window_id).unwrap().size;
+
+        // The surface enters an output of scale 2 before the first configure.
+        CompositorHandler::scale_factor_changed(&mut state, &conn, &queue_handle, &surface, 2);
+        assert_eq!(size(&state), LogicalSize::new(800, 600));
+
+        // The compositor lets the window pick its size, without any state.
+        compositor.send(xdg_window.xdg_toplevel(), TOPLEVEL_CONFIGURE, &[0, 0, 0]);
+        compositor.send(xdg_window.xdg_surface(), SURFACE_CONFIGURE, &[1]);
+        event_loop.dispatch(Duration::ZERO, &mut state).unwrap();
+        assert!(state.windows.get(&window_id).unwrap().configured);
+        assert_eq!(size(&state), LogicalSize::new(800, 600));
+
+        // The size is logical from now on.
+        CompositorHandler::scale_factor_changed(&mut state, &conn, &queue_handle, &surface, 1);
+        assert_eq!(size(&state), LogicalSize::new(800, 600));
+    }
+
+    #[test]
+    fn released_button_starts_no_move_or_resize() {
+        let (mut compositor, mut state, mut event_loop) = FakeCompositor::with_state(&[
+            ("wl_compositor", 6),
+            ("wl_shm", 1),
+            ("xdg_wm_base", 6),
+            ("wl_seat", 7),
+        ]);
+        let seat = state.seat_state.seat.seats().next().unwrap();
+        compositor.send(&seat, SEAT_CAPABILITIES, &[POINTER]);
+        event_loop.dispatch(Duration::ZERO, &mut state).unwrap();
+        let window_id = WindowId::next();
+        state.create_window((window_id, WindowAttributes::default()));
+        let window = state.windows.get(&window_id).unwrap();
+        let surface = window.get_surface_id().protocol_id();
+        let xdg_toplevel = window.toplevel().unwrap().xdg_toplevel().id().protocol_id();
+        let pointer_id = state
+            .seat_state
+            .pointers
+            .seat_device(&seat.id(), Capability::Pointer)
+            .unwrap();
+        let kind = state.seat_state.pointers.kind(&pointer_id).unwrap();
+        let PointerKind::Mouse(pointer) = kind.as_ref() else {
+            panic!("{kind:?} is not a mouse");
+        };
+        let pointer = pointer.pointer().clone();
+        let grabs = |requests: &[Request], opcode| -> Vec<u32> {
+            requests
+                .iter()
+                .filter(|request| request.object == xdg_toplevel && request.opcode == opcode)
+                .map(|request| request.word(1))
+                .collect()
+        };
+
+        compositor.send(
+            &pointer,
+            POINTER_ENTER,
+            &[1, surface, fixed(10.), fixed(10.)],
+        );
+        compositor.send(&pointer, POINTER_BUTTON, &[2, 0, BTN_LEFT, PRESSED]);
+        compositor.send(&pointer, POINTER_FRAME, &[]);
+        event_loop.dispatch(Duration::ZERO, &mut state).unwrap();
+        compositor.requests();
+        // The held press starts the move with its serial.
+        assert!(state.windows.get(&window_id).unwrap().drag_window());
+        assert_eq!(grabs(&compositor.requests(), MOVE), [2]);
+
+        compositor.send(&pointer, POINTER_BUTTON, &[3, 0, BTN_LEFT, RELEASED]);
+        compositor.send(&pointer, POINTER_FRAME, &[]);
+        event_loop.dispatch(Duration::ZERO, &mut state).unwrap();
+        compositor.requests();
+        let window = state.windows.get(&window_id).unwrap();
+        assert!(!window.drag_window());
+        assert!(!window.drag_resize_window(XdgResizeEdge::BottomRight));
+        let requests = compositor.requests();
+        assert!(grabs(&requests, MOVE).is_empty());
+        assert!(grabs(&requests, RESIZE).is_empty());
+    }
+}
@@ -0,0 +1,51 @@
+use dpi::Size;
+
+/// Window changes applied together by [`crate::WaylandWindow::update`].
+#[derive(Debug, Default, Clone)]
+pub struct PendingChanges {
+    pub(crate) title: Option<String>,
+    pub(crate) surface_size: Option<Size>,
+    pub(crate) min_surface_size: Option<Option<Size>>,
+    pub(crate) max_surface_size: Option<Option<Size>>,
+    pub(crate) decorate: Option<bool>,
+    pub(crate) transparent: Option<bool>,
+}
+
+impl PendingChanges {
+    #[inline]
+    pub fn set_title(&mut self, title: impl Into<String>) -> &mut Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Only applied when the user can resize the window.
+    #[inline]
+    pub fn request_surface_size(&mut self, size: impl Into<Size>) -> &mut Self {
+        self.surface_size = Some(size.into());
+        self
+    }
+
+    #[inline]
+    pub fn set_min_surface_size(&mut self, size: Option<Size>) -> &mut Self {
+        self.min_surface_size = Some(size);
+        self
+    }
+
+    #[inline]
+    pub fn set_max_surface_size(&mut self, size: Option<Size>) -> &mut Self {
+        self.max_surface_size = Some(size);
+        self
+    }
+
+    #[inline]
+    pub fn set_decorate(&mut self, decorate: bool) -> &mut Self {
+        self.decorate = Some(decorate);
+        self
+    }
+
+    #[inline]
+    pub fn set_transparent(&mut self, transparent: bool) -> &mut Self {
+        self.transparent = Some(transparent);
+        self
+    }
+}
@@ -0,0 +1,408 @@
+// Handling of the xdg popups, for the menus, the dropdowns and the tooltips.
+
+use std::{error::Error, fmt};
+
+use dpi::{LogicalPosition, LogicalSize};
+pub use smithay_client_toolkit::reexports::protocols::xdg::shell::client::xdg_positioner::{
+    Anchor, ConstraintAdjustment, Gravity,
+};
+use smithay_client_toolkit::{
+    delegate_xdg_popup,
+    error::GlobalError,
+    reexports::client::{
+        Connection, Proxy, QueueHandle, backend::ObjectId, protocol::wl_seat::WlSeat,
+    },
+    shell::xdg::{
+        XdgPositioner, XdgShell,
+        popup::{ConfigureKind, Popup, PopupConfigure, PopupHandler},
+    },
+};
+use tracing::{error, warn};
+
+use crate::{
+    Decorations, Events, Rect, WaylandState, WaylandWindow, WindowAttributes, WindowId,
+    window::ShellSurface,
+};
+
+/// Id of a popup, the popups get the callbacks of the windows with it.
+pub type PopupId = WindowId;
+
+/// Why a popup could not be opened, see [`crate::ApplicationHandler::popup_failed_handle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopupError {
+    /// The grab needs a button press or a touch, none was made.
+    NoGrabSerial,
+    /// A grabbing popup opened from a popup needs its parent to grab too.
+    ParentNotGrabbing,
+    /// A grabbing popup must be opened from the topmost grabbing popup.
+    NotTopmost,
+}
+
+impl fmt::Display for PopupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoGrabSerial => {
+                f.write_str("the popup grab needs a button press or a touch, none was made")
+            }
+            Self::ParentNotGrabbing => {
+                f.write_str("the parent popup of a grabbing popup must have grabbed too")
+            }
+            Self::NotTopmost => {
+                f.write_str("the parent of a grabbing popup must be the topmost one")
+            }
+        }
+    }
+}
+
+impl Error for PopupError {}
+
+/// Geometry of a popup chosen by the compositor, which may flip or slide it to keep it on the
+/// screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PopupPlacement {
+    /// Position of the popup relative to the surface of its parent.
+    pub position: LogicalPosition<i32>,
+    pub size: LogicalSize<u32>,
+    /// The token of [`WaylandWindow::reposition`] when the placement answers it.
+    pub token: Option<u32>,
+}
+
+/// Placement of a popup next to an area of its parent, see the `xdg_positioner` of the
+/// xdg-shell.
+#[derive(Debug, Clone)]
+pub struct PopupAttributes {
+    pub size: LogicalSize<u32>,
+    /// Area of the parent surface the popup is placed next to, e.g. the button of a dropdown, in
+    /// logical surface coordinates.
+    pub anchor_rect: Rect<i32>,
+    /// Edge or corner of the anchor rectangle the popup is placed at.
+    pub anchor: Anchor,
+    /// Direction the popup extends to from the anchor.
+    pub gravity: Gravity,
+    /// Shift of the popup from its anchored position, e.g. to align the items of a submenu.
+    pub offset: LogicalPosition<i32>,
+    /// How the compositor may move the popup to keep it on the screen.
+    pub constraint_adjustment: ConstraintAdjustment,
+    /// Take the keyboard and the pointer with the latest button press or touch, the popup is
+    /// dismissed by a click outside of it. Used by the menus.
+    pub grab: bool,
+}
+
+impl PopupAttributes {
+    /// A popup below the anchor rectangle, flipped or slid by the compositor to stay on the
+    /// screen.
+    pub fn new(size: LogicalSize<u32>, anchor_rect: Rect<i32>) -> Self {
+        Self {
+            size,
+            anchor_rect,
+            anchor: Anchor::BottomLeft,
+            gravity: Gravity::BottomRight,
+            offset: LogicalPosition::new(0, 0),
+            constraint_adjustment: ConstraintAdjustment::FlipX
+                | ConstraintAdjustment::FlipY
+                | ConstraintAdjustment::SlideX
+                | ConstraintAdjustment::SlideY,
+            grab: false,
+        }
+    }
+
+    #[inline]
+    pub fn with_anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    #[inline]
+    pub fn with_gravity(mut self, gravity: Gravity) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    #[inline]
+    pub fn with_offset(mut self, offset: LogicalPosition<i32>) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    #[inline]
+    pub fn with_grab(mut self, grab: bool) -> Self {
+        self.grab = grab;
+        self
+    }
+
+    #[inline]
+    pub fn with_constraint_adjustment(mut self, adjustment: ConstraintAdjustment) -> Self {
+        self.constraint_adjustment = adjustment;
+        self
+    }
+
+    /// The positioner of the popup, the anchor rectangle is moved to the window geometry of the
+    /// parent.
+    pub(crate) fn positioner(
+        &self,
+        xdg_shell: &XdgShell,
+        parent: &WaylandWindow,
+    ) -> Result<XdgPositioner, GlobalError> {
+        let positioner = XdgPositioner::new(xdg_shell)?;
+        let ((x, y), geometry) = parent.window_geometry();
+        // Empty rectangles and the ones out of the parent are protocol errors.
+        let (width, height) = (geometry.width.max(1) as i32, geometry.height.max(1) as i32);
+        let left = (self.anchor_rect.x - x).clamp(0, width - 1);
+        let top = (self.anchor_rect.y - y).clamp(0, height - 1);
+        positioner.set_anchor_rect(
+            left,
+            top,
+            self.anchor_rect.width.clamp(1, width - left),
+            self.anchor_rect.height.clamp(1, height - top),
+        );
+        positioner.set_size(
+            self.size.width.clamp(1, i32::MAX as u32) as i32,
+            self.size.height.clamp(1, i32::MAX as u32) as i32,
+        );
+        positioner.set_anchor(self.anchor);
+        positioner.set_gravity(self.gravity);
+        positioner.set_offset(self.offset.x, self.offset.y);
+        positioner.set_constraint_adjustment(self.constraint_adjustment);
+        Ok(positioner)
+    }
+
+    fn window_attributes(&self) -> WindowAttributes {
+        WindowAttributes {
+            surface_size: Some(self.size.into()),
+            decorations: Decorations::None,
+            ..Default::default()
+        }
+    }
+}
+
+impl WaylandWindow {
+    /// Open a popup next to an area of the window, or of the popup for a submenu.
+    ///
+    /// The popup is handled like a window with the returned id: it's announced with
+    /// [`crate::ApplicationHandler::create_window_handle`] once configured, then it's drawn and
+    /// gets the input like the windows. [`crate::WindowsRegistry::close`] closes it, the popups
+    /// opened from it are closed first. When the compositor dismisses it,
+    /// [`crate::ApplicationHandler::popup_done_handle`] is called before it's destroyed.
+    ///
+    /// A grabbing popup can only be opened from a window or from the topmost grabbing popup,
+    /// [`crate::ApplicationHandler::popup_failed_handle`] is called otherwise.
+    pub fn create_popup(&self, attributes: PopupAttributes) -> PopupId {
+        let id = PopupId::next();
+        let parent = self.immutable.object_id.clone();
+        // The popups are registered in the state.
+        self.loop_handle.insert_idle(move |state| {
+            state.create_popup(parent, id, attributes);
+        });
+        id
+    }
+
+    /// Move the popup to a new placement, e.g. for a submenu following its item.
+    ///
+    /// The compositor answers with a [`PopupPlacement`] carrying the token. Compositors older
+    /// than the version 3 of the xdg-shell can't move the popups, the popup is replaced by a new
+    /// one with the returned id instead, the popups opened from it are closed.
+    pub fn reposition(&self, attributes: PopupAttributes, token: u32) -> PopupId {
+        let Some(popup) = self.immutable.window.popup() else {
+            warn!("Only the popups can be repositioned");
+            return self.get_id();
+        };
+        let object_id = self.immutable.object_id.clone();
+        if popup.xdg_popup().version() >= 3 {
+            self.loop_handle.insert_idle(move |state| {
+                state.reposition_popup(object_id, attributes, token);
+            });
+            return self.get_id();
+        }
+        let Some(parent) = self.parent.clone() else {
+            return self.get_id();
+        };
+        let id = PopupId::next();
+        self.loop_handle.insert_idle(move |state| {
+            state.windows.destroy_request.insert(object_id);
+            state.create_popup(parent, id, attributes);
+        });
+        id
+    }
+
+    #[inline]
+    pub fn is_popup(&self) -> bool {
+        self.immutable.window.popup().is_some()
+    }
+}
+
+impl WaylandState {
+    pub(crate) fn create_popup(
+        &mut self,
+        parent: ObjectId,
+        id: PopupId,
+        attributes: PopupAttributes,
+    ) {
+        let Some(parent_window) = self.windows.get_by_object_id(&parent) else {
+            warn!("Dropping the popup of a closed window");
+            return;
+        };
+        let grab = if attributes.grab {
+            match self.popup_grab(&parent, parent_window) {
+                Ok(grab) => Some(grab),
+                Err(err) => {
+                    error!("Failed to open the popup: {err}");
+                    self.events.push_back(Events::PopupFailed(id, err));
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        let positioner = match attributes.positioner(&self.xdg_shell, parent_window) {
+            Ok(positioner) => positioner,
+            Err(err) => {
+                error!("Failed to position the popup: {err}");
+                return;
+            }
+        };
+        let surface = self.compositor_state.create_surface(&self.queue_handle);
+        let wl_id = surface.id();
+        // The popups of the layer surfaces are attached once created.
+        let popup = match Popup::from_surface(
+            parent_window.immutable.window.xdg_surface(),
+            &positioner,
+            &self.queue_handle,
+            surface,
+            &self.xdg_shell,
+        ) {
+            Ok(popup) => popup,
+            Err(err) => {
+                error!("Failed to create the popup: {err}");
+                return;
+            }
+        };
+        #[cfg(feature = "layer-shell")]
+        if let Some(layer) = parent_window.immutable.window.layer() {
+            layer.get_popup(popup.xdg_popup());
+        }
+        // The grab is only allowed before the popup is mapped.
+        if let Some((seat, serial)) = grab {
+            popup.xdg_popup().grab(&seat, serial);
+        }
+        let scale_factor = parent_window.scale_factor;
+        let mut window = WaylandWindow::new(
+            self,
+            ShellSurface::Popup(popup),
+            scale_factor,
+            id,
+            attributes.window_attributes(),
+        );
+        window.parent = Some(parent);
+        window.grabbing = attributes.grab;
+        // The compositor answers the initial commit with the configure mapping the popup.
+        window.immutable.window.wl_surface().commit();
+        self.windows.insert(id, wl_id, window);
+    }
+
+    /// The seat and the serial to grab with, if the protocol allows the grab from the parent.
+    fn popup_grab(
+        &self,
+        parent_id: &ObjectId,
+        parent: &WaylandWindow,
+    ) -> Result<(WlSeat, u32), PopupError> {
+        if parent.is_popup() {
+            if !parent.grabbing {
+                return Err(PopupError::ParentNotGrabbing);
+            }
+            // The popups being destroyed are already out of the way.
+            let covered = self.windows.windows.iter().any(|(object_id, window)| {
+                window.parent.as_ref() == Some(parent_id)
+                    && window.grabbing
+                    && !self.windows.destroy_request.contains(object_id)
+            });
+            if covered {
+                return Err(PopupError::NotTopmost);
+            }
+        }
+        self.seat_state
+            .serials
+            .latest_implicit_grab_serial()
+            .map(|(seat, serial)| (seat.clone(), serial))
+            .ok_or(PopupError::NoGrabSerial)
+    }
+
+    fn reposition_popup(&mut self, object_id: ObjectId, attributes: PopupAttributes, token: u32) {
+        let Some(window) = self.windows.get_by_object_id(&object_id) else {
+            return;
+        };
+        let (Some(popup), Some(parent)) = (
+            window.immutable.window.popup(),
+            window
+                .parent
+                .as_ref()
+                .and_then(|parent| self.windows.get_by_object_id(parent)),
+        ) else {
+            return;
+        };
+        match attributes.positioner(&self.xdg_shell, parent) {
+            Ok(positioner) => popup.reposition(&positioner, token),
+            Err(err) => error!("Failed to position the popup: {err}"),
+        }
+    }
+}
+
+impl PopupHandler for WaylandState {
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        popup: &Popup,
+        config: PopupConfigure,
+    ) {
+        let id = popup.wl_surface().id();
+        // The position is relative to the window geometry of the parent.
+        let Some(((x, y), _)) = self
+            .windows
+            .get_by_object_id(&id)
+            .and_then(|window| window.parent.as_ref())
+            .and_then(|parent| self.windows.get_by_object_id(parent))
+            .map(WaylandWindow::window_geometry)
+        else {
+            return;
+        };
+        let Some(window) = self.windows.get_mut_by_object_id(&id) else {
+            return;
+        };
+        // The compositor may shrink the popup to keep it on the screen.
+        let size = match (u32::try_from(config.width), u32::try_from(config.height)) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => LogicalSize::new(width, height),
+            _ => window.size,
+        };
+        let first_configure = !window.configured;
+        window.configured = true;
+        // The inhibitors only take effect on the mapped surfaces, every configure maps the
+        // surface again after an unmap.
+        window.inhibit_idle();
+        let resize = size != window.size;
+        if first_configure || resize {
+            window.resize(size);
+        }
+        self.windows
+            .request_configured(id.clone(), first_configure, resize);
+        let token = match config.kind {
+            ConfigureKind::Reposition { token } => Some(token),
+            _ => None,
+        };
+        let placement = PopupPlacement {
+            position: LogicalPosition::new(config.position.0 + x, config.position.1 + y),
+            size,
+            token,
+        };
+        self.events.push_back(Events::PopupConfigure(id, placement));
+    }
+
+    fn done(&mut self, _: &Connection, _: &QueueHandle<Self>, popup: &Popup) {
+        let id = popup.wl_surface().id();
+        if self.windows.get_by_object_id(&id).is_some() {
+            self.events.push_back(Events::PopupDone(id.clone()));
+            self.windows.destroy_request.insert(id);
+        }
+    }
+}
+
+delegate_xdg_popup!(WaylandState);
@@ -0,0 +1,179 @@
+use std::sync::mpsc;
+
+use cursor_icon::CursorIcon;
+use dpi::Size;
+use smithay_client_toolkit::reexports::calloop::ping::Ping;
+use tracing::error;
+
+use crate::{CustomCursor, Decorations, DragAction, DragContent, DragIcon, Monitor, WindowId};
+
+/// Change of a window requested through a [`WindowProxy`].
+#[derive(Debug, Clone)]
+pub enum WindowCommand {
+    SetTitle(String),
+    SetCursor(CursorIcon),
+    SetCustomCursor(CustomCursor),
+    SetCursorVisible(bool),
+    RequestSurfaceSize(Size),
+    SetMinSurfaceSize(Option<Size>),
+    SetMaxSurfaceSize(Option<Size>),
+    SetDecorate(bool),
+    SetDecorationMode(Decorations),
+    SetTransparent(bool),
+    SetMaximized(bool),
+    SetMinimized,
+    SetFullscreen(Option<Monitor>),
+    UnsetFullscreen,
+    DragWindow,
+    AcceptDrag(Option<String>, DragAction),
+    ChooseDragAction(Option<DragAction>),
+    StartDrag(DragContent, Option<DragIcon>),
+    RedrawRequest,
+    /// Destroy the window without asking the application.
+    Close,
+}
+
+/// Queue of the window commands, sending one wakes the event loop up.
+#[derive(Debug, Clone)]
+pub(crate) struct CommandSender {
+    sender: mpsc::Sender<(WindowId, WindowCommand)>,
+    ping: Ping,
+}
+
+impl CommandSender {
+    pub(crate) fn new(sender: mpsc::Sender<(WindowId, WindowCommand)>, ping: Ping) -> Self {
+        Self { sender, ping }
+    }
+
+    fn send(&self, command: (WindowId, WindowCommand)) {
+        match self.sender.send(command) {
+            Ok(()) => self.ping.ping(),
+            Err(err) => error!("{err}"),
+        }
+    }
+}
+
+/// Cheap handle to change a window from any handler callback, or from outside of the event loop.
+///
+/// The commands are applied in the order they were sent, before the draws. The ones sent from a
+/// handler callback are applied in the same iteration of the event loop, the others wake it up.
+/// Commands targeting a closed window are dropped.
+#[derive(Debug, Clone)]
+pub struct WindowProxy {
+    window_id: WindowId,
+    sender: CommandSender,
+}
+
+impl WindowProxy {
+    pub(crate) fn new(window_id: WindowId, sender: CommandSender) -> Self {
+        Self { window_id, sender }
+    }
+
+    pub fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    pub fn send(&self, command: WindowCommand) {
+        self.sender.send((self.window_id, command));
+    }
+
+    #[inline]
+    pub fn set_title(&self, title: impl Into<String>) {
+        self.send(WindowCommand::SetTitle(title.into()));
+    }
+
+    #[inline]
+    pub fn set_cursor(&self, cursor: CursorIcon) {
+        self.send(WindowCommand::SetCursor(cursor));
+    }
+
+    #[inline]
+    pub fn set_custom_cursor(&self, cursor: CustomCursor) {
+        self.send(WindowCommand::SetCustomCursor(cursor));
+    }
+
+    #[inline]
+    pub fn set_cursor_visible(&self, visible: bool) {
+        self.send(WindowCommand::SetCursorVisible(visible));
+    }
+
+    #[inline]
+    pub fn request_surface_size(&self, size: impl Into<Size>) {
+        self.send(WindowCommand::RequestSurfaceSize(size.into()));
+    }
+
+    #[inline]
+    pub fn set_min_surface_size(&self, size: Option<Size>) {
+        self.send(WindowCommand::SetMinSurfaceSize(size));
+    }
+
+    #[inline]
+    pub fn set_max_surface_size(&self, size: Option<Size>) {
+        self.send(WindowCommand::SetMaxSurfaceSize(size));
+    }
+
+    #[inline]
+    pub fn set_decorate(&self, decorate: bool) {
+        self.send(WindowCommand::SetDecorate(decorate));
+    }
+
+    #[inline]
+    pub fn set_decoration_mode(&self, decorations: Decorations) {
+        self.send(WindowCommand::SetDecorationMode(decorations));
+    }
+
+    #[inline]
+    pub fn set_transparent(&self, transparent: bool) {
+        self.send(WindowCommand::SetTransparent(transparent));
+    }
+
+    #[inline]
+    pub fn set_maximized(&self, maximized: bool) {
+        self.send(WindowCommand::SetMaximized(maximized));
+    }
+
+    #[inline]
+    pub fn set_minimized(&self) {
+        self.send(WindowCommand::SetMinimized);
+    }
+
+    #[inline]
+    pub fn set_fullscreen(&self, monitor: Option<Monitor>) {
+        self.send(WindowCommand::SetFullscreen(monitor));
+    }
+
+    #[inline]
+    pub fn unset_fullscreen(&self) {
+        self.send(WindowCommand::UnsetFullscreen);
+    }
+
+    #[inline]
+    pub fn drag_window(&self) {
+        self.send(WindowCommand::DragWindow);
+    }
+
+    #[inline]
+    pub fn accept_drag(&self, mime_type: Option<String>, preferred: DragAction) {
+        self.send(WindowCommand::AcceptDrag(mime_type, preferred));
+    }
+
+    #[inline]
+    pub fn choose_drag_action(&self, action: Option<DragAction>) {
+        self.send(WindowCommand::ChooseDragAction(action));
+    }
+
+    #[inline]
+    pub fn start_drag(&self, content: DragContent, icon: Option<DragIcon>) {
+        self.send(WindowCommand::StartDrag(content, icon));
+    }
+
+    #[inline]
+    pub fn redraw_request(&self) {
+        self.send(WindowCommand::RedrawRequest);
+    }
+
+    #[inline]
+    pub fn close(&self) {
+        self.send(WindowCommand::Close);
+    }
+}
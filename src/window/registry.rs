@@ -1,18 +1,24 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, mem, sync::Arc};
 
 use indexmap::IndexSet;
 use smithay_client_toolkit::reexports::client::backend::ObjectId;
 
-use crate::{WaylandWindow, WindowId};
+use crate::{Monitor, WaylandWindow, WindowId, WindowImmutable};
 
 #[derive(Default)]
 pub struct WindowsRegistry {
     pub(crate) id_converter: HashMap<WindowId, ObjectId>,
     pub(crate) windows: HashMap<ObjectId, WaylandWindow>,
+    pub(crate) create_request: IndexSet<ObjectId>,
     pub(crate) rescale_request: IndexSet<ObjectId>,
+    pub(crate) monitor_request: IndexSet<ObjectId>,
+    pub(crate) kiosk_request: IndexSet<ObjectId>,
     pub(crate) resize_request: IndexSet<ObjectId>,
     pub(crate) redraw_request: IndexSet<ObjectId>,
     pub(crate) close_request: IndexSet<ObjectId>,
+    pub(crate) destroy_request: IndexSet<ObjectId>,
+    /// The outputs in the order they were advertised.
+    pub(crate) monitors: Vec<Monitor>,
 }
 
 impl WindowsRegistry {
@@ -27,14 +33,23 @@ impl WindowsRegistry {
         }
     }
 
-    pub fn remove(&mut self, object_id: &ObjectId) -> WindowId {
-        if let Some(window) = self.windows.remove(object_id) {
-            let id = &window.immutable.window_id;
-            if let Some(_) = self.id_converter.remove(id) {
-                return *id;
-            }
+    /// Remove the window of the surface, `None` if there is no such window.
+    pub fn remove(&mut self, object_id: &ObjectId) -> Option<WindowId> {
+        // No request may outlive the window.
+        self.create_request.shift_remove(object_id);
+        self.rescale_request.shift_remove(object_id);
+        self.monitor_request.shift_remove(object_id);
+        self.kiosk_request.shift_remove(object_id);
+        self.resize_request.shift_remove(object_id);
+        self.redraw_request.shift_remove(object_id);
+        self.close_request.shift_remove(object_id);
+        self.destroy_request.shift_remove(object_id);
+        let window = self.windows.remove(object_id)?;
+        for child in self.windows.values_mut() {
+            child.parent_destroyed(object_id);
         }
-        panic!("Failed to remove window");
+        let id = window.immutable.window_id;
+        self.id_converter.remove(&id).map(|_| id)
     }
 
     pub(crate) fn get_mut_by_object_id(&mut self, id: &ObjectId) -> Option<&mut WaylandWindow> {
@@ -58,11 +73,132 @@ impl WindowsRegistry {
             .and_then(|id| self.windows.get(id))
     }
 
+    /// The handle given with [`crate::ApplicationHandler::create_window_handle`].
+    ///
+    /// The handle keeps the surface alive, so drop it by the time
+    /// [`crate::ApplicationHandler::destroyed_handle`] is called.
+    pub fn immutable(&self, id: &WindowId) -> Option<Arc<WindowImmutable>> {
+        self.get(id).map(|window| window.immutable.clone())
+    }
+
     pub fn get_id(&self, object_id: &ObjectId) -> Option<&WindowId> {
         self.windows.get(object_id).map(|w| &w.immutable.window_id)
     }
 
+    /// Destroy the window at the end of the current iteration, without asking the application.
+    pub fn close(&mut self, id: &WindowId) {
+        if let Some(object_id) = self.id_converter.get(id) {
+            self.destroy_request.insert(object_id.clone());
+        }
+    }
+
+    /// Request the callbacks of a configure, the size of a new window is reported with its
+    /// creation.
+    pub(crate) fn request_configured(
+        &mut self,
+        object_id: ObjectId,
+        first_configure: bool,
+        resize: bool,
+    ) {
+        if first_configure {
+            self.create_request.insert(object_id);
+        } else if resize {
+            self.resize_request.insert(object_id);
+        }
+    }
+
+    /// Take the rescale requests, the scale of the windows created in the same iteration is
+    /// reported with their creation.
+    pub(crate) fn take_rescale_requests(
+        &mut self,
+        created: &IndexSet<ObjectId>,
+    ) -> IndexSet<ObjectId> {
+        let mut requests = mem::take(&mut self.rescale_request);
+        requests.retain(|object_id| !created.contains(object_id));
+        requests
+    }
+
+    /// The outputs of the compositor, e.g. to let the user pick the one for a fullscreen window.
+    pub fn available_monitors(&self) -> &[Monitor] {
+        &self.monitors
+    }
+
+    /// The primary monitor of the window, with the latest mode of the output.
+    pub fn current_monitor(&self, id: &WindowId) -> Option<Monitor> {
+        let output = self.get(id)?.output.as_ref()?;
+        self.monitors
+            .iter()
+            .find(|monitor| monitor.output() == output)
+            .cloned()
+    }
+
+    /// The window with the popups opened from it, the topmost popups first as they have to be
+    /// destroyed before their parent.
+    pub(crate) fn with_popups(&self, object_id: &ObjectId) -> Vec<ObjectId> {
+        if !self.windows.contains_key(object_id) {
+            return Vec::new();
+        }
+        let mut ids = vec![object_id.clone()];
+        let mut index = 0;
+        while let Some(parent) = ids.get(index).cloned() {
+            ids.extend(
+                self.windows
+                    .iter()
+                    .filter(|(_, window)| window.parent.as_ref() == Some(&parent))
+                    .map(|(id, _)| id.clone()),
+            );
+            index += 1;
+        }
+        ids.reverse();
+        ids
+    }
+
     pub fn is_empty(&self) -> bool {
         self.windows.is_empty()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeCompositor;
+
+    #[test]
+    fn first_configure_creates_the_window() {
+        let mut windows = WindowsRegistry::default();
+        windows.request_configured(ObjectId::null(), true, true);
+        assert!(windows.create_request.contains(&ObjectId::null()));
+        // The size goes with the creation, not with a resize before it.
+        assert!(windows.resize_request.is_empty());
+
+        windows.create_request.clear();
+        windows.request_configured(ObjectId::null(), false, false);
+        assert!(windows.create_request.is_empty());
+        assert!(windows.resize_request.is_empty());
+
+        windows.request_configured(ObjectId::null(), false, true);
+        assert!(windows.create_request.is_empty());
+        assert!(windows.resize_request.contains(&ObjectId::null()));
+    }
+
+    #[test]
+    fn new_windows_get_their_scale_with_the_creation() {
+        let compositor = FakeCompositor::new(&[("wl_compositor", 6)]);
+        let [created, known]: [ObjectId; 2] = compositor.object_ids(2).try_into().unwrap();
+        let mut windows = WindowsRegistry::default();
+        windows.request_configured(created.clone(), true, false);
+        windows.rescale_request.insert(created.clone());
+        windows.rescale_request.insert(known.clone());
+
+        let create_req = mem::take(&mut windows.create_request);
+        let rescale_req = windows.take_rescale_requests(&create_req);
+        assert_eq!(rescale_req.into_iter().collect::<Vec<_>>(), [known]);
+        assert!(windows.rescale_request.is_empty());
+    }
+
+    #[test]
+    fn removing_an_unknown_window_gives_none() {
+        let mut windows = WindowsRegistry::default();
+        assert_eq!(windows.remove(&ObjectId::null()), None);
+    }
+}
@@ -0,0 +1,276 @@
+// Handling of the subsurfaces embedded in the windows, e.g. for the video planes.
+
+use std::sync::{Arc, Mutex, Weak};
+
+use dpi::{LogicalPosition, LogicalSize};
+use raw_window_handle::{
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
+    RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle, WindowHandle,
+};
+use smithay_client_toolkit::reexports::{
+    client::{
+        Proxy,
+        backend::ObjectId,
+        protocol::{wl_display::WlDisplay, wl_subsurface::WlSubsurface, wl_surface::WlSurface},
+    },
+    protocols::wp::viewporter::client::wp_viewport::WpViewport,
+};
+use tracing::warn;
+
+use crate::WaylandWindow;
+
+/// The objects of a subsurface, shared by its handle and its window.
+#[derive(Debug)]
+pub(crate) struct Subsurface {
+    subsurface: WlSubsurface,
+    surface: WlSurface,
+    /// The surface of the window.
+    parent: WlSurface,
+    viewport: Option<WpViewport>,
+    position: Mutex<SubsurfacePosition>,
+}
+
+impl Subsurface {
+    pub(crate) fn object_id(&self) -> ObjectId {
+        self.surface.id()
+    }
+
+    /// The position applied by the compositor, where the input is.
+    pub(crate) fn position(&self) -> LogicalPosition<i32> {
+        self.position.lock().unwrap().current
+    }
+
+    /// The window is committed, so is the pending position.
+    pub(crate) fn latch_position(&self) {
+        self.position.lock().unwrap().latch();
+    }
+}
+
+/// The position of a subsurface is state of its parent, applied with the commit of the window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct SubsurfacePosition {
+    current: LogicalPosition<i32>,
+    pending: Option<LogicalPosition<i32>>,
+}
+
+impl SubsurfacePosition {
+    fn set(&mut self, position: LogicalPosition<i32>) {
+        self.pending = Some(position);
+    }
+
+    fn latch(&mut self) {
+        if let Some(position) = self.pending.take() {
+            self.current = position;
+        }
+    }
+
+    /// The latest position set, applied or not.
+    fn requested(&self) -> LogicalPosition<i32> {
+        self.pending.unwrap_or(self.current)
+    }
+}
+
+impl Drop for Subsurface {
+    fn drop(&mut self) {
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+        self.subsurface.destroy();
+        self.surface.destroy();
+    }
+}
+
+/// A surface drawn inside of a window by its own renderer, see
+/// [`WaylandWindow::create_subsurface`].
+///
+/// The subsurface is destroyed when the handle is dropped. The changes of the position and of the
+/// order are applied with the next commit of the window.
+#[derive(Debug)]
+pub struct SubsurfaceHandle {
+    inner: Arc<Subsurface>,
+    display: WlDisplay,
+}
+
+impl SubsurfaceHandle {
+    /// Position relative to the surface of the window.
+    pub fn set_position(&self, position: LogicalPosition<i32>) {
+        self.inner.position.lock().unwrap().set(position);
+        self.inner.subsurface.set_position(position.x, position.y);
+    }
+
+    /// The latest position set, even if the window is not committed yet.
+    pub fn position(&self) -> LogicalPosition<i32> {
+        self.inner.position.lock().unwrap().requested()
+    }
+
+    /// Scale the buffers to the size.
+    ///
+    /// Without wp-viewporter the buffers are drawn at their own size instead.
+    pub fn set_size(&self, size: LogicalSize<u32>) {
+        let Some(viewport) = self.inner.viewport.as_ref() else {
+            warn!("wp-viewporter is not supported, the subsurface has the size of its buffers");
+            return;
+        };
+        // A zero size is a protocol error.
+        viewport.set_destination(
+            size.width.clamp(1, i32::MAX as u32) as i32,
+            size.height.clamp(1, i32::MAX as u32) as i32,
+        );
+    }
+
+    /// Stack the subsurface right above a sibling subsurface, or above the window with `None`.
+    pub fn place_above(&self, sibling: Option<&SubsurfaceHandle>) {
+        let sibling = sibling.map_or(&self.inner.parent, |sibling| &sibling.inner.surface);
+        self.inner.subsurface.place_above(sibling);
+    }
+
+    /// Stack the subsurface right below a sibling subsurface, or below the window with `None`.
+    pub fn place_below(&self, sibling: Option<&SubsurfaceHandle>) {
+        let sibling = sibling.map_or(&self.inner.parent, |sibling| &sibling.inner.surface);
+        self.inner.subsurface.place_below(sibling);
+    }
+
+    /// In the synchronized mode, the default one, the commits of the subsurface are applied with
+    /// the next commit of the window. Otherwise they are applied right away, e.g. for a video
+    /// playing at its own rate.
+    pub fn set_sync(&self, sync: bool) {
+        if sync {
+            self.inner.subsurface.set_sync();
+        } else {
+            self.inner.subsurface.set_desync();
+        }
+    }
+
+    #[inline]
+    pub fn wl_surface(&self) -> &WlSurface {
+        &self.inner.surface
+    }
+
+    /// Same as dropping the handle.
+    pub fn destroy(self) {}
+
+    #[inline]
+    pub fn raw_window_handle_rwh_06(&self) -> Result<RawWindowHandle, HandleError> {
+        Ok(WaylandWindowHandle::new({
+            let ptr = self.inner.surface.id().as_ptr();
+            std::ptr::NonNull::new(ptr as *mut _).expect("wl_surface will never be null")
+        })
+        .into())
+    }
+
+    #[inline]
+    pub fn raw_display_handle_rwh_06(&self) -> Result<RawDisplayHandle, HandleError> {
+        Ok(WaylandDisplayHandle::new({
+            let ptr = self.display.id().as_ptr();
+            std::ptr::NonNull::new(ptr as *mut _).expect("wl_proxy should never be null")
+        })
+        .into())
+    }
+}
+
+impl HasWindowHandle for SubsurfaceHandle {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let raw = self.raw_window_handle_rwh_06()?;
+
+        // SAFETY: The surface is only destroyed with the handle.
+        Ok(unsafe { WindowHandle::borrow_raw(raw) })
+    }
+}
+
+impl HasDisplayHandle for SubsurfaceHandle {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        let raw = self.raw_display_handle_rwh_06()?;
+
+        // SAFETY: The display is alive as long as the connection.
+        Ok(unsafe { DisplayHandle::borrow_raw(raw) })
+    }
+}
+
+impl WaylandWindow {
+    /// Create a surface drawn inside of the window by its own renderer, without copying its
+    /// pixels, e.g. a video plane. Its buffers are scaled to `size` when wp-viewporter is
+    /// supported.
+    ///
+    /// The input on the subsurface is reported for the window, in the coordinates of the window.
+    ///
+    /// Returns `None` when the compositor does not support wl_subcompositor.
+    pub fn create_subsurface(
+        &mut self,
+        position: LogicalPosition<i32>,
+        size: LogicalSize<u32>,
+    ) -> Option<SubsurfaceHandle> {
+        let Some(subcompositor) = self.subcompositor_state.as_ref() else {
+            warn!("wl_subcompositor is not supported");
+            return None;
+        };
+        let (subsurface, surface) = subcompositor.create_subsurface(
+            self.immutable.window.wl_surface().clone(),
+            &self.queue_handle,
+        );
+        let viewport = self
+            .viewporter_state
+            .as_ref()
+            .map(|state| state.get_viewport(&surface, &self.queue_handle));
+        let handle = SubsurfaceHandle {
+            inner: Arc::new(Subsurface {
+                subsurface,
+                surface,
+                parent: self.immutable.window.wl_surface().clone(),
+                viewport,
+                position: Mutex::new(SubsurfacePosition::default()),
+            }),
+            display: self.immutable.display.clone(),
+        };
+        handle.set_position(position);
+        if handle.inner.viewport.is_some() {
+            handle.set_size(size);
+        }
+        self.subsurfaces
+            .retain(|subsurface| subsurface.strong_count() > 0);
+        self.subsurfaces.push(Arc::downgrade(&handle.inner));
+        Some(handle)
+    }
+
+    /// Position of the subsurface of the window with the surface, for the input on it.
+    pub(crate) fn subsurface_position(
+        &self,
+        surface_id: &ObjectId,
+    ) -> Option<LogicalPosition<i32>> {
+        self.subsurfaces
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|subsurface| subsurface.object_id() == *surface_id)
+            .map(|subsurface| subsurface.position())
+    }
+
+    /// Apply the pending positions of the subsurfaces, called when the window is committed.
+    pub(crate) fn latch_subsurface_positions(&self) {
+        for subsurface in self.subsurfaces.iter().filter_map(Weak::upgrade) {
+            subsurface.latch_position();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_follows_the_committed_position() {
+        let mut position = SubsurfacePosition::default();
+        position.set(LogicalPosition::new(10, 20));
+        // The input stays at the old position until the window is committed.
+        assert_eq!(position.current, LogicalPosition::new(0, 0));
+        assert_eq!(position.requested(), LogicalPosition::new(10, 20));
+
+        position.latch();
+        assert_eq!(position.current, LogicalPosition::new(10, 20));
+
+        position.set(LogicalPosition::new(30, 40));
+        position.set(LogicalPosition::new(50, 60));
+        position.latch();
+        assert_eq!(position.current, LogicalPosition::new(50, 60));
+        position.latch();
+        assert_eq!(position.current, LogicalPosition::new(50, 60));
+    }
+}